@@ -0,0 +1,29 @@
+use std::{env, path::Path, process::Command};
+
+/// Embeds the target triple and the git commit (if built from git
+/// repository) so that `ccpp --version` can print them.
+fn main() {
+    // cargo reruns the script when a watched path doesn't exist, so the git
+    // files are watched only when building from git repository
+    println!("cargo:rerun-if-changed=build.rs");
+    for path in [".git/HEAD", ".git/refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+
+    if let Ok(target) = env::var("TARGET") {
+        println!("cargo:rustc-env=CCPP_TARGET={target}");
+    }
+
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
+        .filter(|h| !h.is_empty());
+    if let Some(hash) = hash {
+        println!("cargo:rustc-env=CCPP_GIT_HASH={hash}");
+    }
+}
@@ -1,8 +1,20 @@
-use std::{env, fs::create_dir_all, path::PathBuf};
+use std::{
+    env,
+    fs::create_dir_all,
+    path::{Component, PathBuf},
+    time::Duration,
+};
 
 use thiserror::Error;
 
-use crate::err::{Error, Result};
+use crate::{
+    clean::CleanMode,
+    diagnostics::DiagnosticsFormat,
+    duration::HumanDuration,
+    err::{Error, Result},
+    scaffold::License,
+    size_report::SizeReport,
+};
 
 macro_rules! next_arg {
     ($args:ident, $err:expr) => {
@@ -31,21 +43,139 @@ pub enum ArgError {
     NoAction,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Action {
     None,
     Clean,
     Build,
     Run,
-    Help,
+    /// Help, optionally only for the given action
+    Help(Option<String>),
     New(PathBuf),
+    /// Build and run single source file
+    Script(PathBuf),
+    /// Generate configuration for other tools
+    Generate(Generator),
+    /// Print the merged configuration
+    ConfigShow,
+    /// Print the path to the binary
+    Path,
+    /// Print the sources that include the file
+    Rdeps(PathBuf),
+    /// Print the commands that would run to build the project
+    Plan,
+    /// Print the recorded sizes of the binary
+    SizeHistory,
+    /// Print the versions of the resolved compilers
+    CompilerVersion,
+    /// Print the version of ccpp
+    Version,
+    /// Print the command that compiles the source
+    PrintFlags(PathBuf),
+    /// Build and run the programs in `tests/`
+    Test,
+    /// Print the preprocessed source
+    Preprocess(PathBuf),
 }
 
-#[derive(Debug)]
+/// Tool for which configuration can be generated.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Generator {
+    /// `tasks.json` and `launch.json` for VS Code
+    Vscode,
+}
+
+#[derive(Debug, Clone)]
 pub struct Args {
     pub action: Action,
     pub release: bool,
+    pub verbosity: u8,
+    pub iwyu: bool,
+    pub iwyu_fail: bool,
+    /// Specific source files to build
+    pub files: Vec<PathBuf>,
+    /// Build only the sources in this directory
+    pub dir: Option<PathBuf>,
+    /// Command used to run the target
+    pub runner: Option<String>,
+    /// Max time of single compile/link job
+    pub job_timeout: Option<Duration>,
+    pub keep_going: bool,
+    pub max_errors: Option<usize>,
+    /// Max number of jobs running at the same time
+    pub jobs: Option<usize>,
+    /// Number of retries of crashed compile/link jobs
+    pub retries: usize,
+    /// Build the files that failed in the last build first
+    pub retry_failed: bool,
+    /// Overrides the bin directory from the configuration
+    pub bin_dir: Option<PathBuf>,
+    /// Don't wait for the build lock
+    pub no_wait: bool,
+    /// Print build events as NDJSON
+    pub events: bool,
+    pub diagnostics: Option<DiagnosticsFormat>,
     pub app_args: Vec<String>,
+    /// Compiler flags for the script
+    pub script_flags: Vec<String>,
+    /// Don't use the user configuration
+    pub no_user_config: bool,
+    /// Workspace member to use
+    pub package: Option<String>,
+    /// What is removed by `clean`
+    pub clean: CleanMode,
+    /// Don't print the commands and the path to the binary
+    pub quiet: bool,
+    /// Print all the files produced by the build
+    pub print_outputs: bool,
+    /// Print the size of the binary after the build
+    pub size_report: Option<SizeReport>,
+    /// Run the existing binary without building it
+    pub no_build: bool,
+    /// Print the stable path to the binary
+    pub stable: bool,
+    /// Print the plan as JSON
+    pub json: bool,
+    /// Run the discovered `test_*` functions of the test sources
+    pub auto_tests: bool,
+    /// File where the preprocessed source is written
+    pub output: Option<PathBuf>,
+    /// License of the new project
+    pub license: Option<License>,
+    /// User template of the new project
+    pub template: Option<String>,
+    /// Additional arguments for compiling from `--cflag`
+    pub cflags: Vec<String>,
+    /// Additional arguments for linking from `--ldflag`
+    pub ldflags: Vec<String>,
+    /// Build once with each of the standards from `--std-matrix`
+    pub std_matrix: Vec<String>,
+}
+
+impl Action {
+    /// Gets the name of the action as it is given on the command line.
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Clean => Some("clean"),
+            Self::Build => Some("build"),
+            Self::Run => Some("run"),
+            Self::Help(_) => Some("help"),
+            Self::New(_) => Some("new"),
+            Self::Script(_) => Some("script"),
+            Self::Generate(_) => Some("generate"),
+            Self::ConfigShow => Some("config"),
+            Self::Path => Some("path"),
+            Self::Rdeps(_) => Some("rdeps"),
+            Self::Plan => Some("plan"),
+            Self::SizeHistory => Some("size"),
+            Self::CompilerVersion => Some("--compiler-version"),
+            Self::Version => Some("version"),
+            Self::PrintFlags(_) => Some("print-flags"),
+            Self::Test => Some("test"),
+            Self::Preprocess(_) => Some("preprocess"),
+        }
+    }
 }
 
 impl Args {
@@ -64,17 +194,35 @@ impl Args {
 
         while let Some(arg) = args.next() {
             match arg {
+                _ if res.action == Action::Help(None)
+                    && !arg.starts_with('-') =>
+                {
+                    res.action = Action::Help(Some(arg.to_owned()))
+                }
                 "clean" => res.action = Action::Clean,
                 "build" => res.action = Action::Build,
                 "run" => res.action = Action::Run,
+                "path" => res.action = Action::Path,
+                "plan" => res.action = Action::Plan,
+                "test" => res.action = Action::Test,
+                "--compiler-version" => res.action = Action::CompilerVersion,
+                "version" | "--version" => res.action = Action::Version,
                 "help" | "h" | "-h" | "-?" | "--help" => {
-                    res.action = Action::Help
+                    // `ccpp <action> --help` shows help for the action
+                    let topic = res.action.name().map(Into::into);
+                    res.action = Action::Help(topic);
                 }
                 "new" => {
                     let value = next_arg!(
                         args,
                         ArgError::MissingArgument(arg.to_owned())
                     );
+                    // `ccpp new --help` shows help instead of creating the
+                    // folder `--help`
+                    if matches!(value, "-h" | "-?" | "--help") {
+                        res.action = Action::Help(Some(arg.to_owned()));
+                        continue;
+                    }
                     let folder: PathBuf = value.into();
                     if folder.exists() && !folder.is_dir() {
                         return Err(ArgError::InvalidValue {
@@ -92,11 +240,409 @@ impl Args {
                         res.action = Action::New(folder);
                     }
                 }
+                "script" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    if matches!(value, "-h" | "-?" | "--help") {
+                        res.action = Action::Help(Some(arg.to_owned()));
+                        continue;
+                    }
+                    let file: PathBuf = value.into();
+                    if !file.is_file() {
+                        return Err(ArgError::InvalidValue {
+                            value: value.into(),
+                            arg: arg.into(),
+                            expl: "Expected existing source file",
+                        }
+                        .into());
+                    }
+                    res.action = Action::Script(file);
+                }
+                "rdeps" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    if matches!(value, "-h" | "-?" | "--help") {
+                        res.action = Action::Help(Some(arg.to_owned()));
+                        continue;
+                    }
+                    let file: PathBuf = value.into();
+                    if !file.is_file() {
+                        return Err(ArgError::InvalidValue {
+                            value: value.into(),
+                            arg: arg.into(),
+                            expl: "Expected existing file",
+                        }
+                        .into());
+                    }
+                    res.action = Action::Rdeps(file);
+                }
+                "print-flags" | "--print-flags" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    if matches!(value, "-h" | "-?" | "--help") {
+                        res.action = Action::Help(Some(arg.to_owned()));
+                        continue;
+                    }
+                    let file: PathBuf = value.into();
+                    if !file.is_file() {
+                        return Err(ArgError::InvalidValue {
+                            value: value.into(),
+                            arg: arg.into(),
+                            expl: "Expected existing source file",
+                        }
+                        .into());
+                    }
+                    res.action = Action::PrintFlags(file);
+                }
+                "preprocess" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    if matches!(value, "-h" | "-?" | "--help") {
+                        res.action = Action::Help(Some(arg.to_owned()));
+                        continue;
+                    }
+                    let file: PathBuf = value.into();
+                    if !file.is_file() {
+                        return Err(ArgError::InvalidValue {
+                            value: value.into(),
+                            arg: arg.into(),
+                            expl: "Expected existing source file",
+                        }
+                        .into());
+                    }
+                    res.action = Action::Preprocess(file);
+                }
+                "generate" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    let generator = match value {
+                        "vscode" => Generator::Vscode,
+                        "-h" | "-?" | "--help" => {
+                            res.action = Action::Help(Some(arg.to_owned()));
+                            continue;
+                        }
+                        _ => {
+                            return Err(ArgError::InvalidValue {
+                                value: value.into(),
+                                arg: arg.into(),
+                                expl: "Expected `vscode`",
+                            }
+                            .into())
+                        }
+                    };
+                    res.action = Action::Generate(generator);
+                }
+                "size" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    match value {
+                        "history" => res.action = Action::SizeHistory,
+                        "-h" | "-?" | "--help" => {
+                            res.action = Action::Help(Some(arg.to_owned()))
+                        }
+                        _ => {
+                            return Err(ArgError::InvalidValue {
+                                value: value.into(),
+                                arg: arg.into(),
+                                expl: "Expected `history`",
+                            }
+                            .into())
+                        }
+                    }
+                }
+                "config" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    match value {
+                        "show" => res.action = Action::ConfigShow,
+                        "-h" | "-?" | "--help" => {
+                            res.action = Action::Help(Some(arg.to_owned()))
+                        }
+                        _ => {
+                            return Err(ArgError::InvalidValue {
+                                value: value.into(),
+                                arg: arg.into(),
+                                expl: "Expected `show`",
+                            }
+                            .into())
+                        }
+                    }
+                }
+                "--no-user-config" => res.no_user_config = true,
+                "--cache" if res.action == Action::Clean => {
+                    res.clean = CleanMode::Cache
+                }
+                "--all" if res.action == Action::Clean => {
+                    res.clean = CleanMode::All
+                }
+                "--no-build" if res.action == Action::Run => {
+                    res.no_build = true
+                }
+                "--stable" if res.action == Action::Path => res.stable = true,
+                "--json" if res.action == Action::Plan => res.json = true,
+                "--auto" if res.action == Action::Test => {
+                    res.auto_tests = true
+                }
+                "-o" | "--output"
+                    if matches!(res.action, Action::Preprocess(_)) =>
+                {
+                    res.output = Some(
+                        next_arg!(
+                            args,
+                            ArgError::MissingArgument(arg.to_owned())
+                        )
+                        .into(),
+                    );
+                }
+                "--dir"
+                    if matches!(res.action, Action::Build | Action::Plan) =>
+                {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    let dir: PathBuf = value.into();
+                    if !dir.is_dir() {
+                        return Err(ArgError::InvalidValue {
+                            value: value.into(),
+                            arg: arg.into(),
+                            expl: "Expected existing directory",
+                        }
+                        .into());
+                    }
+                    res.dir = Some(dir);
+                }
+                "--std-matrix" if res.action == Action::Build => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    let stds: Vec<_> = value
+                        .split(',')
+                        .map(|s| s.trim().to_owned())
+                        .collect();
+                    if stds.iter().any(|s| s.is_empty()) {
+                        return Err(ArgError::InvalidValue {
+                            value: value.into(),
+                            arg: arg.into(),
+                            expl: "Expected comma separated standards (e.g. \
+                                `c11,c17,c23`)",
+                        }
+                        .into());
+                    }
+                    res.std_matrix = stds;
+                }
+                "--license" if matches!(res.action, Action::New(_)) => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    res.license =
+                        Some(License::from_name(value).ok_or_else(|| {
+                            ArgError::InvalidValue {
+                                value: value.into(),
+                                arg: arg.into(),
+                                expl: "Expected `mit` or `apache-2.0`",
+                            }
+                        })?);
+                }
+                "--template" if matches!(res.action, Action::New(_)) => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    res.template = Some(value.to_owned());
+                }
+                "--flags" if matches!(res.action, Action::Script(_)) => {
+                    // all arguments up to `--` are flags for the compiler
+                    while let Some(a) = args.next() {
+                        if a == "--" {
+                            res.app_args
+                                .extend(args.by_ref().map(|a| a.to_owned()));
+                            break;
+                        }
+                        res.script_flags.push(a.to_owned());
+                    }
+                }
                 "-r" | "--release" => res.release = true,
+                "--runner" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    res.runner = Some(value.to_owned());
+                }
+                "-j" | "--jobs" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    let jobs = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|j| *j != 0)
+                        .ok_or_else(|| ArgError::InvalidValue {
+                            value: value.into(),
+                            arg: arg.into(),
+                            expl: "Expected positive integer",
+                        })?;
+                    res.jobs = Some(jobs);
+                }
+                "--job-timeout" | "--timeout" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    let timeout =
+                        value.parse::<HumanDuration>().map_err(|_| {
+                            ArgError::InvalidValue {
+                            value: value.into(),
+                            arg: arg.into(),
+                            expl: "Expected positive duration (e.g. `300s` or \
+                                `5m`)",
+                        }
+                        })?;
+                    res.job_timeout = Some(timeout.0);
+                }
+                "-k" | "--keep-going" => res.keep_going = true,
+                "--retry-failed" => res.retry_failed = true,
+                "--max-errors" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    let max = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|m| *m != 0)
+                        .ok_or_else(|| ArgError::InvalidValue {
+                            value: value.into(),
+                            arg: arg.into(),
+                            expl: "Expected positive integer",
+                        })?;
+                    res.max_errors = Some(max);
+                }
+                "--retry" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    res.retries = value.parse::<usize>().map_err(|_| {
+                        ArgError::InvalidValue {
+                            value: value.into(),
+                            arg: arg.into(),
+                            expl: "Expected non-negative integer",
+                        }
+                    })?;
+                }
+                "--cflag" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    res.cflags.push(value.to_owned());
+                }
+                "--ldflag" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    res.ldflags.push(value.to_owned());
+                }
+                "--bin-dir" => {
+                    res.bin_dir = Some(
+                        next_arg!(
+                            args,
+                            ArgError::MissingArgument(arg.to_owned())
+                        )
+                        .into(),
+                    );
+                }
+                "-p" | "--package" => {
+                    res.package = Some(
+                        next_arg!(
+                            args,
+                            ArgError::MissingArgument(arg.to_owned())
+                        )
+                        .to_owned(),
+                    );
+                }
+                "--no-wait" => res.no_wait = true,
+                "--events" => res.events = true,
+                "--diagnostics-format" => {
+                    let value = next_arg!(
+                        args,
+                        ArgError::MissingArgument(arg.to_owned())
+                    );
+                    res.diagnostics =
+                        Some(DiagnosticsFormat::from_name(value).ok_or_else(
+                            || ArgError::InvalidValue {
+                                value: value.into(),
+                                arg: arg.into(),
+                                expl: "Expected `github` or `sarif`",
+                            },
+                        )?);
+                }
+                "--iwyu" => res.iwyu = true,
+                "--iwyu-strict" => {
+                    res.iwyu = true;
+                    res.iwyu_fail = true;
+                }
+                "-q" | "--quiet" => res.quiet = true,
+                "--print-outputs" => res.print_outputs = true,
+                "--size-report" => res.size_report = Some(SizeReport::Binary),
+                _ if arg.starts_with("--size-report=") => {
+                    let value = &arg["--size-report=".len()..];
+                    res.size_report =
+                        Some(SizeReport::from_name(value).ok_or_else(
+                            || ArgError::InvalidValue {
+                                value: value.into(),
+                                arg: "--size-report".into(),
+                                expl: "Expected `binary` or `objects`",
+                            },
+                        )?);
+                }
+                "-v" | "--verbose" => res.verbosity += 1,
+                "-vv" => res.verbosity += 2,
+                "-vvv" => res.verbosity += 3,
                 "--" => {
                     res.app_args.extend(args.map(|a| a.to_owned()));
                     break;
                 }
+                _ if matches!(res.action, Action::Build | Action::Plan)
+                    && !arg.starts_with('-') =>
+                {
+                    let file: PathBuf = arg.into();
+                    if !file.is_file() {
+                        return Err(ArgError::InvalidValue {
+                            value: arg.into(),
+                            arg: res.action.name().unwrap_or_default().into(),
+                            expl: "Expected existing source file",
+                        }
+                        .into());
+                    }
+                    // remove the `.` components so that the path can be
+                    // compared with the source directory
+                    res.files.push(
+                        file.components()
+                            .filter(|c| *c != Component::CurDir)
+                            .collect(),
+                    );
+                }
                 _ => {
                     return Err(Error::Arg(ArgError::UnknownArgument(
                         arg.to_owned(),
@@ -108,7 +654,7 @@ impl Args {
         if res.action == Action::None {
             #[cfg(not(debug_assertions))]
             {
-                res.action = Action::Help;
+                res.action = Action::Help(None);
                 Ok(res)
             }
             #[cfg(debug_assertions)]
@@ -126,7 +672,146 @@ impl Default for Args {
         Self {
             action: Action::None,
             release: false,
+            verbosity: 0,
+            iwyu: false,
+            iwyu_fail: false,
+            files: vec![],
+            runner: None,
+            job_timeout: None,
+            keep_going: false,
+            max_errors: None,
+            jobs: None,
+            retries: 0,
+            retry_failed: false,
+            bin_dir: None,
+            no_wait: false,
+            events: false,
+            diagnostics: None,
             app_args: vec![],
+            script_flags: vec![],
+            no_user_config: false,
+            package: None,
+            clean: CleanMode::Artifacts,
+            quiet: false,
+            print_outputs: false,
+            size_report: None,
+            no_build: false,
+            auto_tests: false,
+            output: None,
+            stable: false,
+            json: false,
+            license: None,
+            dir: None,
+            template: None,
+            cflags: vec![],
+            ldflags: vec![],
+            std_matrix: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::help::{ACTIONS, FLAGS};
+
+    /// Gets the flags that are matched by [`Args::parse`].
+    fn parsed_flags() -> Vec<&'static str> {
+        let src = include_str!("arg_parser.rs");
+        let start = src.find("pub fn parse<").unwrap();
+        let end = start + src[start..].find("impl Default for Args").unwrap();
+        // every other part is string literal
+        src[start..end]
+            .split('"')
+            .skip(1)
+            .step_by(2)
+            .filter(|s| {
+                let name = s.trim_start_matches('-');
+                s.starts_with('-')
+                    && name
+                        .starts_with(|c: char| c.is_alphabetic() || c == '?')
+                    && name
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || "-?".contains(c))
+            })
+            .collect()
+    }
+
+    /// Checks whether the flag is described in the help.
+    fn documented(flag: &str) -> bool {
+        let quoted = format!("`{flag}`");
+        FLAGS.iter().any(|f| {
+            f.names.contains(&flag) || f.description.contains(&quoted)
+        }) || ACTIONS
+            .iter()
+            .any(|a| a.names.contains(&flag) || a.args.contains(flag))
+    }
+
+    #[test]
+    fn all_flags_are_documented() {
+        let flags = parsed_flags();
+        assert!(flags.contains(&"--release") && flags.contains(&"-v"));
+        for flag in flags {
+            assert!(documented(flag), "`{flag}` is not in the help");
+        }
+    }
+
+    #[test]
+    fn documented_flags_are_accepted() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("main.c");
+        std::fs::write(&file, "").unwrap();
+        let file = file.to_str().unwrap();
+        let dir_path = dir.path().to_str().unwrap();
+        let new_dir = dir.path().join("new");
+        let new_dir = new_dir.to_str().unwrap();
+
+        for flag in FLAGS {
+            let value = match flag.value {
+                "" => None,
+                // optional value after `=`
+                v if v.starts_with('[') => None,
+                "<count>" => Some("2"),
+                "<duration>" => Some("5s"),
+                "<github|sarif>" => Some("github"),
+                "<mit|apache-2.0>" => Some("mit"),
+                "<stds>" => Some("c11,c17"),
+                "<dir>" => Some(dir_path),
+                _ => Some("x"),
+            };
+            for action in flag.actions {
+                let mut args = vec![*action];
+                match *action {
+                    "new" => args.push(new_dir),
+                    "script" | "rdeps" | "print-flags" | "preprocess" => {
+                        args.push(file)
+                    }
+                    "generate" => args.push("vscode"),
+                    "size" => args.push("history"),
+                    "config" => args.push("show"),
+                    _ => {}
+                }
+                for name in flag.names {
+                    let mut args = args.clone();
+                    args.push(name);
+                    args.extend(value);
+                    let res = Args::parse(args.iter().copied());
+                    assert!(res.is_ok(), "{args:?} is not accepted");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn actions_with_help() {
+        for action in ACTIONS {
+            for name in action.names {
+                let args = Args::parse([*name, "--help"].into_iter()).unwrap();
+                assert!(
+                    matches!(args.action, Action::Help(_)),
+                    "`{name} --help` doesn't show help"
+                );
+            }
         }
     }
 }
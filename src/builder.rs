@@ -1,18 +1,34 @@
 use std::{
-    collections::HashSet,
-    fs, mem,
-    path::PathBuf,
-    process::{Child, Command},
-    thread,
-    time::Duration,
+    collections::{HashMap, HashSet},
+    env,
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    iter, mem,
+    path::{Component, Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
+use log::{debug, info};
+use serde::{Serialize, Serializer};
+use termal::printcln;
+
 use crate::{
     compiler::Compiler,
     config::Config,
-    dependency::{DepCache, DepFile, Dependency},
-    err::{Error, Result},
+    dep_rules::DepRules,
+    dependency::{DepCache, DepFile, Dependency, Pairing},
+    depfile,
+    diagnostics::DiagFilter,
+    err::{Error, Result, ResultExt},
     file_type::{FileState, FileType, Language},
+    metadata,
+    object_cache::{CacheKey, ObjectCache},
+    paths,
+    shell::command_to_string,
 };
 
 pub struct Builder {
@@ -20,19 +36,163 @@ pub struct Builder {
     thread_count: usize,
     compiler: Compiler,
     print_command: bool,
+    /// Max time that a single command may run
+    job_timeout: Option<Duration>,
+    /// Continue building other files when a command fails
+    keep_going: bool,
+    /// Stop the build after this number of failed commands
+    max_errors: Option<usize>,
+    /// Number of times a command that crashed is retried
+    retries: usize,
+    /// Number of failed commands
+    error_count: usize,
+    /// Files that failed to build
+    failed: HashSet<DepFile>,
+    /// Source files that failed to compile
+    failed_sources: HashSet<DepFile>,
+    /// The file of the first failed command
+    first_failed: Option<PathBuf>,
+    /// Function that is called when a command starts or finishes
+    event_handler: Option<EventHandler>,
+    /// Captured lines of stderr of the commands
+    captured: Option<Captured>,
+    /// Patterns of files whose diagnostics are not shown
+    suppress_diagnostics: Arc<[String]>,
+    /// Source files that are always rebuilt
+    always_dirty: HashSet<PathBuf>,
+    /// Additional files that cause relink of executables when changed
+    link_deps: Vec<DepFile>,
+    /// Additional files that cause rebuild of all objects when changed
+    obj_deps: Vec<DepFile>,
+    /// Prebuilt objects linked to the executables after the project objects
+    extra_objects: Vec<PathBuf>,
+    /// Include directories from the configuration (`-I` and `-isystem`)
+    include_dirs: Vec<PathBuf>,
+    /// Map file written by the linker
+    map_file: Option<PathBuf>,
+    /// Arguments of all the objects that are saved to [`paths::OBJ_ARGS`]
+    /// before the build
+    obj_args: (PathBuf, String),
+    /// Arguments of the executables that are saved to [`paths::LINK_ARGS`]
+    /// before the build
+    link_args: (PathBuf, String),
+    /// [`Self::obj_args`] are different than the saved arguments
+    obj_args_changed: bool,
+    /// [`Self::link_args`] are different than the saved arguments
+    link_args_changed: bool,
+    /// Rules for detecting changes of dependencies
+    dep_rules: DepRules,
+    /// Write makefile dependency files for the objects
+    emit_depfiles: bool,
+    /// Objects of the build whose dependency files are written after the
+    /// build, also when they are up to date
+    depfile_objects: Vec<Dependency>,
+    /// Cache of the compiled objects shared by the projects
+    object_cache: Option<ObjectCache>,
+    /// Included files that didn't exist when the objects were compiled
+    missing: HashMap<PathBuf, HashSet<PathBuf>>,
+    /// File where [`Self::missing`] is stored
+    missing_file: PathBuf,
+    /// Dependencies that vanished since they were scanned
+    vanished: HashSet<PathBuf>,
+    /// All the files produced by the build, including the up to date files
+    outputs: Vec<PathBuf>,
     built: HashSet<DepFile>,
-    dep_queue: Vec<Dependency>,
+    /// Files that already have command in the plan that is being created
+    planned: HashSet<DepFile>,
+    /// Dirty files that are waiting to be planned with the reason why they
+    /// must be rebuilt
+    dep_queue: Vec<(Dependency, String)>,
     command_queue: Vec<QCommand>,
     cache: DepCache,
-    pool: Vec<(Child, QCommand)>,
+}
+
+type EventHandler = Box<dyn FnMut(BuildEvent)>;
+type Captured = Arc<Mutex<Vec<String>>>;
+
+/// Event that happens during the build.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BuildEvent<'a> {
+    /// Command that builds the files has started.
+    Started { files: Vec<&'a Path> },
+    /// The files were successfully built.
+    Finished { files: Vec<&'a Path> },
+    /// Command that builds the files has failed.
+    Failed {
+        files: Vec<&'a Path>,
+        code: Option<i32>,
+        /// The command was killed because it exceeded the job timeout
+        timed_out: bool,
+    },
+    /// The binary was successfully built.
+    Binary { path: &'a Path },
+    /// All the files produced by the build.
+    Outputs { files: Vec<&'a Path> },
+}
+
+/// Commands that must run to build the targets. Each command comes after
+/// the commands that provide the files it requires.
+#[derive(Debug, Serialize)]
+pub struct BuildPlan {
+    pub commands: Vec<PlannedCommand>,
+}
+
+/// Command of the [`BuildPlan`].
+#[derive(Debug, Serialize)]
+pub struct PlannedCommand {
+    #[serde(serialize_with = "ser_lossy")]
+    pub program: OsString,
+    #[serde(serialize_with = "ser_lossy_all")]
+    pub args: Vec<OsString>,
+    /// Files that must be built before the command runs
+    #[serde(serialize_with = "ser_paths")]
+    pub requires: Vec<DepFile>,
+    /// Files that are produced by the command
+    #[serde(serialize_with = "ser_paths")]
+    pub provides: Vec<DepFile>,
+    /// Why the command must run (e.g. `src/main.h is newer`)
+    pub reason: String,
+    /// Source files that are compiled by the command
+    #[serde(skip)]
+    sources: Vec<DepFile>,
+    /// Dependency of the object compiled by the command
+    #[serde(skip)]
+    object: Option<Dependency>,
+}
+
+/// Result of successful [`Builder::execute`].
+#[derive(Debug)]
+pub struct BuildReport {
+    /// Files that were built, in the order of the plan
+    pub built: Vec<PathBuf>,
 }
 
 struct QCommand {
     command: Command,
     requires: Vec<DepFile>,
     provides: Vec<DepFile>,
+    /// Source files that are compiled by the command
+    sources: Vec<DepFile>,
+    /// Dependency of the object compiled by the command
+    object: Option<Dependency>,
+    /// Key of the object in the object cache, set if the object should be
+    /// stored to the cache when it is built
+    cache_key: Option<CacheKey>,
+    /// Thread that forwards the stderr of the running command
+    forwarder: Option<JoinHandle<()>>,
+    /// Time when the command was started
+    started: Option<Instant>,
+    /// Number of times the command was retried
+    retried: usize,
+    /// The command was killed because it exceeded the job timeout
+    timed_out: bool,
 }
 
+/// Base delay before retrying crashed command, multiplied by the number of
+/// the attempt.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
 //===========================================================================//
 //                                   Public                                  //
 //===========================================================================//
@@ -45,35 +205,326 @@ impl Builder {
             &conf.debug_build
         };
 
+        let mut compiler = Compiler::new(
+            build.cc.clone(),
+            build.cpp.clone(),
+            &build.compiler_conf,
+        );
+
+        // the metadata changes with every build, so the files that use it
+        // are always rebuilt
+        let mut always_dirty = HashSet::new();
+        if !build.metadata.files.is_empty() {
+            let profile = if release { "release" } else { "debug" };
+            let args = metadata::defines(&build.metadata, profile);
+            for f in &build.metadata.files {
+                let f: PathBuf = f
+                    .components()
+                    .filter(|c| c != &Component::CurDir)
+                    .collect();
+                compiler.add_file_args(f.clone(), args.iter().cloned());
+                always_dirty.insert(f);
+            }
+        }
+
+        // the name and version of the project are defined for all the
+        // objects
+        let bin_root = &build.compiler_conf.bin_root;
+        let obj_args: String = conf
+            .project
+            .defines()
+            .iter()
+            .map(|(n, v)| format!("-D{n}={}\n", v.as_deref().unwrap_or("")))
+            .collect();
+        let obj_args = (bin_root.join(paths::OBJ_ARGS), obj_args);
+        let link_args = (bin_root.join(paths::LINK_ARGS), String::new());
+
+        let dep_rules = DepRules::new(
+            &conf.project.dep_rules,
+            build.compiler_conf.bin_root.join(paths::HASHES),
+        )?;
+        let missing_file = build.compiler_conf.bin_root.join(paths::MISSING);
+        let cc = &build.compiler_conf;
+        let include_dirs: Vec<_> = cc
+            .include_dirs
+            .iter()
+            .chain(&cc.system_include_dirs)
+            .cloned()
+            .collect();
+        let object_cache = if build.object_cache {
+            // the embedded headers are the only headers in the bin directory
+            let cache_dirs = include_dirs
+                .iter()
+                .map(|d| {
+                    if *d == cc.bin_root {
+                        d.join(paths::EMBED_DIR)
+                    } else {
+                        d.clone()
+                    }
+                })
+                .collect();
+            Some(ObjectCache::new(paths::object_cache_dir()?, cache_dirs))
+        } else {
+            None
+        };
+        let missing = load_missing(&missing_file)?;
+
         Ok(Self {
             thread_count: std::thread::available_parallelism()
                 .map_or(1, |t| t.get().checked_sub(2).unwrap_or(1)),
-            compiler: Compiler::new(
-                build.cc.clone(),
-                build.cpp.clone(),
-                &build.compiler_conf,
-            )?,
+            compiler,
             print_command: true,
+            job_timeout: None,
+            keep_going: false,
+            max_errors: None,
+            retries: 0,
+            error_count: 0,
+            failed: HashSet::new(),
+            failed_sources: HashSet::new(),
+            first_failed: None,
+            event_handler: None,
+            captured: None,
+            suppress_diagnostics: conf
+                .project
+                .suppress_diagnostics_from
+                .clone()
+                .into(),
+            always_dirty,
+            link_deps: iter::once(&link_args.0)
+                .chain(&build.compiler_conf.whole_archive)
+                .map(|f| f.clone().into())
+                .collect(),
+            obj_deps: vec![obj_args.0.clone().into()],
+            extra_objects: build.extra_objects.clone(),
+            include_dirs,
+            map_file: build.compiler_conf.map_file.clone(),
+            obj_args,
+            link_args,
+            obj_args_changed: false,
+            link_args_changed: false,
+            dep_rules,
+            emit_depfiles: build.emit_depfiles,
+            depfile_objects: vec![],
+            object_cache,
+            missing,
+            missing_file,
+            vanished: HashSet::new(),
+            outputs: vec![],
             built: HashSet::new(),
+            planned: HashSet::new(),
             dep_queue: vec![],
             command_queue: vec![],
             cache: DepCache::new(),
-            pool: vec![],
         })
     }
 
-    pub fn build_all<P1, P2, I>(
+    /// Sets the max number of commands running at the same time.
+    pub fn set_jobs(&mut self, jobs: usize) {
+        self.thread_count = jobs;
+    }
+
+    /// Sets the max time that a single command may run. Commands that run
+    /// longer are killed.
+    pub fn set_job_timeout(&mut self, timeout: Option<Duration>) {
+        self.job_timeout = timeout;
+    }
+
+    /// Sets whether to continue building other files after a command fails.
+    pub fn set_keep_going(&mut self, keep_going: bool) {
+        self.keep_going = keep_going;
+    }
+
+    /// Sets the number of failed commands after which no more commands are
+    /// started.
+    pub fn set_max_errors(&mut self, max_errors: Option<usize>) {
+        self.max_errors = max_errors;
+    }
+
+    /// Sets how many times a command is retried when the compiler crashes
+    /// (is killed by signal or reports internal compiler error). Commands
+    /// that fail because of errors in the code are never retried.
+    pub fn set_retries(&mut self, retries: usize) {
+        self.retries = retries;
+    }
+
+    /// Disables printing of the commands.
+    pub fn set_quiet(&mut self) {
+        self.print_command = false;
+    }
+
+    /// Sets function that is called for each build event. When set, the
+    /// commands are not printed.
+    pub fn set_event_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(BuildEvent) + 'static,
+    {
+        self.print_command = false;
+        self.event_handler = Some(Box::new(handler));
+    }
+
+    /// Saves the list of source files that failed to compile to the given
+    /// file. If there are no such files, the file is removed.
+    pub fn save_failed<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if self.failed_sources.is_empty() {
+            return match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    Err(Error::io_at("remove", path, e))
+                }
+                _ => Ok(()),
+            };
+        }
+
+        let mut list = String::new();
+        for f in &self.failed_sources {
+            list.push_str(&f.to_string_lossy());
+            list.push('\n');
+        }
+        fs::write(path, list).with_path("write", path)?;
+        Ok(())
+    }
+
+    /// Adds file that causes relink of executables when it changes (e.g. the
+    /// configuration file with the linker flags).
+    pub fn add_link_dependency<P>(&mut self, file: P)
+    where
+        P: Into<PathBuf>,
+    {
+        self.link_deps.push(file.into().into());
+    }
+
+    /// Adds arguments to all the compiled objects and to all the linked
+    /// executables (e.g. `--cflag` and `--ldflag`). Changes of the arguments
+    /// cause rebuild.
+    pub fn add_args(&mut self, compile: &[String], link: &[String]) {
+        self.compiler.add_args(compile, link);
+        for a in compile {
+            self.obj_args.1.push_str(a);
+            self.obj_args.1.push('\n');
+        }
+        for a in link {
+            self.link_args.1.push_str(a);
+            self.link_args.1.push('\n');
+        }
+    }
+
+    /// Enables capturing of stderr of the commands. The stderr is still
+    /// printed.
+    pub fn capture_output(&mut self) {
+        self.captured = Some(Default::default());
+    }
+
+    /// Gets the captured lines of stderr of the commands.
+    pub fn captured_output(&self) -> Vec<String> {
+        self.captured
+            .as_ref()
+            .and_then(|c| c.lock().ok().map(|c| c.clone()))
+            .unwrap_or_default()
+    }
+
+    /// Gets all the files produced by the build (the binary, objects and
+    /// dependency files), including the files that were already up to
+    /// date.
+    pub fn outputs(&self) -> &[PathBuf] {
+        &self.outputs
+    }
+
+    /// Gets the sources from `srcs` that are reachable from the entry (see
+    /// [`DepCache::reachable_sources`]).
+    pub fn reachable_sources(
+        &mut self,
+        entry: &Path,
+        srcs: &[PathBuf],
+        pairing: Pairing,
+    ) -> Result<Vec<PathBuf>> {
+        self.cache.reachable_sources(entry, srcs, pairing)
+    }
+
+    /// Finds the sources from `srcs` that include the file, directly or
+    /// indirectly. Returns the sources with their objects and whether the
+    /// objects would be rebuilt.
+    pub fn reverse_deps(
+        &mut self,
+        file: &Path,
+        srcs: &[PathBuf],
+    ) -> Result<Vec<(DepFile, DepFile, bool)>> {
+        for s in srcs {
+            self.cache.get_dependencies(s.clone().into())?;
+        }
+
+        let file = file.canonicalize().with_path("find", file)?;
+        let mut rdeps: Vec<_> = self
+            .cache
+            .reverse_deps(&file)
+            .into_iter()
+            .filter(|d| srcs.iter().any(|s| s == d.path.as_ref()))
+            .collect();
+        rdeps.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut res = vec![];
+        for src in rdeps {
+            let obj = self.obj_dependency(src.clone())?;
+            let dirty = self.check_dirty(&obj)?.is_some();
+            res.push((src, obj.file, dirty));
+        }
+        Ok(res)
+    }
+
+    /// Gets all the files that were used by the build: the scanned files,
+    /// included files that don't exist, link dependencies and the outputs.
+    pub fn used_files(&self) -> Vec<&Path> {
+        let mut res: Vec<&Path> = vec![];
+        for d in self.cache.iter() {
+            res.push(&d.file);
+            res.extend(d.indirect.iter().map(|f| f.as_ref()));
+            res.extend(d.missing.iter().map(|f| f.as_path()));
+        }
+        res.extend(self.link_deps.iter().map(|f| f.as_ref()));
+        res.extend(self.obj_deps.iter().map(|f| f.as_ref()));
+        res.extend(self.outputs.iter().map(|f| f.as_path()));
+        res
+    }
+
+    /// Gets the command that would compile the source to its object,
+    /// regardless of whether the object is up to date.
+    pub fn compile_command(&mut self, src: &Path) -> Result<Command> {
+        let obj = self.obj_dependency(src.to_path_buf().into())?;
+        Ok(self.compiler.build(obj)?.0)
+    }
+
+    /// Gets the command that runs only the preprocessor on the source with
+    /// the same arguments as when it is compiled. The output is written to
+    /// `out`, or to the standard output if it is not set.
+    pub fn preprocess_command(
+        &mut self,
+        src: &Path,
+        out: Option<&Path>,
+    ) -> Result<Command> {
+        let obj = self.obj_dependency(src.to_path_buf().into())?;
+        self.compiler.preprocess(obj, out)
+    }
+
+    pub fn compiler(&self) -> &Compiler {
+        &self.compiler
+    }
+
+    /// Plans the commands that build the executable from the sources. Only
+    /// the files that are not up to date are built by the plan.
+    pub fn plan<P1, P2, I>(
         &mut self,
         target: P1,
         sources: I,
-    ) -> Result<()>
+    ) -> Result<BuildPlan>
     where
         P1: Into<PathBuf>,
         P2: Into<PathBuf>,
         I: IntoIterator<Item = P2>,
     {
         let mut lang = Language::C;
-        let direct = sources
+        let mut direct: Vec<_> = sources
             .into_iter()
             .map(|s| {
                 let res: DepFile = s.into().into();
@@ -90,6 +541,21 @@ impl Builder {
             })
             .collect();
 
+        // the prebuilt objects are linked as they are, in the configured
+        // order after the objects of the project
+        for o in &self.extra_objects {
+            if !o.is_file() {
+                return Err(Error::MissingExtraObject(o.clone()));
+            }
+            direct.push(DepFile {
+                path: o.clone().into(),
+                typ: Some(FileType {
+                    lang: Language::C,
+                    state: FileState::Object,
+                }),
+            });
+        }
+
         let file = DepFile {
             path: target.into().into(),
             typ: Some(FileType {
@@ -100,35 +566,141 @@ impl Builder {
 
         let mut file = Dependency::new(file, direct, Default::default());
 
+        for src in &file.direct {
+            if src.typ.is_some_and(|t| t.state == FileState::Object) {
+                continue;
+            }
+            if let Ok(mut obj) = self.compiler.obj_dependency(src.clone()) {
+                self.add_output(&obj.file);
+                if self.emit_depfiles {
+                    self.cache.fill_dependency(&mut obj)?;
+                    self.depfile_objects.push(obj);
+                }
+            }
+        }
+        self.add_output(&file.file);
+        if let Some(map) = self.map_file.clone() {
+            self.push_output(map);
+        }
+
         self.cache.fill_dependency(&mut file)?;
         self.queue_target(file)?;
-        self.build()
+        self.take_plan()
     }
 
-    pub fn queue_target(&mut self, target: Dependency) -> Result<()> {
-        if !target.is_up_to_date()? {
-            self.dep_queue.push(target);
+    /// Plans the commands that build only the object files of the given
+    /// sources.
+    pub fn plan_objects<P, I>(&mut self, sources: I) -> Result<BuildPlan>
+    where
+        P: Into<PathBuf>,
+        I: IntoIterator<Item = P>,
+    {
+        for s in sources {
+            let file = self.obj_dependency(s.into().into())?;
+            self.add_output(&file.file);
+            if self.emit_depfiles {
+                self.depfile_objects.push(file.clone());
+            }
+            self.queue_target(file)?;
         }
-        Ok(())
+        self.take_plan()
+    }
+
+    /// Builds the executable from the sources.
+    pub fn build_all<P1, P2, I>(
+        &mut self,
+        target: P1,
+        sources: I,
+    ) -> Result<BuildReport>
+    where
+        P1: Into<PathBuf>,
+        P2: Into<PathBuf>,
+        I: IntoIterator<Item = P2>,
+    {
+        let plan = self.plan(target, sources)?;
+        self.execute(plan)
+    }
+
+    /// Builds only the object files of the given sources.
+    pub fn build_objects<P, I>(&mut self, sources: I) -> Result<BuildReport>
+    where
+        P: Into<PathBuf>,
+        I: IntoIterator<Item = P>,
+    {
+        let plan = self.plan_objects(sources)?;
+        self.execute(plan)
     }
 
-    pub fn build(&mut self) -> Result<()> {
+    /// Runs the commands of the plan. Each command starts when all the
+    /// commands that it requires have finished. Commands that require files
+    /// that failed to build never run.
+    pub fn execute(&mut self, plan: BuildPlan) -> Result<BuildReport> {
+        info!("Running the build with {} threads", self.thread_count);
+        // the linker doesn't create the directory of the map file
+        if let Some(dir) = self.map_file.as_ref().and_then(|m| m.parent()) {
+            fs::create_dir_all(dir).with_path("create directory", dir)?;
+        }
         let mut child_pool: Vec<(Child, QCommand)> = vec![];
 
+        // files that are not rebuilt now will be older than the changed
+        // arguments
+        save_args(&self.obj_args.0, &self.obj_args.1)?;
+        save_args(&self.link_args.0, &self.link_args.1)?;
+        self.obj_args_changed = false;
+        self.link_args_changed = false;
+
+        let built = plan
+            .commands
+            .iter()
+            .flat_map(|c| c.provides.iter().map(|p| p.to_path_buf()))
+            .collect();
+        self.command_queue =
+            plan.commands.into_iter().map(QCommand::from).collect();
+
+        // the recorded hashes are valid only if the whole build succeeds
+        if !self.command_queue.is_empty() {
+            self.dep_rules.invalidate()?;
+        }
+
         // don't return until all processes have exited
 
-        let res = if let Err(e) = self.build_with_pool(&mut child_pool) {
+        let res = self.build_with_pool(&mut child_pool);
+        self.save_missing()?;
+        let res = if let Err(e) = res {
             e
         } else {
-            return Ok(());
+            self.dep_rules.save()?;
+            if let Some(Err(e)) = self.object_cache.as_ref().map(|c| c.evict())
+            {
+                info!("Failed to evict objects from the object cache: {e}");
+            }
+            for obj in mem::take(&mut self.depfile_objects) {
+                depfile::write(&obj)?;
+            }
+            return Ok(BuildReport { built });
         };
 
-        // wait for all proceses to exit
-        for (mut c, _) in child_pool {
-            if c.wait().is_err() {
+        // wait for all proceses to exit, jobs that exceed the timeout are
+        // killed
+        for mut run in child_pool {
+            let status = self.wait_job(&mut run);
+            if status.is_err() {
                 // if kill fails, there is nothing we can do to exit the
                 // process
-                _ = c.kill();
+                _ = run.0.kill();
+            }
+            run.1.join_forwarder();
+            // every started job must end with event
+            let files = paths(&run.1.provides);
+            match status {
+                Ok(s) if s.success() => {
+                    self.emit(BuildEvent::Finished { files })
+                }
+                s => self.emit(BuildEvent::Failed {
+                    files,
+                    code: s.ok().and_then(|s| s.code()),
+                    timed_out: run.1.timed_out,
+                }),
             }
         }
 
@@ -137,17 +709,65 @@ impl Builder {
 }
 
 impl Builder {
+    /// Queues the target for planning if it is not up to date.
+    fn queue_target(&mut self, mut target: Dependency) -> Result<()> {
+        // the arguments are saved only when the plan is executed
+        self.obj_args_changed = args_changed(&self.obj_args);
+        self.link_args_changed = args_changed(&self.link_args);
+
+        for v in self.cache.drop_vanished() {
+            info!("{:?} vanished since it was scanned", v.path);
+            self.vanished.insert(v.to_path_buf());
+        }
+        let gone: Vec<_> = target
+            .indirect
+            .iter()
+            .filter(|d| self.vanished.contains(d.path.as_ref()))
+            .cloned()
+            .collect();
+        for g in gone {
+            target.indirect.remove(&g);
+            target.missing.insert(g.to_path_buf());
+        }
+
+        if let Some(reason) = self.dirty_reason(&target)? {
+            debug!("Queueing target {:?}", target.file.path);
+            self.dep_queue.push((target, reason));
+        }
+        Ok(())
+    }
+
+    /// Creates the plan from the queued targets and the files that they
+    /// require.
+    fn take_plan(&mut self) -> Result<BuildPlan> {
+        self.planned.clear();
+        let mut commands = vec![];
+        while let Some(c) = self.plan_command()? {
+            commands.push(c);
+        }
+        Ok(BuildPlan {
+            commands: order_commands(commands),
+        })
+    }
+
     fn build_with_pool(
         &mut self,
         pool: &mut Vec<(Child, QCommand)>,
     ) -> Result<()> {
         loop {
             match self.select_command() {
-                Ok(Some(cmd)) => {
+                Ok(Some(mut cmd)) => {
+                    if self.restore_cached(&mut cmd)? {
+                        self.job_succeeded(cmd);
+                        continue;
+                    }
                     self.wait_and_run_command(pool, cmd)?;
                 }
                 Ok(None) => break,
                 Err(Error::DependencyCycle) => {
+                    debug!(
+                        "No command is ready, waiting for running commands"
+                    );
                     if !self.wait_for_any(pool)? {
                         return Err(Error::DependencyCycle);
                     }
@@ -156,82 +776,318 @@ impl Builder {
             }
         }
 
-        self.wait_for_all(pool)
+        self.wait_for_all(pool)?;
+
+        match &self.first_failed {
+            Some(first) if self.error_count != 0 => Err(Error::BuildFailed {
+                count: self.error_count,
+                first: first.clone(),
+            }),
+            _ => Ok(()),
+        }
     }
 
+    /// Takes the first queued command whose required files were all built.
+    /// Returns [`Error::DependencyCycle`] if no command is ready.
     fn select_command(&mut self) -> Result<Option<QCommand>> {
-        let mut idx = None;
+        // commands that depend on failed files will never run
+        let failed = &self.failed;
+        self.command_queue.retain(|c| {
+            let skip = c.requires.iter().any(|r| failed.contains(r));
+            if skip {
+                debug!(
+                    "Skipping {:?}, its dependencies failed",
+                    paths(&c.provides)
+                );
+            }
+            !skip
+        });
+
+        let built = &self.built;
+        let idx = self.command_queue.iter_mut().position(|c| {
+            c.requires.retain(|r| !built.contains(r));
+            c.requires.is_empty()
+        });
 
-        for (i, c) in self.command_queue.iter_mut().enumerate().rev() {
-            c.requires.retain(|i| !self.built.contains(i));
-            if c.requires.is_empty() {
-                idx = Some(i);
-                break;
+        match idx {
+            Some(i) => {
+                let cmd = self.command_queue.remove(i);
+                debug!(
+                    "Popped {:?} from the command queue",
+                    paths(&cmd.provides)
+                );
+                Ok(Some(cmd))
             }
+            None if self.command_queue.is_empty() => Ok(None),
+            None => Err(Error::DependencyCycle),
         }
+    }
 
-        if let Some(i) = idx {
-            return Ok(Some(self.command_queue.remove(i)));
-        }
+    /// Plans the command for the next queued file. The files required by the
+    /// command that are not up to date are queued.
+    fn plan_command(&mut self) -> Result<Option<PlannedCommand>> {
+        // the same file may be queued more times (e.g. source given twice),
+        // it must be built only once
+        let (file, reason) = loop {
+            match self.dep_queue.pop() {
+                Some((f, _)) if self.planned.contains(&f.file) => {
+                    debug!("{:?} is already planned", f.file.path);
+                }
+                Some(d) => break d,
+                None => return Ok(None),
+            }
+        };
+        self.planned.insert(file.file.clone());
 
-        let mut cmd = None;
+        let resolved = file.file.clone();
+        let sources = file
+            .direct
+            .iter()
+            .filter(|d| {
+                matches!(
+                    d.typ,
+                    Some(FileType {
+                        state: FileState::Source,
+                        ..
+                    })
+                )
+            })
+            .cloned()
+            .collect();
+        let is_obj = matches!(
+            file.file.typ,
+            Some(FileType {
+                state: FileState::Object,
+                ..
+            })
+        );
+        let object = is_obj.then(|| file.clone());
+        let (command, deps) = self.compiler.build(file)?;
 
-        while let Some(c) = self.fetch_command()? {
-            if c.requires.is_empty() {
-                cmd = Some(c);
-                break;
+        // the objects of the link command depend only on their sources, the
+        // included headers must be added so that their changes are detected
+        let mut dirty = vec![];
+        for mut d in deps {
+            if self.built.contains(&d.file) {
+                continue;
+            }
+            self.cache.fill_dependency(&mut d)?;
+            if let Some(r) = self.dirty_reason(&d)? {
+                dirty.push((d, r));
             }
-            self.command_queue.push(c);
         }
 
-        if let Some(cmd) = cmd {
-            return Ok(Some(cmd));
+        let res = PlannedCommand {
+            program: command.get_program().to_owned(),
+            args: command.get_args().map(|a| a.to_owned()).collect(),
+            requires: dirty.iter().map(|(d, _)| d.file.clone()).collect(),
+            provides: vec![resolved],
+            reason,
+            sources,
+            object,
+        };
+
+        self.dep_queue.extend(dirty.into_iter().rev());
+
+        Ok(Some(res))
+    }
+
+    /// Creates dependency for the object of the source with all the files
+    /// included by the source (also transitively), so that change of any of
+    /// them rebuilds the object.
+    fn obj_dependency(&mut self, src: DepFile) -> Result<Dependency> {
+        let mut res = self.compiler.obj_dependency(src)?;
+        self.cache.fill_dependency(&mut res)?;
+        Ok(res)
+    }
+
+    /// Gets the reason why the dependency must be rebuilt, [`None`] if it is
+    /// up to date. Records the missing includes of objects for the next
+    /// build. Fails if the output exists but it is not a file (e.g. a
+    /// leftover directory), because its modification time is meaningless and
+    /// the command couldn't overwrite it.
+    fn dirty_reason(&mut self, dep: &Dependency) -> Result<Option<String>> {
+        let path = dep.file.path.as_ref();
+        if fs::metadata(path).is_ok_and(|m| !m.is_file()) {
+            return Err(Error::OutputNotFile(path.to_owned()));
+        }
+
+        let res = self.check_dirty(dep)?;
+        match &res {
+            Some(r) => debug!("{:?} is dirty: {r}", dep.file.path),
+            None => debug!("{:?} is up to date", dep.file.path),
         }
 
-        if self.command_queue.is_empty() {
-            Ok(None)
-        } else {
-            Err(Error::DependencyCycle)
+        let state = dep.file.typ.map(|t| t.state);
+        if state == Some(FileState::Object) {
+            self.missing
+                .insert(dep.file.to_path_buf(), dep.missing.clone());
+        } else if state == Some(FileState::Executable) {
+            // the objects are checked only if the executable is not up to
+            // date, so record them here if they don't have record yet
+            for src in &dep.direct {
+                let Some(src_dep) = self.cache.get(src) else {
+                    continue;
+                };
+                let obj = self.compiler.obj_dependency(src.clone())?.file;
+                self.missing
+                    .entry(obj.to_path_buf())
+                    .or_insert_with(|| src_dep.missing.clone());
+            }
         }
+
+        Ok(res)
     }
 
-    fn fetch_command(&mut self) -> Result<Option<QCommand>> {
-        let file = if let Some(file) = self.dep_queue.pop() {
-            file
-        } else {
-            return Ok(None);
-        };
+    /// Gets the reason why the dependency must be rebuilt, [`None`] if it is
+    /// up to date. Dependencies of source files that are always rebuilt are
+    /// never up to date. Executables must also be newer than their object
+    /// files and the configuration file.
+    fn check_dirty(&self, dep: &Dependency) -> Result<Option<String>> {
+        if let Some(f) = dep
+            .direct
+            .iter()
+            .find(|f| self.always_dirty.contains(f.path.as_ref()))
+        {
+            return Ok(Some(format!(
+                "{} is always rebuilt",
+                f.to_string_lossy()
+            )));
+        }
 
-        let resolved = file.file.clone();
-        let (command, mut deps) = self.compiler.build(file)?;
-        deps.retain(|d| {
-            !self.built.contains(&d.file)
-                && !self.pool.iter().any(|p| p.1.provides.contains(&d.file))
-        });
+        // the compiler decides whether the vanished file is still included
+        if let Some(v) =
+            dep.missing.iter().find(|m| self.vanished.contains(*m))
+        {
+            return Ok(Some(format!("{} vanished", v.to_string_lossy())));
+        }
 
-        let mut i = 0;
-        while i < deps.len() {
-            self.cache.fill_dependency(&mut deps[i])?;
-            if deps[i].is_up_to_date()? {
-                deps.remove(i);
+        // the included file may have been created with old modification time
+        if let Some(m) = self.created_include(&dep.file) {
+            return Ok(Some(format!(
+                "missing include {} was created",
+                m.to_string_lossy()
+            )));
+        }
+
+        if let Some(r) = dep.dirty_reason(&self.dep_rules)? {
+            return Ok(Some(r));
+        }
+
+        match dep.file.typ.map(|t| t.state) {
+            Some(FileState::Executable) => {}
+            Some(FileState::Object) if self.obj_args_changed => {
+                return Ok(Some("the arguments changed".to_owned()));
+            }
+            Some(FileState::Object) => {
+                return Dependency::new(
+                    dep.file.clone(),
+                    self.obj_deps.clone(),
+                    Default::default(),
+                )
+                .dirty_reason(&self.dep_rules);
+            }
+            _ => return Ok(None),
+        }
+
+        if self.link_args_changed {
+            return Ok(Some("the link arguments changed".to_owned()));
+        }
+
+        // the objects may have been rebuilt (or removed) without changing
+        // the sources
+        let mut link_deps = self.link_deps.clone();
+        for src in &dep.direct {
+            let is_src = matches!(
+                src.typ,
+                Some(FileType {
+                    state: FileState::Source,
+                    ..
+                })
+            );
+            if !is_src {
                 continue;
             }
-            i += 1;
+            let obj = self.compiler.obj_dependency(src.clone())?.file;
+            if !obj.exists() {
+                return Ok(Some(format!(
+                    "object {} doesn't exist",
+                    obj.to_string_lossy()
+                )));
+            }
+            if let Some(m) = self.created_include(&obj) {
+                return Ok(Some(format!(
+                    "missing include {} of {} was created",
+                    m.to_string_lossy(),
+                    obj.to_string_lossy()
+                )));
+            }
+            link_deps.push(obj);
         }
 
-        let res = QCommand {
-            command,
-            requires: deps.iter().map(|d| d.file.clone()).collect(),
-            provides: vec![resolved],
-        };
+        Dependency::new(dep.file.clone(), link_deps, Default::default())
+            .dirty_reason(&self.dep_rules)
+    }
+
+    /// Gets the include of the object that didn't exist when the object was
+    /// compiled but exists now.
+    fn created_include(&self, obj: &Path) -> Option<&PathBuf> {
+        self.missing.get(obj)?.iter().find(|m| m.exists())
+    }
+
+    /// Saves the included files that didn't exist when the objects were
+    /// compiled.
+    fn save_missing(&self) -> Result<()> {
+        let mut res = String::new();
+        for (obj, missing) in &self.missing {
+            for m in missing {
+                res.push_str(&format!(
+                    "{}\t{}\n",
+                    obj.to_string_lossy(),
+                    m.to_string_lossy()
+                ));
+            }
+        }
+
+        if res.is_empty() {
+            return match fs::remove_file(&self.missing_file) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    Err(Error::io_at("remove", &self.missing_file, e))
+                }
+                _ => Ok(()),
+            };
+        }
+        if let Some(p) = self.missing_file.parent() {
+            fs::create_dir_all(p).with_path("create directory", p)?;
+        }
+        fs::write(&self.missing_file, res)
+            .with_path("write", &self.missing_file)?;
+        Ok(())
+    }
 
-        for d in deps.iter_mut() {
-            self.cache.fill_dependency(d)?;
+    /// Records file produced by the build. The dependency files are also
+    /// recorded if they are emitted.
+    fn add_output(&mut self, file: &DepFile) {
+        self.push_output(file.to_path_buf());
+        let is_obj = file.typ.is_some_and(|t| t.state == FileState::Object);
+        if self.emit_depfiles && is_obj {
+            let mut d = file.to_path_buf();
+            d.as_mut_os_string().push(".d");
+            self.push_output(d);
         }
+    }
 
-        self.dep_queue.extend(deps.into_iter().rev());
+    /// Records file produced by the build if it isn't recorded yet (the
+    /// same files may be planned more times, e.g. with `--retry-failed`).
+    fn push_output(&mut self, file: PathBuf) {
+        if !self.outputs.contains(&file) {
+            self.outputs.push(file);
+        }
+    }
 
-        Ok(Some(res))
+    /// Checks whether more commands may run at the same time.
+    fn parallel(&self) -> bool {
+        self.thread_count > 1
     }
 
     fn wait_and_run_command(
@@ -240,20 +1096,30 @@ impl Builder {
         mut cmd: QCommand,
     ) -> Result<()> {
         if pool.len() < self.thread_count {
-            let child = cmd.run(self.print_command)?;
+            let child = self.start_job(&mut cmd, pool.len())?;
             pool.push((child, cmd));
             return Ok(());
         }
 
         'wait: loop {
-            for run in pool.iter_mut() {
-                if let Some(r) = run.0.try_wait()? {
+            for (i, run) in pool.iter_mut().enumerate() {
+                if let Some(r) = self.try_wait_job(run)? {
                     if !r.success() {
-                        return Err(Error::ProcessFailed(r.code()));
+                        run.1.join_forwarder();
+                        if self.retry_job(run, r, i)? {
+                            continue;
+                        }
+                        if let Err(e) = self.job_failed(&run.1, r.code()) {
+                            // the job has exited, don't wait for it again
+                            pool.swap_remove(i);
+                            return Err(e);
+                        }
                     }
-                    let child = cmd.run(self.print_command)?;
+                    let child = self.start_job(&mut cmd, i)?;
                     let run = mem::replace(run, (child, cmd));
-                    self.built.extend(run.1.provides);
+                    if r.success() {
+                        self.job_succeeded(run.1);
+                    }
                     break 'wait;
                 }
             }
@@ -275,9 +1141,16 @@ impl Builder {
 
         let idx = 'wait: loop {
             for (i, run) in pool.iter_mut().enumerate() {
-                if let Some(r) = run.0.try_wait()? {
+                if let Some(r) = self.try_wait_job(run)? {
                     if !r.success() {
-                        return Err(Error::ProcessFailed(r.code()));
+                        run.1.join_forwarder();
+                        if self.retry_job(run, r, i)? {
+                            continue;
+                        }
+                        let res = self.job_failed(&run.1, r.code());
+                        // the job has exited, don't wait for it again
+                        pool.swap_remove(i);
+                        return res.map(|_| true);
                     }
                     break 'wait i;
                 }
@@ -288,7 +1161,7 @@ impl Builder {
         };
 
         let run = pool.swap_remove(idx);
-        self.built.extend(run.1.provides);
+        self.job_succeeded(run.1);
         Ok(true)
     }
 
@@ -297,31 +1170,591 @@ impl Builder {
         pool: &mut Vec<(Child, QCommand)>,
     ) -> Result<()> {
         while let Some(mut cmd) = pool.pop() {
-            let r = cmd.0.wait()?;
-            if !r.success() {
-                pool.push(cmd);
-                return Err(Error::ProcessFailed(r.code()));
+            let r = match self.wait_job(&mut cmd) {
+                Ok(r) => r,
+                Err(e) => {
+                    pool.push(cmd);
+                    return Err(e);
+                }
+            };
+            if r.success() {
+                self.job_succeeded(cmd.1);
+                continue;
+            }
+
+            cmd.1.join_forwarder();
+            match self.retry_job(&mut cmd, r, pool.len()) {
+                Ok(true) => {
+                    pool.push(cmd);
+                    continue;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    pool.push(cmd);
+                    return Err(e);
+                }
             }
+            // the job has exited, so it isn't returned to the pool
+            self.job_failed(&cmd.1, r.code())?;
         }
 
         Ok(())
     }
+
+    /// Starts the command in the given pool slot.
+    fn start_job(&mut self, cmd: &mut QCommand, slot: usize) -> Result<Child> {
+        debug!("Running {:?} in pool slot {slot}", paths(&cmd.provides));
+        self.write_depfile(cmd)?;
+        self.emit(BuildEvent::Started {
+            files: paths(&cmd.provides),
+        });
+        let filter = (!self.suppress_diagnostics.is_empty())
+            .then(|| DiagFilter::new(self.suppress_diagnostics.clone()));
+        cmd.run(
+            self.print_command,
+            self.parallel(),
+            self.captured.clone(),
+            filter,
+        )
+    }
+
+    /// Writes the makefile dependency file of the compiled object if it is
+    /// enabled.
+    fn write_depfile(&self, cmd: &QCommand) -> Result<()> {
+        match &cmd.object {
+            Some(dep) if self.emit_depfiles => depfile::write(dep),
+            _ => Ok(()),
+        }
+    }
+
+    /// Copies the object from the object cache instead of compiling it. If
+    /// the object is not cached, its key is stored in the command so that
+    /// the object is cached when it is compiled. Returns true if the object
+    /// was restored.
+    fn restore_cached(&mut self, cmd: &mut QCommand) -> Result<bool> {
+        let (Some(cache), Some(obj)) = (&mut self.object_cache, &cmd.object)
+        else {
+            return Ok(false);
+        };
+        // the arguments of the always rebuilt files change with every build
+        if obj
+            .direct
+            .iter()
+            .any(|f| self.always_dirty.contains(f.path.as_ref()))
+        {
+            return Ok(false);
+        }
+
+        // the object cache only speeds up the build, so its errors are not
+        // fatal
+        let inputs = obj.direct.iter().chain(&obj.indirect);
+        let key = match cache.key(&cmd.command, inputs.map(|f| f.as_ref())) {
+            Ok(Some(k)) => k,
+            Ok(None) => return Ok(false),
+            Err(e) => {
+                info!("Not caching {:?}: {e}", obj.file.path);
+                return Ok(false);
+            }
+        };
+        match cache.restore(&key, &obj.file) {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!("{:?} is not cached", obj.file.path);
+                cmd.cache_key = Some(key);
+                return Ok(false);
+            }
+            Err(e) => {
+                info!("Failed to restore {:?} from cache: {e}", obj.file.path);
+                return Ok(false);
+            }
+        }
+
+        debug!("Restored {:?} from the object cache", obj.file.path);
+        if self.print_command {
+            printcln!("{'g bold}     Cached{'_} {}", cmd.short_name());
+        }
+        self.emit(BuildEvent::Started {
+            files: paths(&cmd.provides),
+        });
+        self.write_depfile(cmd)?;
+        Ok(true)
+    }
+
+    /// Records successfully finished command.
+    fn job_succeeded(&mut self, mut cmd: QCommand) {
+        cmd.join_forwarder();
+        if let (Some(cache), Some(key)) =
+            (&mut self.object_cache, &cmd.cache_key)
+        {
+            for p in &cmd.provides {
+                if let Err(e) = cache.store(key, p) {
+                    info!("Failed to cache {:?}: {e}", p.path);
+                }
+            }
+        }
+        debug!("Built {:?}", paths(&cmd.provides));
+        self.emit(BuildEvent::Finished {
+            files: paths(&cmd.provides),
+        });
+        self.built.extend(cmd.provides);
+    }
+
+    fn emit(&mut self, event: BuildEvent) {
+        if let Some(h) = &mut self.event_handler {
+            h(event);
+        }
+    }
+
+    /// Restarts the failed job in the same slot if the failure is transient
+    /// and it may be retried. Returns true if the job was restarted.
+    fn retry_job(
+        &mut self,
+        run: &mut (Child, QCommand),
+        status: ExitStatus,
+        slot: usize,
+    ) -> Result<bool> {
+        // the job would most likely time out again
+        if run.1.retried >= self.retries
+            || run.1.timed_out
+            || !is_transient_failure(status)
+        {
+            return Ok(false);
+        }
+
+        run.1.retried += 1;
+        let msg = format!(
+            "{} crashed ({status}), attempt {} of {}",
+            run.1.short_name(),
+            run.1.retried,
+            self.retries
+        );
+        if self.print_command {
+            printcln!("{'y bold}   Retrying{'_} {}", msg);
+        } else {
+            info!("Retrying {msg}");
+        }
+
+        thread::sleep(RETRY_BACKOFF * run.1.retried as u32);
+        run.0 = self.start_job(&mut run.1, slot)?;
+        Ok(true)
+    }
+
+    /// Records failed command. Returns error if the build should stop.
+    fn job_failed(&mut self, cmd: &QCommand, code: Option<i32>) -> Result<()> {
+        debug!("Failed to build {:?}", paths(&cmd.provides));
+        self.emit(BuildEvent::Failed {
+            files: paths(&cmd.provides),
+            code,
+            timed_out: cmd.timed_out,
+        });
+        self.error_count += 1;
+        self.failed.extend(cmd.provides.iter().cloned());
+        self.failed_sources.extend(cmd.sources.iter().cloned());
+
+        let state = cmd.provides.first().and_then(|p| p.typ).map(|t| t.state);
+        let (action, file) = match state {
+            // for objects, the source file is more useful than the object
+            Some(FileState::Object) => ("compile", cmd.sources.first()),
+            Some(FileState::Executable) => ("link", None),
+            _ => ("build", None),
+        };
+        let file = file
+            .or(cmd.provides.first())
+            .map(|f| f.path.to_path_buf())
+            .unwrap_or_default();
+        let first = self.first_failed.get_or_insert(file.clone()).clone();
+        if let Some(obj) = &cmd.object {
+            self.note_missing_includes(obj);
+        }
+
+        if cmd.timed_out && !self.keep_going {
+            return Err(Error::Timeout {
+                file,
+                duration: self.job_timeout.unwrap_or_default(),
+            });
+        }
+        if !self.keep_going {
+            return Err(Error::JobFailed {
+                action,
+                file,
+                command: command_to_string(&cmd.command),
+                code,
+            });
+        }
+
+        if self.max_errors.is_some_and(|m| self.error_count >= m) {
+            return Err(Error::TooManyErrors {
+                count: self.error_count,
+                first,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Prints the includes of the failed object that were not found, they
+    /// are the likely cause of the failure.
+    fn note_missing_includes(&self, obj: &Dependency) {
+        let cwd = env::current_dir().unwrap_or_default();
+        for (file, loc, inc) in obj.missing_includes() {
+            // the compiler also searches the include directories
+            if self.include_dirs.iter().any(|d| d.join(&inc).exists()) {
+                continue;
+            }
+            let file = file.strip_prefix(&cwd).unwrap_or(&file);
+            let msg = format!(
+                "{}:{loc}: included file {} was not found",
+                file.to_string_lossy(),
+                inc.to_string_lossy()
+            );
+            if self.print_command {
+                printcln!("{'y bold}       Note{'_} {}", msg);
+            } else {
+                info!("{msg}");
+            }
+        }
+    }
+
+    /// Waits for the job to exit. If the job exceeds the timeout, it is
+    /// killed and marked as timed out.
+    fn wait_job(&self, run: &mut (Child, QCommand)) -> Result<ExitStatus> {
+        if self.job_timeout.is_none() {
+            return Ok(run.0.wait()?);
+        }
+
+        loop {
+            if let Some(r) = self.try_wait_job(run)? {
+                return Ok(r);
+            }
+            // Arbitrary sleep time so that the thread isn't using all its
+            // power to just check in cycle that the process exited.
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Checks whether the job has exited. If the job exceeds the timeout, it
+    /// is killed and marked as timed out.
+    fn try_wait_job(
+        &self,
+        run: &mut (Child, QCommand),
+    ) -> Result<Option<ExitStatus>> {
+        if let Some(r) = run.0.try_wait()? {
+            return Ok(Some(r));
+        }
+
+        let (timeout, started) = match (self.job_timeout, run.1.started) {
+            (Some(t), Some(s)) => (t, s),
+            _ => return Ok(None),
+        };
+
+        if started.elapsed() <= timeout {
+            return Ok(None);
+        }
+
+        // the process may have exited in the mean time
+        _ = run.0.kill();
+        debug!("{:?} timed out, the job was killed", paths(&run.1.provides));
+        run.1.timed_out = true;
+        Ok(Some(run.0.wait()?))
+    }
+}
+
+/// Loads the list of source files that failed to compile saved with
+/// [`Builder::save_failed`]. Files that no longer exist are skipped.
+pub fn load_failed<P>(path: P) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let list = match fs::read_to_string(path) {
+        Ok(l) => l,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(Error::io_at("read", path, e)),
+    };
+
+    Ok(list
+        .lines()
+        .map(PathBuf::from)
+        .filter(|p| p.is_file())
+        .collect())
+}
+
+/// Checks whether the failure is most likely not caused by the compiled code:
+/// the process was killed by signal or the compiler reported internal
+/// compiler error.
+fn is_transient_failure(status: ExitStatus) -> bool {
+    /// Exit code of gcc on internal compiler error
+    const ICE_EXIT_CODE: i32 = 4;
+
+    match status.code() {
+        // on unix, there is no exit code when killed by signal
+        None => true,
+        Some(ICE_EXIT_CODE) => true,
+        // NTSTATUS error codes (e.g. access violation)
+        #[cfg(windows)]
+        Some(c) => c as u32 >= 0xC000_0000,
+        #[cfg(not(windows))]
+        Some(_) => false,
+    }
+}
+
+fn paths(files: &[DepFile]) -> Vec<&Path> {
+    files.iter().map(|f| f.path.as_ref()).collect()
+}
+
+/// Orders the commands so that each command comes after the commands that
+/// provide the files it requires. Otherwise the order is kept.
+fn order_commands(commands: Vec<PlannedCommand>) -> Vec<PlannedCommand> {
+    fn visit(
+        i: usize,
+        commands: &[PlannedCommand],
+        providers: &HashMap<&DepFile, usize>,
+        visited: &mut [bool],
+        order: &mut Vec<usize>,
+    ) {
+        if mem::replace(&mut visited[i], true) {
+            return;
+        }
+        for r in &commands[i].requires {
+            if let Some(&p) = providers.get(r) {
+                visit(p, commands, providers, visited, order);
+            }
+        }
+        order.push(i);
+    }
+
+    let mut order = vec![];
+    {
+        let mut providers = HashMap::new();
+        for (i, c) in commands.iter().enumerate() {
+            for p in &c.provides {
+                providers.entry(p).or_insert(i);
+            }
+        }
+        let mut visited = vec![false; commands.len()];
+        for i in 0..commands.len() {
+            visit(i, &commands, &providers, &mut visited, &mut order);
+        }
+    }
+
+    let mut commands: Vec<_> = commands.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|i| commands[i].take())
+        .collect()
+}
+
+fn ser_lossy<S>(s: &OsStr, ser: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    ser.serialize_str(&s.to_string_lossy())
+}
+
+fn ser_lossy_all<S>(
+    v: &[OsString],
+    ser: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    ser.collect_seq(v.iter().map(|a| a.to_string_lossy()))
+}
+
+fn ser_paths<S>(v: &[DepFile], ser: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    ser.collect_seq(v.iter().map(|f| f.path.to_string_lossy()))
+}
+
+impl PlannedCommand {
+    /// Creates the command that can be run.
+    pub fn command(&self) -> Command {
+        let mut res = Command::new(&self.program);
+        res.args(&self.args);
+        res
+    }
+}
+
+impl From<PlannedCommand> for QCommand {
+    fn from(value: PlannedCommand) -> Self {
+        Self {
+            command: value.command(),
+            requires: value.requires,
+            provides: value.provides,
+            sources: value.sources,
+            object: value.object,
+            cache_key: None,
+            forwarder: None,
+            started: None,
+            retried: 0,
+            timed_out: false,
+        }
+    }
 }
 
 impl QCommand {
-    fn run(&mut self, print: bool) -> Result<Child> {
+    /// Runs the command. When `parallel` is true, stderr of the command is
+    /// forwarded line by line with prefix so that the output of multiple
+    /// commands can be distinguished. If `capture` is set, the lines from
+    /// stderr are also stored there. The lines dropped by `filter` are
+    /// neither shown nor captured.
+    fn run(
+        &mut self,
+        print: bool,
+        parallel: bool,
+        capture: Option<Captured>,
+        filter: Option<DiagFilter>,
+    ) -> Result<Child> {
         for r in &self.provides {
             if let Some(p) = r.parent() {
-                fs::create_dir_all(p)?;
+                fs::create_dir_all(p).with_path("create directory", p)?;
             }
         }
         if print {
-            print!("{}", self.command.get_program().to_string_lossy());
-            for a in self.command.get_args() {
-                print!(" '{}'", a.to_string_lossy());
+            println!("{}", command_to_string(&self.command));
+        }
+
+        self.started = Some(Instant::now());
+        if !parallel && capture.is_none() && filter.is_none() {
+            return self
+                .command
+                .spawn()
+                .with_path("run", self.command.get_program().as_ref());
+        }
+
+        self.command.stderr(Stdio::piped());
+        let mut child = self
+            .command
+            .spawn()
+            .with_path("run", self.command.get_program().as_ref())?;
+        if let Some(stderr) = child.stderr.take() {
+            let prefix = parallel.then(|| self.short_name());
+            self.forwarder = Some(thread::spawn(move || {
+                forward_lines(prefix.as_deref(), stderr, capture, filter)
+            }));
+        }
+        Ok(child)
+    }
+
+    /// Waits for the stderr forwarding thread to finish.
+    fn join_forwarder(&mut self) {
+        if let Some(f) = self.forwarder.take() {
+            // the forwarder only fails if it panics, in that case its output
+            // is lost
+            _ = f.join();
+        }
+    }
+
+    /// Gets short name of the file that is produced by the command, for
+    /// objects this is the name of the source file.
+    fn short_name(&self) -> String {
+        let file = if let Some(file) = self.provides.first() {
+            file
+        } else {
+            return String::new();
+        };
+
+        let name = file.file_name().unwrap_or(file.as_os_str());
+        let is_obj = matches!(
+            file.typ,
+            Some(FileType {
+                state: FileState::Object,
+                ..
+            })
+        );
+
+        if is_obj {
+            Path::new(name)
+                .file_stem()
+                .unwrap_or(name)
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            name.to_string_lossy().into_owned()
+        }
+    }
+}
+
+/// Writes all lines from `read` that pass the filter to stderr with the
+/// given prefix and stores them to `capture`.
+fn forward_lines<R>(
+    prefix: Option<&str>,
+    read: R,
+    capture: Option<Captured>,
+    mut filter: Option<DiagFilter>,
+) where
+    R: Read,
+{
+    let write = |lines: Vec<String>| {
+        {
+            // lock so that the lines of different commands don't mix
+            let mut err = io::stderr().lock();
+            for line in &lines {
+                _ = match prefix {
+                    Some(p) => writeln!(err, "[{p}] {line}"),
+                    None => writeln!(err, "{line}"),
+                };
+            }
+        }
+        if let Some(c) = &capture {
+            // the lock fails only if other thread panicked
+            if let Ok(mut c) = c.lock() {
+                c.extend(lines);
             }
-            println!();
         }
-        Ok(self.command.spawn()?)
+    };
+
+    for line in BufReader::new(read).lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        match &mut filter {
+            Some(f) => write(f.push(line)),
+            None => write(vec![line]),
+        }
+    }
+    if let Some(f) = filter {
+        write(f.finish());
+    }
+}
+
+/// Checks whether the arguments are different than the saved arguments.
+fn args_changed((path, args): &(PathBuf, String)) -> bool {
+    fs::read_to_string(path).map_or(true, |a| a != *args)
+}
+
+/// Saves the arguments. The file is written only if they changed, so its
+/// modification time tells when the files that use them must be rebuilt.
+fn save_args(path: &Path, args: &str) -> Result<()> {
+    if fs::read_to_string(path).is_ok_and(|a| a == args) {
+        return Ok(());
+    }
+
+    debug!("Arguments in {path:?} changed");
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_path("create directory", dir)?;
+    }
+    fs::write(path, args).with_path("write", path)?;
+    Ok(())
+}
+
+/// Loads the included files that didn't exist when the objects were
+/// compiled, saved with [`Builder::save_missing`].
+fn load_missing(path: &Path) -> Result<HashMap<PathBuf, HashSet<PathBuf>>> {
+    let list = match fs::read_to_string(path) {
+        Ok(l) => l,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(HashMap::new())
+        }
+        Err(e) => return Err(Error::io_at("read", path, e)),
+    };
+
+    let mut res: HashMap<_, HashSet<_>> = HashMap::new();
+    for (obj, missing) in list.lines().filter_map(|l| l.split_once('\t')) {
+        res.entry(obj.into()).or_default().insert(missing.into());
     }
+    Ok(res)
 }
@@ -0,0 +1,129 @@
+use std::{fs, io, path::Path};
+
+use crate::{err::Result, paths};
+
+/// What is removed by `ccpp clean`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CleanMode {
+    /// Objects, binaries and other build artifacts
+    Artifacts,
+    /// Build artifacts and caches
+    Cache,
+    /// The whole bin directory
+    All,
+}
+
+/// Statistics of the removed files.
+#[derive(Debug, Default)]
+pub struct Removed {
+    /// Number of removed build artifacts
+    pub artifacts: usize,
+    /// Number of removed cache files
+    pub caches: usize,
+    /// Total size of the removed files in bytes
+    pub size: u64,
+}
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Removes the files in the bin directory of a profile. The statistics are
+/// added to `removed`.
+pub fn clean(
+    bin_root: &Path,
+    mode: CleanMode,
+    removed: &mut Removed,
+) -> Result<()> {
+    let entries = match fs::read_dir(bin_root) {
+        Ok(e) => e,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name == paths::LOCK {
+            continue;
+        }
+
+        let is_cache = paths::CACHES.contains(&name.as_ref());
+        if is_cache && mode == CleanMode::Artifacts {
+            continue;
+        }
+
+        let (count, size) = remove(&entry.path())?;
+        if is_cache {
+            removed.caches += count;
+        } else {
+            removed.artifacts += count;
+        }
+        removed.size += size;
+    }
+
+    // the lock is held by this process, so it may be removed with the
+    // directory
+    if mode == CleanMode::All {
+        fs::remove_dir_all(bin_root)?;
+    }
+
+    Ok(())
+}
+
+/// Removes the shared cache directory (e.g. the object cache). The removed
+/// files are counted as caches in `removed`.
+pub fn clean_shared(dir: &Path, removed: &mut Removed) -> Result<()> {
+    match fs::symlink_metadata(dir) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    }
+    let (count, size) = remove(dir)?;
+    removed.caches += count;
+    removed.size += size;
+    Ok(())
+}
+
+impl Removed {
+    /// Formats the total size with binary unit (e.g. `1.5 MiB`).
+    pub fn size_str(&self) -> String {
+        const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+        if self.size < 1024 {
+            return format!("{} B", self.size);
+        }
+
+        let mut size = self.size as f64 / 1024.;
+        let mut unit = 0;
+        while size >= 1024. && unit + 1 < UNITS.len() {
+            size /= 1024.;
+            unit += 1;
+        }
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Removes the file or directory. Returns the number of removed files and
+/// their total size.
+fn remove(path: &Path) -> Result<(usize, u64)> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        fs::remove_file(path)?;
+        return Ok((1, meta.len()));
+    }
+
+    let mut count = 0;
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let (c, s) = remove(&entry?.path())?;
+        count += c;
+        size += s;
+    }
+    fs::remove_dir(path)?;
+    Ok((count, size))
+}
@@ -0,0 +1,137 @@
+use std::{
+    env, fs, io,
+    path::Path,
+    process::{self, Command, Stdio},
+};
+
+use log::debug;
+use termal::printcln;
+
+use crate::err::{Error, Result};
+
+use super::{
+    config::{Asan, Config},
+    probe,
+};
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Checks whether the address sanitizer should be used with the compiler.
+/// With [`Asan::Auto`] it is disabled with warning if it is not available,
+/// with [`Asan::Enabled`] it is error if it is not available.
+pub fn enabled(bin: &Path, conf: &Config) -> Result<bool> {
+    resolve(conf.asan, bin, || available(bin, conf))
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Decides whether the address sanitizer is used. `available` is called
+/// only if the result depends on it, so that the probe doesn't run when it
+/// isn't needed.
+fn resolve<F>(asan: Asan, bin: &Path, available: F) -> Result<bool>
+where
+    F: FnOnce() -> bool,
+{
+    match asan {
+        Asan::Disabled => Ok(false),
+        _ if available() => Ok(true),
+        Asan::Enabled => Err(Error::AsanUnavailable(bin.to_owned())),
+        Asan::Auto => {
+            printcln!(
+                "{'y bold}    Warning{'_} AddressSanitizer runtime is not \
+                available for {}, building without it (set `asan = false` \
+                to silence this)",
+                bin.to_string_lossy()
+            );
+            Ok(false)
+        }
+    }
+}
+
+/// Checks whether the address sanitizer is available with the compiler.
+fn available(bin: &Path, conf: &Config) -> bool {
+    let res = probe::cached(conf, bin, "asan", || {
+        run_probe(bin, &conf.driver_args).map(|r| (r as u8).to_string())
+    });
+    // the compiler itself may be missing if the probe fails, let the build
+    // report it
+    res.is_none_or(|r| r == "1")
+}
+
+/// Links and runs empty program with the address sanitizer. `args` are the
+/// arguments of the driver, so that e.g. `-m32` probes the 32-bit runtime.
+/// Returns error if the probe couldn't run at all.
+fn run_probe(bin: &Path, args: &[String]) -> io::Result<bool> {
+    let dir = env::temp_dir().join(format!("ccpp-asan-{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    let res = run_probe_in(bin, args, &dir);
+    _ = fs::remove_dir_all(&dir);
+    res
+}
+
+fn run_probe_in(bin: &Path, args: &[String], dir: &Path) -> io::Result<bool> {
+    let src = dir.join("probe.c");
+    let out = dir.join("probe");
+    fs::write(&src, "int main(void) { return 0; }\n")?;
+
+    debug!("Probing AddressSanitizer with {bin:?} {args:?}");
+    let linked = Command::new(bin)
+        .args(args)
+        .arg("-fsanitize=address")
+        .arg("-o")
+        .arg(&out)
+        .arg(&src)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?
+        .success();
+    if !linked {
+        debug!("Linking with -fsanitize=address failed");
+        return Ok(false);
+    }
+
+    // the runtime may be missing even if the link succeeds, the leak
+    // detection fails in some containers so it is not used when probing
+    let started = Command::new(&out)
+        .env("ASAN_OPTIONS", "detect_leaks=0")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match started {
+        Ok(s) => Ok(s.success()),
+        // the binary may be for other platform (e.g. with sysroot)
+        Err(e) => {
+            debug!("Failed to run the AddressSanitizer probe: {e}");
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_doesnt_probe() {
+        let res = resolve(Asan::Disabled, Path::new("gcc"), || unreachable!());
+        assert!(!res.unwrap());
+    }
+
+    #[test]
+    fn available_is_used() {
+        for asan in [Asan::Auto, Asan::Enabled] {
+            assert!(resolve(asan, Path::new("gcc"), || true).unwrap());
+        }
+    }
+
+    #[test]
+    fn unavailable() {
+        assert!(!resolve(Asan::Auto, Path::new("gcc"), || false).unwrap());
+        let res = resolve(Asan::Enabled, Path::new("gcc"), || false);
+        assert!(matches!(res, Err(Error::AsanUnavailable(_))));
+    }
+}
@@ -1,4 +1,7 @@
-use std::{path::PathBuf, process::Command};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 use crate::{compiler::common::Compiler, dependency::Dependency, err::Result};
 
@@ -8,8 +11,10 @@ pub struct Clang {
     bin: PathBuf,
     src_root: PathBuf,
     bin_root: PathBuf,
+    obj_root: PathBuf,
     compile_args: Vec<String>,
     link_args: Vec<String>,
+    unsupported: Vec<String>,
 }
 
 impl Clang {
@@ -20,12 +25,22 @@ impl Clang {
         gcc::build(self, file)
     }
 
+    pub fn preprocess(
+        &self,
+        file: Dependency,
+        out: Option<&Path>,
+    ) -> Result<Command> {
+        gcc::preprocess(self, file, out)
+    }
+
     pub fn new(bin: PathBuf, conf: &Config) -> Result<Self> {
         gcc::try_new(bin, conf)
     }
 }
 
 impl Compiler for Clang {
+    const SUPPORTS_OZ: bool = true;
+
     fn bin(&self) -> &std::path::Path {
         &self.bin
     }
@@ -38,6 +53,10 @@ impl Compiler for Clang {
         &self.bin_root
     }
 
+    fn obj_root(&self) -> &std::path::Path {
+        &self.obj_root
+    }
+
     fn compile_args(&self) -> &Vec<String> {
         &self.compile_args
     }
@@ -46,18 +65,25 @@ impl Compiler for Clang {
         &self.link_args
     }
 
+    fn unsupported(&self) -> &[String] {
+        &self.unsupported
+    }
+
     fn try_new(
         bin: PathBuf,
         compile_args: Vec<String>,
         link_args: Vec<String>,
+        unsupported: Vec<String>,
         conf: &Config,
     ) -> Result<Self> {
         Ok(Self {
             bin,
             src_root: conf.src_root.clone(),
             bin_root: conf.bin_root.clone(),
+            obj_root: conf.obj_root(),
             compile_args,
             link_args,
+            unsupported,
         })
     }
 }
@@ -11,8 +11,10 @@ pub struct Clangpp {
     bin: PathBuf,
     src_root: PathBuf,
     bin_root: PathBuf,
+    obj_root: PathBuf,
     compile_args: Vec<String>,
     link_args: Vec<String>,
+    unsupported: Vec<String>,
 }
 
 impl Clangpp {
@@ -23,12 +25,22 @@ impl Clangpp {
         gcc::build(self, file)
     }
 
+    pub fn preprocess(
+        &self,
+        file: Dependency,
+        out: Option<&Path>,
+    ) -> Result<Command> {
+        gcc::preprocess(self, file, out)
+    }
+
     pub fn new(bin: PathBuf, conf: &Config, is_c: bool) -> Result<Self> {
         gpp::try_new(bin, conf, is_c)
     }
 }
 
 impl Compiler for Clangpp {
+    const SUPPORTS_OZ: bool = true;
+
     fn bin(&self) -> &Path {
         &self.bin
     }
@@ -41,6 +53,10 @@ impl Compiler for Clangpp {
         &self.bin_root
     }
 
+    fn obj_root(&self) -> &Path {
+        &self.obj_root
+    }
+
     fn compile_args(&self) -> &Vec<String> {
         &self.compile_args
     }
@@ -49,18 +65,25 @@ impl Compiler for Clangpp {
         &self.link_args
     }
 
+    fn unsupported(&self) -> &[String] {
+        &self.unsupported
+    }
+
     fn try_new(
         bin: PathBuf,
         compile_args: Vec<String>,
         link_args: Vec<String>,
+        unsupported: Vec<String>,
         conf: &Config,
     ) -> Result<Self> {
         Ok(Self {
             bin,
             src_root: conf.src_root.clone(),
             bin_root: conf.bin_root.clone(),
+            obj_root: conf.obj_root(),
             compile_args,
             link_args,
+            unsupported,
         })
     }
 }
@@ -5,20 +5,31 @@ use crate::err::Result;
 use super::config::Config;
 
 pub(super) trait Compiler {
+    /// Whether the compiler supports `-Oz`, otherwise `-Os` is used instead
+    const SUPPORTS_OZ: bool;
+
     fn bin(&self) -> &Path;
 
     fn src_root(&self) -> &Path;
 
     fn bin_root(&self) -> &Path;
 
+    /// Directory for objects of the project sources
+    fn obj_root(&self) -> &Path;
+
     fn compile_args(&self) -> &Vec<String>;
 
     fn link_args(&self) -> &Vec<String>;
 
+    /// Warning flags from the configuration that the compiler doesn't
+    /// support, they are not in [`Self::compile_args`]
+    fn unsupported(&self) -> &[String];
+
     fn try_new(
         bin: PathBuf,
         compile_args: Vec<String>,
         link_args: Vec<String>,
+        unsupported: Vec<String>,
         conf: &Config,
     ) -> Result<Self>
     where
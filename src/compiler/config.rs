@@ -1,11 +1,33 @@
-use std::{ops::RangeBounds, path::PathBuf};
+use std::{fmt::Display, ops::RangeBounds, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+use crate::paths;
+
+/// Arguments of gcc and clang that make implicit declarations errors in C
+/// (`strict_c`)
+pub const STRICT_C_ARGS: &[&str] = &[
+    "-Werror=implicit-function-declaration",
+    "-Werror=implicit-int",
+];
+
+/// Optimization level. In the configuration it is either number (e.g. `2`
+/// for `-O2`) or name (`"none"`, `"all"`, `"debug"`, `"size"`, `"z"` or
+/// `"fast"`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "SerdeOptimization", into = "SerdeOptimization")]
 pub enum Optimization {
     None,
     All,
+    /// Optimizations that don't interfere with debugging (`-Og`)
+    Debug,
+    /// Optimize for size (`-Os`)
+    Size,
+    /// Optimize for size even at the cost of speed (`-Oz`)
+    SizeAggressive,
+    /// All optimizations, including the ones that break the standard
+    /// (`-Ofast`)
+    Fast,
     Level(i32),
 }
 
@@ -15,21 +37,40 @@ impl Optimization {
         R: RangeBounds<i32>,
     {
         matches!(self, Self::Level(l) if range.contains(l))
-            || matches!(self, Self::All | Self::None)
+            || !matches!(self, Self::Level(_))
     }
 }
 
-impl ToString for Optimization {
-    fn to_string(&self) -> String {
+impl Display for Optimization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::None => "None".to_owned(),
-            Self::All => "All".to_owned(),
-            Self::Level(n) => n.to_string(),
+            Self::None => write!(f, "none"),
+            Self::All => write!(f, "all"),
+            Self::Debug => write!(f, "debug"),
+            Self::Size => write!(f, "size"),
+            Self::SizeAggressive => write!(f, "z"),
+            Self::Fast => write!(f, "fast"),
+            Self::Level(n) => write!(f, "{n}"),
         }
     }
 }
 
+/// Whether the address sanitizer is used. In the configuration it is `true`,
+/// `false` or `"auto"`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "SerdeAsan", into = "SerdeAsan")]
+pub enum Asan {
+    /// Use it only if it is available
+    Auto,
+    /// Always use it, fail if it is not available
+    Enabled,
+    Disabled,
+}
+
+/// Standard of the language. In the configuration it is either number
+/// (e.g. `23` for `-std=c23`) or the whole name (e.g. `"gnu2x"`).
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "SerdeStd", into = "SerdeStd")]
 pub enum Std {
     Number(i32),
     Name(String),
@@ -37,11 +78,11 @@ pub enum Std {
 
 impl Std {
     pub fn is_c_num(&self) -> bool {
-        matches!(self, Self::Number(99 | 11 | 17))
+        matches!(self, Self::Number(89 | 90 | 99 | 11 | 17 | 18 | 23))
     }
 
     pub fn is_cpp_num(&self) -> bool {
-        matches!(self, Self::Number(98 | 3 | 11 | 14 | 17 | 20))
+        matches!(self, Self::Number(98 | 3 | 11 | 14 | 17 | 20 | 23 | 26))
     }
 }
 
@@ -57,17 +98,300 @@ impl From<i32> for Std {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Config {
     pub bin_root: PathBuf,
     pub src_root: PathBuf,
+    pub include_dirs: Vec<PathBuf>,
+    /// Include directories whose headers don't produce warnings
+    /// (`-isystem`)
+    pub system_include_dirs: Vec<PathBuf>,
     pub optimization: Optimization,
-    pub asan: bool,
+    pub asan: Asan,
     pub dbg_symbols: bool,
+    /// Remove unused functions and data from the binary
+    pub gc_sections: bool,
+    /// Implicit function declarations and implicit `int` are errors in C
+    pub strict_c: bool,
     pub c_std: Std,
     pub cpp_std: Std,
     pub defines: Vec<(String, Option<String>)>,
     pub warn: Vec<String>,
     pub no_warn: Vec<String>,
     pub args: Vec<String>,
+    /// Args only for compiling
+    pub compile_args: Vec<String>,
+    /// Args only for linking
+    pub link_args: Vec<String>,
+    /// Args only for C
+    pub c_args: Vec<String>,
+    /// Args only for C++
+    pub cpp_args: Vec<String>,
+    /// Root directory for headers and libraries (`--sysroot`)
+    pub sysroot: Option<String>,
+    /// Module-definition file with the exported symbols (Windows only)
+    pub module_def: Option<PathBuf>,
+    /// Static libraries that are linked with all their objects, even the
+    /// unreferenced ones
+    pub whole_archive: Vec<PathBuf>,
+    /// Map file written by the linker
+    pub map_file: Option<PathBuf>,
+    /// Arguments given with the binary of the compiler (e.g. `-m32` from
+    /// `CC="gcc -m32"`), they are passed before all the other arguments
+    pub driver_args: Vec<String>,
+    /// Put objects of the project sources to `project` subdirectory of the
+    /// bin directory
+    pub obj_subdir: bool,
+    /// Compiler defined by the configuration, it is used instead of the
+    /// detected compiler
+    pub custom: Option<CustomCompiler>,
+}
+
+/// Compiler defined by templates of its arguments. Each template is list of
+/// arguments with placeholders in braces (e.g. `"-I{dir}"`). Empty template
+/// means that the compiler doesn't support the option.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CustomCompiler {
+    pub bin: PathBuf,
+    /// Compiling object, `{src}` is the source and `{out}` the object
+    pub compile: Vec<String>,
+    /// Linking executable, `{objs}` are the objects and `{out}` the binary
+    pub link: Vec<String>,
+    /// Preprocessing source `{src}`, the output is written to stdout
+    pub preprocess: Vec<String>,
+    /// Optimization level `{level}` from 0 to 3, `s`, `z` or `fast`
+    pub optimization: Vec<String>,
+    /// Optimizations that don't interfere with debugging
+    pub debug_optimization: Vec<String>,
+    pub dbg_symbols: Vec<String>,
+    /// Address sanitizer, used both when compiling and linking
+    pub asan: Vec<String>,
+    /// Standard `{std}` of the language (e.g. `c17` or `c++20`)
+    pub std: Vec<String>,
+    /// Define `{name}` without value
+    pub define: Vec<String>,
+    /// Define `{name}` with `{value}`
+    pub define_value: Vec<String>,
+    /// Include directory `{dir}`
+    pub include_dir: Vec<String>,
+    /// Enable warning `{name}`
+    pub warn: Vec<String>,
+    /// Disable warning `{name}`
+    pub no_warn: Vec<String>,
+    /// Sysroot `{dir}`, used both when compiling and linking
+    pub sysroot: Vec<String>,
+    /// Linking all the objects of static library `{lib}`
+    pub whole_archive: Vec<String>,
+    /// Writing linker map to `{file}`
+    pub map_file: Vec<String>,
+    /// System include directory `{dir}`
+    pub system_include_dir: Vec<String>,
+    /// Errors for implicit declarations in C
+    pub strict_c: Vec<String>,
+    /// Putting functions and data to separate sections when compiling
+    pub gc_sections: Vec<String>,
+    /// Removing unused sections when linking
+    pub gc_sections_link: Vec<String>,
+}
+
+impl Config {
+    /// Gets the directory for objects of the project sources.
+    pub fn obj_root(&self) -> PathBuf {
+        if self.obj_subdir {
+            self.bin_root.join(paths::OBJ_DIR)
+        } else {
+            self.bin_root.clone()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SerdeAsan {
+    Bool(bool),
+    Auto(AsanAuto),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AsanAuto {
+    Auto,
+}
+
+impl From<SerdeAsan> for Asan {
+    fn from(value: SerdeAsan) -> Self {
+        match value {
+            SerdeAsan::Bool(true) => Self::Enabled,
+            SerdeAsan::Bool(false) => Self::Disabled,
+            SerdeAsan::Auto(_) => Self::Auto,
+        }
+    }
+}
+
+impl From<Asan> for SerdeAsan {
+    fn from(value: Asan) -> Self {
+        match value {
+            Asan::Enabled => Self::Bool(true),
+            Asan::Disabled => Self::Bool(false),
+            Asan::Auto => Self::Auto(AsanAuto::Auto),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SerdeOptimization {
+    Level(i32),
+    Name(NamedOptimization),
+    /// The old format, e.g. `"Debug"` or `{ Level = 2 }`
+    Tagged(TaggedOptimization),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum NamedOptimization {
+    None,
+    All,
+    Debug,
+    Size,
+    Z,
+    Fast,
+}
+
+#[derive(Serialize, Deserialize)]
+enum TaggedOptimization {
+    None,
+    All,
+    Debug,
+    Level(i32),
+}
+
+impl From<SerdeOptimization> for Optimization {
+    fn from(value: SerdeOptimization) -> Self {
+        match value {
+            SerdeOptimization::Level(n)
+            | SerdeOptimization::Tagged(TaggedOptimization::Level(n)) => {
+                Self::Level(n)
+            }
+            SerdeOptimization::Name(NamedOptimization::None)
+            | SerdeOptimization::Tagged(TaggedOptimization::None) => {
+                Self::None
+            }
+            SerdeOptimization::Name(NamedOptimization::All)
+            | SerdeOptimization::Tagged(TaggedOptimization::All) => Self::All,
+            SerdeOptimization::Name(NamedOptimization::Debug)
+            | SerdeOptimization::Tagged(TaggedOptimization::Debug) => {
+                Self::Debug
+            }
+            SerdeOptimization::Name(NamedOptimization::Size) => Self::Size,
+            SerdeOptimization::Name(NamedOptimization::Z) => {
+                Self::SizeAggressive
+            }
+            SerdeOptimization::Name(NamedOptimization::Fast) => Self::Fast,
+        }
+    }
+}
+
+impl From<Optimization> for SerdeOptimization {
+    fn from(value: Optimization) -> Self {
+        let name = match value {
+            Optimization::Level(n) => return Self::Level(n),
+            Optimization::None => NamedOptimization::None,
+            Optimization::All => NamedOptimization::All,
+            Optimization::Debug => NamedOptimization::Debug,
+            Optimization::Size => NamedOptimization::Size,
+            Optimization::SizeAggressive => NamedOptimization::Z,
+            Optimization::Fast => NamedOptimization::Fast,
+        };
+        Self::Name(name)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SerdeStd {
+    Number(i32),
+    Name(String),
+    /// The old format, e.g. `{ Number = 17 }`
+    Tagged(TaggedStd),
+}
+
+#[derive(Serialize, Deserialize)]
+enum TaggedStd {
+    Number(i32),
+    Name(String),
+}
+
+impl From<SerdeStd> for Std {
+    fn from(value: SerdeStd) -> Self {
+        match value {
+            SerdeStd::Number(n) | SerdeStd::Tagged(TaggedStd::Number(n)) => {
+                Self::Number(n)
+            }
+            SerdeStd::Name(n) | SerdeStd::Tagged(TaggedStd::Name(n)) => {
+                Self::Name(n)
+            }
+        }
+    }
+}
+
+impl From<Std> for SerdeStd {
+    fn from(value: Std) -> Self {
+        match value {
+            Std::Number(n) => Self::Number(n),
+            Std::Name(n) => Self::Name(n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Conf {
+        optimization: Optimization,
+    }
+
+    fn parse(value: &str) -> Optimization {
+        toml::from_str::<Conf>(&format!("optimization = {value}"))
+            .unwrap()
+            .optimization
+    }
+
+    #[test]
+    fn optimization_round_trip() {
+        for opt in [
+            Optimization::None,
+            Optimization::All,
+            Optimization::Debug,
+            Optimization::Size,
+            Optimization::SizeAggressive,
+            Optimization::Fast,
+            Optimization::Level(2),
+        ] {
+            let toml = toml::to_string(&Conf { optimization: opt }).unwrap();
+            let parsed: Conf = toml::from_str(&toml).unwrap();
+            assert_eq!(parsed.optimization, opt, "{toml}");
+        }
+    }
+
+    #[test]
+    fn optimization_names() {
+        assert_eq!(parse("\"debug\""), Optimization::Debug);
+        assert_eq!(parse("\"size\""), Optimization::Size);
+        assert_eq!(parse("\"z\""), Optimization::SizeAggressive);
+        assert_eq!(parse("\"fast\""), Optimization::Fast);
+        assert_eq!(parse("3"), Optimization::Level(3));
+        // the old format is still accepted
+        assert_eq!(parse("\"Debug\""), Optimization::Debug);
+        assert_eq!(parse("{ Level = 2 }"), Optimization::Level(2));
+        assert_eq!(
+            toml::to_string(&Conf {
+                optimization: Optimization::SizeAggressive
+            })
+            .unwrap(),
+            "optimization = \"z\"\n"
+        );
+    }
 }
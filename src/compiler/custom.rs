@@ -0,0 +1,436 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::debug;
+
+use crate::{
+    dependency::Dependency,
+    err::{Error, Result, ResultExt},
+    file_type::{FileState, Language},
+};
+
+use super::{
+    common::Compiler,
+    config::{Asan, Config, CustomCompiler, Optimization, Std},
+    gcc,
+};
+
+/// Compiler defined by the templates in `[compiler.custom]`.
+pub struct Custom {
+    bin: PathBuf,
+    src_root: PathBuf,
+    bin_root: PathBuf,
+    obj_root: PathBuf,
+    compile_args: Vec<String>,
+    link_args: Vec<String>,
+    unsupported: Vec<String>,
+    /// Template of the command that compiles object
+    compile: Vec<String>,
+    /// Template of the command that links executable
+    link: Vec<String>,
+    /// Template of the command that preprocesses source
+    preprocess: Vec<String>,
+}
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+impl Custom {
+    pub fn build(
+        &self,
+        file: Dependency,
+    ) -> Result<(Command, Vec<Dependency>)> {
+        let typ = if let Some(typ) = file.file.typ {
+            typ
+        } else {
+            return Err(Error::InvalidFileType(file.file));
+        };
+
+        match typ.state {
+            FileState::Object => self.build_object(file),
+            FileState::Executable => self.build_executable(file),
+            _ => Err(Error::InvalidFileType(file.file)),
+        }
+    }
+
+    /// Creates command that runs only the preprocessor on the sources of the
+    /// object. The template writes to the standard output, so it is
+    /// redirected to `out` if it is set.
+    pub fn preprocess(
+        &self,
+        file: Dependency,
+        out: Option<&Path>,
+    ) -> Result<Command> {
+        if file.direct.is_empty() {
+            return Err(Error::NothingToBuild(file.file.path.to_path_buf()));
+        }
+
+        let srcs: Vec<_> =
+            file.direct.iter().map(|s| s.to_string_lossy()).collect();
+        let srcs: Vec<_> = srcs.iter().map(|s| s.as_ref()).collect();
+
+        let mut cmd = Command::new(&self.bin);
+        cmd.args(expand("preprocess", &self.preprocess, &[("src", &srcs)])?);
+        cmd.args(&self.compile_args);
+        if let Some(out) = out {
+            cmd.stdout(File::create(out).with_path("write", out)?);
+        }
+
+        Ok(cmd)
+    }
+
+    /// Creates the compiler for the language. `bin` overrides the binary
+    /// from the definition.
+    pub fn new(
+        bin: Option<PathBuf>,
+        conf: &Config,
+        custom: &CustomCompiler,
+        lang: Language,
+    ) -> Result<Self> {
+        let mut compile_args = conf.driver_args.clone();
+        let mut link_args = conf.driver_args.clone();
+
+        if !conf.optimization.in_range(0..=3) {
+            return Err(Error::InvalidCompilerValue {
+                option: "optimization".to_owned(),
+                value: conf.optimization.to_string(),
+            });
+        }
+
+        let level = match conf.optimization {
+            Optimization::Debug => None,
+            Optimization::None => Some("0".to_owned()),
+            Optimization::All => Some("3".to_owned()),
+            Optimization::Size => Some("s".to_owned()),
+            Optimization::SizeAggressive => Some("z".to_owned()),
+            Optimization::Fast => Some("fast".to_owned()),
+            Optimization::Level(n) => Some(n.to_string()),
+        };
+        match level {
+            Some(l) => compile_args.extend(expand(
+                "optimization",
+                &custom.optimization,
+                &[("level", &[&l])],
+            )?),
+            None => compile_args.extend(expand(
+                "debug_optimization",
+                &custom.debug_optimization,
+                &[],
+            )?),
+        }
+
+        // the custom compiler can't be probed for the sanitizer
+        match conf.asan {
+            Asan::Enabled => {
+                let asan = expand("asan", &custom.asan, &[])?;
+                compile_args.extend(asan.iter().cloned());
+                link_args.extend(asan);
+            }
+            Asan::Auto => debug!("Not using asan with custom compiler"),
+            Asan::Disabled => {}
+        }
+
+        if conf.dbg_symbols {
+            compile_args.extend(expand(
+                "dbg_symbols",
+                &custom.dbg_symbols,
+                &[],
+            )?);
+        }
+
+        let std = std_name(conf, lang)?;
+        compile_args.extend(expand("std", &custom.std, &[("std", &[&std])])?);
+
+        for (name, value) in &conf.defines {
+            let arg = match value {
+                Some(v) => expand(
+                    "define_value",
+                    &custom.define_value,
+                    &[("name", &[name]), ("value", &[v])],
+                )?,
+                None => {
+                    expand("define", &custom.define, &[("name", &[name])])?
+                }
+            };
+            compile_args.extend(arg);
+        }
+
+        if let Some(sysroot) = &conf.sysroot {
+            let sysroot =
+                expand("sysroot", &custom.sysroot, &[("dir", &[sysroot])])?;
+            compile_args.extend(sysroot.iter().cloned());
+            link_args.extend(sysroot);
+        }
+
+        if let Some(def) = &conf.module_def {
+            debug!("Ignoring module_def {def:?} with custom compiler");
+        }
+
+        for lib in &conf.whole_archive {
+            link_args.extend(expand(
+                "whole_archive",
+                &custom.whole_archive,
+                &[("lib", &[&lib.to_string_lossy()])],
+            )?);
+        }
+
+        if let Some(map) = &conf.map_file {
+            link_args.extend(expand(
+                "map_file",
+                &custom.map_file,
+                &[("file", &[&map.to_string_lossy()])],
+            )?);
+        }
+
+        for dir in &conf.include_dirs {
+            compile_args.extend(expand(
+                "include_dir",
+                &custom.include_dir,
+                &[("dir", &[&dir.to_string_lossy()])],
+            )?);
+        }
+
+        for dir in &conf.system_include_dirs {
+            compile_args.extend(expand(
+                "system_include_dir",
+                &custom.system_include_dir,
+                &[("dir", &[&dir.to_string_lossy()])],
+            )?);
+        }
+
+        for w in &conf.warn {
+            compile_args.extend(expand(
+                "warn",
+                &custom.warn,
+                &[("name", &[w])],
+            )?);
+        }
+        for w in &conf.no_warn {
+            compile_args.extend(expand(
+                "no_warn",
+                &custom.no_warn,
+                &[("name", &[w])],
+            )?);
+        }
+
+        if conf.gc_sections {
+            compile_args.extend(expand(
+                "gc_sections",
+                &custom.gc_sections,
+                &[],
+            )?);
+            link_args.extend(expand(
+                "gc_sections_link",
+                &custom.gc_sections_link,
+                &[],
+            )?);
+        }
+
+        if conf.strict_c && lang == Language::C {
+            compile_args.extend(expand("strict_c", &custom.strict_c, &[])?);
+        }
+
+        let lang_args = match lang {
+            Language::C => &conf.c_args,
+            Language::Cpp => &conf.cpp_args,
+        };
+        compile_args.extend(conf.args.iter().cloned());
+        link_args.extend(conf.args.iter().cloned());
+        compile_args.extend(conf.compile_args.iter().cloned());
+        link_args.extend(conf.link_args.iter().cloned());
+        compile_args.extend(lang_args.iter().cloned());
+        link_args.extend(lang_args.iter().cloned());
+
+        // check the command templates early so that the error is not
+        // reported for each file
+        expand("compile", &custom.compile, &[("src", &[]), ("out", &[])])?;
+        expand("link", &custom.link, &[("objs", &[]), ("out", &[])])?;
+        expand("preprocess", &custom.preprocess, &[("src", &[])])?;
+
+        let mut res: Self = Compiler::try_new(
+            bin.unwrap_or_else(|| custom.bin.clone()),
+            compile_args,
+            link_args,
+            vec![],
+            conf,
+        )?;
+        res.compile = custom.compile.clone();
+        res.link = custom.link.clone();
+        res.preprocess = custom.preprocess.clone();
+        Ok(res)
+    }
+}
+
+impl Compiler for Custom {
+    const SUPPORTS_OZ: bool = false;
+
+    fn bin(&self) -> &Path {
+        &self.bin
+    }
+
+    fn src_root(&self) -> &Path {
+        &self.src_root
+    }
+
+    fn bin_root(&self) -> &Path {
+        &self.bin_root
+    }
+
+    fn obj_root(&self) -> &Path {
+        &self.obj_root
+    }
+
+    fn compile_args(&self) -> &Vec<String> {
+        &self.compile_args
+    }
+
+    fn link_args(&self) -> &Vec<String> {
+        &self.link_args
+    }
+
+    fn unsupported(&self) -> &[String] {
+        &self.unsupported
+    }
+
+    fn try_new(
+        bin: PathBuf,
+        compile_args: Vec<String>,
+        link_args: Vec<String>,
+        unsupported: Vec<String>,
+        conf: &Config,
+    ) -> Result<Self> {
+        Ok(Self {
+            bin,
+            src_root: conf.src_root.clone(),
+            bin_root: conf.bin_root.clone(),
+            obj_root: conf.obj_root(),
+            compile_args,
+            link_args,
+            unsupported,
+            compile: vec![],
+            link: vec![],
+            preprocess: vec![],
+        })
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+impl Custom {
+    fn build_object(
+        &self,
+        file: Dependency,
+    ) -> Result<(Command, Vec<Dependency>)> {
+        if file.direct.is_empty() {
+            return Err(Error::NothingToBuild(file.file.path.to_path_buf()));
+        }
+
+        let srcs: Vec<_> =
+            file.direct.iter().map(|s| s.to_string_lossy()).collect();
+        let srcs: Vec<_> = srcs.iter().map(|s| s.as_ref()).collect();
+        let out = file.file.to_string_lossy();
+
+        let mut cmd = Command::new(&self.bin);
+        cmd.args(expand(
+            "compile",
+            &self.compile,
+            &[("src", &srcs), ("out", &[&out])],
+        )?);
+        cmd.args(&self.compile_args);
+
+        Ok((cmd, vec![]))
+    }
+
+    fn build_executable(
+        &self,
+        file: Dependency,
+    ) -> Result<(Command, Vec<Dependency>)> {
+        if file.direct.is_empty() {
+            return Err(Error::NothingToBuild(file.file.path.to_path_buf()));
+        }
+
+        let out = file.file.to_string_lossy().into_owned();
+        let (objs, deps) = gcc::link_inputs(self, file)?;
+        let objs: Vec<_> = objs.iter().map(|o| o.to_string_lossy()).collect();
+        let objs: Vec<_> = objs.iter().map(|o| o.as_ref()).collect();
+
+        let mut cmd = Command::new(&self.bin);
+        cmd.args(expand(
+            "link",
+            &self.link,
+            &[("objs", &objs), ("out", &[&out])],
+        )?);
+        cmd.args(&self.link_args);
+
+        Ok((cmd, deps))
+    }
+}
+
+/// Gets the name of the standard in the form used by gcc (e.g. `c17` or
+/// `c++20`).
+fn std_name(conf: &Config, lang: Language) -> Result<String> {
+    let (std, option, prefix, valid) = match lang {
+        Language::C => (&conf.c_std, "c_std", "c", conf.c_std.is_c_num()),
+        Language::Cpp => {
+            (&conf.cpp_std, "cpp_std", "c++", conf.cpp_std.is_cpp_num())
+        }
+    };
+    match std {
+        Std::Number(n) if !valid => Err(Error::InvalidCompilerValue {
+            option: option.to_owned(),
+            value: n.to_string(),
+        }),
+        Std::Number(n) => Ok(format!("{prefix}{n}")),
+        Std::Name(n) => Ok(n.clone()),
+    }
+}
+
+/// Fills the placeholders in the template. Argument that is only placeholder
+/// expands to all the values (e.g. `{objs}`), placeholders inside other text
+/// are replaced with the values separated by space.
+fn expand(
+    option: &'static str,
+    template: &[String],
+    vars: &[(&str, &[&str])],
+) -> Result<Vec<String>> {
+    let get = |name: &str| {
+        vars.iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| *v)
+            .ok_or_else(|| Error::UnknownPlaceholder {
+                option,
+                placeholder: name.to_owned(),
+            })
+    };
+
+    let mut res = vec![];
+    for arg in template {
+        if let Some(name) =
+            arg.strip_prefix('{').and_then(|a| a.strip_suffix('}'))
+        {
+            if !name.contains(['{', '}']) {
+                res.extend(get(name)?.iter().map(|&v| v.to_owned()));
+                continue;
+            }
+        }
+
+        let mut out = String::new();
+        let mut rest = arg.as_str();
+        while let Some((start, len)) =
+            rest.find('{').and_then(|s| Some((s, rest[s..].find('}')?)))
+        {
+            out.push_str(&rest[..start]);
+            out.push_str(&get(&rest[start + 1..start + len])?.join(" "));
+            rest = &rest[start + len + 1..];
+        }
+        out.push_str(rest);
+        res.push(out);
+    }
+    Ok(res)
+}
@@ -3,23 +3,30 @@ use std::{
     process::Command,
 };
 
+use log::debug;
+use termal::printcln;
+
 use crate::{
     dependency::{DepFile, Dependency},
     err::{Error, Result},
-    file_type::{FileState, FileType},
+    file_type::{FileState, FileType, Language},
 };
 
 use super::{
+    asan,
     common::Compiler,
-    config::{Config, Optimization, Std},
+    config::{Config, Optimization, Std, STRICT_C_ARGS},
+    warn,
 };
 
 pub struct Gcc {
     bin: PathBuf,
     src_root: PathBuf,
     bin_root: PathBuf,
+    obj_root: PathBuf,
     compile_args: Vec<String>,
     link_args: Vec<String>,
+    unsupported: Vec<String>,
 }
 
 impl Gcc {
@@ -30,12 +37,22 @@ impl Gcc {
         build(self, file)
     }
 
+    pub fn preprocess(
+        &self,
+        file: Dependency,
+        out: Option<&Path>,
+    ) -> Result<Command> {
+        preprocess(self, file, out)
+    }
+
     pub fn new(bin: PathBuf, conf: &Config) -> Result<Self> {
         try_new(bin, conf)
     }
 }
 
 impl Compiler for Gcc {
+    const SUPPORTS_OZ: bool = false;
+
     fn bin(&self) -> &Path {
         &self.bin
     }
@@ -48,6 +65,10 @@ impl Compiler for Gcc {
         &self.bin_root
     }
 
+    fn obj_root(&self) -> &Path {
+        &self.obj_root
+    }
+
     fn compile_args(&self) -> &Vec<String> {
         &self.compile_args
     }
@@ -56,46 +77,103 @@ impl Compiler for Gcc {
         &self.link_args
     }
 
+    fn unsupported(&self) -> &[String] {
+        &self.unsupported
+    }
+
     fn try_new(
         bin: PathBuf,
         compile_args: Vec<String>,
         link_args: Vec<String>,
+        unsupported: Vec<String>,
         conf: &Config,
     ) -> Result<Self> {
         Ok(Self {
             bin,
             src_root: conf.src_root.clone(),
             bin_root: conf.bin_root.clone(),
+            obj_root: conf.obj_root(),
             compile_args,
             link_args,
+            unsupported,
         })
     }
 }
 
-// the implementation of the compilation is implemented on the common compiler
-// trait so that other compilers may reuse the code
-
-pub(super) fn try_new<C>(bin: PathBuf, conf: &Config) -> Result<C>
+/// Gets the argument for the optimization level. `-Oz` is replaced with `-Os`
+/// with warning if the compiler doesn't support it.
+pub(super) fn optimization_arg<C>(
+    bin: &Path,
+    opt: Optimization,
+) -> Result<String>
 where
     C: Compiler,
 {
-    let mut compile_args = vec![];
-    let mut link_args = vec![];
-
-    if !conf.optimization.in_range(0..=3) {
+    if !opt.in_range(0..=3) {
         return Err(Error::InvalidCompilerValue {
             option: "optimization".to_owned(),
-            value: conf.optimization.to_string(),
+            value: opt.to_string(),
         });
     }
 
-    match conf.optimization {
-        Optimization::None => compile_args.push("-O0".to_owned()),
-        Optimization::All => compile_args.push("-O3".to_owned()),
-        Optimization::Level(n) => compile_args.push(format!("-O{n}")),
+    Ok(match opt {
+        Optimization::None => "-O0".to_owned(),
+        Optimization::All => "-O3".to_owned(),
+        Optimization::Debug => "-Og".to_owned(),
+        Optimization::Size => "-Os".to_owned(),
+        Optimization::SizeAggressive if C::SUPPORTS_OZ => "-Oz".to_owned(),
+        Optimization::SizeAggressive => {
+            printcln!(
+                "{'y bold}    Warning{'_} {} doesn't support `-Oz`, `-Os` is \
+                used instead",
+                bin.to_string_lossy()
+            );
+            "-Os".to_owned()
+        }
+        Optimization::Fast => "-Ofast".to_owned(),
+        Optimization::Level(n) => format!("-O{n}"),
+    })
+}
+
+/// Gets the arguments of the linker from the configuration whose syntax
+/// depends on the platform.
+pub(super) fn platform_link_args(conf: &Config) -> Vec<String> {
+    let mut res = vec![];
+    for lib in &conf.whole_archive {
+        let lib = lib.to_string_lossy();
+        if cfg!(target_os = "macos") {
+            res.push(format!("-Wl,-force_load,{lib}"));
+        } else {
+            res.push("-Wl,--whole-archive".to_owned());
+            res.push(lib.into_owned());
+            res.push("-Wl,--no-whole-archive".to_owned());
+        }
+    }
+
+    if let Some(map) = &conf.map_file {
+        let map = map.to_string_lossy();
+        if cfg!(target_os = "macos") {
+            res.push(format!("-Wl,-map,{map}"));
+        } else {
+            res.push(format!("-Wl,-Map={map}"));
+        }
     }
+    res
+}
 
-    if conf.asan {
+// the implementation of the compilation is implemented on the common compiler
+// trait so that other compilers may reuse the code
+
+pub(super) fn try_new<C>(bin: PathBuf, conf: &Config) -> Result<C>
+where
+    C: Compiler,
+{
+    let mut compile_args = conf.driver_args.clone();
+    let mut link_args = conf.driver_args.clone();
+
+    compile_args.push(optimization_arg::<C>(&bin, conf.optimization)?);
+
+    if asan::enabled(&bin, conf)? {
         compile_args.push("-fsanitize=address".to_owned());
         link_args.push("-fsanitize=address".to_owned());
     }
@@ -104,6 +182,16 @@ where
         compile_args.push("-g".to_owned())
     }
 
+    if conf.gc_sections {
+        compile_args.push("-ffunction-sections".to_owned());
+        compile_args.push("-fdata-sections".to_owned());
+        if cfg!(target_os = "macos") {
+            link_args.push("-Wl,-dead_strip".to_owned());
+        } else {
+            link_args.push("-Wl,--gc-sections".to_owned());
+        }
+    }
+
     match &conf.c_std {
         Std::Number(n) => {
             if !conf.c_std.is_c_num() {
@@ -125,12 +213,46 @@ where
         }
     }));
 
-    compile_args.extend(conf.warn.iter().map(|w| format!("-W{w}")));
-    compile_args.extend(conf.no_warn.iter().map(|w| format!("-Wno-{w}")));
+    if let Some(sysroot) = &conf.sysroot {
+        compile_args.push(format!("--sysroot={sysroot}"));
+        link_args.push(format!("--sysroot={sysroot}"));
+    }
+
+    // MinGW takes the `.def` file as input of the linker, other platforms
+    // have no use for it
+    if let Some(def) = &conf.module_def {
+        if cfg!(windows) {
+            link_args.push(def.to_string_lossy().into_owned());
+        } else {
+            debug!("Ignoring module_def {def:?}, it is used only on windows");
+        }
+    }
+
+    link_args.extend(platform_link_args(conf));
+
+    for dir in &conf.include_dirs {
+        compile_args.push("-I".to_owned());
+        compile_args.push(dir.to_string_lossy().into_owned());
+    }
+
+    for dir in &conf.system_include_dirs {
+        compile_args.push("-isystem".to_owned());
+        compile_args.push(dir.to_string_lossy().into_owned());
+    }
+
+    let (warn, unsupported) = warn::args(&bin, conf, Language::C);
+    compile_args.extend(warn);
+    if conf.strict_c {
+        compile_args.extend(STRICT_C_ARGS.iter().map(|a| a.to_string()));
+    }
     compile_args.extend(conf.args.iter().cloned());
     link_args.extend(conf.args.iter().cloned());
+    compile_args.extend(conf.compile_args.iter().cloned());
+    link_args.extend(conf.link_args.iter().cloned());
+    compile_args.extend(conf.c_args.iter().cloned());
+    link_args.extend(conf.c_args.iter().cloned());
 
-    C::try_new(bin, compile_args, link_args, conf)
+    C::try_new(bin, compile_args, link_args, unsupported, conf)
 }
 
 pub(super) fn build<C>(
@@ -166,23 +288,36 @@ where
 
     let mut cmd = Command::new(cc.bin());
     cmd.args(["-c", "-o"]).arg(file.file.path.as_ref());
+    source_args(&mut cmd, file.direct)?;
+    cmd.args(cc.compile_args());
 
-    for file in file.direct {
-        if !matches!(
-            file.typ,
-            Some(FileType {
-                state: FileState::Source,
-                ..
-            })
-        ) {
-            return Err(Error::InvalidFileType(file));
-        }
-        cmd.arg(file.path.as_ref());
+    Ok((cmd, vec![]))
+}
+
+/// Creates command that runs only the preprocessor on the sources of the
+/// object. The output is written to `out`, or to the standard output if it
+/// is not set.
+pub(super) fn preprocess<C>(
+    cc: &C,
+    file: Dependency,
+    out: Option<&Path>,
+) -> Result<Command>
+where
+    C: Compiler,
+{
+    if file.direct.is_empty() {
+        return Err(Error::NothingToBuild(file.file.path.to_path_buf()));
     }
 
+    let mut cmd = Command::new(cc.bin());
+    cmd.arg("-E");
+    if let Some(out) = out {
+        cmd.arg("-o").arg(out);
+    }
+    source_args(&mut cmd, file.direct)?;
     cmd.args(cc.compile_args());
 
-    Ok((cmd, vec![]))
+    Ok(cmd)
 }
 
 pub(super) fn build_executable<C>(
@@ -199,6 +334,27 @@ where
     let mut cmd = Command::new(cc.bin());
     cmd.arg("-o").arg(file.file.as_ref());
 
+    let libs = pragma_libs(&file);
+    let (objs, deps) = link_inputs(cc, file)?;
+    cmd.args(objs.iter().map(|o| o.as_ref()));
+
+    cmd.args(cc.link_args());
+    cmd.args(libs);
+
+    Ok((cmd, deps))
+}
+
+/// Gets the objects that are linked into the executable. Sources are
+/// replaced with their objects, the dependencies of the objects are returned
+/// as the second value.
+pub(super) fn link_inputs<C>(
+    cc: &C,
+    file: Dependency,
+) -> Result<(Vec<DepFile>, Vec<Dependency>)>
+where
+    C: Compiler,
+{
+    let mut objs = vec![];
     let mut deps = vec![];
 
     for file in file.direct {
@@ -209,27 +365,77 @@ where
         };
 
         match typ.state {
-            FileState::Object => _ = cmd.arg(file.as_ref()),
+            FileState::Object => objs.push(file),
             FileState::Source => {
                 let dep = obj_source_dep(cc, file)?;
-                cmd.arg(dep.file.as_ref());
+                objs.push(dep.file.clone());
                 deps.push(dep);
             }
             _ => return Err(Error::InvalidFileType(file)),
         }
     }
 
-    cmd.args(cc.link_args());
+    Ok((objs, deps))
+}
 
-    Ok((cmd, deps))
+/// Adds the sources to the command, with their language when the compiler
+/// wouldn't detect it from the extension.
+fn source_args(cmd: &mut Command, srcs: Vec<DepFile>) -> Result<()> {
+    for file in srcs {
+        let lang = match file.typ {
+            Some(FileType {
+                state: FileState::Source,
+                lang,
+            }) => lang,
+            _ => return Err(Error::InvalidFileType(file)),
+        };
+        // the compiler wouldn't recognize the language of custom extensions
+        // and may recognize different language with case insensitive
+        // extensions
+        if file.extension().and_then(FileType::builtin_lang) != Some(lang) {
+            match lang {
+                Language::C => cmd.args(["-x", "c"]),
+                Language::Cpp => cmd.args(["-x", "c++"]),
+            };
+        }
+        cmd.arg(file.path.as_ref());
+    }
+    Ok(())
+}
+
+/// Gets the linker flags for the libraries requested with
+/// `#pragma comment(lib, "name")`. The pragma is used only by code for
+/// windows, so it is ignored on other platforms.
+fn pragma_libs(file: &Dependency) -> Vec<String> {
+    if !cfg!(windows) {
+        if !file.libs.is_empty() {
+            debug!("Ignoring pragma libraries {:?}", file.libs);
+        }
+        return vec![];
+    }
+
+    let mut libs: Vec<_> = file
+        .libs
+        .iter()
+        .map(|l| {
+            let l = l.strip_suffix(".lib").unwrap_or(l);
+            format!("-l{l}")
+        })
+        .collect();
+    libs.sort();
+    libs
 }
 
 pub(super) fn obj_source_dep<C>(cc: &C, file: DepFile) -> Result<Dependency>
 where
     C: Compiler,
 {
-    let mut res = cc.bin_root().join("project");
-    res.push(file.strip_prefix(cc.src_root())?);
+    // generated sources are already in the bin directory
+    let mut res = if let Ok(p) = file.strip_prefix(cc.src_root()) {
+        cc.obj_root().join(p)
+    } else {
+        cc.bin_root().join(file.strip_prefix(cc.bin_root())?)
+    };
     res.as_mut_os_string().push(".o");
 
     let res = DepFile {
@@ -3,23 +3,29 @@ use std::{
     process::Command,
 };
 
+use log::debug;
+
 use crate::{
     dependency::Dependency,
     err::{Error, Result},
+    file_type::Language,
 };
 
 use super::{
+    asan,
     common::Compiler,
-    config::{Config, Optimization, Std},
-    gcc,
+    config::{Config, Std},
+    gcc, warn,
 };
 
 pub struct Gpp {
     bin: PathBuf,
     src_root: PathBuf,
     bin_root: PathBuf,
+    obj_root: PathBuf,
     compile_args: Vec<String>,
     link_args: Vec<String>,
+    unsupported: Vec<String>,
 }
 
 impl Gpp {
@@ -30,12 +36,22 @@ impl Gpp {
         gcc::build(self, file)
     }
 
+    pub fn preprocess(
+        &self,
+        file: Dependency,
+        out: Option<&Path>,
+    ) -> Result<Command> {
+        gcc::preprocess(self, file, out)
+    }
+
     pub fn new(bin: PathBuf, conf: &Config, is_c: bool) -> Result<Self> {
         try_new(bin, conf, is_c)
     }
 }
 
 impl Compiler for Gpp {
+    const SUPPORTS_OZ: bool = false;
+
     fn bin(&self) -> &Path {
         &self.bin
     }
@@ -48,6 +64,10 @@ impl Compiler for Gpp {
         &self.bin_root
     }
 
+    fn obj_root(&self) -> &Path {
+        &self.obj_root
+    }
+
     fn compile_args(&self) -> &Vec<String> {
         &self.compile_args
     }
@@ -56,18 +76,25 @@ impl Compiler for Gpp {
         &self.link_args
     }
 
+    fn unsupported(&self) -> &[String] {
+        &self.unsupported
+    }
+
     fn try_new(
         bin: PathBuf,
         compile_args: Vec<String>,
         link_args: Vec<String>,
+        unsupported: Vec<String>,
         conf: &Config,
     ) -> Result<Self> {
         Ok(Self {
             bin,
             src_root: conf.src_root.clone(),
             bin_root: conf.bin_root.clone(),
+            obj_root: conf.obj_root(),
             compile_args,
             link_args,
+            unsupported,
         })
     }
 }
@@ -76,27 +103,16 @@ pub(super) fn try_new<C>(bin: PathBuf, conf: &Config, is_c: bool) -> Result<C>
 where
     C: Compiler,
 {
-    let mut compile_args = vec![];
-    let mut link_args = vec![];
+    let mut compile_args = conf.driver_args.clone();
+    let mut link_args = conf.driver_args.clone();
 
     if is_c {
         link_args.push("-lstdc++".to_owned());
     }
 
-    if !conf.optimization.in_range(0..=3) {
-        return Err(Error::InvalidCompilerValue {
-            option: "optimization".to_owned(),
-            value: conf.optimization.to_string(),
-        });
-    }
+    compile_args.push(gcc::optimization_arg::<C>(&bin, conf.optimization)?);
 
-    match conf.optimization {
-        Optimization::None => compile_args.push("-O0".to_owned()),
-        Optimization::All => compile_args.push("-O3".to_owned()),
-        Optimization::Level(n) => compile_args.push(format!("-O{n}")),
-    }
-
-    if conf.asan {
+    if asan::enabled(&bin, conf)? {
         compile_args.push("-fsanitize=address".to_owned());
         link_args.push("-fsanitize=address".to_owned());
     }
@@ -105,6 +121,16 @@ where
         compile_args.push("-g".to_owned())
     }
 
+    if conf.gc_sections {
+        compile_args.push("-ffunction-sections".to_owned());
+        compile_args.push("-fdata-sections".to_owned());
+        if cfg!(target_os = "macos") {
+            link_args.push("-Wl,-dead_strip".to_owned());
+        } else {
+            link_args.push("-Wl,--gc-sections".to_owned());
+        }
+    }
+
     match &conf.cpp_std {
         Std::Number(n) => {
             if !conf.cpp_std.is_cpp_num() {
@@ -126,10 +152,41 @@ where
         }
     }));
 
-    compile_args.extend(conf.warn.iter().map(|w| format!("-W{w}")));
-    compile_args.extend(conf.no_warn.iter().map(|w| format!("-Wno-{w}")));
+    if let Some(sysroot) = &conf.sysroot {
+        compile_args.push(format!("--sysroot={sysroot}"));
+        link_args.push(format!("--sysroot={sysroot}"));
+    }
+
+    // MinGW takes the `.def` file as input of the linker, other platforms
+    // have no use for it
+    if let Some(def) = &conf.module_def {
+        if cfg!(windows) {
+            link_args.push(def.to_string_lossy().into_owned());
+        } else {
+            debug!("Ignoring module_def {def:?}, it is used only on windows");
+        }
+    }
+
+    link_args.extend(gcc::platform_link_args(conf));
+
+    for dir in &conf.include_dirs {
+        compile_args.push("-I".to_owned());
+        compile_args.push(dir.to_string_lossy().into_owned());
+    }
+
+    for dir in &conf.system_include_dirs {
+        compile_args.push("-isystem".to_owned());
+        compile_args.push(dir.to_string_lossy().into_owned());
+    }
+
+    let (warn, unsupported) = warn::args(&bin, conf, Language::Cpp);
+    compile_args.extend(warn);
     compile_args.extend(conf.args.iter().cloned());
     link_args.extend(conf.args.iter().cloned());
+    compile_args.extend(conf.compile_args.iter().cloned());
+    link_args.extend(conf.link_args.iter().cloned());
+    compile_args.extend(conf.cpp_args.iter().cloned());
+    link_args.extend(conf.cpp_args.iter().cloned());
 
-    C::try_new(bin, compile_args, link_args, conf)
+    C::try_new(bin, compile_args, link_args, unsupported, conf)
 }
@@ -1,32 +1,43 @@
 use std::{
     borrow::Cow,
+    cell::{Cell, OnceCell},
+    collections::HashMap,
     env,
     path::{Path, PathBuf},
     process::Command,
 };
 
+use log::debug;
+
 use crate::{
-    dependency::Dependency,
+    dependency::{DepFile, Dependency},
     err::{Error, Result},
-    file_type::Language,
+    file_type::{FileState, Language},
+    shell::split_shell,
 };
 
 use self::{
-    clang::Clang, clangpp::Clangpp, config::Config, gcc::Gcc, gpp::Gpp,
+    clang::Clang, clangpp::Clangpp, common::Compiler as _, config::Config,
+    custom::Custom, gcc::Gcc, gpp::Gpp,
 };
 
+mod asan;
 mod clang;
 mod clangpp;
 mod common;
 pub mod config;
+mod custom;
 mod gcc;
 mod gpp;
+mod probe;
+mod warn;
 
 macro_rules! operate {
     ($typ:ident, $compiler:expr, $name:ident, $op:expr) => {
         match $compiler {
             $typ::Gcc($name) => $op,
             $typ::Clang($name) => $op,
+            $typ::Custom($name) => $op,
         }
     };
 }
@@ -48,11 +59,19 @@ const MAX_SCORE: i32 = 3;
 enum CCompiler {
     Gcc(Gcc),
     Clang(Clang),
+    Custom(Custom),
 }
 
 impl CCompiler {
     pub fn new(path: Option<PathBuf>, conf: &Config) -> Result<Self> {
-        let (path, typ) = find_compiler(path, Language::C);
+        if let Some(custom) = &conf.custom {
+            let (path, driver_args) = split_driver(path);
+            let conf = &with_driver_args(conf, driver_args);
+            let cc = Custom::new(path, conf, custom, Language::C)?;
+            return Ok(Self::Custom(cc));
+        }
+        let (path, typ, driver_args) = find_compiler(path, Language::C);
+        let conf = &with_driver_args(conf, driver_args);
         match typ {
             CompilerType::Gcc | CompilerType::Gpp | CompilerType::Other => {
                 Ok(Self::Gcc(Gcc::new(path, conf)?))
@@ -67,11 +86,19 @@ impl CCompiler {
 enum CppCompiler {
     Gcc(Gpp),
     Clang(Clangpp),
+    Custom(Custom),
 }
 
 impl CppCompiler {
     pub fn new(path: Option<PathBuf>, conf: &Config) -> Result<Self> {
-        let (path, typ) = find_compiler(path, Language::Cpp);
+        if let Some(custom) = &conf.custom {
+            let (path, driver_args) = split_driver(path);
+            let conf = &with_driver_args(conf, driver_args);
+            let cpp = Custom::new(path, conf, custom, Language::Cpp)?;
+            return Ok(Self::Custom(cpp));
+        }
+        let (path, typ, driver_args) = find_compiler(path, Language::Cpp);
+        let conf = &with_driver_args(conf, driver_args);
         match typ {
             CompilerType::Gcc | CompilerType::Other => {
                 Ok(Self::Gcc(Gpp::new(path, conf, true)?))
@@ -96,9 +123,39 @@ enum CompilerType {
     Other,
 }
 
+impl CompilerType {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Gcc => "gcc",
+            Self::Gpp => "g++",
+            Self::Clang => "clang",
+            Self::Clangpp => "clang++",
+            Self::Other => "unknown",
+        }
+    }
+}
+
+/// The compilers for the individual languages are created when they are
+/// first used, so projects that use only one language don't need compiler
+/// for the other.
 pub struct Compiler {
-    c: CCompiler,
-    cpp: CppCompiler,
+    c: OnceCell<CCompiler>,
+    cpp: OnceCell<CppCompiler>,
+    /// Path to the C compiler from the configuration
+    c_path: Option<PathBuf>,
+    /// Path to the C++ compiler from the configuration
+    cpp_path: Option<PathBuf>,
+    conf: Config,
+    /// Additional arguments used when compiling the specific source files
+    file_args: HashMap<PathBuf, Vec<String>>,
+    /// Additional arguments used when compiling all the objects
+    extra_compile_args: Vec<String>,
+    /// Additional arguments used when linking
+    extra_link_args: Vec<String>,
+    /// The unsupported warnings of the C compiler were reported
+    c_reported: Cell<bool>,
+    /// The unsupported warnings of the C++ compiler were reported
+    cpp_reported: Cell<bool>,
 }
 
 impl Compiler {
@@ -106,52 +163,237 @@ impl Compiler {
         c: Option<PathBuf>,
         cpp: Option<PathBuf>,
         conf: &Config,
-    ) -> Result<Self> {
-        Ok(Self {
-            c: CCompiler::new(c, conf)?,
-            cpp: CppCompiler::new(cpp, conf)?,
-        })
+    ) -> Self {
+        Self {
+            c: OnceCell::new(),
+            cpp: OnceCell::new(),
+            c_path: c,
+            cpp_path: cpp,
+            conf: conf.clone(),
+            file_args: HashMap::new(),
+            extra_compile_args: vec![],
+            extra_link_args: vec![],
+            c_reported: Cell::new(false),
+            cpp_reported: Cell::new(false),
+        }
     }
 
     pub fn build(
         &self,
         file: Dependency,
     ) -> Result<(Command, Vec<Dependency>)> {
-        if let Some(typ) = file.file.typ {
-            match typ.lang {
-                Language::C => c_op!(&self.c, cc, cc.build(file)),
-                Language::Cpp => cpp_op!(&self.cpp, cpp, cpp.build(file)),
+        let typ = if let Some(typ) = file.file.typ {
+            typ
+        } else {
+            return Err(Error::InvalidFileType(file.file));
+        };
+
+        if typ.state == FileState::Object {
+            self.report_unsupported(typ.lang)?;
+        }
+
+        let extra: Vec<_> = match typ.state {
+            FileState::Object => self.object_args(&file),
+            FileState::Executable => self.extra_link_args.clone(),
+            _ => vec![],
+        };
+
+        let (mut cmd, deps) = match typ.lang {
+            Language::C => c_op!(self.c()?, cc, cc.build(file))?,
+            Language::Cpp => cpp_op!(self.cpp()?, cpp, cpp.build(file))?,
+        };
+        cmd.args(extra);
+        Ok((cmd, deps))
+    }
+
+    /// Creates command that runs only the preprocessor on the sources of the
+    /// object, with the same arguments as when the object is compiled. The
+    /// output is written to `out`, or to the standard output if it is not
+    /// set.
+    pub fn preprocess(
+        &self,
+        file: Dependency,
+        out: Option<&Path>,
+    ) -> Result<Command> {
+        let lang = match file.file.typ {
+            Some(typ) if typ.state == FileState::Object => typ.lang,
+            _ => return Err(Error::InvalidFileType(file.file)),
+        };
+
+        self.report_unsupported(lang)?;
+        let extra = self.object_args(&file);
+        let mut cmd = match lang {
+            Language::C => c_op!(self.c()?, cc, cc.preprocess(file, out))?,
+            Language::Cpp => {
+                cpp_op!(self.cpp()?, cpp, cpp.preprocess(file, out))?
             }
+        };
+        cmd.args(extra);
+        Ok(cmd)
+    }
+
+    /// Adds arguments that are used when compiling all the objects and when
+    /// linking.
+    pub fn add_args(&mut self, compile: &[String], link: &[String]) {
+        self.extra_compile_args.extend(compile.iter().cloned());
+        self.extra_link_args.extend(link.iter().cloned());
+    }
+
+    /// Adds arguments that are used only when compiling the given source
+    /// file.
+    pub fn add_file_args<I>(&mut self, file: PathBuf, args: I)
+    where
+        I: IntoIterator<Item = String>,
+    {
+        self.file_args.entry(file).or_default().extend(args);
+    }
+}
+
+impl Compiler {
+    /// Creates dependency for the object file that is compiled from the given
+    /// source file. Only the source is direct dependency, the included files
+    /// must be added with [`crate::dependency::DepCache::fill_dependency`]
+    /// before checking whether the object is up to date.
+    pub fn obj_dependency(&self, file: DepFile) -> Result<Dependency> {
+        let lang = if let Some(typ) = file.typ {
+            typ.lang
         } else {
-            Err(Error::InvalidFileType(file.file))
+            return Err(Error::InvalidFileType(file));
+        };
+
+        match lang {
+            Language::C => c_op!(self.c()?, cc, gcc::obj_source_dep(cc, file)),
+            Language::Cpp => {
+                cpp_op!(self.cpp()?, cpp, gcc::obj_source_dep(cpp, file))
+            }
+        }
+    }
+
+    /// Gets the additional arguments used when compiling the object.
+    fn object_args(&self, file: &Dependency) -> Vec<String> {
+        self.extra_compile_args
+            .iter()
+            .chain(
+                file.direct
+                    .iter()
+                    .filter_map(|f| self.file_args.get(f.path.as_ref()))
+                    .flatten(),
+            )
+            .cloned()
+            .collect()
+    }
+
+    /// Reports the unsupported warnings of the compiler for the language
+    /// when it is used for the first time, so that they are not reported for
+    /// languages that the project doesn't use.
+    fn report_unsupported(&self, lang: Language) -> Result<()> {
+        let (reported, bin, unsupported) = match lang {
+            Language::C => (
+                &self.c_reported,
+                c_op!(self.c()?, cc, cc.bin()),
+                c_op!(self.c()?, cc, cc.unsupported()),
+            ),
+            Language::Cpp => (
+                &self.cpp_reported,
+                cpp_op!(self.cpp()?, cpp, cpp.bin()),
+                cpp_op!(self.cpp()?, cpp, cpp.unsupported()),
+            ),
+        };
+        if !reported.replace(true) {
+            warn::report(bin, unsupported);
         }
+        Ok(())
+    }
+
+    /// Gets the arguments used when compiling source files of the given
+    /// language.
+    pub fn compile_args(&self, lang: Language) -> Result<&[String]> {
+        Ok(match lang {
+            Language::C => c_op!(self.c()?, cc, cc.compile_args()),
+            Language::Cpp => cpp_op!(self.cpp()?, cpp, cpp.compile_args()),
+        })
+    }
+
+    /// Gets the path to the compiler used for the language and the name of
+    /// its detected type. The compiler is not created, so its features are
+    /// not probed.
+    pub fn resolve_bin(&self, lang: Language) -> (PathBuf, &'static str) {
+        let path = match lang {
+            Language::C => self.c_path.clone(),
+            Language::Cpp => self.cpp_path.clone(),
+        };
+        match &self.conf.custom {
+            Some(custom) => {
+                let (bin, _) = split_driver(path);
+                (bin.unwrap_or_else(|| custom.bin.clone()), "custom")
+            }
+            None => {
+                let (bin, typ, _) = find_compiler(path, lang);
+                (bin, typ.name())
+            }
+        }
+    }
+
+    /// Gets the C compiler, it is created on the first use.
+    fn c(&self) -> Result<&CCompiler> {
+        if let Some(c) = self.c.get() {
+            return Ok(c);
+        }
+        let c = CCompiler::new(self.c_path.clone(), &self.conf)?;
+        Ok(self.c.get_or_init(|| c))
+    }
+
+    /// Gets the C++ compiler, it is created on the first use.
+    fn cpp(&self) -> Result<&CppCompiler> {
+        if let Some(cpp) = self.cpp.get() {
+            return Ok(cpp);
+        }
+        let cpp = CppCompiler::new(self.cpp_path.clone(), &self.conf)?;
+        Ok(self.cpp.get_or_init(|| cpp))
     }
 }
 
+/// Gets the output of `--version` of the compiler. Returns [`None`] if the
+/// compiler cannot be run or fails.
+pub fn version(bin: &Path) -> Option<String> {
+    let out = Command::new(bin).arg("--version").output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Finds the compiler for the language. Returns its binary, type and the
+/// arguments that were given after the binary (e.g. `-m32` from
+/// `CC="gcc -m32"`).
 fn find_compiler(
     path: Option<PathBuf>,
     lng: Language,
-) -> (PathBuf, CompilerType) {
-    let (mut path, mut typ, mut score) = if let Some(p) = path {
-        if let Some(c) = test_compiler(&p) {
-            return (p, c);
-        } else {
-            (Cow::Owned(p), CompilerType::Other, 0)
+) -> (PathBuf, CompilerType, Vec<String>) {
+    let (mut path, mut typ, mut score, mut args) = match split_driver(path) {
+        (Some(p), args) => {
+            if let Some(c) = test_compiler(&p) {
+                return (p, c, args);
+            } else {
+                (Cow::Owned(p), CompilerType::Other, 0, args)
+            }
         }
-    } else {
-        (Path::new("gcc").into(), CompilerType::Gcc, -2)
+        (None, _) => (Path::new("gcc").into(), CompilerType::Gcc, -2, vec![]),
     };
 
-    let str2path = |s| Cow::Borrowed(Path::new(s));
-    let string2path = |s| Cow::Owned(PathBuf::from(s));
+    let str2path = |s| (Cow::Borrowed(Path::new(s)), vec![]);
+    let env2path = |s: String| {
+        let (path, args) = split_driver(Some(s.into()));
+        path.map(|p| (Cow::Owned(p), args))
+    };
 
     let c = env::var("CC")
         .into_iter()
-        .map(string2path)
+        .filter_map(env2path)
         .chain(["cc", "gcc", "clang"].into_iter().map(str2path));
     let cpp = env::var("CXX")
         .into_iter()
-        .map(string2path)
+        .filter_map(env2path)
         .chain(["c++", "g++", "clang++"].into_iter().map(str2path));
     let mix = ["cl"].into_iter().map(str2path);
 
@@ -160,20 +402,59 @@ fn find_compiler(
         Language::Cpp => cpp.chain(mix).chain(c),
     };
 
-    for c in comps {
+    for (c, a) in comps {
         let t = test_compiler(&c);
         let s = score_compiler(t, lng);
         if s > score {
             path = c;
             typ = t.unwrap_or(CompilerType::Other);
             score = s;
+            args = a;
             if s == MAX_SCORE {
-                return (path.into_owned(), typ);
+                return (path.into_owned(), typ, args);
             }
         }
     }
 
-    (path.into_owned(), typ)
+    (path.into_owned(), typ, args)
+}
+
+/// Splits the compiler command (from `cc`, `cpp`, `CC` or `CXX`) to the
+/// binary and the arguments after it, so that e.g. `gcc -m32` is `gcc`
+/// with `-m32`. Existing file is always used as the binary, so paths with
+/// spaces don't have to be quoted.
+fn split_driver(cmd: Option<PathBuf>) -> (Option<PathBuf>, Vec<String>) {
+    let Some(cmd) = cmd else {
+        return (None, vec![]);
+    };
+    if cmd.is_file() {
+        return (Some(cmd), vec![]);
+    }
+    let Some(mut args) = cmd.to_str().and_then(|c| split_shell(c).ok()) else {
+        return (Some(cmd), vec![]);
+    };
+    if args.is_empty() {
+        return (None, vec![]);
+    }
+    let bin = args.remove(0);
+    if !args.is_empty() {
+        debug!("Using {bin:?} with the arguments {args:?}");
+    }
+    (Some(bin.into()), args)
+}
+
+/// Adds the arguments given after the binary of the compiler to the
+/// configuration.
+fn with_driver_args(
+    conf: &Config,
+    driver_args: Vec<String>,
+) -> Cow<'_, Config> {
+    if driver_args.is_empty() {
+        return Cow::Borrowed(conf);
+    }
+    let mut conf = conf.clone();
+    conf.driver_args = driver_args;
+    Cow::Owned(conf)
 }
 
 fn score_compiler(comp: Option<CompilerType>, lng: Language) -> i32 {
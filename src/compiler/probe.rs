@@ -0,0 +1,125 @@
+use std::{fs, io, path::Path};
+
+use log::debug;
+
+use crate::paths;
+
+use super::config::Config;
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Gets the result of probing the toolchain. The results are cached in the
+/// bin directory for each compiler (with the arguments of the driver) and
+/// probe, so the probe runs only if there is no cached result. Probes that
+/// fail to run are not cached and they return [`None`].
+pub fn cached<F>(
+    conf: &Config,
+    bin: &Path,
+    probe: &str,
+    f: F,
+) -> Option<String>
+where
+    F: FnOnce() -> io::Result<String>,
+{
+    let cmd = if conf.driver_args.is_empty() {
+        bin.to_owned()
+    } else {
+        format!("{} {}", bin.to_string_lossy(), conf.driver_args.join(" "))
+            .into()
+    };
+    cached_in(&conf.bin_root.join(paths::PROBES), &cmd, probe, f)
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Gets the result of the probe cached in the file `cache`, see
+/// [`cached`].
+fn cached_in<F>(cache: &Path, bin: &Path, probe: &str, f: F) -> Option<String>
+where
+    F: FnOnce() -> io::Result<String>,
+{
+    let key = bin.to_string_lossy();
+    let cached = fs::read_to_string(cache).unwrap_or_default();
+    let hit = cached
+        .lines()
+        .filter_map(|l| {
+            let (b, rest) = l.split_once('\t')?;
+            let (p, res) = rest.split_once('\t')?;
+            Some((b, p, res))
+        })
+        .find(|(b, p, _)| *b == key && *p == probe);
+    if let Some((_, _, res)) = hit {
+        debug!("Result of probe `{probe}` for {bin:?} is cached");
+        return Some(res.to_owned());
+    }
+
+    let res = match f() {
+        Ok(r) => r,
+        Err(e) => {
+            debug!("Failed to run probe `{probe}` with {bin:?}: {e}");
+            return None;
+        }
+    };
+
+    let mut cached = cached;
+    cached.push_str(&format!("{key}\t{probe}\t{res}\n"));
+    let dir = cache.parent().unwrap_or(Path::new(""));
+    if let Err(e) =
+        fs::create_dir_all(dir).and_then(|_| fs::write(cache, cached))
+    {
+        debug!("Failed to cache result of probe `{probe}`: {e}");
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn result_is_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("bin").join(paths::PROBES);
+        let gcc = Path::new("gcc");
+        let res = cached_in(&cache, gcc, "asan", || Ok("0".into()));
+        assert_eq!(res.as_deref(), Some("0"));
+        let res = cached_in(&cache, gcc, "asan", || unreachable!());
+        assert_eq!(res.as_deref(), Some("0"));
+
+        // each compiler and probe has its own result
+        let res =
+            cached_in(&cache, Path::new("clang"), "asan", || Ok("1".into()));
+        assert_eq!(res.as_deref(), Some("1"));
+        let res = cached_in(&cache, gcc, "other", || Ok("x y".into()));
+        assert_eq!(res.as_deref(), Some("x y"));
+        assert_eq!(
+            fs::read_to_string(&cache).unwrap(),
+            "gcc\tasan\t0\nclang\tasan\t1\ngcc\tother\tx y\n"
+        );
+    }
+
+    #[test]
+    fn failed_probe_is_not_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join(paths::PROBES);
+        let gcc = Path::new("gcc");
+        let res = cached_in(&cache, gcc, "asan", || Err(io::Error::other("")));
+        assert_eq!(res, None);
+        let res = cached_in(&cache, gcc, "asan", || Ok("1".into()));
+        assert_eq!(res.as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn injected_result_is_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join(paths::PROBES);
+        fs::write(&cache, "gcc\tasan\t0\n").unwrap();
+        let res =
+            cached_in(&cache, Path::new("gcc"), "asan", || unreachable!());
+        assert_eq!(res.as_deref(), Some("0"));
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    io,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use log::debug;
+use termal::printcln;
+
+use crate::file_type::Language;
+
+use super::{config::Config, probe};
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Gets the compiler arguments for the warnings from the configuration. The
+/// warnings that the compiler doesn't know for the language (e.g. gcc and
+/// clang have different warnings) are not used, they are returned as the
+/// second value.
+pub fn args(
+    bin: &Path,
+    conf: &Config,
+    lang: Language,
+) -> (Vec<String>, Vec<String>) {
+    let warn = conf.warn.iter().map(|w| (w, format!("-W{w}")));
+    let no_warn = conf.no_warn.iter().map(|w| (w, format!("-Wno-{w}")));
+    let flags: Vec<_> = warn.chain(no_warn).collect();
+    if flags.is_empty() {
+        return (vec![], vec![]);
+    }
+
+    // the compiler may not report unknown `-Wno-` flags, so the positive
+    // form is checked instead
+    let names: Vec<_> = flags.iter().map(|(n, _)| n.as_str()).collect();
+    let probe_name = format!("warn {} {}", lang_name(lang), names.join(" "));
+    let unknown = probe::cached(conf, bin, &probe_name, || {
+        unknown_warnings(bin, &conf.driver_args, lang, &names)
+            .map(|u| u.join(" "))
+    })
+    .unwrap_or_default();
+    let unknown: Vec<_> = unknown.split_whitespace().collect();
+
+    let (unsupported, supported): (Vec<_>, Vec<_>) = flags
+        .into_iter()
+        .partition(|(n, _)| unknown.contains(&n.as_str()));
+    (
+        supported.into_iter().map(|(_, f)| f).collect(),
+        unsupported.into_iter().map(|(_, f)| f).collect(),
+    )
+}
+
+/// Reports the warning flags that the compiler doesn't support.
+pub fn report(bin: &Path, unsupported: &[String]) {
+    for f in unsupported {
+        printcln!(
+            "{'y bold}    Warning{'_} {} doesn't support `{}`, it is not used",
+            bin.to_string_lossy(),
+            f
+        );
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Runs the compiler with all the warnings on empty input and finds the
+/// warnings that it reports as unknown or invalid for the language. `args`
+/// are the arguments of the driver.
+fn unknown_warnings(
+    bin: &Path,
+    args: &[String],
+    lang: Language,
+    names: &[&str],
+) -> io::Result<Vec<String>> {
+    let mut unknown = vec![];
+    // the driver may stop on unknown flags before the compiler proper
+    // reports the flags invalid for the language, so run it again without
+    // the unknown flags
+    loop {
+        let rest: Vec<_> =
+            names.iter().filter(|n| !unknown.contains(*n)).collect();
+        if rest.is_empty() {
+            return Ok(unknown.into_iter().map(Into::into).collect());
+        }
+
+        debug!("Probing warnings {rest:?} with {bin:?}");
+        let out = Command::new(bin)
+            .args(args)
+            .args(["-fsyntax-only", "-x", lang_name(lang), "-"])
+            .args(rest.iter().map(|n| format!("-W{n}")))
+            // the messages must not be translated and must use ascii quotes
+            .env("LC_ALL", "C")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()?;
+        let stderr = String::from_utf8_lossy(&out.stderr);
+
+        let found: Vec<_> = rest
+            .into_iter()
+            .filter(|n| stderr.contains(&format!("'-W{n}'")))
+            .collect();
+        if found.is_empty() {
+            return Ok(unknown.into_iter().map(Into::into).collect());
+        }
+        unknown.extend(found.into_iter().copied());
+    }
+}
+
+/// Gets the name of the language for the `-x` argument.
+fn lang_name(lang: Language) -> &'static str {
+    match lang {
+        Language::C => "c",
+        Language::Cpp => "c++",
+    }
+}
@@ -1,15 +1,42 @@
-use std::path::PathBuf;
+use std::{env, path::PathBuf, time::Duration};
 
-use crate::compiler;
+use crate::{
+    compiler, dep_rules::DepRule, dependency::Pairing, file_type::ExtConfig,
+    version::Version,
+};
 
 pub struct Config {
+    /// File from which the configuration was loaded
+    pub file: Option<PathBuf>,
+    /// User configuration file that is merged into the configuration. It
+    /// doesn't have to exist, its creation also changes the build.
+    pub user_file: Option<PathBuf>,
     pub project: Project,
     pub debug_build: Build,
     pub release_build: Build,
+    pub conditional_sources: Vec<ConditionalSource>,
+    pub run: Run,
+    /// Files that are embedded into the binary
+    pub embed: Vec<PathBuf>,
+    /// Path that points to the binary of the last built profile
+    pub stable_target: Option<PathBuf>,
 }
 
 pub struct Project {
     pub name: String,
+    pub version: Option<Version>,
+    pub extensions: ExtConfig,
+    /// Rules for detecting changes of dependencies, the first matching rule
+    /// is used
+    pub dep_rules: Vec<DepRule>,
+    /// Skip the source files that are ignored by git
+    pub respect_gitignore: bool,
+    /// Compile only the sources reachable from this file
+    pub entry: Option<PathBuf>,
+    /// How headers are paired with sources when [`Self::entry`] is set
+    pub entry_pairing: Pairing,
+    /// Patterns of files whose diagnostics are not shown
+    pub suppress_diagnostics_from: Vec<String>,
 }
 
 pub struct Build {
@@ -17,6 +44,202 @@ pub struct Build {
     pub cc: Option<PathBuf>,
     pub cpp: Option<PathBuf>,
     pub compiler_conf: CompilerConfig,
+    pub metadata: Metadata,
+    /// Write makefile dependency files (`.d`) next to the object files
+    pub emit_depfiles: bool,
+    /// Reuse the objects compiled from the same inputs from the shared
+    /// object cache
+    pub object_cache: bool,
+    /// Max time that a single compile/link job may run
+    pub job_timeout: Option<Duration>,
+    /// Prebuilt objects that are linked to the executable after the objects
+    /// of the project
+    pub extra_objects: Vec<PathBuf>,
+    /// Compile the sources in batches, [`None`] if unity build is disabled
+    pub unity: Option<Unity>,
+    /// Glob patterns of the sources that are not compiled in this profile
+    pub exclude_sources: Vec<String>,
+    /// Glob patterns of the sources that are compiled in this profile even
+    /// if they match [`Self::exclude_sources`]
+    pub include_sources: Vec<String>,
+}
+
+/// Unity (jumbo) build, the sources are included into batches that are
+/// compiled instead of the sources.
+pub struct Unity {
+    /// Max number of sources in single batch
+    pub batch_size: usize,
+    /// Sources that are always compiled alone
+    pub exclude: Vec<PathBuf>,
+}
+
+/// Information about the build that is passed as defines to the selected
+/// files.
+#[derive(Default)]
+pub struct Metadata {
+    /// Define `BUILD_GIT_HASH` with the short hash of the git commit
+    pub git_hash: bool,
+    /// Define `BUILD_GIT_DIRTY` as 1 if there are uncommited changes
+    pub git_dirty: bool,
+    /// Define `BUILD_DATE` with the time of the build
+    pub timestamp: bool,
+    /// Define `BUILD_PROFILE` with the name of the build profile
+    pub profile: bool,
+    /// Files that get the defines. They are rebuilt with every build.
+    pub files: Vec<PathBuf>,
+}
+
+pub struct Run {
+    /// Command used to run the target, the target and its arguments are
+    /// appended to it.
+    pub runner: Option<String>,
+}
+
+/// Source files that are compiled only when the condition is met.
+pub struct ConditionalSource {
+    pub files: Vec<PathBuf>,
+    /// Name of the OS (e.g. `linux`) or OS family (e.g. `unix`)
+    pub platform: Option<String>,
+    /// Name of define that must be defined
+    pub define: Option<String>,
 }
 
 pub type CompilerConfig = compiler::config::Config;
+
+impl Project {
+    /// Gets the defines with the name and version of the project that are
+    /// passed to every compiled file:
+    /// - `CCPP_PROJECT_NAME`: string with the name of the project
+    /// - `CCPP_PROJECT_VERSION`: string with the whole version
+    /// - `CCPP_PROJECT_VERSION_MAJOR`, `CCPP_PROJECT_VERSION_MINOR` and
+    ///   `CCPP_PROJECT_VERSION_PATCH`: the numbers of the version
+    ///
+    /// The version defines are present only if the version is set.
+    pub fn defines(&self) -> Vec<(String, Option<String>)> {
+        let def = |n: &str, v: String| (n.to_owned(), Some(v));
+        let mut res = vec![def("CCPP_PROJECT_NAME", c_string(&self.name))];
+        if let Some(v) = &self.version {
+            res.extend([
+                def("CCPP_PROJECT_VERSION", c_string(&v.to_string())),
+                def("CCPP_PROJECT_VERSION_MAJOR", v.major.to_string()),
+                def("CCPP_PROJECT_VERSION_MINOR", v.minor.to_string()),
+                def("CCPP_PROJECT_VERSION_PATCH", v.patch.to_string()),
+            ]);
+        }
+        res
+    }
+}
+
+impl ConditionalSource {
+    /// Checks whether the condition is met with the compiler configuration.
+    /// The define may be in `defines` or passed as `-D` in the arguments.
+    pub fn matches(&self, conf: &CompilerConfig) -> bool {
+        let args = conf
+            .args
+            .iter()
+            .chain(&conf.compile_args)
+            .chain(&conf.c_args)
+            .chain(&conf.cpp_args);
+        let defines = conf.defines.iter().map(|(n, _)| n.as_str());
+        self.matches_defines(defines.chain(arg_defines(args)))
+    }
+
+    /// Checks whether the condition is met with the names of the defines.
+    fn matches_defines<'a, I>(&self, defines: I) -> bool
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let platform = self
+            .platform
+            .as_ref()
+            .is_none_or(|p| p == env::consts::OS || p == env::consts::FAMILY);
+        let define = self
+            .define
+            .as_ref()
+            .is_none_or(|d| defines.into_iter().any(|n| n == d));
+        platform && define
+    }
+}
+
+/// Gets the names of the defines in the compiler arguments (`-DNAME`,
+/// `-DNAME=value` or `-D NAME`).
+fn arg_defines<'a, I>(args: I) -> Vec<&'a str>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let mut res = vec![];
+    let mut args = args.into_iter();
+    while let Some(a) = args.next() {
+        let def = match a.strip_prefix("-D") {
+            Some("") => args.next().map(|a| a.as_str()),
+            d => d,
+        };
+        if let Some(d) = def {
+            res.push(d.split_once('=').map_or(d, |(n, _)| n));
+        }
+    }
+    res
+}
+
+/// Creates C string literal with the given value.
+fn c_string(s: &str) -> String {
+    let mut res = String::from('"');
+    for c in s.chars() {
+        if matches!(c, '"' | '\\') {
+            res.push('\\');
+        }
+        res.push(c);
+    }
+    res.push('"');
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cond(
+        platform: Option<&str>,
+        define: Option<&str>,
+    ) -> ConditionalSource {
+        ConditionalSource {
+            files: vec![],
+            platform: platform.map(|p| p.to_owned()),
+            define: define.map(|d| d.to_owned()),
+        }
+    }
+
+    #[test]
+    fn conditional_source_matches_platform() {
+        assert!(cond(Some(env::consts::OS), None).matches_defines([]));
+        assert!(cond(Some(env::consts::FAMILY), None).matches_defines([]));
+        assert!(!cond(Some("plan9"), None).matches_defines([]));
+        assert!(cond(None, None).matches_defines([]));
+    }
+
+    #[test]
+    fn conditional_source_matches_define() {
+        let c = cond(None, Some("TRACE"));
+        assert!(c.matches_defines(["DEBUG", "TRACE"]));
+        assert!(!c.matches_defines(["DEBUG"]));
+        assert!(!c.matches_defines(["TRACE_ALL"]));
+    }
+
+    #[test]
+    fn conditional_source_matches_both() {
+        let c = cond(Some(env::consts::OS), Some("TRACE"));
+        assert!(c.matches_defines(["TRACE"]));
+        assert!(!c.matches_defines([]));
+        let c = cond(Some("plan9"), Some("TRACE"));
+        assert!(!c.matches_defines(["TRACE"]));
+    }
+
+    #[test]
+    fn defines_in_args() {
+        let args: Vec<_> = ["-DA", "-DB=1", "-O2", "-D", "C", "-I", "-DD"]
+            .into_iter()
+            .map(|a| a.to_owned())
+            .collect();
+        assert_eq!(arg_defines(&args), ["A", "B", "C", "D"]);
+    }
+}
@@ -0,0 +1,203 @@
+use std::path::Path;
+
+use log::debug;
+
+use crate::{err::Result, include_deps::significant_content};
+
+/// Functions defined in a source file that are significant for the tests and
+/// the unity build.
+#[derive(Debug, Default)]
+pub struct Definitions {
+    /// Names of the test functions (`void test_*(void)`)
+    pub tests: Vec<String>,
+    /// The file defines `main`
+    pub main: bool,
+}
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Finds the definitions of the test functions and of `main` in the source.
+/// Only the definitions at file scope are found, static test functions are
+/// not tests.
+pub fn scan(file: &Path) -> Result<Definitions> {
+    let content = significant_content(file)?;
+    let toks = tokens(&content);
+    let mut res = Definitions::default();
+
+    let mut depth = 0_usize;
+    for (i, tok) in toks.iter().enumerate() {
+        match *tok {
+            "{" => depth += 1,
+            "}" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        if depth != 0 {
+            continue;
+        }
+
+        let rest = &toks[i..];
+        let prev = i.checked_sub(1).map(|p| toks[p]);
+        if *tok == "void"
+            && !is_static(&toks[..i])
+            && rest.get(1).is_some_and(|n| n.starts_with("test_"))
+            && (rest[2..].starts_with(&["(", "void", ")", "{"])
+                || rest[2..].starts_with(&["(", ")", "{"]))
+        {
+            debug!("Found test {} in {file:?}", rest[1]);
+            res.tests.push(rest[1].to_owned());
+        } else if *tok == "main"
+            && prev.is_some_and(is_identifier)
+            && rest.get(1) == Some(&"(")
+        {
+            // skip the parameters, declaration ends with `;`
+            let close = rest.iter().position(|t| *t == ")");
+            res.main |= close.is_some_and(|c| rest.get(c + 1) == Some(&"{"));
+        }
+    }
+
+    Ok(res)
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Splits the significant content (see [`significant_content`]) to
+/// identifiers and single punctuation characters. Preprocessor directives
+/// are skipped and literals are replaced with `""`.
+fn tokens(content: &str) -> Vec<&str> {
+    let mut res = vec![];
+    let mut line_start = true;
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\n' => {
+                line_start = true;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '#' if line_start => {
+                while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+            }
+            '\'' | '"' => {
+                while let Some((_, l)) = chars.next() {
+                    if l == '\\' {
+                        chars.next();
+                    } else if l == c {
+                        break;
+                    }
+                }
+                res.push("\"\"");
+            }
+            c if c == '_' || c.is_alphanumeric() => {
+                let mut end = i + c.len_utf8();
+                while let Some((j, n)) =
+                    chars.next_if(|(_, n)| *n == '_' || n.is_alphanumeric())
+                {
+                    end = j + n.len_utf8();
+                }
+                res.push(&content[i..end]);
+            }
+            _ => res.push(&content[i..i + c.len_utf8()]),
+        }
+        line_start = false;
+    }
+
+    res
+}
+
+/// Checks whether the declaration that ends with `specifiers` is static
+/// (e.g. `static inline`).
+fn is_static(specifiers: &[&str]) -> bool {
+    specifiers
+        .iter()
+        .rev()
+        .take_while(|t| !matches!(**t, ";" | "{" | "}"))
+        .any(|t| *t == "static")
+}
+
+fn is_identifier(tok: &str) -> bool {
+    tok.starts_with(|c: char| c == '_' || c.is_alphabetic())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn scan_str(src: &str) -> Definitions {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("test.c");
+        fs::write(&file, src).unwrap();
+        scan(&file).unwrap()
+    }
+
+    #[test]
+    fn tests_are_found() {
+        let defs = scan_str(
+            "void test_a(void) {}\n\
+            void test_b() { CCPP_ASSERT(1); }\n\
+            void\ntest_c(void)\n{\n}\n",
+        );
+        assert_eq!(defs.tests, ["test_a", "test_b", "test_c"]);
+        assert!(!defs.main);
+    }
+
+    #[test]
+    fn static_functions_are_not_tests() {
+        let defs = scan_str(
+            "static void test_a(void) {}\n\
+            static inline void test_b(void) {}\n\
+            inline static void test_c(void) {}\n\
+            int x; void test_d(void) {}\n",
+        );
+        assert_eq!(defs.tests, ["test_d"]);
+    }
+
+    #[test]
+    fn prototypes_are_not_tests() {
+        let defs = scan_str(
+            "void test_a(void);\nvoid test_b();\n\
+            int test_c(void) { return 0; }\n\
+            void test_d(int x) {}\n\
+            void helper(void) { void test_e(void); }\n",
+        );
+        assert!(defs.tests.is_empty(), "{:?}", defs.tests);
+    }
+
+    #[test]
+    fn main_definition() {
+        assert!(!scan_str("int main(void);\n").main);
+        assert!(!scan_str("int f(void) { return main(); }\n").main);
+        assert!(scan_str("int main(void) { return 0; }\n").main);
+        assert!(
+            scan_str("int main(int argc, char **argv)\n{\n    return 0;\n}\n")
+                .main
+        );
+    }
+
+    #[test]
+    fn braces_in_literals_are_ignored() {
+        let defs = scan_str(
+            "const char *open = \"{\";\n\
+            char close = '}';\n\
+            const char *esc = \"\\\" {\";\n\
+            void test_a(void) { puts(\"}\"); }\n\
+            // void test_b(void) {}\n\
+            void test_c(void) {}\n",
+        );
+        assert_eq!(defs.tests, ["test_a", "test_c"]);
+    }
+
+    #[test]
+    fn directives_are_skipped() {
+        let defs = scan_str(
+            "#define OPEN {\n#include \"x.h\"\nvoid test_a(void) {}\n",
+        );
+        assert_eq!(defs.tests, ["test_a"]);
+    }
+}
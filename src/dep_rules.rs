@@ -0,0 +1,251 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    env, fs,
+    hash::Hasher,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    err::{Error, Result, ResultExt},
+    file_type::{FileState, FileType},
+    glob,
+    include_deps::significant_content,
+    stable_hash::StableHasher,
+};
+
+/// How changes of dependency are detected.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compare {
+    /// The file changed if it is newer than the target
+    Mtime,
+    /// The file changed if its content is different than in the last build
+    Content,
+    /// The file changed if its content without comments and with normalized
+    /// whitespace is different than in the last build
+    Stripped,
+    /// Changes of the file are ignored
+    Ignore,
+}
+
+/// Rule for dependency files that match the pattern.
+#[derive(Debug, Clone)]
+pub struct DepRule {
+    /// Glob pattern relative to the project, `*` matches anything except
+    /// `/` and `**` matches anything.
+    pub pattern: String,
+    pub compare: Compare,
+    /// The rule applies only to sources and headers
+    pub code_only: bool,
+}
+
+/// Rules for detecting changes of dependencies with the hashes of files
+/// compared by content.
+#[derive(Default)]
+pub struct DepRules {
+    /// Rules with patterns converted to absolute paths
+    rules: Vec<DepRule>,
+    /// File where the hashes are stored
+    hash_file: Option<PathBuf>,
+    /// Hashes from the last builds. The hash is stored for each target and
+    /// its dependency because the target may not have been rebuilt when the
+    /// hash was recorded for another target.
+    recorded: HashMap<(PathBuf, PathBuf), u64>,
+    /// Targets and their dependencies compared by content that were seen
+    /// during this build and how they are compared
+    seen: RefCell<HashMap<(PathBuf, PathBuf), Compare>>,
+}
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+impl DepRules {
+    /// Creates the rules. The hashes for comparing by content are loaded
+    /// from and saved to `hash_file`.
+    pub fn new(rules: &[DepRule], hash_file: PathBuf) -> Result<Self> {
+        if rules.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let cwd = env::current_dir()?.canonicalize()?;
+        let rules = rules
+            .iter()
+            .map(|r| DepRule {
+                pattern: cwd
+                    .join(&r.pattern)
+                    .to_string_lossy()
+                    .replace('\\', "/"),
+                ..r.clone()
+            })
+            .collect();
+
+        let recorded = match fs::read_to_string(&hash_file) {
+            Ok(s) => parse_hashes(&s),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(Error::io_at("read", &hash_file, e)),
+        };
+
+        Ok(Self {
+            rules,
+            hash_file: Some(hash_file),
+            recorded,
+            seen: Default::default(),
+        })
+    }
+
+    /// Gets how changes of the dependency `file` of `target` are detected.
+    pub fn compare(&self, target: &Path, file: &Path) -> Compare {
+        if self.rules.is_empty() {
+            return Compare::Mtime;
+        }
+
+        let path = absolute(file);
+        let path_str = path.to_string_lossy().replace('\\', "/");
+        let is_code = file
+            .extension()
+            .and_then(FileType::from_ext)
+            .is_some_and(|t| {
+                matches!(t.state, FileState::Source | FileState::Header)
+            });
+        let compare = self
+            .rules
+            .iter()
+            .filter(|r| is_code || !r.code_only)
+            .find(|r| glob::matches(&r.pattern, &path_str))
+            .map_or(Compare::Mtime, |r| r.compare);
+
+        if compare.is_content() {
+            self.seen
+                .borrow_mut()
+                .insert((absolute(target), path), compare);
+        }
+        compare
+    }
+
+    /// Checks whether the content of the dependency `file` is the same as
+    /// when `target` was last built. Returns false if the file has no
+    /// recorded hash for the target.
+    pub fn content_unchanged(
+        &self,
+        target: &Path,
+        file: &Path,
+        compare: Compare,
+    ) -> Result<bool> {
+        let key = (absolute(target), absolute(file));
+        let recorded = match self.recorded.get(&key) {
+            Some(h) => *h,
+            None => return Ok(false),
+        };
+        Ok(hash_file(file, compare)? == recorded)
+    }
+
+    /// Removes the saved hashes so that they are not used if the build
+    /// fails. The loaded hashes are still used in this build.
+    pub fn invalidate(&self) -> Result<()> {
+        let Some(path) = &self.hash_file else {
+            return Ok(());
+        };
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(Error::io_at("remove", path, e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Saves the hashes of all the files compared by content that were seen
+    /// during the build. All the seen targets must be up to date.
+    pub fn save(&mut self) -> Result<()> {
+        let Some(path) = &self.hash_file else {
+            return Ok(());
+        };
+
+        let mut hashes = HashMap::new();
+        for ((target, f), compare) in self.seen.borrow_mut().drain() {
+            let hash = *hashes
+                .entry((f.clone(), compare))
+                .or_insert_with(|| hash_file(&f, compare).ok());
+            match hash {
+                Some(h) => _ = self.recorded.insert((target, f), h),
+                // the file may have been removed
+                None => _ = self.recorded.remove(&(target, f)),
+            }
+        }
+
+        let mut res = String::new();
+        for ((target, f), h) in &self.recorded {
+            res.push_str(&format!(
+                "{h:016x} {}\t{}\n",
+                target.to_string_lossy(),
+                f.to_string_lossy()
+            ));
+        }
+        debug!("Saving content hashes to {path:?}");
+        fs::write(path, res).with_path("write", path)?;
+        Ok(())
+    }
+}
+
+impl Compare {
+    /// Checks whether the files are compared by their content.
+    pub fn is_content(self) -> bool {
+        matches!(self, Self::Content | Self::Stripped)
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+fn hash_file(file: &Path, compare: Compare) -> Result<u64> {
+    let mut hasher = StableHasher::new();
+    if compare == Compare::Stripped {
+        // the hashes of the same file must differ if the comparison changes
+        hasher.write_u8(1);
+        hasher.write(significant_content(file)?.as_bytes());
+    } else {
+        hasher.write(&fs::read(file).with_path("read", file)?);
+    }
+    Ok(hasher.finish())
+}
+
+/// Parses lines in the format `<hash> <target>\t<dependency>`.
+fn parse_hashes(s: &str) -> HashMap<(PathBuf, PathBuf), u64> {
+    s.lines()
+        .filter_map(|l| {
+            let (hash, paths) = l.split_once(' ')?;
+            let (target, dep) = paths.split_once('\t')?;
+            let hash = u64::from_str_radix(hash, 16).ok()?;
+            Some(((target.into(), dep.into()), hash))
+        })
+        .collect()
+}
+
+/// Makes the path absolute and removes `.` and `..` from it without
+/// resolving symlinks.
+fn absolute(file: &Path) -> PathBuf {
+    let file = if file.is_absolute() {
+        file.to_owned()
+    } else {
+        match env::current_dir().and_then(|d| d.canonicalize()) {
+            Ok(d) => d.join(file),
+            Err(_) => file.to_owned(),
+        }
+    };
+
+    let mut res = PathBuf::new();
+    for c in file.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => _ = res.pop(),
+            c => res.push(c),
+        }
+    }
+    res
+}
@@ -7,10 +7,15 @@ use std::{
     rc::Rc,
 };
 
+use log::debug;
+use serde::{Deserialize, Serialize};
+use termal::printcln;
+
 use crate::{
-    err::{Error, Result},
-    file_type::FileType,
-    include_deps::get_included_files,
+    dep_rules::{Compare, DepRules},
+    err::{Error, Result, ResultExt},
+    file_type::{FileState, FileType},
+    include_deps::{get_directives, Directive, SourceLoc},
 };
 
 #[derive(Debug, Clone)]
@@ -21,6 +26,12 @@ pub struct Dependency {
     pub direct: Vec<DepFile>,
     /// Indirect dependencies of [`Self::file`]
     pub indirect: HashSet<DepFile>,
+    /// Included files that don't exist. The file must be rebuilt when any
+    /// of them is created.
+    pub missing: HashSet<PathBuf>,
+    /// Libraries requested with `#pragma comment(lib, "name")` by the
+    /// dependencies
+    pub libs: HashSet<String>,
 }
 
 #[derive(Clone, Eq, Debug)]
@@ -67,11 +78,40 @@ impl From<PathBuf> for DepFile {
 
 pub struct DepCache {
     cache: HashMap<DepFile, Dependency>,
+    /// Files that depend on the key file, inverse of the cached indirect
+    /// dependencies
+    rdeps: HashMap<DepFile, HashSet<DepFile>>,
+    /// Dependencies whose existence was already checked
+    checked: HashSet<DepFile>,
+    /// Files in the circular includes that were already reported, sorted
+    cycles: HashSet<Vec<Rc<Path>>>,
+}
+
+/// How headers are paired with the sources that implement them.
+#[derive(
+    Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Pairing {
+    /// Sources with the same stem as the header anywhere in the project
+    #[default]
+    Stem,
+    /// Sources with the same stem in the same directory as the header
+    SameDir,
 }
 
-enum DepDirection {
-    Same(DepFile),
-    LastDeeper(DepFile),
+/// File whose includes are being scanned.
+struct ScanFrame {
+    dep: Dependency,
+    /// Includes that weren't examined yet
+    rest: Vec<DepFile>,
+    /// Lowest index of file on the scan stack that this file (indirectly)
+    /// includes. If it is below this file, the file is in a cycle and its
+    /// dependencies are complete only when that file is done.
+    low: usize,
+    /// Files in a cycle with this file, their dependencies are complete
+    /// when this file is done
+    members: Vec<DepFile>,
 }
 
 //===========================================================================//
@@ -88,33 +128,138 @@ impl Dependency {
             file,
             direct,
             indirect,
+            missing: HashSet::new(),
+            libs: HashSet::new(),
+        }
+    }
+
+    /// Finds where the missing includes are included. Returns the including
+    /// file, the location of the include and the included path as it is
+    /// written. The includes of the direct dependencies come first.
+    pub fn missing_includes(&self) -> Vec<(PathBuf, SourceLoc, PathBuf)> {
+        let mut res = vec![];
+        if self.missing.is_empty() {
+            return res;
+        }
+        let mut indirect: Vec<_> = self.indirect.iter().collect();
+        indirect.sort_by_key(|f| &f.path);
+        for f in self.direct.iter().chain(indirect) {
+            let Some(parent) = f.parent() else {
+                continue;
+            };
+            // the files were already scanned, the locations are only
+            // informative
+            let Ok(dirs) = get_directives(f.clone()) else {
+                continue;
+            };
+            for d in dirs {
+                match d {
+                    Directive::Include(inc)
+                        if inc.relative
+                            && self
+                                .missing
+                                .contains(&parent.join(&inc.path)) =>
+                    {
+                        res.push((f.to_path_buf(), inc.loc, inc.path))
+                    }
+                    _ => {}
+                }
+            }
         }
+        res
     }
 
     pub fn is_up_to_date(&self) -> Result<bool> {
+        self.is_up_to_date_with(&DepRules::default())
+    }
+
+    /// Checks whether the file is up to date. Changes of the dependencies
+    /// are detected by the given rules.
+    pub fn is_up_to_date_with(&self, rules: &DepRules) -> Result<bool> {
+        match self.dirty_reason(rules)? {
+            Some(r) => {
+                debug!("{:?} is dirty: {r}", self.file.path);
+                Ok(false)
+            }
+            None => {
+                debug!("{:?} is up to date", self.file.path);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Gets the reason why the file is not up to date, or [`None`] if it is
+    /// up to date. Changes of the dependencies are detected by the given
+    /// rules.
+    pub fn dirty_reason(&self, rules: &DepRules) -> Result<Option<String>> {
+        // get the rules first so that the files compared by content are
+        // recorded even if the file is dirty
+        let deps: Vec<_> = self
+            .direct
+            .iter()
+            .chain(self.indirect.iter())
+            .map(|d| (d, rules.compare(&self.file, d)))
+            .collect();
+
         if !self.file.exists() {
-            return Ok(false);
+            return Ok(Some("it doesn't exist".to_owned()));
         }
 
         // get the last modified date, this may not be supported, in that case
-        // always return false
-        let last_mod = match self.file.metadata()?.modified() {
+        // the file is always dirty
+        let meta = self.file.metadata().with_path("read", &self.file)?;
+        let last_mod = match meta.modified() {
             Ok(dt) => dt,
             Err(e) if e.kind() == io::ErrorKind::Unsupported => {
-                return Ok(false);
+                return Ok(Some(
+                    "modification time is not supported".to_owned(),
+                ));
             }
-            e => e?,
+            e => e.with_path("read", &self.file)?,
         };
 
+        if let Some(m) = self.missing.iter().find(|m| m.exists()) {
+            return Ok(Some(format!(
+                "missing include {} was created",
+                m.to_string_lossy()
+            )));
+        }
+
         // need to update if dependency is newer than file
-        for dep in self.direct.iter().chain(self.indirect.iter()) {
-            let dep_mod = dep.metadata()?.modified()?;
-            if dep_mod > last_mod {
-                return Ok(false);
+        for (dep, compare) in deps {
+            if compare == Compare::Ignore {
+                continue;
+            }
+            // the compiler decides whether the vanished file is needed
+            let dep_mod = match dep.metadata() {
+                Ok(m) => m.modified().with_path("read", dep)?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    return Ok(Some(format!(
+                        "{} doesn't exist",
+                        dep.to_string_lossy()
+                    )));
+                }
+                Err(e) => return Err(Error::io_at("read", dep, e)),
+            };
+            if dep_mod <= last_mod {
+                continue;
+            }
+            if compare.is_content()
+                && rules.content_unchanged(&self.file, dep, compare)?
+            {
+                debug!(
+                    "{:?} is newer than {:?} but its content is the same",
+                    dep.path, self.file.path
+                );
+            } else {
+                return Ok(Some(format!(
+                    "{} is newer",
+                    dep.to_string_lossy()
+                )));
             }
         }
 
-        Ok(true)
+        Ok(None)
     }
 }
 
@@ -122,103 +267,581 @@ impl DepCache {
     pub fn new() -> Self {
         Self {
             cache: HashMap::new(),
+            rdeps: HashMap::new(),
+            checked: HashSet::new(),
+            cycles: HashSet::new(),
         }
     }
 
-    /// Finds the indirect dependencies for the given dependency file.
+    /// Iterates over all the cached dependencies.
+    pub fn iter(&self) -> impl Iterator<Item = &Dependency> {
+        self.cache.values()
+    }
+
+    /// Gets the cached files that depend on the file, directly or
+    /// indirectly.
+    pub fn reverse_deps(&self, file: &Path) -> Vec<DepFile> {
+        let file: DepFile = file.to_path_buf().into();
+        self.rdeps
+            .get(&file)
+            .map(|r| r.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Gets the cached dependencies of the file.
+    pub fn get(&self, file: &DepFile) -> Option<&Dependency> {
+        self.cache.get(file)
+    }
+
+    /// Finds the indirect dependencies for the given dependency file. If the
+    /// file is already in the cache, the cached dependencies are used.
     pub fn fill_dependency(&mut self, dep: &mut Dependency) -> Result<()> {
-        if self.cache.contains_key(&dep.file) {
-            return Err(Error::DuplicateDependency);
+        if let Some(cached) = self.cache.get(&dep.file) {
+            debug!("Reusing cached dependencies of {:?}", dep.file.path);
+            dep.indirect.extend(cached.indirect.iter().cloned());
+            dep.missing.extend(cached.missing.iter().cloned());
+            dep.libs.extend(cached.libs.iter().cloned());
+            return Ok(());
         }
 
-        for file in &dep.direct {
+        // prebuilt objects don't include anything
+        let scanned = dep
+            .direct
+            .iter()
+            .filter(|f| !f.typ.is_some_and(|t| t.state == FileState::Object));
+        for file in scanned {
             let deps = self.get_dependencies(file.clone())?;
             dep.indirect.extend(deps.indirect.iter().cloned());
+            dep.missing.extend(deps.missing.iter().cloned());
+            dep.libs.extend(deps.libs.iter().cloned());
         }
 
         Ok(())
     }
 
-    pub fn get_dependencies(&mut self, file: DepFile) -> Result<&Dependency> {
-        let mut indirect: HashSet<DepFile> = HashSet::new();
+    /// Finds the sources that are reachable from the entry. Headers included
+    /// by a reachable source make the sources paired with them reachable.
+    /// The sources are returned in the same order as in `srcs`.
+    pub fn reachable_sources(
+        &mut self,
+        entry: &Path,
+        srcs: &[PathBuf],
+        pairing: Pairing,
+    ) -> Result<Vec<PathBuf>> {
+        // compare the canonical paths because the includes are canonical
+        let canon: Vec<_> = srcs
+            .iter()
+            .map(|s| s.canonicalize().unwrap_or_else(|_| s.clone()))
+            .collect();
 
-        if let Some(parent) = file.parent() {
-            indirect.extend(
-                get_included_files(file.clone())?
-                    .into_iter()
-                    .filter(|d| d.relative)
-                    .map(|d| parent.join(d.path).canonicalize())
-                    .filter_map(|d| d.ok())
-                    .map(|d| d.into()),
-            );
+        let mut reachable = HashSet::new();
+        let entry = entry
+            .canonicalize()
+            .map_err(|_| Error::MissingEntry(entry.to_owned()))?;
+        let mut to_exam = vec![entry];
+        while let Some(src) = to_exam.pop() {
+            if !reachable.insert(src.clone()) {
+                continue;
+            }
+            let deps = self.get_dependencies(src.into())?;
+            for header in &deps.indirect {
+                let paired = canon.iter().filter(|s| {
+                    !reachable.contains(*s) && is_pair(header, s, pairing)
+                });
+                to_exam.extend(paired.cloned());
+            }
         }
 
-        let mut to_exam: Vec<_> = indirect
+        Ok(srcs
             .iter()
-            .map(|f| DepDirection::Same(f.clone()))
+            .zip(canon)
+            .filter(|(_, c)| reachable.contains(c))
+            .map(|(s, _)| s.clone())
+            .collect())
+    }
+
+    /// Checks that the cached dependencies still exist, each file is checked
+    /// only once. The files that vanished since they were scanned (e.g.
+    /// moved headers) are removed from the cache and they are kept as
+    /// missing includes of the files that depended on them. Returns the
+    /// vanished files.
+    pub fn drop_vanished(&mut self) -> Vec<DepFile> {
+        let vanished: Vec<_> = self
+            .rdeps
+            .keys()
+            .filter(|f| self.checked.insert((*f).clone()) && !f.exists())
+            .cloned()
             .collect();
-        let mut dep_stack =
-            vec![Dependency::new(file.clone(), vec![], indirect)];
-        while let Some(file) = to_exam.pop() {
-            let mut pop = false;
-            let file = match file {
-                DepDirection::Same(path) => path,
-                DepDirection::LastDeeper(path) => {
-                    pop = true;
-                    path
+
+        for v in &vanished {
+            for d in self.rdeps.remove(v).unwrap_or_default() {
+                if let Some(d) = self.cache.get_mut(&d) {
+                    d.indirect.remove(v);
+                    d.missing.insert(v.to_path_buf());
                 }
+            }
+            let Some(dep) = self.cache.remove(v) else {
+                continue;
             };
-
-            if let Some(dep) = self.cache.get(&file) {
-                if let Some(top) = dep_stack.last_mut() {
-                    top.indirect.extend(dep.indirect.iter().cloned());
+            for d in &dep.indirect {
+                if let Some(r) = self.rdeps.get_mut(d) {
+                    r.remove(v);
                 }
-            } else if let Some(parent) = file.parent() {
-                let indirect = get_included_files(file.clone())?
-                    .into_iter()
-                    .filter(|d| d.relative)
-                    .map(|d| parent.join(d.path).canonicalize())
-                    .filter(|d| d.is_ok())
-                    .map(|d| d.unwrap().into())
-                    .filter(|d| {
-                        *d != file && !dep_stack.iter().any(|d2| d2.file == *d)
-                    })
-                    .collect();
+            }
+        }
+
+        vanished
+    }
 
-                let dep = Dependency::new(file, vec![], indirect);
+    pub fn get_dependencies(&mut self, file: DepFile) -> Result<&Dependency> {
+        debug!("Scanning dependencies of {:?}", file.path);
+        // generated sources don't exist when the build is only planned
+        if !file.exists() {
+            debug!("{:?} doesn't exist, it has no dependencies", file.path);
+            self.insert(Dependency::new(file.clone(), vec![], HashSet::new()));
+            return self.cached(&file);
+        }
 
-                let mut indirect = dep.indirect.iter();
+        // files whose includes are being scanned, including them again
+        // would be a cycle
+        let mut stack = vec![ScanFrame::new(file.clone(), 0)?];
+        while let Some(top) = stack.last_mut() {
+            let Some(inc) = top.rest.pop() else {
+                let Some(done) = stack.pop() else {
+                    break;
+                };
+                let idx = stack.len();
+                self.finish_frame(done, idx, stack.last_mut());
+                continue;
+            };
 
-                if let Some(d) = indirect.next() {
-                    to_exam.push(DepDirection::LastDeeper(d.clone()));
-                    to_exam.extend(
-                        indirect.map(|d| DepDirection::Same(d.clone())),
-                    );
-                    dep_stack.push(dep);
-                } else {
-                    self.cache.insert(dep.file.clone(), dep);
+            if let Some(dep) = self.cache.get(&inc) {
+                debug!("Dependency cache hit for {:?}", inc.path);
+                top.merge(dep);
+            } else if let Some(i) =
+                stack.iter().position(|f| f.dep.file == inc)
+            {
+                let chain: Vec<_> = stack[i..]
+                    .iter()
+                    .map(|f| f.dep.file.clone())
+                    .chain([inc])
+                    .collect();
+                self.report_cycle(&chain);
+                if let Some(top) = stack.last_mut() {
+                    top.low = top.low.min(i);
                 }
+            } else {
+                debug!("Dependency cache miss for {:?}", inc.path);
+                let frame = ScanFrame::new(inc, stack.len())?;
+                stack.push(frame);
             }
+        }
 
-            if pop {
-                if let Some(dep) = dep_stack.pop() {
-                    if let Some(top_dep) = dep_stack.last_mut() {
-                        top_dep.indirect.extend(dep.indirect.iter().cloned());
-                    }
-                    self.cache.insert(dep.file.clone(), dep);
-                }
+        self.cached(&file)
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+impl ScanFrame {
+    /// Reads the includes of the file that is at the index `idx` on the
+    /// scan stack.
+    fn new(file: DepFile, idx: usize) -> Result<Self> {
+        let Some(parent) = file.parent() else {
+            return Ok(Self {
+                dep: Dependency::new(file, vec![], HashSet::new()),
+                rest: vec![],
+                low: idx,
+                members: vec![],
+            });
+        };
+        let (indirect, missing, libs) = resolve_includes(&file, parent)?;
+
+        let mut seen = HashSet::new();
+        let mut rest: Vec<_> = indirect
+            .into_iter()
+            .filter(|d| *d != file && seen.insert(d.clone()))
+            .collect();
+        // the includes are examined from the end
+        rest.reverse();
+
+        let mut dep = Dependency::new(file, vec![], seen);
+        dep.missing = missing;
+        dep.libs = libs;
+        Ok(Self {
+            dep,
+            rest,
+            low: idx,
+            members: vec![],
+        })
+    }
+
+    /// Adds the dependencies of included file.
+    fn merge(&mut self, dep: &Dependency) {
+        let Dependency {
+            file,
+            indirect,
+            missing,
+            libs,
+            ..
+        } = &mut self.dep;
+        indirect.extend(dep.indirect.iter().filter(|d| *d != file).cloned());
+        missing.extend(dep.missing.iter().cloned());
+        libs.extend(dep.libs.iter().cloned());
+    }
+}
+
+impl DepCache {
+    /// Gets the dependency that is in the cache.
+    fn cached(&self, file: &DepFile) -> Result<&Dependency> {
+        self.cache.get(file).ok_or(Error::DoesNotHappen(
+            "Item just iserted into hashmap is not in the hashmap?",
+        ))
+    }
+
+    /// Caches the dependencies of file at the index `idx` of the scan stack
+    /// whose includes were all scanned and adds them to the file that
+    /// includes it. Files in a cycle are cached when the first file of the
+    /// cycle on the stack is done, they depend on the same files as it.
+    fn finish_frame(
+        &mut self,
+        done: ScanFrame,
+        idx: usize,
+        parent: Option<&mut ScanFrame>,
+    ) {
+        let ScanFrame {
+            dep,
+            low,
+            mut members,
+            ..
+        } = done;
+        if low < idx {
+            if let Some(parent) = parent {
+                parent.merge(&dep);
+                parent.low = parent.low.min(low);
+                parent.members.push(dep.file);
+                parent.members.append(&mut members);
             }
+            return;
         }
 
-        if dep_stack.len() > 1 {
-            Err(Error::DoesNotHappen("Dependency stack has too many items."))
-        } else if let Some(res) = dep_stack.into_iter().next() {
-            self.cache.insert(file.clone(), res);
-            self.cache.get(&file).ok_or(Error::DoesNotHappen(
-                "Item just iserted into hashmap is not in the hashmap?",
-            ))
-        } else {
-            Err(Error::DoesNotHappen("Dependency stack has no items"))
+        for m in members {
+            let mut indirect = dep.indirect.clone();
+            indirect.insert(dep.file.clone());
+            indirect.remove(&m);
+            let mut member = Dependency::new(m, vec![], indirect);
+            member.missing = dep.missing.clone();
+            member.libs = dep.libs.clone();
+            self.insert(member);
         }
+        if let Some(parent) = parent {
+            parent.merge(&dep);
+        }
+        self.insert(dep);
+    }
+
+    /// Warns about the circular include. The chain starts and ends with the
+    /// same file. Each cycle is reported only once.
+    fn report_cycle(&mut self, chain: &[DepFile]) {
+        let mut files: Vec<_> = chain.iter().map(|f| f.path.clone()).collect();
+        files.sort();
+        files.dedup();
+        if !self.cycles.insert(files) {
+            return;
+        }
+        let chain: Vec<_> =
+            chain.iter().map(|f| f.path.to_string_lossy()).collect();
+        printcln!(
+            "{'y bold}    Warning{'_} circular include: {}",
+            chain.join(" -> ")
+        );
+    }
+
+    /// Inserts the dependency to the cache and updates the reverse
+    /// dependencies.
+    fn insert(&mut self, dep: Dependency) {
+        let file = dep.file.clone();
+        for d in &dep.indirect {
+            self.rdeps
+                .entry(d.clone())
+                .or_default()
+                .insert(file.clone());
+        }
+        let Some(old) = self.cache.insert(file.clone(), dep) else {
+            return;
+        };
+        let new = &self.cache[&file].indirect;
+        for d in old.indirect.difference(new) {
+            if let Some(r) = self.rdeps.get_mut(d) {
+                r.remove(&file);
+            }
+        }
+    }
+}
+
+/// Checks whether the source implements the header.
+fn is_pair(header: &Path, src: &Path, pairing: Pairing) -> bool {
+    if header == src || header.file_stem() != src.file_stem() {
+        return false;
+    }
+    match pairing {
+        Pairing::Stem => true,
+        Pairing::SameDir => header.parent() == src.parent(),
+    }
+}
+
+/// Resolves the relative includes of the file relative to `parent`. Returns
+/// the existing included files, the paths of the included files that don't
+/// exist and the libraries requested by the file.
+fn resolve_includes(
+    file: &DepFile,
+    parent: &Path,
+) -> Result<(Vec<DepFile>, HashSet<PathBuf>, HashSet<String>)> {
+    let mut found = vec![];
+    let mut missing = HashSet::new();
+    let mut libs = HashSet::new();
+    for dir in get_directives(file.clone())? {
+        let inc = match dir {
+            Directive::Include(inc) if inc.relative => inc,
+            Directive::Include(_) => continue,
+            Directive::Lib(lib) => {
+                libs.insert(lib);
+                continue;
+            }
+        };
+        let path = parent.join(inc.path);
+        match path.canonicalize() {
+            Ok(p) => found.push(p.into()),
+            Err(_) => {
+                debug!(
+                    "{}:{}: included file {:?} doesn't exist",
+                    file.to_string_lossy(),
+                    inc.loc,
+                    path
+                );
+                missing.insert(path);
+            }
+        }
+    }
+    Ok((found, missing, libs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Creates the files in temporary directory. The directory is
+    /// canonical, because the includes are resolved to canonical paths.
+    fn files(files: &[(&str, &str)]) -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for (path, content) in files {
+            fs::write(root.join(path), content).unwrap();
+        }
+        (dir, root)
+    }
+
+    #[test]
+    fn missing_includes_are_recorded() {
+        let (_dir, root) = files(&[(
+            "main.c",
+            "#if __has_include(\"local.h\")\n#include \"local.h\"\n#endif\n",
+        )]);
+        let main: DepFile = root.join("main.c").into();
+        let mut cache = DepCache::new();
+        let dep = cache.get_dependencies(main.clone()).unwrap();
+        assert!(dep.indirect.is_empty());
+        assert_eq!(dep.missing, HashSet::from([root.join("local.h")]));
+
+        // the object is up to date until the header is created
+        let obj = root.join("main.o");
+        fs::write(&obj, "").unwrap();
+        let mut obj = Dependency::new(obj.into(), vec![main], HashSet::new());
+        cache.fill_dependency(&mut obj).unwrap();
+        let rules = DepRules::default();
+        assert_eq!(obj.dirty_reason(&rules).unwrap(), None);
+
+        fs::write(root.join("local.h"), "").unwrap();
+        let reason = obj.dirty_reason(&rules).unwrap().unwrap();
+        assert!(reason.contains("local.h was created"), "{reason}");
+    }
+
+    /// Gets the sorted file names of the reverse dependencies.
+    fn rdeps(cache: &DepCache, root: &Path, file: &str) -> Vec<String> {
+        let mut res: Vec<_> = cache
+            .reverse_deps(&root.join(file))
+            .iter()
+            .map(|d| d.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn reverse_deps_of_diamond() {
+        let (_dir, root) = files(&[
+            ("main.c", "#include \"b.h\"\n#include \"c.h\"\n"),
+            ("other.c", "#include \"c.h\"\n"),
+            ("b.h", "#include \"d.h\"\n"),
+            ("c.h", "#include \"d.h\"\n"),
+            ("d.h", ""),
+        ]);
+        let mut cache = DepCache::new();
+        cache.get_dependencies(root.join("main.c").into()).unwrap();
+        cache.get_dependencies(root.join("other.c").into()).unwrap();
+
+        let main = cache.get(&root.join("main.c").into()).unwrap();
+        assert_eq!(main.indirect.len(), 3);
+        assert_eq!(
+            rdeps(&cache, &root, "d.h"),
+            ["b.h", "c.h", "main.c", "other.c"]
+        );
+        assert_eq!(rdeps(&cache, &root, "b.h"), ["main.c"]);
+        assert_eq!(rdeps(&cache, &root, "c.h"), ["main.c", "other.c"]);
+        assert!(rdeps(&cache, &root, "main.c").is_empty());
+        assert_eq!(cache.iter().count(), 5);
+    }
+
+    #[test]
+    fn reverse_deps_after_vanished() {
+        let (_dir, root) = files(&[
+            ("main.c", "#include \"b.h\"\n#include \"c.h\"\n"),
+            ("b.h", "#include \"d.h\"\n"),
+            ("c.h", "#include \"d.h\"\n"),
+            ("d.h", ""),
+        ]);
+        let mut cache = DepCache::new();
+        cache.get_dependencies(root.join("main.c").into()).unwrap();
+
+        fs::remove_file(root.join("b.h")).unwrap();
+        let vanished = cache.drop_vanished();
+        assert_eq!(vanished, [DepFile::from(root.join("b.h"))]);
+        assert!(rdeps(&cache, &root, "b.h").is_empty());
+        // d.h is still included through c.h
+        assert_eq!(rdeps(&cache, &root, "d.h"), ["c.h", "main.c"]);
+
+        let main = cache.get(&root.join("main.c").into()).unwrap();
+        assert!(main.missing.contains(&root.join("b.h")));
+        assert!(cache.get(&root.join("b.h").into()).is_none());
+    }
+
+    #[test]
+    fn filling_dependency_again_is_idempotent() {
+        let (_dir, root) = files(&[
+            ("main.c", "#include \"a.h\"\n#include \"b.h\"\n"),
+            ("a.h", "#include \"b.h\"\n"),
+            ("b.h", ""),
+        ]);
+        let main: DepFile = root.join("main.c").into();
+        let mut cache = DepCache::new();
+        let obj = |main: &DepFile| {
+            Dependency::new(
+                root.join("main.o").into(),
+                vec![main.clone()],
+                HashSet::new(),
+            )
+        };
+
+        let mut first = obj(&main);
+        cache.fill_dependency(&mut first).unwrap();
+        assert_eq!(first.indirect.len(), 2);
+
+        // the same target queued again and the source reached both
+        // directly and through the cached object
+        let mut again = obj(&main);
+        cache.fill_dependency(&mut again).unwrap();
+        cache.fill_dependency(&mut again).unwrap();
+        assert_eq!(again.indirect, first.indirect);
+        cache.get_dependencies(main.clone()).unwrap();
+        assert_eq!(cache.iter().count(), 3);
+    }
+
+    /// Gets the names of the cached dependencies of the file.
+    fn deps(cache: &DepCache, root: &Path, file: &str) -> Vec<String> {
+        let file: DepFile = root.join(file).into();
+        let mut res: Vec<_> = cache
+            .get(&file)
+            .unwrap()
+            .indirect
+            .iter()
+            .map(|d| d.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn deep_includes_are_cached_complete() {
+        let (_dir, root) = files(&[
+            ("main.c", "#include \"a.h\"\n"),
+            ("a.h", "#include \"b.h\"\n"),
+            ("b.h", "#include \"c.h\"\n#include \"e.h\"\n"),
+            ("c.h", "#include \"d.h\"\n"),
+            ("d.h", ""),
+            ("e.h", ""),
+        ]);
+        let mut cache = DepCache::new();
+        cache.get_dependencies(root.join("main.c").into()).unwrap();
+        assert_eq!(
+            deps(&cache, &root, "main.c"),
+            ["a.h", "b.h", "c.h", "d.h", "e.h"]
+        );
+        assert_eq!(deps(&cache, &root, "a.h"), ["b.h", "c.h", "d.h", "e.h"]);
+        assert_eq!(deps(&cache, &root, "b.h"), ["c.h", "d.h", "e.h"]);
+        assert_eq!(deps(&cache, &root, "c.h"), ["d.h"]);
+        assert!(deps(&cache, &root, "d.h").is_empty());
+    }
+
+    #[test]
+    fn files_in_cycle_are_cached() {
+        let (_dir, root) = files(&[
+            ("main.c", "#include \"a.h\"\n"),
+            ("other.c", "#include \"c.h\"\n"),
+            ("a.h", "#include \"b.h\"\n"),
+            ("b.h", "#include \"c.h\"\n#include \"d.h\"\n"),
+            ("c.h", "#include \"a.h\"\n"),
+            ("d.h", ""),
+        ]);
+        let mut cache = DepCache::new();
+        cache.get_dependencies(root.join("main.c").into()).unwrap();
+        assert_eq!(
+            deps(&cache, &root, "main.c"),
+            ["a.h", "b.h", "c.h", "d.h"]
+        );
+        assert_eq!(deps(&cache, &root, "a.h"), ["b.h", "c.h", "d.h"]);
+        assert_eq!(deps(&cache, &root, "b.h"), ["a.h", "c.h", "d.h"]);
+        assert_eq!(deps(&cache, &root, "c.h"), ["a.h", "b.h", "d.h"]);
+        assert_eq!(cache.cycles.len(), 1);
+
+        // the cycle is not scanned again
+        cache.get_dependencies(root.join("other.c").into()).unwrap();
+        assert_eq!(
+            deps(&cache, &root, "other.c"),
+            ["a.h", "b.h", "c.h", "d.h"]
+        );
+        assert_eq!(cache.cycles.len(), 1);
+        assert_eq!(cache.iter().count(), 6);
+    }
+
+    #[test]
+    fn nested_cycles_are_cached() {
+        let (_dir, root) = files(&[
+            ("main.c", "#include \"a.h\"\n"),
+            ("a.h", "#include \"b.h\"\n"),
+            ("b.h", "#include \"c.h\"\n#include \"a.h\"\n"),
+            ("c.h", "#include \"b.h\"\n"),
+        ]);
+        let mut cache = DepCache::new();
+        cache.get_dependencies(root.join("main.c").into()).unwrap();
+        assert_eq!(deps(&cache, &root, "a.h"), ["b.h", "c.h"]);
+        assert_eq!(deps(&cache, &root, "b.h"), ["a.h", "c.h"]);
+        assert_eq!(deps(&cache, &root, "c.h"), ["a.h", "b.h"]);
+        assert_eq!(cache.cycles.len(), 2);
     }
 }
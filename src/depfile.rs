@@ -0,0 +1,162 @@
+use std::fs;
+
+use log::debug;
+
+use crate::{dependency::Dependency, err::Result};
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Writes the dependencies of the file in the makefile syntax to
+/// `<file>.d`. Each header has also its own empty rule so that make doesn't
+/// fail when the header is removed. The file is not touched if it didn't
+/// change.
+pub fn write(dep: &Dependency) -> Result<()> {
+    let mut path = dep.file.path.to_path_buf();
+    path.as_mut_os_string().push(".d");
+    let content = to_makefile(dep);
+    if fs::read_to_string(&path).is_ok_and(|c| c == content) {
+        return Ok(());
+    }
+    debug!("Writing dependency file {path:?}");
+    // the object directory is created only when the compiler runs
+    if let Some(p) = path.parent() {
+        fs::create_dir_all(p)?;
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Creates the makefile rule with the dependencies of the file.
+fn to_makefile(dep: &Dependency) -> String {
+    let mut indirect: Vec<_> = dep
+        .indirect
+        .iter()
+        .map(|d| escape(&d.to_string_lossy()))
+        .collect();
+    indirect.sort();
+
+    let mut res = escape(&dep.file.to_string_lossy());
+    res.push(':');
+    for d in &dep.direct {
+        res.push_str(" \\\n ");
+        res.push_str(&escape(&d.to_string_lossy()));
+    }
+    for d in &indirect {
+        res.push_str(" \\\n ");
+        res.push_str(d);
+    }
+    res.push('\n');
+
+    for d in &indirect {
+        res.push('\n');
+        res.push_str(d);
+        res.push_str(":\n");
+    }
+
+    res
+}
+
+/// Escapes the characters that have special meaning in makefile rules.
+fn escape(path: &str) -> String {
+    let mut res = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ' ' | '#' | ':' => {
+                res.push('\\');
+                res.push(c);
+            }
+            '$' => res.push_str("$$"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::PathBuf};
+
+    use super::*;
+
+    /// Splits the makefile into rules of unescaped target and its
+    /// dependencies.
+    fn parse(makefile: &str) -> Vec<(String, Vec<String>)> {
+        let joined = makefile.replace("\\\n", " ");
+        let mut res = vec![];
+        for line in joined.lines().filter(|l| !l.is_empty()) {
+            let mut words = vec![];
+            let mut word = String::new();
+            let mut chars = line.chars().peekable();
+            let mut target = None;
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => word.push(chars.next().unwrap()),
+                    '$' => word.push(chars.next().unwrap()),
+                    ':' if target.is_none() => {
+                        target = Some(std::mem::take(&mut word));
+                    }
+                    ' ' => {
+                        if !word.is_empty() {
+                            words.push(std::mem::take(&mut word));
+                        }
+                    }
+                    c => word.push(c),
+                }
+            }
+            if !word.is_empty() {
+                words.push(word);
+            }
+            res.push((target.expect("missing `:`"), words));
+        }
+        res
+    }
+
+    fn dep(file: &str, direct: &[&str], indirect: &[&str]) -> Dependency {
+        let path = |p: &&str| PathBuf::from(*p).into();
+        Dependency::new(
+            path(&file),
+            direct.iter().map(path).collect(),
+            indirect.iter().map(path).collect::<HashSet<_>>(),
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let dep = dep(
+            "bin/debug/project/main.c.o",
+            &["src/main.c"],
+            &["src/b.h", "src/a.h"],
+        );
+        let rules = parse(&to_makefile(&dep));
+        assert_eq!(rules[0].0, "bin/debug/project/main.c.o");
+        assert_eq!(rules[0].1, ["src/main.c", "src/a.h", "src/b.h"]);
+        // each header has empty rule
+        assert_eq!(rules[1], ("src/a.h".to_owned(), vec![]));
+        assert_eq!(rules[2], ("src/b.h".to_owned(), vec![]));
+        assert_eq!(rules.len(), 3);
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        let names = ["src/my file.c", "src/a#b.h", "src/c:d.h", "src/$x.h"];
+        let dep = dep("bin/x y.o", &names[..1], &names[1..]);
+        let makefile = to_makefile(&dep);
+        assert!(makefile.starts_with("bin/x\\ y.o:"), "{makefile}");
+        assert!(makefile.contains("src/c\\:d.h"), "{makefile}");
+        assert!(makefile.contains("src/$$x.h"), "{makefile}");
+
+        let rules = parse(&makefile);
+        assert_eq!(rules[0].0, "bin/x y.o");
+        let mut deps = rules[0].1.clone();
+        deps.sort();
+        let mut expected = names.map(String::from).to_vec();
+        expected.sort();
+        assert_eq!(deps, expected);
+    }
+}
@@ -0,0 +1,526 @@
+use std::{
+    collections::HashSet,
+    env, mem,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::glob;
+
+/// Severity of diagnostic produced by the compiler.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// Single diagnostic parsed from the output of gcc or clang.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub col: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+    /// The warning flag (e.g. `-Wunused-variable`)
+    pub flag: Option<String>,
+}
+
+/// Drops the diagnostics from files that match any of the patterns together
+/// with the lines that belong to them (the code snippets after them and the
+/// `In file included from` lines before them).
+pub struct DiagFilter {
+    patterns: Arc<[String]>,
+    root: PathBuf,
+    /// Lines that belong to the next diagnostic
+    pending: Vec<String>,
+    /// The last diagnostic was dropped, so are its code snippets
+    suppressing: bool,
+}
+
+/// Format in which the diagnostics are reported.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DiagnosticsFormat {
+    /// GitHub workflow commands printed to stdout
+    Github,
+    /// SARIF file
+    Sarif,
+}
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+impl Diagnostic {
+    /// Parses line in the format `file:line:col: severity: message [flag]`.
+    /// Returns [`None`] if the line is not diagnostic.
+    pub fn parse(line: &str) -> Option<Self> {
+        const SEVERITIES: [(&str, Severity); 4] = [
+            (": fatal error: ", Severity::Error),
+            (": error: ", Severity::Error),
+            (": warning: ", Severity::Warning),
+            (": note: ", Severity::Note),
+        ];
+
+        let (pos, sev, severity) = SEVERITIES
+            .iter()
+            .filter_map(|(s, sev)| line.find(s).map(|p| (p, *s, *sev)))
+            .min_by_key(|(p, _, _)| *p)?;
+
+        let (file, line_num, col) = parse_location(&line[..pos])?;
+        let message = line[pos + sev.len()..].trim();
+
+        // the flag is at the end of the message in square brackets
+        let (message, flag) = match message.rsplit_once(" [") {
+            Some((msg, flag))
+                if flag.starts_with('-') && flag.ends_with(']') =>
+            {
+                (msg, Some(flag[..flag.len() - 1].to_owned()))
+            }
+            _ => (message, None),
+        };
+
+        Some(Self {
+            file: file.to_owned(),
+            line: line_num,
+            col,
+            severity,
+            message: message.to_owned(),
+            flag,
+        })
+    }
+}
+
+impl DiagFilter {
+    /// Creates filter for the patterns relative to the current directory.
+    pub fn new(patterns: Arc<[String]>) -> Self {
+        Self {
+            patterns,
+            root: env::current_dir().unwrap_or_default(),
+            pending: vec![],
+            suppressing: false,
+        }
+    }
+
+    /// Processes the next line of the output. Returns the lines that should
+    /// be shown, lines that may belong to the next diagnostic are kept until
+    /// it is known whether it is dropped.
+    pub fn push(&mut self, line: String) -> Vec<String> {
+        if let Some(d) = Diagnostic::parse(&line) {
+            self.suppressing = self.is_suppressed(&d.file);
+            let mut res = mem::take(&mut self.pending);
+            if self.suppressing {
+                return vec![];
+            }
+            res.push(line);
+            res
+        } else if is_diagnostic_context(&line) {
+            self.pending.push(line);
+            vec![]
+        } else if self.suppressing && is_snippet(&line) {
+            vec![]
+        } else {
+            // other lines (e.g. `compilation terminated.`) don't belong to
+            // the dropped diagnostic
+            self.suppressing = false;
+            let mut res = mem::take(&mut self.pending);
+            res.push(line);
+            res
+        }
+    }
+
+    /// Gets the lines that were kept when the output ended.
+    pub fn finish(self) -> Vec<String> {
+        self.pending
+    }
+
+    fn is_suppressed(&self, file: &str) -> bool {
+        let path = Path::new(file);
+        let path = path.strip_prefix(&self.root).unwrap_or(path);
+        let path = path.to_string_lossy().replace('\\', "/");
+        let path = path.trim_start_matches("./");
+        self.patterns.iter().any(|p| glob::matches(p, path))
+    }
+}
+
+impl DiagnosticsFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "github" => Some(Self::Github),
+            "sarif" => Some(Self::Sarif),
+            _ => None,
+        }
+    }
+}
+
+/// Parses all diagnostics from the lines. Duplicate diagnostics (e.g. from
+/// headers included in multiple files) are removed.
+pub fn parse_all<I, S>(lines: I) -> Vec<Diagnostic>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut seen = HashSet::new();
+    lines
+        .into_iter()
+        .filter_map(|l| Diagnostic::parse(l.as_ref()))
+        .filter(|d| seen.insert(d.clone()))
+        .collect()
+}
+
+/// Converts the diagnostics to GitHub workflow commands, one per line.
+pub fn to_github(diags: &[Diagnostic]) -> String {
+    let mut res = String::new();
+    for d in diags {
+        let cmd = match d.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "notice",
+        };
+        res.push_str(&format!(
+            "::{cmd} file={},line={}",
+            escape_github_prop(&d.file),
+            d.line
+        ));
+        if let Some(col) = d.col {
+            res.push_str(&format!(",col={col}"));
+        }
+        if let Some(flag) = &d.flag {
+            res.push_str(&format!(",title={}", escape_github_prop(flag)));
+        }
+        res.push_str("::");
+        res.push_str(&escape_github_data(&d.message));
+        res.push('\n');
+    }
+    res
+}
+
+/// Converts the diagnostics to SARIF log.
+pub fn to_sarif(diags: &[Diagnostic]) -> serde_json::Value {
+    let results: Vec<_> = diags
+        .iter()
+        .map(|d| {
+            let level = match d.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Note => "note",
+            };
+            let mut region = json!({ "startLine": d.line });
+            if let Some(col) = d.col {
+                region["startColumn"] = col.into();
+            }
+            let mut res = json!({
+                "level": level,
+                "message": { "text": d.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": d.file },
+                        "region": region,
+                    }
+                }],
+            });
+            if let Some(flag) = &d.flag {
+                res["ruleId"] = flag.as_str().into();
+            }
+            res
+        })
+        .collect();
+
+    json!({
+        "version": "2.1.0",
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "ccpp",
+                    "version": option_env!("CARGO_PKG_VERSION")
+                        .unwrap_or("unknown"),
+                }
+            },
+            "results": results,
+        }],
+    })
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Parses `file:line:col` or `file:line`.
+fn parse_location(loc: &str) -> Option<(&str, usize, Option<usize>)> {
+    let (rest, last) = loc.rsplit_once(':')?;
+    let last: usize = last.parse().ok()?;
+
+    if let Some((file, line)) = rest.rsplit_once(':') {
+        if let Ok(line) = line.parse() {
+            return Some((file, line, Some(last)));
+        }
+    }
+
+    Some((rest, last, None))
+}
+
+/// Checks whether the line is printed before diagnostic to describe where it
+/// is (e.g. `In file included from a.c:1:` or `a.h: In function 'f':`).
+fn is_diagnostic_context(line: &str) -> bool {
+    line.starts_with("In file included from ")
+        || line.trim_start().starts_with("from ") && line.ends_with([':', ','])
+        || line.ends_with(':')
+            && [": In ", ": At "].iter().any(|s| line.contains(s))
+}
+
+/// Checks whether the line is part of the code snippet printed after
+/// diagnostic (e.g. `    3 |     int x;`).
+fn is_snippet(line: &str) -> bool {
+    line.starts_with([' ', '\t'])
+}
+
+fn escape_github_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_github_prop(s: &str) -> String {
+    escape_github_data(s)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GCC: &str = "\
+src/main.c: In function 'main':
+src/main.c:3:9: warning: unused variable 'x' [-Wunused-variable]
+    3 |     int x;
+      |         ^
+src/main.c:4:5: error: 'y' undeclared (first use in this function)
+    4 |     y = 1;
+      |     ^
+src/main.c:4:5: note: each undeclared identifier is reported only once
+In file included from src/main.c:1:
+src/util.h:2:12: warning: 'f' defined but not used [-Wunused-function]
+cc1: some warnings being treated as errors
+";
+
+    const CLANG: &str = "\
+src/main.cpp:5:10: fatal error: 'missing.h' file not found
+    5 | #include \"missing.h\"
+      |          ^~~~~~~~~~~
+src/a.cpp:7:3: warning: expression result unused [-Wunused-value]
+1 warning and 1 error generated.
+";
+
+    #[test]
+    fn parse_gcc() {
+        let diags = parse_all(GCC.lines());
+        assert_eq!(diags.len(), 4);
+        assert_eq!(
+            diags[0],
+            Diagnostic {
+                file: "src/main.c".into(),
+                line: 3,
+                col: Some(9),
+                severity: Severity::Warning,
+                message: "unused variable 'x'".into(),
+                flag: Some("-Wunused-variable".into()),
+            }
+        );
+        assert_eq!(diags[1].severity, Severity::Error);
+        assert_eq!(diags[1].flag, None);
+        assert_eq!(diags[2].severity, Severity::Note);
+        assert_eq!(diags[3].file, "src/util.h");
+    }
+
+    #[test]
+    fn parse_clang() {
+        let diags = parse_all(CLANG.lines());
+        assert_eq!(diags.len(), 2);
+        assert_eq!(diags[0].severity, Severity::Error);
+        assert_eq!(diags[0].message, "'missing.h' file not found");
+        assert_eq!((diags[1].line, diags[1].col), (7, Some(3)));
+        assert_eq!(diags[1].flag.as_deref(), Some("-Wunused-value"));
+    }
+
+    #[test]
+    fn parse_location_variants() {
+        let d = Diagnostic::parse("a.c:12: error: no column").unwrap();
+        assert_eq!((d.file.as_str(), d.line, d.col), ("a.c", 12, None));
+
+        let d = Diagnostic::parse(r"C:\src\a.c:1:2: warning: w").unwrap();
+        assert_eq!(
+            (d.file.as_str(), d.line, d.col),
+            (r"C:\src\a.c", 1, Some(2))
+        );
+
+        // message that contains another severity
+        let d = Diagnostic::parse("a.c:1:1: note: see: error: x").unwrap();
+        assert_eq!(d.severity, Severity::Note);
+        assert_eq!(d.message, "see: error: x");
+
+        // square brackets that are not flag
+        let d = Diagnostic::parse("a.c:1:1: error: bad [x]").unwrap();
+        assert_eq!((d.message.as_str(), d.flag), ("bad [x]", None));
+
+        assert!(Diagnostic::parse("    3 |     int x;").is_none());
+        assert!(Diagnostic::parse("src/main.c: In function 'main':").is_none());
+        assert!(Diagnostic::parse("ld: error: undefined symbol").is_none());
+    }
+
+    #[test]
+    fn duplicates_are_removed() {
+        let line = "inc/a.h:1:1: warning: w [-Wx]";
+        let diags = parse_all([line, "b.c:1:1: error: e", line]);
+        assert_eq!(diags.len(), 2);
+    }
+
+    #[test]
+    fn github_commands() {
+        let diags = parse_all([
+            "src/a,b.c:3:9: warning: 50% of 'x'\r [-Wunused]",
+            "a.c:1: error: e",
+        ]);
+        assert_eq!(
+            to_github(&diags),
+            "::warning file=src/a%2Cb.c,line=3,col=9,title=-Wunused::50%25 \
+            of 'x'%0D\n::error file=a.c,line=1::e\n"
+        );
+    }
+
+    #[test]
+    fn sarif_log() {
+        let sarif = to_sarif(&parse_all(GCC.lines()));
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0]["level"], "warning");
+        assert_eq!(results[0]["ruleId"], "-Wunused-variable");
+        let loc = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(loc["artifactLocation"]["uri"], "src/main.c");
+        assert_eq!(loc["region"]["startLine"], 3);
+        assert_eq!(loc["region"]["startColumn"], 9);
+        assert!(results[1].get("ruleId").is_none());
+    }
+
+    fn filter(patterns: &[&str], root: &str) -> DiagFilter {
+        DiagFilter {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            root: root.into(),
+            pending: vec![],
+            suppressing: false,
+        }
+    }
+
+    /// Filters all the lines and gets the shown lines.
+    fn filtered(mut filter: DiagFilter, output: &str) -> String {
+        let mut res: Vec<_> = output
+            .lines()
+            .flat_map(|l| filter.push(l.to_owned()))
+            .collect();
+        res.extend(filter.finish());
+        res.iter().map(|l| format!("{l}\n")).collect()
+    }
+
+    #[test]
+    fn diagnostic_context() {
+        assert!(is_diagnostic_context("In file included from a.c:1:"));
+        assert!(is_diagnostic_context("                 from b.h:2,"));
+        assert!(is_diagnostic_context("                 from b.c:3:"));
+        assert!(is_diagnostic_context("a.h: In function 'f':"));
+        assert!(is_diagnostic_context("a.c: At top level:"));
+        assert!(!is_diagnostic_context("    3 |     int x;"));
+        assert!(!is_diagnostic_context("from now on"));
+        assert!(!is_diagnostic_context("compilation terminated."));
+    }
+
+    #[test]
+    fn include_chain_of_suppressed_diagnostic_is_dropped() {
+        let out = "\
+In file included from src/main.c:1:
+                 from src/a.h:2,
+vendor/lib.h:2:12: warning: 'f' defined but not used [-Wunused-function]
+    2 | static int f(void) { return 0; }
+      |            ^
+In file included from src/main.c:2:
+src/util.h:1:5: warning: w [-Wx]
+";
+        assert_eq!(
+            filtered(filter(&["vendor/**"], "/p"), out),
+            "\
+In file included from src/main.c:2:
+src/util.h:1:5: warning: w [-Wx]
+"
+        );
+    }
+
+    #[test]
+    fn note_in_kept_file_is_shown() {
+        let out = "\
+vendor/lib.h:2:6: error: conflicting types for 'f'
+    2 | void f(int);
+      |      ^
+src/main.c:1:6: note: previous declaration of 'f' with type 'void(void)'
+    1 | void f(void);
+      |      ^
+";
+        assert_eq!(
+            filtered(filter(&["vendor/**"], "/p"), out),
+            "\
+src/main.c:1:6: note: previous declaration of 'f' with type 'void(void)'
+    1 | void f(void);
+      |      ^
+"
+        );
+    }
+
+    #[test]
+    fn lines_after_suppressed_snippet_are_shown() {
+        let out = "\
+vendor/lib.h:1:10: fatal error: 'x.h' file not found
+    1 | #include \"x.h\"
+      |          ^~~~~
+compilation terminated.
+";
+        assert_eq!(
+            filtered(filter(&["vendor/**"], "/p"), out),
+            "compilation terminated.\n"
+        );
+    }
+
+    #[test]
+    fn pending_lines_are_shown_at_end() {
+        let out = "\
+src/a.c: In function 'f':
+vendor/lib.h:1:1: warning: w [-Wx]
+In file included from src/main.c:1:
+src/a.c: At top level:
+";
+        assert_eq!(
+            filtered(filter(&["vendor/**"], "/p"), out),
+            "In file included from src/main.c:1:\nsrc/a.c: At top level:\n"
+        );
+    }
+
+    #[test]
+    fn relative_and_absolute_paths_are_matched() {
+        let out = "\
+./vendor/a.h:1:1: warning: a [-Wx]
+/p/vendor/b.h:1:1: warning: b [-Wx]
+/other/vendor/c.h:1:1: warning: c [-Wx]
+src/main.c:1:1: warning: d [-Wx]
+";
+        assert_eq!(
+            filtered(filter(&["vendor/**"], "/p"), out),
+            "\
+/other/vendor/c.h:1:1: warning: c [-Wx]
+src/main.c:1:1: warning: d [-Wx]
+"
+        );
+    }
+}
@@ -1,43 +1,90 @@
-use crate::{config::Config, err::Result};
-use std::{borrow::Cow, fs::read_dir, path::PathBuf};
+use crate::{
+    config::Config,
+    err::{Error, Result, ResultExt},
+    file_type::FileType,
+    glob,
+    ignore_file::IgnoreFile,
+};
+use ignore::WalkBuilder;
+use log::debug;
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
 
 pub struct DirStructure {
-    /// extensions of source files
-    src_extensions: Vec<String>,
     /// all source files, each file coresponds to obj file
     src_files: Vec<PathBuf>,
     src_root: PathBuf,
+    /// source files that are excluded because their condition is not met
+    excluded: HashSet<PathBuf>,
+    /// glob patterns of the sources that are excluded in the profile
+    exclude_patterns: Vec<String>,
+    /// glob patterns of the sources that are not excluded by
+    /// `exclude_patterns`
+    include_patterns: Vec<String>,
+    /// rules from `.ccppignore`
+    ignore: Arc<IgnoreFile>,
+    /// skip files ignored by git
+    respect_gitignore: bool,
 }
 
+/// File with paths that are not searched for sources, in gitignore syntax
+const IGNORE_FILE: &str = ".ccppignore";
+
 //===========================================================================//
 //                                   Public                                  //
 //===========================================================================//
 
 impl DirStructure {
-    pub fn from_config(conf: &Config, release: bool) -> Self {
-        if release {
-            DirStructure::new(
-                conf.release_build.compiler_conf.src_root.clone(),
-            )
+    pub fn from_config(conf: &Config, release: bool) -> Result<Self> {
+        let build = if release {
+            &conf.release_build
         } else {
-            DirStructure::new(conf.debug_build.compiler_conf.src_root.clone())
-        }
+            &conf.debug_build
+        };
+
+        let mut res = DirStructure::new(build.compiler_conf.src_root.clone());
+
+        let (included, excluded): (Vec<_>, Vec<_>) = conf
+            .conditional_sources
+            .iter()
+            .partition(|c| c.matches(&build.compiler_conf));
+        // file is included if at least one of its conditions is met
+        let included: HashSet<_> =
+            included.into_iter().flat_map(|c| c.files.iter()).collect();
+        res.excluded = excluded
+            .into_iter()
+            .flat_map(|c| c.files.iter())
+            .filter(|f| !included.contains(f))
+            .cloned()
+            .collect();
+        res.exclude_patterns = build.exclude_sources.clone();
+        res.include_patterns = build.include_sources.clone();
+        res.ignore = Arc::new(IgnoreFile::load(IGNORE_FILE)?);
+        res.respect_gitignore = conf.project.respect_gitignore;
+
+        Ok(res)
     }
 
     pub fn new(src_root: PathBuf) -> Self {
         Self {
-            src_extensions: vec![
-                "c".into(),
-                "C".into(),
-                "cc".into(),
-                "cpp".into(),
-                "CPP".into(),
-                "c++".into(),
-                "cp".into(),
-                "cxx".into(),
-            ],
             src_files: vec![],
             src_root,
+            excluded: HashSet::new(),
+            exclude_patterns: vec![],
+            include_patterns: vec![],
+            ignore: Default::default(),
+            respect_gitignore: false,
+        }
+    }
+
+    /// Creates directory structure with the given source files.
+    pub fn with_files(src_root: PathBuf, files: Vec<PathBuf>) -> Self {
+        Self {
+            src_files: files,
+            ..Self::new(src_root)
         }
     }
 
@@ -52,6 +99,34 @@ impl DirStructure {
     pub fn srcs(&self) -> &[PathBuf] {
         &self.src_files
     }
+
+    /// Gets the found source files that are in the given directory. The
+    /// directory must be inside the source directory.
+    pub fn srcs_in(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let root = self
+            .src_root
+            .canonicalize()
+            .with_path("find", &self.src_root)?;
+        let rel = dir
+            .canonicalize()
+            .with_path("find", dir)?
+            .strip_prefix(&root)
+            .map(|r| r.to_owned())
+            .map_err(|_| Error::OutsideSrcRoot {
+                dir: dir.to_owned(),
+                src_root: self.src_root.clone(),
+            })?;
+
+        Ok(self
+            .src_files
+            .iter()
+            .filter(|f| {
+                f.strip_prefix(&self.src_root)
+                    .is_ok_and(|f| f.starts_with(&rel))
+            })
+            .cloned()
+            .collect())
+    }
 }
 
 //===========================================================================//
@@ -59,48 +134,72 @@ impl DirStructure {
 //===========================================================================//
 
 impl DirStructure {
-    /// finds all files in the directory [`Self::src`] with one of the
-    /// extensions from [`Self::src_extensions`]
+    /// finds all files in the directory [`Self::src`] with extension of
+    /// source file (see [`FileType::is_source_ext`])
     fn find_src_files(&mut self) -> Result<()> {
-        let mut dirs = vec![self.src_root.clone()];
-
-        // Recursively search the directory for files with one of the
-        // extensions. The recursion is achieved with the dirs stack.
-        while let Some(dir) = dirs.pop() {
-            for item in read_dir(dir)? {
-                let item = item?;
-                let typ = item.file_type()?;
-
-                // recursively search in directories
-                if typ.is_dir() {
-                    dirs.push(item.path());
-                    continue;
+        // ignored directories are not searched at all
+        let ignore = self.ignore.clone();
+        let walk = WalkBuilder::new(&self.src_root)
+            .standard_filters(false)
+            .git_ignore(self.respect_gitignore)
+            .git_exclude(self.respect_gitignore)
+            .parents(self.respect_gitignore)
+            .require_git(false)
+            .filter_entry(move |e| {
+                let is_dir = e.file_type().is_some_and(|t| t.is_dir());
+                let ignored = ignore.is_ignored(e.path(), is_dir);
+                if ignored {
+                    debug!("Ignoring {:?}, it is in {IGNORE_FILE}", e.path());
                 }
+                !ignored
+            })
+            .build();
 
-                // add only files
-                if !typ.is_file() {
-                    continue;
-                }
+        for item in walk {
+            let item = item.map_err(|e| Error::Other(e.into()))?;
 
-                // get the file path and extension
-                let item = item.path();
-                let ext = item
-                    .extension()
-                    .map_or_else(|| "".into(), |e| e.to_string_lossy());
-
-                // check if the extension matches
-                if !self
-                    .src_extensions
-                    .iter()
-                    .any(|i| Cow::Borrowed(i.as_str()) == ext)
-                {
-                    continue;
-                }
+            // add only files
+            if !item.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
 
-                self.src_files.push(item);
+            // check if the extension matches
+            let item = item.into_path();
+            if !item.extension().is_some_and(FileType::is_source_ext) {
+                continue;
             }
+
+            if self.excluded.contains(&item) {
+                debug!("Excluding {item:?}, its condition is not met");
+                continue;
+            }
+
+            if self.is_excluded_in_profile(&item) {
+                debug!("Excluding {item:?}, it is excluded in the profile");
+                continue;
+            }
+
+            self.src_files.push(item);
         }
 
         Ok(())
     }
+
+    /// Checks whether the source matches any of the patterns from
+    /// `exclude_sources` and none of the patterns from `include_sources`.
+    /// The patterns are relative to the project.
+    fn is_excluded_in_profile(&self, file: &Path) -> bool {
+        if self.exclude_patterns.is_empty() {
+            return false;
+        }
+        let path: PathBuf = file
+            .components()
+            .filter(|c| c != &Component::CurDir)
+            .collect();
+        let path = path.to_string_lossy().replace('\\', "/");
+        let matches =
+            |p: &String| glob::matches(p.trim_start_matches("./"), &path);
+        self.exclude_patterns.iter().any(matches)
+            && !self.include_patterns.iter().any(matches)
+    }
 }
@@ -0,0 +1,57 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+/// Positive duration given as number with optional unit `s`, `m` or `h`
+/// (e.g. `300s` or `1.5m`). Number without unit is in seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HumanDuration(pub Duration);
+
+impl FromStr for HumanDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (num, mul) = if let Some(n) = s.strip_suffix('h') {
+            (n, 3600.)
+        } else if let Some(n) = s.strip_suffix('m') {
+            (n, 60.)
+        } else {
+            (s.strip_suffix('s').unwrap_or(s), 1.)
+        };
+
+        num.trim()
+            .parse::<f64>()
+            .ok()
+            .map(|n| n * mul)
+            .filter(|s| s.is_finite() && *s > 0.)
+            .and_then(|s| Duration::try_from_secs_f64(s).ok())
+            .map(Self)
+            .ok_or_else(|| {
+                format!(
+                    "invalid duration `{s}`, expected positive number with \
+                    optional unit `s`, `m` or `h` (e.g. `300s`)"
+                )
+            })
+    }
+}
+
+impl Display for HumanDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}s", self.0.as_secs_f64())
+    }
+}
+
+impl TryFrom<String> for HumanDuration {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<HumanDuration> for String {
+    fn from(value: HumanDuration) -> Self {
+        value.to_string()
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+use crate::{
+    dependency::{DepFile, Dependency},
+    err::{Error, Result},
+};
+
+/// Number of bytes on a single line in the generated source
+const BYTES_PER_LINE: usize = 12;
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Generates C source and header for each of the files into `out_dir`. The
+/// source defines byte array with the contents of the file and its length.
+/// The source is regenerated only if the file is newer. Returns the paths to
+/// the generated sources.
+///
+/// For file `data/logo.png` the generated header is `<out_dir>/logo_png.h`
+/// and it declares:
+/// ```c
+/// extern const unsigned char logo_png[];
+/// extern const size_t logo_png_len;
+/// ```
+pub fn generate(files: &[PathBuf], out_dir: &Path) -> Result<Vec<PathBuf>> {
+    let res = sources(files, out_dir)?;
+
+    for (file, src) in files.iter().zip(&res) {
+        let name = symbol_name(file);
+        let header = out_dir.join(format!("{name}.h"));
+        fs::create_dir_all(out_dir)?;
+
+        // the header doesn't depend on the contents of the file, so it is
+        // written only once so that it doesn't trigger rebuilds
+        if !header.exists() {
+            fs::write(&header, gen_header(&name))?;
+        }
+
+        let dep = Dependency::new(
+            DepFile::from(src.clone()),
+            vec![DepFile::from(file.clone())],
+            Default::default(),
+        );
+        if dep.is_up_to_date()? {
+            debug!("Embedded file {file:?} is up to date");
+        } else {
+            debug!("Generating {src:?} from {file:?}");
+            fs::write(src, gen_source(&name, &fs::read(file)?))?;
+        }
+    }
+
+    Ok(res)
+}
+
+/// Gets the paths of the sources that are generated for the files by
+/// [`generate`] without generating them.
+pub fn sources(files: &[PathBuf], out_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut names = HashSet::new();
+    let mut res = vec![];
+
+    for file in files {
+        let name = symbol_name(file);
+        if !names.insert(name.clone()) {
+            return Err(Error::Generic(format!(
+                "Cannot embed {file:?}, another embedded file has the same \
+                symbol name `{name}`"
+            )));
+        }
+        res.push(out_dir.join(format!("{name}.c")));
+    }
+
+    Ok(res)
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Gets the symbol name from the file name, all characters that are not
+/// valid in C identifier are replaced with `_`.
+fn symbol_name(file: &Path) -> String {
+    let name = file
+        .file_name()
+        .map_or_else(|| file.to_string_lossy(), |n| n.to_string_lossy());
+
+    let mut res: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    if res.starts_with(|c: char| c.is_ascii_digit()) {
+        res.insert(0, '_');
+    }
+
+    res
+}
+
+fn gen_header(name: &str) -> String {
+    let guard = name.to_ascii_uppercase();
+    format!(
+        "// Generated by ccpp, don't edit.
+#ifndef EMBED_{guard}_H_INCLUDED
+#define EMBED_{guard}_H_INCLUDED
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern \"C\" {{
+#endif
+
+extern const unsigned char {name}[];
+extern const size_t {name}_len;
+
+#ifdef __cplusplus
+}}
+#endif
+
+#endif // EMBED_{guard}_H_INCLUDED
+"
+    )
+}
+
+fn gen_source(name: &str, data: &[u8]) -> String {
+    let mut res = format!(
+        "// Generated by ccpp, don't edit.
+#include <stddef.h>
+
+const unsigned char {name}[] = {{
+"
+    );
+
+    for line in data.chunks(BYTES_PER_LINE) {
+        res.push_str("   ");
+        for b in line {
+            // writing to string cannot fail
+            _ = write!(res, " 0x{b:02x},");
+        }
+        res.push('\n');
+    }
+
+    // C doesn't allow empty arrays
+    if data.is_empty() {
+        res.push_str("    0\n");
+    }
+
+    _ = write!(res, "}};\n\nconst size_t {name}_len = {};\n", data.len());
+    res
+}
@@ -0,0 +1,196 @@
+use std::{collections::BTreeMap, env, sync::Mutex};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ExpandError {
+    #[error("environment variable `{}` is not set", .0)]
+    Unset(String),
+    #[error("missing `}}` after `${{`")]
+    Unterminated,
+    #[error("missing variable name in `${{}}`")]
+    EmptyName,
+}
+
+/// Environment variables used by [`expand_env`] and their values
+static EXPANDED: Mutex<BTreeMap<String, Option<String>>> =
+    Mutex::new(BTreeMap::new());
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Expands the environment variables in the string (see [`expand`]).
+pub fn expand_env(s: &str) -> Result<String, ExpandError> {
+    expand(s, lookup_env)
+}
+
+/// Gets the environment variables that were used by [`expand_env`] with
+/// their values, sorted by name.
+pub fn expanded_vars() -> Vec<(String, Option<String>)> {
+    EXPANDED
+        .lock()
+        .map(|e| e.iter().map(|(n, v)| (n.clone(), v.clone())).collect())
+        .unwrap_or_default()
+}
+
+/// Expands the variables in the string. `${VAR}` is replaced with the value
+/// of the variable and it is error if the variable is not set.
+/// `${VAR:-default}` uses the default if the variable is not set or it is
+/// empty, the default may contain other variables. `$$` is a single `$` and
+/// `$` that is not followed by `{` or `$` is kept as it is (e.g.
+/// `$ORIGIN`).
+pub fn expand<F>(s: &str, lookup: F) -> Result<String, ExpandError>
+where
+    F: Fn(&str) -> Option<String> + Copy,
+{
+    let mut res = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        res.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(r) = rest.strip_prefix('$') {
+            res.push('$');
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('{') {
+            let end = closing_brace(r).ok_or(ExpandError::Unterminated)?;
+            res.push_str(&expand_var(&r[..end], lookup)?);
+            rest = &r[end + 1..];
+        } else {
+            res.push('$');
+        }
+    }
+    res.push_str(rest);
+    Ok(res)
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Gets the value of the environment variable and records it.
+fn lookup_env(name: &str) -> Option<String> {
+    let value = env::var(name).ok();
+    if let Ok(mut e) = EXPANDED.lock() {
+        e.insert(name.to_owned(), value.clone());
+    }
+    value
+}
+
+/// Expands the contents of `${...}`.
+fn expand_var<F>(var: &str, lookup: F) -> Result<String, ExpandError>
+where
+    F: Fn(&str) -> Option<String> + Copy,
+{
+    let (name, default) = match var.split_once(":-") {
+        Some((n, d)) => (n, Some(d)),
+        None => (var, None),
+    };
+    if name.is_empty() {
+        return Err(ExpandError::EmptyName);
+    }
+
+    match (lookup(name), default) {
+        (Some(v), Some(_)) if !v.is_empty() => Ok(v),
+        (_, Some(d)) => expand(d, lookup),
+        (Some(v), None) => Ok(v),
+        (None, None) => Err(ExpandError::Unset(name.to_owned())),
+    }
+}
+
+/// Finds the `}` that closes `${`, the `${` of nested variables in the
+/// default value are skipped.
+fn closing_brace(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '$' if chars.peek().is_some_and(|(_, c)| *c == '$') => {
+                chars.next();
+            }
+            '$' if chars.peek().is_some_and(|(_, c)| *c == '{') => {
+                chars.next();
+                depth += 1;
+            }
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/user".to_owned()),
+            "EMPTY" => Some(String::new()),
+            "DIR" => Some("lib".to_owned()),
+            _ => None,
+        }
+    }
+
+    fn exp(s: &str) -> Result<String, ExpandError> {
+        expand(s, lookup)
+    }
+
+    #[test]
+    fn variables_are_expanded() {
+        assert_eq!(exp("${HOME}/include").unwrap(), "/home/user/include");
+        assert_eq!(exp("${DIR}${DIR}").unwrap(), "liblib");
+        assert_eq!(exp("${EMPTY}x").unwrap(), "x");
+        assert_eq!(exp("no variables").unwrap(), "no variables");
+    }
+
+    #[test]
+    fn unset_variable_is_error() {
+        assert!(matches!(
+            exp("a ${MISSING} b"),
+            Err(ExpandError::Unset(n)) if n == "MISSING"
+        ));
+        assert!(matches!(exp("${}"), Err(ExpandError::EmptyName)));
+        assert!(matches!(exp("${:-x}"), Err(ExpandError::EmptyName)));
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(exp("${MISSING:-/usr}").unwrap(), "/usr");
+        assert_eq!(exp("${HOME:-/usr}").unwrap(), "/home/user");
+        // empty value also uses the default
+        assert_eq!(exp("${EMPTY:-x}").unwrap(), "x");
+        assert_eq!(exp("${MISSING:-}").unwrap(), "");
+        // the default may contain other variables
+        assert_eq!(exp("${MISSING:-${DIR}/a}").unwrap(), "lib/a");
+        assert_eq!(exp("${A:-${B:-${DIR}}}").unwrap(), "lib");
+        assert!(matches!(
+            exp("${A:-${B}}"),
+            Err(ExpandError::Unset(n)) if n == "B"
+        ));
+        // the default is expanded only when it is used
+        assert_eq!(exp("${DIR:-${MISSING}}").unwrap(), "lib");
+    }
+
+    #[test]
+    fn dollar_escapes() {
+        assert_eq!(exp("$$").unwrap(), "$");
+        assert_eq!(exp("$${HOME}").unwrap(), "${HOME}");
+        assert_eq!(exp("$$${HOME}").unwrap(), "$/home/user");
+        assert_eq!(exp("${MISSING:-$$}").unwrap(), "$");
+        assert_eq!(exp("${MISSING:-$${}").unwrap(), "${");
+        // `$` without `{` is kept
+        assert_eq!(exp("-Wl,-rpath,$ORIGIN").unwrap(), "-Wl,-rpath,$ORIGIN");
+        assert_eq!(exp("a$").unwrap(), "a$");
+    }
+
+    #[test]
+    fn unclosed_braces() {
+        assert!(matches!(exp("${HOME"), Err(ExpandError::Unterminated)));
+        assert!(matches!(exp("x ${"), Err(ExpandError::Unterminated)));
+        assert!(matches!(exp("${A:-${B}"), Err(ExpandError::Unterminated)));
+        // `}` without `${` is kept
+        assert_eq!(exp("}${DIR}}").unwrap(), "}lib}");
+    }
+}
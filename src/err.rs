@@ -1,11 +1,23 @@
-use std::path::{PathBuf, StripPrefixError};
+use std::{
+    path::{Path, PathBuf, StripPrefixError},
+    time::Duration,
+};
 
 use thiserror::Error;
 
-use crate::{arg_parser::ArgError, dependency::DepFile};
+use crate::{
+    arg_parser::ArgError, dependency::DepFile, env_expand::ExpandError,
+    unit_test,
+};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Adds the path of the file to IO errors, see [`Error::io_at`].
+pub trait ResultExt<T> {
+    /// Adds the path and the operation (e.g. `read`) to the error.
+    fn with_path(self, action: &'static str, path: &Path) -> Result<T>;
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error(
@@ -13,11 +25,6 @@ pub enum Error {
         each other in cycle"
     )]
     DependencyCycle,
-    #[error(
-        "The given file has inconsitent dependencies. Cannot create \
-        dependency twice for the same file."
-    )]
-    DuplicateDependency,
     #[error(
         "Cannot build file {} because it has no files to be build from",
         .0.to_string_lossy()
@@ -31,21 +38,155 @@ pub enum Error {
     InvalidFileType(DepFile),
     #[error("Invalid value `{value}` for {option} in compiler option.")]
     InvalidCompilerValue { option: String, value: String },
+    #[error(
+        "Unknown placeholder `{{{placeholder}}}` in `{option}` of the custom \
+        compiler"
+    )]
+    UnknownPlaceholder {
+        option: &'static str,
+        placeholder: String,
+    },
+    #[error(
+        "Failed to build {count} files, the first failed file is {}",
+        .first.to_string_lossy()
+    )]
+    BuildFailed { count: usize, first: PathBuf },
+    #[error(
+        "Stopped the build early after {count} failed files, the first \
+        failed file is {}",
+        .first.to_string_lossy()
+    )]
+    TooManyErrors { count: usize, first: PathBuf },
+    #[error(
+        "Building {} timed out after {:.1}s, the job was killed",
+        .file.to_string_lossy(),
+        .duration.as_secs_f64()
+    )]
+    Timeout { file: PathBuf, duration: Duration },
+    #[error(
+        "Failed to build {count} workspace members, the first failed member \
+        is {first}"
+    )]
+    WorkspaceFailed { count: usize, first: String },
+    #[error(
+        "Failed to build with {count} of the standards, the first failed \
+        standard is {first}"
+    )]
+    StdMatrixFailed { count: usize, first: String },
+    #[error("There are no test sources in `{}`", unit_test::TESTS_DIR)]
+    NoTests,
+    #[error(
+        "{count} of the tests failed, the first failed test is {}",
+        .first.to_string_lossy()
+    )]
+    TestsFailed { count: usize, first: PathBuf },
+    #[error("Workspace member {member} failed: {err}")]
+    MemberFailed { member: String, err: Box<Error> },
+    #[error(
+        "The binary {} doesn't exist, build it first or run without \
+        `--no-build`",
+        .0.to_string_lossy()
+    )]
+    MissingBinary(PathBuf),
+    #[error(
+        "The stable link {} points to binary that no longer exists, build \
+        the project first",
+        .0.to_string_lossy()
+    )]
+    BrokenStableLink(PathBuf),
+    #[error("The stable link is disabled with `stable_link = false`")]
+    NoStableLink,
+    #[error("Workspace member {} has no ccpp.toml", .0)]
+    MissingMember(String),
+    #[error("There is no workspace member {}", .0)]
+    UnknownMember(String),
+    #[error(
+        "There is no template `{name}`, available templates: {}",
+        .available.join(", ")
+    )]
+    UnknownTemplate {
+        name: String,
+        available: Vec<String>,
+    },
+    #[error("There are no templates in {}", .0.to_string_lossy())]
+    NoTemplates(PathBuf),
+    #[error(
+        "{} is outside of the source directory {}",
+        .dir.to_string_lossy(),
+        .src_root.to_string_lossy()
+    )]
+    OutsideSrcRoot { dir: PathBuf, src_root: PathBuf },
+    #[error("There are no source files in {}", .0.to_string_lossy())]
+    NoSourcesInDir(PathBuf),
+    #[error("Select the workspace member with `-p <member>`")]
+    MemberRequired,
+    #[error("The entry file {} doesn't exist", .0.to_string_lossy())]
+    MissingEntry(PathBuf),
+    #[error(
+        "The object {} from `build.extra_objects` doesn't exist",
+        .0.to_string_lossy()
+    )]
+    MissingExtraObject(PathBuf),
+    #[error(
+        "The output {} exists, but it is not a file. Remove it and build \
+        again.",
+        .0.to_string_lossy()
+    )]
+    OutputNotFile(PathBuf),
+    #[error(
+        "AddressSanitizer runtime (libasan) is not available for {}. \
+        Install it or set `asan = false` or `asan = \"auto\"`.",
+        .0.to_string_lossy()
+    )]
+    AsanUnavailable(PathBuf),
+    #[error("Found {} unnecessary includes", .0)]
+    UnusedIncludes(usize),
+    #[error("include-what-you-use failed to analyse {} files", .0)]
+    IwyuFailed(usize),
     #[error("{}", .0)]
     Generic(String),
     #[error("This is a bug, please report it: {}", .0)]
     DoesNotHappen(&'static str),
     #[error(transparent)]
     Arg(#[from] ArgError),
+    #[error("Invalid value of `{key}` in the configuration: {err}")]
+    EnvExpansion { key: String, err: ExpandError },
     #[error(
         "child process exited with code {}",
         if let Some(c) = .0 { *c } else { 1 }
     )]
     ProcessFailed(Option<i32>),
+    #[error(
+        "Failed to {action} {}, the command {}: {command}",
+        .file.to_string_lossy(),
+        if let Some(c) = .code {
+            format!("exited with code {c}")
+        } else {
+            "was terminated".to_owned()
+        }
+    )]
+    JobFailed {
+        /// What the job did, e.g. `compile` or `link`
+        action: &'static str,
+        /// The source file or the file that was built
+        file: PathBuf,
+        command: String,
+        code: Option<i32>,
+    },
     #[error(transparent)]
     TomlSer(#[from] toml::ser::Error),
     #[error(transparent)]
     TomlDe(#[from] toml::de::Error),
+    #[error("Failed to parse {}:{}", .file.to_string_lossy(), .msg)]
+    TomlParse { file: PathBuf, msg: String },
+    #[error("Failed to {action} {}: {err}", .path.to_string_lossy())]
+    IoAt {
+        /// What failed, e.g. `read` or `create directory`
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
@@ -53,3 +194,33 @@ pub enum Error {
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
+
+impl Error {
+    /// Creates error of the operation (e.g. `read`) on the file.
+    pub fn io_at(
+        action: &'static str,
+        path: &Path,
+        err: std::io::Error,
+    ) -> Self {
+        Self::IoAt {
+            action,
+            path: path.to_owned(),
+            err,
+        }
+    }
+
+    /// Adds the path and the operation to the error if it is IO error
+    /// without them, other errors are kept as they are.
+    pub fn at_path(self, action: &'static str, path: &Path) -> Self {
+        match self {
+            Self::Io(e) => Self::io_at(action, path, e),
+            e => e,
+        }
+    }
+}
+
+impl<T> ResultExt<T> for std::io::Result<T> {
+    fn with_path(self, action: &'static str, path: &Path) -> Result<T> {
+        self.map_err(|e| Error::io_at(action, path, e))
+    }
+}
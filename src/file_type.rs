@@ -1,4 +1,4 @@
-use std::ffi::OsStr;
+use std::{ffi::OsStr, sync::OnceLock};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Language {
@@ -20,42 +20,131 @@ pub struct FileType {
     pub state: FileState,
 }
 
+/// Configuration of the recognized file extensions.
+#[derive(Debug, Clone)]
+pub struct ExtConfig {
+    /// When false, extensions are compared case insensitively, so `.C` is the
+    /// same as `.c`.
+    pub case_sensitive: bool,
+    /// Additional extensions of C source files
+    pub c_sources: Vec<String>,
+    /// Additional extensions of C++ source files
+    pub cpp_sources: Vec<String>,
+}
+
+/// All the known extensions and the types of the files.
+const EXTENSIONS: &[(&str, Language, FileState)] = &[
+    ("c", Language::C, FileState::Source),
+    ("C", Language::Cpp, FileState::Source),
+    ("cc", Language::Cpp, FileState::Source),
+    ("cpp", Language::Cpp, FileState::Source),
+    ("CPP", Language::Cpp, FileState::Source),
+    ("c++", Language::Cpp, FileState::Source),
+    ("cp", Language::Cpp, FileState::Source),
+    ("cxx", Language::Cpp, FileState::Source),
+    ("h", Language::C, FileState::Header),
+    ("H", Language::Cpp, FileState::Header),
+    ("hh", Language::Cpp, FileState::Header),
+    ("hpp", Language::Cpp, FileState::Header),
+    ("hxx", Language::Cpp, FileState::Header),
+    ("h++", Language::Cpp, FileState::Header),
+    // template implementation headers
+    ("ipp", Language::Cpp, FileState::Header),
+    ("tpp", Language::Cpp, FileState::Header),
+    ("tcc", Language::Cpp, FileState::Header),
+    ("txx", Language::Cpp, FileState::Header),
+    ("inl", Language::Cpp, FileState::Header),
+];
+
+static EXT_CONFIG: OnceLock<ExtConfig> = OnceLock::new();
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Sets the configuration of extensions. It can be set only once, later
+/// calls are ignored.
+pub fn configure(conf: ExtConfig) {
+    _ = EXT_CONFIG.set(conf);
+}
+
 impl FileType {
     pub fn from_ext(ext: &OsStr) -> Option<FileType> {
-        if ext == "c" {
-            Some(Self {
-                lang: Language::C,
-                state: FileState::Source,
-            })
-        } else if ext == "C"
-            || ext == "cc"
-            || ext == "cpp"
-            || ext == "CPP"
-            || ext == "c++"
-            || ext == "cp"
-            || ext == "cxx"
-        {
+        let conf = EXT_CONFIG.get_or_init(Default::default);
+
+        let ext = ext.to_string_lossy();
+        let ext = if conf.case_sensitive {
+            ext
+        } else {
+            ext.to_lowercase().into()
+        };
+
+        let source = |lang| {
             Some(Self {
-                lang: Language::Cpp,
+                lang,
                 state: FileState::Source,
             })
-        } else if ext == "h" {
-            Some(Self {
-                lang: Language::C,
-                state: FileState::Header,
-            })
-        } else if ext == "H"
-            || ext == "hh"
-            || ext == "hpp"
-            || ext == "hxx"
-            || ext == "h++"
+        };
+
+        if let Some((_, lang, state)) =
+            EXTENSIONS.iter().find(|(e, _, _)| *e == ext)
         {
             Some(Self {
-                lang: Language::Cpp,
-                state: FileState::Header,
+                lang: *lang,
+                state: *state,
             })
+        } else if conf.c_sources.iter().any(|e| conf.ext_eq(e, &ext)) {
+            source(Language::C)
+        } else if conf.cpp_sources.iter().any(|e| conf.ext_eq(e, &ext)) {
+            source(Language::Cpp)
         } else {
             None
         }
     }
+
+    /// Gets the language of the extension as it would be recognized by
+    /// compilers (always case sensitive and without the custom extensions).
+    pub fn builtin_lang(ext: &OsStr) -> Option<Language> {
+        EXTENSIONS
+            .iter()
+            .find(|(e, _, _)| *e == ext)
+            .map(|(_, lang, _)| *lang)
+    }
+
+    /// Checks whether the extension is extension of a source file.
+    pub fn is_source_ext(ext: &OsStr) -> bool {
+        matches!(
+            Self::from_ext(ext),
+            Some(FileType {
+                state: FileState::Source,
+                ..
+            })
+        )
+    }
+}
+
+impl Default for ExtConfig {
+    fn default() -> Self {
+        Self {
+            // the file systems on windows and macos are case insensitive by
+            // default
+            case_sensitive: !cfg!(any(windows, target_os = "macos")),
+            c_sources: vec![],
+            cpp_sources: vec![],
+        }
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+impl ExtConfig {
+    fn ext_eq(&self, a: &str, b: &str) -> bool {
+        if self.case_sensitive {
+            a == b
+        } else {
+            a.eq_ignore_ascii_case(b)
+        }
+    }
 }
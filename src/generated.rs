@@ -0,0 +1,23 @@
+use std::{fs, path::Path};
+
+use log::debug;
+
+use crate::err::Result;
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Writes the file, unless it already has the content, so that its
+/// dependents are not rebuilt.
+pub fn write_if_changed(path: &Path, content: &str) -> Result<()> {
+    if fs::read_to_string(path).is_ok_and(|c| c == content) {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    debug!("Generating {path:?}");
+    fs::write(path, content)?;
+    Ok(())
+}
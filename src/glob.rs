@@ -0,0 +1,74 @@
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Matches the path against glob pattern. `*` matches anything except `/`,
+/// `**` matches anything, `?` matches single character except `/` and
+/// `[...]` matches one of the characters (e.g. `[a-z_]`, `[!0-9]`).
+pub fn matches(pat: &str, path: &str) -> bool {
+    let pat: Vec<_> = pat.chars().collect();
+    let path: Vec<_> = path.chars().collect();
+    match_chars(&pat, &path)
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+fn match_chars(pat: &[char], path: &[char]) -> bool {
+    match pat {
+        [] => path.is_empty(),
+        ['*', '*', rest @ ..] => {
+            (0..=path.len()).any(|i| match_chars(rest, &path[i..]))
+        }
+        ['*', rest @ ..] => {
+            let end =
+                path.iter().position(|c| *c == '/').unwrap_or(path.len());
+            (0..=end).any(|i| match_chars(rest, &path[i..]))
+        }
+        ['?', rest @ ..] => {
+            matches!(path, [c, ..] if *c != '/')
+                && match_chars(rest, &path[1..])
+        }
+        ['[', rest @ ..] => match match_class(rest, path.first().copied()) {
+            Some((true, rest)) => match_chars(rest, &path[1..]),
+            Some((false, _)) => false,
+            // not a valid class, match `[` literally
+            None => matches!(path, ['[', ..]) && match_chars(rest, &path[1..]),
+        },
+        [p, rest @ ..] => {
+            matches!(path, [c, ..] if c == p) && match_chars(rest, &path[1..])
+        }
+    }
+}
+
+/// Matches the character against the class after `[`. Returns whether the
+/// character matches and the rest of the pattern after `]`, or [`None`] if
+/// the class is not terminated.
+fn match_class(pat: &[char], c: Option<char>) -> Option<(bool, &[char])> {
+    let (negate, mut pat) = match pat {
+        ['!' | '^', rest @ ..] => (true, rest),
+        _ => (false, pat),
+    };
+
+    let mut found = false;
+    let mut first = true;
+    loop {
+        match pat {
+            [']', rest @ ..] if !first => {
+                let res = c.is_some_and(|c| c != '/' && found != negate);
+                return Some((res, rest));
+            }
+            [a, '-', b, rest @ ..] if *b != ']' => {
+                found |= c.is_some_and(|c| (*a..=*b).contains(&c));
+                pat = rest;
+            }
+            [a, rest @ ..] => {
+                found |= c == Some(*a);
+                pat = rest;
+            }
+            [] => return None,
+        }
+        first = false;
+    }
+}
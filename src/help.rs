@@ -0,0 +1,813 @@
+use std::env;
+
+use termal::{formatc, gradient};
+
+use crate::err::{Error, Result};
+
+/// Description of action for the help.
+pub struct ActionInfo {
+    /// All the names of the action, the first is the main name
+    pub names: &'static [&'static str],
+    /// Arguments of the action (e.g. `<project folder>`)
+    pub args: &'static str,
+    pub description: &'static str,
+    pub example: &'static str,
+}
+
+/// Description of flag for the help.
+pub struct FlagInfo {
+    /// All the names of the flag
+    pub names: &'static [&'static str],
+    /// Name of the value of the flag (e.g. `<seconds>`)
+    pub value: &'static str,
+    pub description: &'static str,
+    /// Main names of the actions that use the flag
+    pub actions: &'static [&'static str],
+}
+
+/// Actions that use the build flags
+const BUILD: &[&str] = &["build", "run", "script"];
+
+/// All the actions that ccpp accepts.
+pub const ACTIONS: &[ActionInfo] = &[
+    ActionInfo {
+        names: &["help", "h", "-h", "-?", "--help"],
+        args: "[action]",
+        description: "Shows this help. If action is given, shows help only \
+            for the action. `ccpp <action> --help` also shows help for the \
+            action.",
+        example: "ccpp help build",
+    },
+    ActionInfo {
+        names: &["clean"],
+        args: "",
+        description: "Delete the build artifacts (binary and object files). \
+            The caches that speed up the next build are kept unless \
+            `--cache` or `--all` is used.",
+        example: "ccpp clean",
+    },
+    ActionInfo {
+        names: &["build"],
+        args: "[source file] [source file] ...",
+        description: "Build the source code. If source files are given, \
+            only their object files are built.",
+        example: "ccpp build -r",
+    },
+    ActionInfo {
+        names: &["run"],
+        args: "[-- [arg] [arg] ...]",
+        description: "Build the source and run the app with the arguments \
+            after `--`.",
+        example: "ccpp run -- --my-arg",
+    },
+    ActionInfo {
+        names: &["new"],
+        args: "<project folder>",
+        description: "Create a new project in the given folder. The project \
+            name will be the folder name. If the folder doesn't exist, it is \
+            created. The authors are taken from the git configuration.",
+        example: "ccpp new my-app --license mit",
+    },
+    ActionInfo {
+        names: &["script"],
+        args: "<source file> [--flags [flag] ...] [-- [arg] [arg] ...]",
+        description: "Build single source file without project and run it \
+            with the arguments after `--`. The arguments after `--flags` are \
+            passed to the compiler. The binary is cached in \
+            `$XDG_CACHE_HOME/ccpp/script` and rebuilt only when the file or \
+            its includes change.",
+        example: "ccpp script hello.c --flags -lm -- arg1",
+    },
+    ActionInfo {
+        names: &["generate"],
+        args: "<vscode>",
+        description: "Generate configuration for other tools. `vscode` \
+            generates `.vscode/tasks.json` with tasks for building, running \
+            and cleaning and `.vscode/launch.json` for debugging the debug \
+            build. Existing files are not overwritten.",
+        example: "ccpp generate vscode",
+    },
+    ActionInfo {
+        names: &["path"],
+        args: "",
+        description: "Print the absolute path to the binary without \
+            building it. The path reflects all the overrides (profile, bin \
+            directory and the name of the target).",
+        example: "ccpp path -r",
+    },
+    ActionInfo {
+        names: &["rdeps"],
+        args: "<file>",
+        description: "Print the sources that include the file, directly or \
+            through other headers, and whether their object files would be \
+            rebuilt.",
+        example: "ccpp rdeps src/util.h",
+    },
+    ActionInfo {
+        names: &["print-flags", "--print-flags"],
+        args: "<source file>",
+        description: "Print the exact command that would compile the source \
+            file, even if its object file is up to date. With unity build, \
+            the batch that includes the source is also shown.",
+        example: "ccpp print-flags src/main.c -r",
+    },
+    ActionInfo {
+        names: &["preprocess"],
+        args: "<source file>",
+        description: "Run only the preprocessor on the source file with the \
+            same flags that are used to compile it and print the output. See \
+            `--output` to write it to a file.",
+        example: "ccpp preprocess src/main.c -o main.i",
+    },
+    ActionInfo {
+        names: &["test"],
+        args: "",
+        description: "Build each source in the `tests` directory together \
+            with the project sources (except the ones that define `main`) \
+            into program and run it. The test fails if its program fails. \
+            See `--auto` for tests without `main`.",
+        example: "ccpp test --auto",
+    },
+    ActionInfo {
+        names: &["plan"],
+        args: "[source file] [source file] ...",
+        description: "Print the commands that `build` would run, without \
+            running them, each with the reason why it must run. If source \
+            files are given, only their object files are planned.",
+        example: "ccpp plan --json",
+    },
+    ActionInfo {
+        names: &["size"],
+        args: "<history>",
+        description: "`history` prints the sizes of the binary recorded by \
+            the last builds that linked it, with the changes between them.",
+        example: "ccpp size history -r",
+    },
+    ActionInfo {
+        names: &["version", "--version"],
+        args: "",
+        description: "Print the version of ccpp, the git commit and the \
+            target that it was built from and the default settings of the \
+            profiles. With `--verbose` also prints the C and C++ compilers \
+            that would be used (as `--compiler-version`).",
+        example: "ccpp --version --verbose",
+    },
+    ActionInfo {
+        names: &["--compiler-version"],
+        args: "",
+        description: "Print the output of `--version` of the C and C++ \
+            compilers that would be used to build the project (or outside of \
+            project, the compilers selected by default). Useful when \
+            reporting bugs.",
+        example: "ccpp --compiler-version -r",
+    },
+    ActionInfo {
+        names: &["config"],
+        args: "<show>",
+        description: "`show` prints the configuration of the project merged \
+            with the user configuration and the paths to the configuration \
+            files.",
+        example: "ccpp config show",
+    },
+];
+
+/// All the flags that ccpp accepts.
+pub const FLAGS: &[FlagInfo] = &[
+    FlagInfo {
+        names: &["-r", "--release"],
+        value: "",
+        description: "Build/run in release mode.",
+        actions: &[
+            "build",
+            "run",
+            "script",
+            "path",
+            "rdeps",
+            "plan",
+            "size",
+            "print-flags",
+            "preprocess",
+            "test",
+        ],
+    },
+    FlagInfo {
+        names: &["--runner"],
+        value: "<command>",
+        description: "Run the target with the given command (e.g. \
+            `valgrind` or `gdb`). This overrides the `runner` from the \
+            `[run]` section in ccpp.toml.",
+        actions: &["run", "script"],
+    },
+    FlagInfo {
+        names: &["--cflag"],
+        value: "<flag>",
+        description: "Pass the flag to the compiler when compiling all the \
+            objects, in addition to the flags from the configuration. May be \
+            given multiple times. The objects are rebuilt when the flags \
+            change.",
+        actions: &[
+            "build",
+            "run",
+            "script",
+            "plan",
+            "print-flags",
+            "preprocess",
+            "test",
+        ],
+    },
+    FlagInfo {
+        names: &["--ldflag"],
+        value: "<flag>",
+        description: "Pass the flag to the compiler when linking, in \
+            addition to the flags from the configuration. May be given \
+            multiple times.",
+        actions: &["build", "run", "script", "plan", "test"],
+    },
+    FlagInfo {
+        names: &["-j", "--jobs"],
+        value: "<count>",
+        description: "Run at most the given number of compile/link jobs at \
+            the same time. When not set, the job count from `MAKEFLAGS` is \
+            used if ccpp is invoked from make.",
+        actions: &["build", "run", "script", "test"],
+    },
+    FlagInfo {
+        names: &["--timeout", "--job-timeout"],
+        value: "<duration>",
+        description: "Kill compile/link jobs that run longer than the given \
+            time (e.g. `300s`, `5m`, number without unit is in seconds). The \
+            killed job fails and the build continues as with other failures \
+            (see `--keep-going`). This overrides `job_timeout` from \
+            ccpp.toml.",
+        actions: BUILD,
+    },
+    FlagInfo {
+        names: &["-k", "--keep-going"],
+        value: "",
+        description: "Continue building other files when some file fails to \
+            build.",
+        actions: BUILD,
+    },
+    FlagInfo {
+        names: &["--retry-failed"],
+        value: "",
+        description: "First compile the source files that failed to compile \
+            in the previous build and only then build the rest.",
+        actions: BUILD,
+    },
+    FlagInfo {
+        names: &["--max-errors"],
+        value: "<count>",
+        description: "Stop starting new compile/link jobs after the given \
+            number of failures.",
+        actions: BUILD,
+    },
+    FlagInfo {
+        names: &["--retry"],
+        value: "<count>",
+        description: "Retry compile/link job up to the given number of \
+            times when the compiler crashes (is killed by signal or reports \
+            internal compiler error). Jobs that fail because of errors in the \
+            code are not retried.",
+        actions: BUILD,
+    },
+    FlagInfo {
+        names: &["--events"],
+        value: "",
+        description: "Print events about the build to stdout as newline \
+            delimited JSON instead of printing the commands. Each event is \
+            object with the field `event` (`started`, `finished` or \
+            `failed`) and `files` that are built.",
+        actions: BUILD,
+    },
+    FlagInfo {
+        names: &["--diagnostics-format"],
+        value: "<github|sarif>",
+        description: "Report warnings and errors from the compiler as GitHub \
+            workflow commands printed to stdout (`github`) or to the file \
+            `diagnostics.sarif` in the bin directory of the profile \
+            (`sarif`).",
+        actions: BUILD,
+    },
+    FlagInfo {
+        names: &["--cache"],
+        value: "",
+        description: "Also delete the caches (e.g. the list of files that \
+            failed to compile) and the shared object cache if the project \
+            uses it.",
+        actions: &["clean"],
+    },
+    FlagInfo {
+        names: &["--all"],
+        value: "",
+        description: "Delete the whole bin directory.",
+        actions: &["clean"],
+    },
+    FlagInfo {
+        names: &["-p", "--package"],
+        value: "<member>",
+        description: "Use only the given member of the workspace. This is \
+            required by `run` and `path` in workspace.",
+        actions: &["clean", "build", "run", "path"],
+    },
+    FlagInfo {
+        names: &["--bin-dir"],
+        value: "<dir>",
+        description: "Use the given directory for all files generated by \
+            ccpp instead of the directory from the configuration. The \
+            directory may be outside of the project, so the project itself \
+            doesn't have to be writable. The directory may be also set with \
+            the `CCPP_BIN_ROOT` environment variable.",
+        actions: &[
+            "clean",
+            "build",
+            "run",
+            "script",
+            "generate",
+            "path",
+            "plan",
+            "print-flags",
+            "preprocess",
+            "test",
+        ],
+    },
+    FlagInfo {
+        names: &["--no-user-config"],
+        value: "",
+        description: "Don't use the user configuration (e.g. \
+            `~/.config/ccpp/config.toml`).",
+        actions: &[
+            "clean",
+            "build",
+            "run",
+            "new",
+            "script",
+            "generate",
+            "config",
+            "path",
+            "plan",
+            "print-flags",
+            "preprocess",
+            "test",
+        ],
+    },
+    FlagInfo {
+        names: &["--no-wait"],
+        value: "",
+        description: "Fail immediately if the build directory is locked by \
+            another running ccpp instead of waiting for it to finish.",
+        actions: &["clean", "build", "run", "script", "plan", "test"],
+    },
+    FlagInfo {
+        names: &["--no-build"],
+        value: "",
+        description: "Run the existing binary without building it. Fails if \
+            the binary doesn't exist.",
+        actions: &["run"],
+    },
+    FlagInfo {
+        names: &["--stable"],
+        value: "",
+        description: "Print the stable path `<bin>/<name>` that points to \
+            the binary of the last built profile.",
+        actions: &["path"],
+    },
+    FlagInfo {
+        names: &["--license"],
+        value: "<mit|apache-2.0>",
+        description: "Set the license of the new project and write its text \
+            to `LICENSE`.",
+        actions: &["new"],
+    },
+    FlagInfo {
+        names: &["--template"],
+        value: "<name>",
+        description: "Copy the files from the user template \
+            `~/.config/ccpp/templates/<name>/` to the new project. `{{name}}` \
+            in the file names and contents is replaced with the name of the \
+            project.",
+        actions: &["new"],
+    },
+    FlagInfo {
+        names: &["--dir"],
+        value: "<dir>",
+        description: "Build only the object files of the sources in the \
+            given directory (in addition to the given source files). The \
+            directory must be inside the source directory.",
+        actions: &["build", "plan"],
+    },
+    FlagInfo {
+        names: &["--std-matrix"],
+        value: "<stds>",
+        description: "Build the project once with each of the comma \
+            separated standards (e.g. `c11,c17,c23` or `c++17,c++20`) and \
+            report which of them passed. Names starting with `c++` or \
+            `gnu++` set the C++ standard, other names set the C standard and \
+            numbers set both. Each standard is built into its own \
+            `std-<std>` directory in the bin directory.",
+        actions: &["build"],
+    },
+    FlagInfo {
+        names: &["--json"],
+        value: "",
+        description: "Print the plan as JSON object with list of commands. \
+            Each command has the program, its arguments, the files that it \
+            requires and provides and the reason why it runs.",
+        actions: &["plan"],
+    },
+    FlagInfo {
+        names: &["--auto"],
+        value: "",
+        description: "Generate `main` for each test source that runs its \
+            `void test_*(void)` functions and reports which of them failed. \
+            The tests can include `ccpp_assert.h` and use `CCPP_ASSERT` to \
+            fail. Test sources that define their own `main` are run as \
+            they are.",
+        actions: &["test"],
+    },
+    FlagInfo {
+        names: &["-o", "--output"],
+        value: "<file>",
+        description: "Write the preprocessed source to the file instead of \
+            printing it.",
+        actions: &["preprocess"],
+    },
+    FlagInfo {
+        names: &["--iwyu"],
+        value: "",
+        description: "After building, check the includes of all source files \
+            with include-what-you-use and print its suggestions.",
+        actions: BUILD,
+    },
+    FlagInfo {
+        names: &["--iwyu-strict"],
+        value: "",
+        description: "Same as `--iwyu`, but fail if there are any \
+            unnecessary includes or if a source cannot be analysed.",
+        actions: BUILD,
+    },
+    FlagInfo {
+        names: &["-q", "--quiet"],
+        value: "",
+        description: "Don't print the compiler commands and the path to the \
+            built binary.",
+        actions: &["build", "run", "script", "test"],
+    },
+    FlagInfo {
+        names: &["--print-outputs"],
+        value: "",
+        description: "After successful build, print the absolute paths of \
+            all the produced files (the binary, objects and dependency \
+            files), one per line. With `--events` they are printed as single \
+            `outputs` event.",
+        actions: &["build"],
+    },
+    FlagInfo {
+        names: &["--size-report"],
+        value: "[=binary|objects]",
+        description: "After successful build, print the size of the binary \
+            and its change since the previous build. With `objects` also \
+            print the ten largest object files.",
+        actions: &["build", "run"],
+    },
+    FlagInfo {
+        names: &["-v", "--verbose"],
+        value: "",
+        description: "Print debug logs to stderr. Can be repeated (or used \
+            as `-vv`, `-vvv`) to increase the verbosity: `-v` shows the build \
+            phases, `-vv` shows decisions for each file and `-vvv` also shows \
+            events from the include scanner.",
+        actions: &[
+            "clean", "build", "run", "new", "script", "generate", "config",
+            "plan",
+        ],
+    },
+];
+
+/// Width used when the width of the terminal is not known.
+const DEFAULT_WIDTH: usize = 79;
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Prints the help. If `action` is given, prints only help for the action.
+pub fn print(action: Option<&str>) -> Result<()> {
+    let mut help = header();
+    match action {
+        None => render_all(&mut help),
+        Some(name) => {
+            let action = find_action(name).ok_or_else(|| {
+                let names = ACTIONS.iter().flat_map(|a| a.names.iter());
+                match closest(name, names.copied()) {
+                    Some(c) => Error::Generic(format!(
+                        "Unknown action `{name}`, did you mean `{c}`?"
+                    )),
+                    None => Error::Generic(format!("Unknown action `{name}`")),
+                }
+            })?;
+            render_action(&mut help, action);
+        }
+    }
+
+    let width = terminal_width().unwrap_or(DEFAULT_WIDTH);
+    println!("{}", wrap_text(&help, width));
+    Ok(())
+}
+
+/// Finds the name that is most similar to `name`. Returns [`None`] if no
+/// name is similar enough.
+pub fn closest<'a, I>(name: &str, names: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    names
+        .into_iter()
+        .map(|n| (edit_distance(name, n), n))
+        .filter(|(d, n)| *d <= 2 && *d < n.len())
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, n)| n)
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+fn header() -> String {
+    let v: Option<&str> = option_env!("CARGO_PKG_VERSION");
+    formatc!(
+        "Welcome to {'g i}ccpp{'_} help by {}{'_}
+Version: {}
+",
+        gradient("BonnyAD9", (250, 50, 170), (180, 50, 240)),
+        v.unwrap_or("unknown")
+    )
+}
+
+fn render_all(res: &mut String) {
+    res.push_str(&formatc!(
+        "
+{'g}Usage:
+  {'w}ccpp <action>{'_} {'gr}[flags] [-- [arg] [arg] ...]
+
+{'g}Actions:{'_}
+"
+    ));
+    for a in ACTIONS {
+        render_action_entry(res, a);
+    }
+
+    res.push_str(&formatc!("{'g}Flags:{'_}\n"));
+    for f in FLAGS {
+        render_flag(res, f);
+    }
+    // the last line is added by println
+    res.pop();
+}
+
+fn render_action(res: &mut String, action: &ActionInfo) {
+    res.push_str(&formatc!(
+        "
+{'g}Usage:
+  {'w}ccpp {}{'_}{} {'gr}[flags]{'_}
+
+  {}
+
+",
+        action.names[0],
+        colored_args(action.args),
+        action.description
+    ));
+
+    if action.names.len() > 1 {
+        res.push_str(&formatc!(
+            "{'g}Aliases:{'_}\n  {}\n\n",
+            action.names[1..].join("  ")
+        ));
+    }
+
+    let main = action.names[0];
+    let mut flags = FLAGS.iter().filter(|f| f.actions.contains(&main));
+    if let Some(f) = flags.next() {
+        res.push_str(&formatc!("{'g}Flags:{'_}\n"));
+        render_flag(res, f);
+        for f in flags {
+            render_flag(res, f);
+        }
+    }
+
+    res.push_str(&formatc!("{'g}Example:{'_}\n  {'w}{}{'_}", action.example));
+}
+
+fn render_action_entry(res: &mut String, action: &ActionInfo) {
+    res.push_str(&formatc!(
+        "  {'y}{}{'_}{}\n    {}\n\n",
+        action.names.join("  "),
+        colored_args(action.args),
+        action.description
+    ));
+}
+
+fn render_flag(res: &mut String, flag: &FlagInfo) {
+    let value = if flag.value.is_empty() {
+        String::new()
+    } else {
+        formatc!(" {'w}{}{'_}", flag.value)
+    };
+    res.push_str(&formatc!(
+        "  {'y}{}{'_}{}\n    {}\n\n",
+        flag.names.join("  "),
+        value,
+        flag.description
+    ));
+}
+
+/// Colors the arguments of action, optional arguments are gray.
+fn colored_args(args: &str) -> String {
+    if args.is_empty() {
+        String::new()
+    } else if args.starts_with('[') {
+        formatc!(" {'gr}{}{'_}", args)
+    } else {
+        formatc!(" {'w}{}{'_}", args)
+    }
+}
+
+fn find_action(name: &str) -> Option<&'static ActionInfo> {
+    ACTIONS.iter().find(|a| a.names.contains(&name))
+}
+
+/// Computes the levenshtein distance of the two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<_> = b.chars().collect();
+    let mut prev: Vec<_> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let sub = prev[j] + (ca != *cb) as usize;
+            cur.push(sub.min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Gets the width of the terminal. If the output is not terminal, the
+/// `COLUMNS` environment variable is used (it is usually not exported).
+fn terminal_width() -> Option<usize> {
+    terminal_size::terminal_size()
+        .map(|(w, _)| w.0 as usize)
+        .filter(|w| *w > 0)
+        .or_else(|| parse_columns(env::var("COLUMNS").ok()?.as_str()))
+}
+
+fn parse_columns(columns: &str) -> Option<usize> {
+    columns.trim().parse().ok().filter(|w| *w > 0)
+}
+
+/// Wraps lines that are longer than `width` at spaces. Wrapped lines keep the
+/// indentation of the original line and the overflowing words are moved to
+/// the following line if it has the same indentation. Escape sequences are
+/// not counted to the length.
+fn wrap_text(text: &str, width: usize) -> String {
+    let mut res = String::new();
+    // words that didn't fit on the previous line and its indentation
+    let mut carry: Vec<&str> = vec![];
+    let mut carry_indent = "";
+
+    for line in text.lines() {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let content = line.trim_start();
+
+        if !carry.is_empty() && (indent != carry_indent || content.is_empty())
+        {
+            push_wrapped(&mut res, carry_indent, &carry, width);
+            carry.clear();
+        }
+
+        if carry.is_empty() && visible_len(line) <= width {
+            res.push_str(line);
+            res.push('\n');
+            continue;
+        }
+
+        carry.extend(content.split(' '));
+        carry_indent = indent;
+
+        // keep the last line in carry so that it can be joined with the next
+        // line
+        let mut len = indent.len();
+        let mut start = 0;
+        for (i, w) in carry.iter().enumerate() {
+            let w_len = visible_len(w);
+            if i != start && len + 1 + w_len > width {
+                push_wrapped(&mut res, indent, &carry[start..i], usize::MAX);
+                start = i;
+                len = indent.len();
+            } else if i != start {
+                len += 1;
+            }
+            len += w_len;
+        }
+        carry.drain(..start);
+    }
+
+    if !carry.is_empty() {
+        push_wrapped(&mut res, carry_indent, &carry, width);
+    }
+
+    // `lines` drops the trailing newline
+    res.pop();
+    res
+}
+
+/// Pushes the words to the result, wrapping them at `width`.
+fn push_wrapped(res: &mut String, indent: &str, words: &[&str], width: usize) {
+    let mut len = 0;
+    for w in words {
+        let w_len = visible_len(w);
+        if len != 0 && len + 1 + w_len > width {
+            res.push('\n');
+            len = 0;
+        }
+        if len == 0 {
+            res.push_str(indent);
+            len = indent.len();
+        } else {
+            res.push(' ');
+            len += 1;
+        }
+        res.push_str(w);
+        len += w_len;
+    }
+    res.push('\n');
+}
+
+/// Gets the number of characters in the string that are not part of escape
+/// sequences.
+fn visible_len(s: &str) -> usize {
+    let mut len = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip to the end of the escape sequence
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            len += 1;
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_fallback() {
+        assert_eq!(parse_columns("120"), Some(120));
+        assert_eq!(parse_columns(" 80\n"), Some(80));
+        assert_eq!(parse_columns("0"), None);
+        assert_eq!(parse_columns("wide"), None);
+    }
+
+    #[test]
+    fn short_lines_are_kept() {
+        let text = "short line\n  indented\n\nend";
+        assert_eq!(wrap_text(text, 20), text);
+    }
+
+    #[test]
+    fn long_line_keeps_indent() {
+        let text = "  one two three four five";
+        assert_eq!(wrap_text(text, 12), "  one two\n  three four\n  five");
+    }
+
+    #[test]
+    fn overflow_joins_next_line_with_same_indent() {
+        let text = "  one two three\n  four\nnext";
+        assert_eq!(wrap_text(text, 12), "  one two\n  three four\nnext");
+    }
+
+    #[test]
+    fn escape_sequences_have_no_width() {
+        let text = "\x1b[1mbold\x1b[0m word";
+        assert_eq!(visible_len(text), 9);
+        assert_eq!(wrap_text(text, 9), text);
+    }
+
+    #[test]
+    fn non_ascii_is_counted_by_characters() {
+        assert_eq!(visible_len("příliš žluťoučký"), 16);
+        assert_eq!(wrap_text("příliš žluťoučký", 10), "příliš\nžluťoučký");
+    }
+
+    #[test]
+    fn long_word_is_not_split() {
+        assert_eq!(wrap_text("a verylongword b", 5), "a\nverylongword\nb");
+    }
+}
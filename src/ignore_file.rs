@@ -0,0 +1,133 @@
+use std::{
+    fs, io,
+    path::{Component, Path},
+};
+
+use crate::{
+    err::{Error, Result},
+    glob,
+};
+
+/// Rules from `.ccppignore` file in the gitignore syntax.
+#[derive(Default)]
+pub struct IgnoreFile {
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    pattern: String,
+    /// The rule re-includes the matching files (`!pattern`)
+    negate: bool,
+    /// The rule matches only directories (`pattern/`)
+    dir_only: bool,
+    /// The pattern is matched against the whole path relative to the
+    /// project, otherwise it may match at any level
+    anchored: bool,
+}
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+impl IgnoreFile {
+    /// Loads the rules from the file. If the file doesn't exist, nothing is
+    /// ignored.
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(s) => Ok(Self::parse(&s)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            Err(e) => Err(Error::io_at("read", path, e)),
+        }
+    }
+
+    /// Parses the rules in the gitignore syntax.
+    pub fn parse(s: &str) -> Self {
+        Self {
+            rules: s.lines().filter_map(Rule::parse).collect(),
+        }
+    }
+
+    /// Checks whether the path relative to the project is ignored. The last
+    /// matching rule decides.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+
+        let parts: Vec<_> = path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(c) => Some(c.to_string_lossy()),
+                _ => None,
+            })
+            .collect();
+        let path = parts.join("/");
+
+        self.rules
+            .iter()
+            .rev()
+            .find(|r| r.matches(&path, &parts, is_dir))
+            .is_some_and(|r| !r.negate)
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end_matches(['\r', ' ']);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negate, line) = match line.strip_prefix('!') {
+            Some(l) => (true, l),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(l) => (true, l),
+            None => (false, line),
+        };
+        // pattern with `/` in the middle is relative to the project
+        let anchored = line.contains('/');
+        let pattern = line.strip_prefix('/').unwrap_or(line);
+        if pattern.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            pattern: pattern.to_owned(),
+            negate,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(
+        &self,
+        path: &str,
+        parts: &[impl AsRef<str>],
+        is_dir: bool,
+    ) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            return glob::matches(&self.pattern, path);
+        }
+
+        // match the pattern with the path from any level
+        (0..parts.len()).any(|i| {
+            let sub: Vec<_> = parts[i..].iter().map(|p| p.as_ref()).collect();
+            glob::matches(&self.pattern, &sub.join("/"))
+        })
+    }
+}
@@ -1,10 +1,16 @@
+use log::trace;
 use utf8_chars::{BufReadCharsExt, Chars};
 
-use crate::{dependency::DepFile, err::Result};
+use crate::{
+    dependency::DepFile,
+    err::{Result, ResultExt},
+};
 use std::{
+    collections::VecDeque,
+    fmt::Display,
     fs::File,
     io::{BufRead, BufReader},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 macro_rules! next_chr {
@@ -21,14 +27,46 @@ pub struct IncFile {
     pub path: PathBuf,
     // when true file included as `"file"` otherwise included as `<file>`
     pub relative: bool,
+    /// Location of the `#` of the directive
+    pub loc: SourceLoc,
+}
+
+/// Location in the source file, both the line and the column (in
+/// characters) start at 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SourceLoc {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// Preprocessor directive that affects the build.
+pub enum Directive {
+    Include(IncFile),
+    /// Library requested with `#pragma comment(lib, "name")`
+    Lib(String),
 }
 
+/// Reads characters of the source with the lines spliced: backslash (or
+/// trigraph `??/`) followed by newline is removed. Whitespace between the
+/// backslash and the newline is also accepted (as gcc does). Both `\n` and
+/// `\r` end lines (see [`is_newline`]), so CRLF and CR line endings work.
+/// Other trigraphs are not replaced.
 struct CharReader<'a, R>
 where
     R: BufRead,
 {
     chars: Chars<'a, R>,
     cur: char,
+    /// Location of [`Self::cur`]
+    loc: SourceLoc,
+    /// Location of the next character that will be read
+    next_loc: SourceLoc,
+    /// The last read character was `\r`, so `\n` doesn't start new line
+    prev_cr: bool,
+    /// The end of the input was reached
+    eof: bool,
+    /// Characters that were read ahead to check for line continuation
+    pending: VecDeque<char>,
 }
 
 impl<'a, R> CharReader<'a, R>
@@ -39,61 +77,106 @@ where
         Self {
             chars: read.chars(),
             cur: ' ',
+            loc: SourceLoc::START,
+            next_loc: SourceLoc::START,
+            prev_cr: false,
+            eof: false,
+            pending: VecDeque::new(),
         }
     }
 
-    fn esc_read_while<F>(&mut self, f: F) -> Result<String>
+    fn read_while<F>(&mut self, f: F) -> Result<String>
     where
         F: Fn(char) -> bool,
     {
         let mut res = String::new();
-
-        loop {
-            if self.cur == '\\' {
-                next_chr!(self, res);
-                if self.cur != '\n' {
-                    if !f(self.cur) {
-                        break Ok(res);
-                    }
-                    res.push(self.cur);
-                    continue;
-                }
-                next_chr!(self, res);
-                continue;
-            }
-
-            if !f(self.cur) {
-                break Ok(res);
-            }
-
+        while f(self.cur) {
             res.push(self.cur);
             next_chr!(self, res);
         }
+        Ok(res)
     }
 
-    fn esc_skip_while<F>(&mut self, f: F) -> Result<()>
+    fn skip_while<F>(&mut self, f: F) -> Result<()>
     where
         F: Fn(char) -> bool,
     {
-        loop {
-            if self.cur == '\\' {
-                next_chr!(self, ());
-                if self.cur != '\n' {
-                    if !f(self.cur) {
-                        break Ok(());
-                    }
-                    continue;
-                }
-                next_chr!(self, ());
-                continue;
+        while f(self.cur) {
+            next_chr!(self, ());
+        }
+        Ok(())
+    }
+
+    /// Gets the next character without line splicing.
+    fn next_raw(&mut self) -> Option<Result<char>> {
+        let c = match self.pending.pop_front() {
+            Some(c) => c,
+            None => match self.chars.next()? {
+                Ok(c) => c,
+                Err(e) => return Some(Err(e.into())),
+            },
+        };
+        self.advance(c);
+        Some(Ok(c))
+    }
+
+    /// Moves [`Self::next_loc`] after the read character.
+    fn advance(&mut self, c: char) {
+        match c {
+            '\n' if self.prev_cr => {}
+            c if is_newline(c) => {
+                self.next_loc.line += 1;
+                self.next_loc.col = 1;
+            }
+            _ => self.next_loc.col += 1,
+        }
+        self.prev_cr = c == '\r';
+    }
+
+    /// Gets the character at the given position after the last read
+    /// character without consuming it.
+    fn peek(&mut self, i: usize) -> Result<Option<char>> {
+        while self.pending.len() <= i {
+            match self.chars.next() {
+                Some(c) => self.pending.push_back(c?),
+                None => return Ok(None),
             }
+        }
+        Ok(Some(self.pending[i]))
+    }
 
-            if !f(self.cur) {
-                break Ok(());
+    /// Checks whether the character starts line continuation. If it does, the
+    /// rest of the continuation is consumed.
+    fn splice(&mut self, c: char) -> Result<bool> {
+        let mut i = match c {
+            '\\' => 0,
+            '?' if self.peek(0)? == Some('?')
+                && self.peek(1)? == Some('/') =>
+            {
+                2
             }
+            _ => return Ok(false),
+        };
 
-            next_chr!(self, ());
+        while let Some(c) = self.peek(i)? {
+            if is_newline(c) {
+                for _ in 0..=i {
+                    if let Some(c) = self.pending.pop_front() {
+                        self.advance(c);
+                    }
+                }
+                if c == '\r' && self.peek(0)? == Some('\n') {
+                    self.pending.pop_front();
+                    self.advance('\n');
+                }
+                return Ok(true);
+            }
+            if !c.is_whitespace() {
+                break;
+            }
+            i += 1;
         }
+        Ok(false)
     }
 }
 
@@ -104,58 +187,87 @@ where
     type Item = Result<char>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.chars.next() {
-            Some(Ok(c)) => {
-                self.cur = c;
-                Some(Ok(c))
+        loop {
+            let loc = self.next_loc;
+            let c = match self.next_raw() {
+                Some(Ok(c)) => c,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.eof = true;
+                    return None;
+                }
+            };
+            match self.splice(c) {
+                Ok(true) => continue,
+                Ok(false) => {
+                    self.cur = c;
+                    self.loc = loc;
+                    return Some(Ok(c));
+                }
+                Err(e) => return Some(Err(e)),
             }
-            Some(Err(e)) => Some(Err(e.into())),
-            None => None,
         }
     }
 }
 
-pub fn get_included_files(file: DepFile) -> Result<Vec<IncFile>> {
-    let mut res = vec![];
+pub fn get_directives(file: DepFile) -> Result<Vec<Directive>> {
+    trace!("Scanning {:?} for includes", file.path);
+    let mut read =
+        BufReader::new(File::open(&file).with_path("read", &file.path)?);
+    read_directives(&mut read).map_err(|e| e.at_path("read", &file.path))
+}
 
-    let mut file = BufReader::new(File::open(file)?);
-    let mut chars = CharReader::new(&mut file);
+fn read_directives<R>(read: &mut R) -> Result<Vec<Directive>>
+where
+    R: BufRead,
+{
+    let mut res = vec![];
+    let mut chars = CharReader::new(read);
 
     next_chr!(chars, res);
 
     let mut prev_newline = true;
-    loop {
+    // the reading functions return at the end of the input without moving
+    // to other character
+    while !chars.eof {
         match chars.cur {
-            '\n' => {
+            c if is_newline(c) => {
                 prev_newline = true;
                 next_chr!(chars, res);
             }
             c if c.is_whitespace() => next_chr!(chars, res),
-            '#' if prev_newline => {
-                if let Some(f) = read_macro(&mut chars)? {
-                    res.push(f);
+            '#' if prev_newline => match read_macro(&mut chars)? {
+                Some(Directive::Include(f)) => {
+                    trace!(
+                        "Found include {:?} at {} (relative: {})",
+                        f.path,
+                        f.loc,
+                        f.relative
+                    );
+                    res.push(Directive::Include(f));
                     prev_newline = true;
                 }
-            }
-            '\'' => {
-                prev_newline = false;
-                read_char(&mut chars)?;
-            }
-            '"' => {
+                Some(Directive::Lib(l)) => {
+                    trace!("Found library {l:?}");
+                    res.push(Directive::Lib(l));
+                    prev_newline = true;
+                }
+                None => {}
+            },
+            '\'' | '"' => {
                 prev_newline = false;
-                read_string(&mut chars)?;
+                read_quoted(&mut chars, None)?;
             }
             '/' => {
                 next_chr!(chars, res);
                 if chars.cur == '*' {
+                    // the comment is whitespace, so directive may follow it
                     read_multiline_comment(&mut chars)?;
-                    prev_newline = false;
                 } else if chars.cur == '/' {
                     read_line_comment(&mut chars)?;
                     prev_newline = false;
                 } else {
                     prev_newline = false;
-                    next_chr!(chars, res);
                 }
             }
             _ => {
@@ -164,72 +276,192 @@ pub fn get_included_files(file: DepFile) -> Result<Vec<IncFile>> {
             }
         }
     }
+
+    Ok(res)
+}
+
+/// Gets the content of the file that is significant for the compiler.
+/// Comments are removed and whitespace is normalized: sequence of whitespace
+/// is replaced with single newline if it contains newline, otherwise with
+/// single space. String and character literals are kept verbatim.
+pub fn significant_content(file: &Path) -> Result<String> {
+    trace!("Reading significant content of {file:?}");
+    let mut read = BufReader::new(File::open(file).with_path("read", file)?);
+    read_significant(&mut read).map_err(|e| e.at_path("read", file))
+}
+
+fn read_significant<R>(read: &mut R) -> Result<String>
+where
+    R: BufRead,
+{
+    let mut res = String::new();
+    let mut chars = CharReader::new(read);
+
+    next_chr!(chars, res);
+
+    // whitespace before the current character, it is written only between
+    // other characters
+    let mut space = None;
+    let flush = |res: &mut String, space: &mut Option<char>| {
+        if let Some(s) = space.take() {
+            if !res.is_empty() {
+                res.push(s);
+            }
+        }
+    };
+
+    while !chars.eof {
+        match chars.cur {
+            c if c.is_whitespace() => {
+                if is_newline(c) || space == Some('\n') {
+                    space = Some('\n');
+                } else {
+                    space = Some(' ');
+                }
+                next_chr!(chars, res);
+            }
+            '\'' | '"' => {
+                flush(&mut res, &mut space);
+                read_quoted(&mut chars, Some(&mut res))?;
+            }
+            '/' => {
+                if chars.next().transpose()?.is_none() {
+                    flush(&mut res, &mut space);
+                    res.push('/');
+                    break;
+                }
+                if chars.cur == '*' {
+                    read_multiline_comment(&mut chars)?;
+                    space.get_or_insert(' ');
+                } else if chars.cur == '/' {
+                    read_line_comment(&mut chars)?;
+                    space.get_or_insert(' ');
+                } else {
+                    flush(&mut res, &mut space);
+                    res.push('/');
+                }
+            }
+            c => {
+                flush(&mut res, &mut space);
+                res.push(c);
+                next_chr!(chars, res);
+            }
+        }
+    }
+
+    Ok(res)
 }
 
-fn read_macro<R>(chars: &mut CharReader<R>) -> Result<Option<IncFile>>
+fn read_macro<R>(chars: &mut CharReader<R>) -> Result<Option<Directive>>
 where
     R: BufRead,
 {
+    let loc = chars.loc;
     next_chr!(chars, None);
-    chars.esc_skip_while(|c| c.is_whitespace())?;
+    chars.skip_while(|c| c.is_whitespace())?;
+
+    let mac = chars.read_while(|c| c.is_alphanumeric())?;
 
-    let mac = chars.esc_read_while(|c| c.is_alphanumeric())?;
+    if mac == "pragma" {
+        let lib = read_pragma_lib(chars)?;
+        chars.skip_while(|c| !is_newline(c))?;
+        return Ok(lib.map(Directive::Lib));
+    }
 
+    // the text of other directives is not tokenized, it may contain
+    // unbalanced quotes (e.g. `#error don't`)
     if mac != "include" {
-        return chars.esc_skip_while(|c| c != '\n').map(|_| None);
+        return chars.skip_while(|c| !is_newline(c)).map(|_| None);
     }
 
-    chars.esc_skip_while(|c| c.is_whitespace())?;
+    chars.skip_while(|c| c.is_whitespace())?;
 
-    match chars.cur {
-        '<' => {
-            next_chr!(chars, None);
-            let res = chars.esc_read_while(|c| c != '>')?;
-            next_chr!(chars, None);
-            Ok(Some(IncFile {
-                path: res.into(),
-                relative: false,
-            }))
-        }
-        '"' => {
-            next_chr!(chars, None);
-            let res = chars.esc_read_while(|c| c != '"')?;
-            next_chr!(chars, None);
-            Ok(Some(IncFile {
-                path: res.into(),
-                relative: true,
-            }))
-        }
-        _ => chars.esc_skip_while(|c| c != '\n').map(|_| None),
+    let end = match chars.cur {
+        '<' => '>',
+        '"' => '"',
+        _ => return chars.skip_while(|c| !is_newline(c)).map(|_| None),
+    };
+    next_chr!(chars, None);
+    let res = chars.read_while(|c| c != end && !is_newline(c))?;
+    if chars.cur != end {
+        return Ok(None);
     }
+    // the include may be at the end of file without newline
+    chars.next().transpose()?;
+    Ok(Some(Directive::Include(IncFile {
+        path: res.into(),
+        relative: end == '"',
+        loc,
+    })))
 }
 
-fn read_char<R>(chars: &mut CharReader<R>) -> Result<()>
+/// Reads the library name from `comment(lib, "name")` after `#pragma`. The
+/// rest of the line is not consumed.
+fn read_pragma_lib<R>(chars: &mut CharReader<R>) -> Result<Option<String>>
 where
     R: BufRead,
 {
-    next_chr!(chars, ());
-    while chars.cur != '\'' {
-        if chars.cur == '\\' {
-            next_chr!(chars, ());
-        }
-        next_chr!(chars, ());
-    }
+    let ws = |c: char| c.is_whitespace() && !is_newline(c);
 
-    Ok(())
+    chars.skip_while(ws)?;
+    if chars.read_while(|c| c.is_alphanumeric())? != "comment" {
+        return Ok(None);
+    }
+    chars.skip_while(ws)?;
+    if chars.cur != '(' {
+        return Ok(None);
+    }
+    next_chr!(chars, None);
+    chars.skip_while(ws)?;
+    if chars.read_while(|c| c.is_alphanumeric())? != "lib" {
+        return Ok(None);
+    }
+    chars.skip_while(ws)?;
+    if chars.cur != ',' {
+        return Ok(None);
+    }
+    next_chr!(chars, None);
+    chars.skip_while(ws)?;
+    if chars.cur != '"' {
+        return Ok(None);
+    }
+    next_chr!(chars, None);
+    let lib = chars.read_while(|c| c != '"' && !is_newline(c))?;
+    if chars.cur != '"' || lib.is_empty() {
+        return Ok(None);
+    }
+    next_chr!(chars, None);
+    Ok(Some(lib))
 }
 
-fn read_string<R>(chars: &mut CharReader<R>) -> Result<()>
+/// Reads string or character literal that starts at the current character
+/// and moves after its end. The literal is appended to `out` if it is set.
+fn read_quoted<R>(
+    chars: &mut CharReader<R>,
+    mut out: Option<&mut String>,
+) -> Result<()>
 where
     R: BufRead,
 {
+    let mut push = |c| {
+        if let Some(out) = out.as_deref_mut() {
+            out.push(c);
+        }
+    };
+
+    let quote = chars.cur;
+    push(quote);
     next_chr!(chars, ());
-    while chars.cur != '"' {
+    while chars.cur != quote {
         if chars.cur == '\\' {
+            push('\\');
             next_chr!(chars, ());
         }
+        push(chars.cur);
         next_chr!(chars, ());
     }
+    push(quote);
+    next_chr!(chars, ());
 
     Ok(())
 }
@@ -238,6 +470,8 @@ fn read_multiline_comment<R>(chars: &mut CharReader<R>) -> Result<()>
 where
     R: BufRead,
 {
+    // the `*` of the start can't end the comment (`/*/`)
+    next_chr!(chars, ());
     loop {
         if chars.cur != '*' {
             next_chr!(chars, ());
@@ -256,5 +490,204 @@ fn read_line_comment<R>(chars: &mut CharReader<R>) -> Result<()>
 where
     R: BufRead,
 {
-    chars.esc_skip_while(|c| c != '\n')
+    chars.skip_while(|c| !is_newline(c))
+}
+
+impl SourceLoc {
+    const START: Self = Self { line: 1, col: 1 };
+}
+
+impl Display for SourceLoc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// Checks whether the character ends line. Lone `\r` (classic Mac line
+/// endings) also ends line, the same as with gcc.
+fn is_newline(c: char) -> bool {
+    matches!(c, '\n' | '\r')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gets the included paths, relative includes are prefixed with `"`.
+    fn includes(src: &str) -> Vec<String> {
+        read_directives(&mut src.as_bytes())
+            .unwrap()
+            .into_iter()
+            .filter_map(|d| match d {
+                Directive::Include(f) => Some(format!(
+                    "{}{}",
+                    if f.relative { "\"" } else { "" },
+                    f.path.to_string_lossy()
+                )),
+                Directive::Lib(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn error_and_warning_are_skipped() {
+        let src = "#include \"a.h\"\n\
+            #ifndef X\n\
+            #error \"unterminated\n\
+            #warning don't use \"this\" #include \"bad.h\"\n\
+            #  error it's here\n\
+            #endif\n\
+            #include <b.h>\n";
+        assert_eq!(includes(src), ["\"a.h", "b.h"]);
+    }
+
+    #[test]
+    fn error_with_continuation_is_skipped() {
+        let src = "#error message \\\n#include \"bad.h\"\n#include \"a.h\"\n";
+        assert_eq!(includes(src), ["\"a.h"]);
+    }
+
+    /// Gets the characters read by [`CharReader`] with their locations.
+    fn chars(src: &str) -> Vec<(char, String)> {
+        let mut read = src.as_bytes();
+        let mut chars = CharReader::new(&mut read);
+        let mut res = vec![];
+        while let Some(c) = chars.next() {
+            res.push((c.unwrap(), chars.loc.to_string()));
+        }
+        assert!(chars.eof);
+        res
+    }
+
+    fn text(src: &str) -> String {
+        chars(src).into_iter().map(|(c, _)| c).collect()
+    }
+
+    #[test]
+    fn continuations_are_spliced() {
+        assert_eq!(text("a\\\nb"), "ab");
+        assert_eq!(text("a\\\r\nb"), "ab");
+        assert_eq!(text("a\\ \t\nb"), "ab");
+        assert_eq!(text("a??/\nb"), "ab");
+        assert_eq!(text("a\\\n\\\nb"), "ab");
+    }
+
+    #[test]
+    fn non_continuations_are_kept() {
+        assert_eq!(text("a\\b"), "a\\b");
+        assert_eq!(text("a\\ b\n"), "a\\ b\n");
+        assert_eq!(text("a??b"), "a??b");
+        assert_eq!(text("a??/b"), "a??/b");
+        // characters peeked at the end of the input are not lost
+        assert_eq!(text("a\\"), "a\\");
+        assert_eq!(text("a\\  "), "a\\  ");
+        assert_eq!(text("a??"), "a??");
+    }
+
+    #[test]
+    fn locations_skip_continuations() {
+        assert_eq!(
+            chars("a\\\nb\nc"),
+            [
+                ('a', "1:1".to_owned()),
+                ('b', "2:1".to_owned()),
+                ('\n', "2:2".to_owned()),
+                ('c', "3:1".to_owned()),
+            ]
+        );
+        assert_eq!(chars("\\\r\n\\\r\nx")[0], ('x', "3:1".to_owned()));
+    }
+
+    #[test]
+    fn continued_include_is_assembled() {
+        assert_eq!(includes("#include \"di\\\nr/a.h\"\n"), ["\"dir/a.h"]);
+        assert_eq!(includes("#inc\\  \r\nlude <x\\\r\n.h>\r\n"), ["x.h"]);
+        assert_eq!(includes("#include \\\n  \"a.h\"\n"), ["\"a.h"]);
+    }
+
+    #[test]
+    fn include_at_end_of_file() {
+        assert_eq!(includes("#include \"a.h\""), ["\"a.h"]);
+        assert_eq!(includes("#include <a.h>"), ["a.h"]);
+        // unterminated include doesn't continue on the next line
+        assert_eq!(
+            includes("#include \"a.h\n\"b.h\"\n"),
+            Vec::<String>::new()
+        );
+        assert_eq!(includes("#include <a.h"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn crlf_and_cr_end_lines() {
+        assert!(is_newline('\n'));
+        assert!(is_newline('\r'));
+        assert!(!is_newline(' '));
+
+        let src = "#include \"a.h\"\r\n#include <b.h>\r\n";
+        assert_eq!(includes(src), ["\"a.h", "b.h"]);
+        // lone `\r` before `#include` starts new line
+        let src = "int x;\r#include \"a.h\"\r// \r#include <b.h>\r";
+        assert_eq!(includes(src), ["\"a.h", "b.h"]);
+        // `\r` ends line comment, so the include isn't commented out
+        assert_eq!(includes("// comment\r#include \"a.h\""), ["\"a.h"]);
+    }
+
+    #[test]
+    fn crlf_is_single_line() {
+        // the `\n` of CRLF doesn't move to the next line again
+        let locs: Vec<_> = chars("a\r\nb\rc\n\rd")
+            .into_iter()
+            .map(|(_, l)| l)
+            .collect();
+        assert_eq!(
+            locs,
+            ["1:1", "1:2", "2:1", "2:1", "2:2", "3:1", "3:2", "4:1", "5:1"]
+        );
+    }
+
+    #[test]
+    fn continuation_with_cr() {
+        // the continuation consumes the whole CRLF, so `\n` doesn't end the
+        // directive
+        let src = "#include \\\r\n\"a.h\"\r\n#include \\\r<b.h>\r";
+        assert_eq!(includes(src), ["\"a.h", "b.h"]);
+        let src = "#error x \\\r\n#include \"bad.h\"\r\n#include \"a.h\"";
+        assert_eq!(includes(src), ["\"a.h"]);
+    }
+
+    /// Gets the locations of the includes.
+    fn locations(src: &str) -> Vec<String> {
+        read_directives(&mut src.as_bytes())
+            .unwrap()
+            .into_iter()
+            .filter_map(|d| match d {
+                Directive::Include(f) => Some(f.loc.to_string()),
+                Directive::Lib(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn locations_after_block_comments() {
+        let src = "/* multi\nline */\n  #include \"a.h\"\n\
+            /**/#include <b.h> /* x */\n\
+            /* directive may follow\n */ #include \"c.h\"\n\
+            int x; /* not directive */ #include \"d.h\"\n";
+        assert_eq!(locations(src), ["3:3", "4:5", "6:5"]);
+    }
+
+    #[test]
+    fn locations_after_continuations() {
+        let src = "int \\\nx;\n#include \\\n\"a.h\"\n#include <b.h>\n";
+        assert_eq!(locations(src), ["3:1", "5:1"]);
+        let src = "// comment \\\ncontinued\n#include \"a.h\"\n";
+        assert_eq!(locations(src), ["3:1"]);
+    }
+
+    #[test]
+    fn locations_with_crlf() {
+        let src = "int x;\r\n\r\n #include \"a.h\"\r\n#include <b.h>\r\n";
+        assert_eq!(locations(src), ["3:2", "4:1"]);
+        assert_eq!(locations("\r\r#include \"a.h\"\r"), ["3:1"]);
+    }
 }
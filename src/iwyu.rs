@@ -0,0 +1,174 @@
+use std::{path::PathBuf, process::Command};
+
+use log::{debug, info};
+use termal::printcln;
+
+use crate::{
+    compiler::Compiler,
+    dependency::DepFile,
+    err::{Error, Result, ResultExt},
+};
+
+/// Names under which include-what-you-use may be installed
+const IWYU_NAMES: [&str; 2] = ["include-what-you-use", "iwyu"];
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Runs include-what-you-use on all the given sources with the flags that
+/// would be used to compile them and prints its suggestions. When `fail` is
+/// true, returns error if any unnecessary include was found or if any source
+/// couldn't be analysed.
+pub fn check<I, P>(compiler: &Compiler, sources: I, fail: bool) -> Result<()>
+where
+    I: IntoIterator<Item = P>,
+    P: Into<PathBuf>,
+{
+    let iwyu = IWYU_NAMES
+        .iter()
+        .find_map(|n| which::which(n).ok())
+        .ok_or_else(|| {
+            Error::Generic(
+                "Cannot find include-what-you-use, make sure that it is \
+                installed and in PATH."
+                    .to_owned(),
+            )
+        })?;
+
+    info!("Checking includes with {iwyu:?}");
+
+    let mut unused = 0;
+    let mut failed = 0;
+    for src in sources {
+        let src: DepFile = src.into().into();
+        let typ =
+            src.typ.ok_or_else(|| Error::InvalidFileType(src.clone()))?;
+
+        printcln!("{'g bold}   Checking{'_} {}", src.path.to_string_lossy());
+
+        let out = Command::new(&iwyu)
+            .args(compiler.compile_args(typ.lang)?)
+            .arg(src.as_ref())
+            .output()
+            .with_path("run", &iwyu)?;
+
+        // iwyu prints its suggestions to stderr and older versions fail
+        // even when they only have suggestions, so the analysis failed only
+        // if there are no results
+        let success = out.status.success();
+        let out = String::from_utf8_lossy(&out.stderr);
+        let cnt = count_unused(&out);
+        debug!("{:?} has {cnt} unnecessary includes", src.path);
+        if cnt != 0 || !success {
+            eprint!("{out}");
+        }
+        if !success && !is_analysed(&out) {
+            printcln!(
+                "{'y bold}    Warning{'_} include-what-you-use failed to \
+                analyse {}",
+                src.path.to_string_lossy()
+            );
+            failed += 1;
+        }
+        unused += cnt;
+    }
+
+    if fail && failed != 0 {
+        Err(Error::IwyuFailed(failed))
+    } else if fail && unused != 0 {
+        Err(Error::UnusedIncludes(unused))
+    } else {
+        Ok(())
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Checks whether the iwyu output contains the results of the analysis,
+/// iwyu prints them for each analysed file even if it has no suggestions.
+fn is_analysed(out: &str) -> bool {
+    out.lines().any(|l| {
+        l.ends_with("has correct #includes/fwd-decls)")
+            || l.ends_with("should add these lines:")
+    })
+}
+
+/// Counts the lines in the `should remove these lines:` sections of the
+/// iwyu output.
+fn count_unused(out: &str) -> usize {
+    let mut in_remove = false;
+    let mut cnt = 0;
+    for line in out.lines() {
+        if line.ends_with("should remove these lines:") {
+            in_remove = true;
+        } else if in_remove && line.starts_with("- ") {
+            cnt += 1;
+        } else {
+            in_remove = false;
+        }
+    }
+    cnt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_FILES: &str = "\
+src/main.c should add these lines:
+#include <stdio.h>  // for printf
+
+src/main.c should remove these lines:
+- #include <stdlib.h>  // lines 2-2
+- #include <string.h>  // lines 3-3
+
+The full include-list for src/main.c:
+#include <stdio.h>  // for printf
+---
+src/util.h should add these lines:
+
+src/util.h should remove these lines:
+- #include <math.h>  // lines 1-1
+
+The full include-list for src/util.h:
+---
+";
+
+    #[test]
+    fn unused_in_several_files_are_counted() {
+        assert_eq!(count_unused(TWO_FILES), 3);
+        assert!(is_analysed(TWO_FILES));
+    }
+
+    #[test]
+    fn section_ends_at_other_text() {
+        let out = "\
+src/main.c should remove these lines:
+- #include <stdlib.h>  // lines 2-2
+The full include-list for src/main.c:
+- not an include
+";
+        assert_eq!(count_unused(out), 1);
+    }
+
+    #[test]
+    fn no_suggestions() {
+        let out = "\n(src/main.c has correct #includes/fwd-decls)\n";
+        assert_eq!(count_unused(out), 0);
+        assert!(is_analysed(out));
+    }
+
+    #[test]
+    fn failed_analysis_isnt_analysed() {
+        let out = "\
+src/main.c:1:10: fatal error: 'missing.h' file not found
+#include \"missing.h\"
+         ^~~~~~~~~~~
+";
+        assert_eq!(count_unused(out), 0);
+        assert!(!is_analysed(out));
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
+};
+
+use log::debug;
+use termal::printcln;
+
+use crate::{
+    err::{Error, Result, ResultExt},
+    paths,
+};
+
+/// Advisory lock of a bin directory so that two instances of ccpp don't
+/// build into the same directory at the same time. The lock is released
+/// when dropped.
+pub struct BuildLock {
+    path: PathBuf,
+}
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+impl BuildLock {
+    /// Acquires lock on the given bin directory. If the lock is held by
+    /// another running process, this waits until it is released or fails
+    /// immediately if `wait` is false. Locks held by processes that no longer
+    /// run are stolen.
+    pub fn acquire<P>(bin_root: P, wait: bool) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let bin_root = bin_root.as_ref();
+        fs::create_dir_all(bin_root)
+            .with_path("create directory", bin_root)?;
+        let path = bin_root.join(paths::LOCK);
+        let mut printed = false;
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut f) => {
+                    write!(f, "{}", process::id())
+                        .with_path("write", &path)?;
+                    debug!("Acquired build lock {path:?}");
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(Error::io_at("create", &path, e)),
+            }
+
+            // the file may be removed or not yet written by the other
+            // process, in that case just try again
+            let pid = match fs::read_to_string(&path) {
+                Ok(p) => p.trim().parse::<u32>().ok(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(Error::io_at("read", &path, e)),
+            };
+
+            match pid {
+                Some(pid) if !is_running(pid) => {
+                    debug!("Stealing stale build lock of PID {pid}");
+                    steal_lock(&path, pid)?;
+                    continue;
+                }
+                Some(pid) if !wait => {
+                    return Err(Error::Generic(format!(
+                        "The build directory {bin_root:?} is locked by \
+                        another ccpp process with PID {pid}"
+                    )));
+                }
+                Some(pid) if !printed => {
+                    printcln!(
+                        "{'c bold}   Blocking{'_} waiting for build lock \
+                        held by PID {}",
+                        pid
+                    );
+                    printed = true;
+                }
+                _ => {}
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+impl Drop for BuildLock {
+    fn drop(&mut self) {
+        // the lock may have been stolen, don't remove lock of other process
+        if lock_owner(&self.path) != Some(process::id()) {
+            debug!(
+                "The build lock {:?} was taken by other process",
+                self.path
+            );
+            return;
+        }
+        // nothing can be done if this fails
+        _ = remove_lock(&self.path);
+        debug!("Released build lock {:?}", self.path);
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Removes lock of process that no longer runs. Other processes may try to
+/// steal the same lock at the same time, so the lock is first moved to name
+/// unique for this process (only one of them succeeds) and then it is
+/// removed only if it still belongs to the stale process. Lock that was
+/// acquired by other process in the meantime is moved back.
+fn steal_lock(path: &Path, stale: u32) -> Result<()> {
+    let mut taken = path.as_os_str().to_owned();
+    taken.push(format!(".stale-{}", process::id()));
+    let taken = PathBuf::from(taken);
+
+    match fs::rename(path, &taken) {
+        // other process has already stolen or released the lock
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        r => r.with_path("move", path)?,
+    }
+
+    if lock_owner(&taken) == Some(stale) {
+        return remove_lock(&taken);
+    }
+
+    // hard link doesn't replace lock that was acquired in the meantime
+    debug!("The build lock was acquired by other process, returning it");
+    let res = fs::hard_link(&taken, path);
+    remove_lock(&taken)?;
+    match res {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            debug!("Cannot return the build lock, it was acquired again");
+            Ok(())
+        }
+        r => r.with_path("link", path),
+    }
+}
+
+/// Gets the PID of the process that holds the lock. Returns [`None`] if it
+/// cannot be read.
+fn lock_owner(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn remove_lock(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(Error::io_at("remove", path, e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks whether process with the given PID is running. When it cannot be
+/// determined, the process is considered running.
+fn is_running(pid: u32) -> bool {
+    if pid == process::id() {
+        return true;
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        Path::new("/proc").join(pid.to_string()).exists()
+    }
+
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        process::Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stderr(process::Stdio::null())
+            .status()
+            .map_or(true, |s| s.success())
+    }
+
+    #[cfg(windows)]
+    {
+        process::Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {pid}"), "/NH"])
+            .output()
+            .map_or(true, |o| {
+                String::from_utf8_lossy(&o.stdout).contains(&pid.to_string())
+            })
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PID of process that doesn't run.
+    const DEAD: u32 = u32::MAX - 1;
+
+    #[test]
+    fn stale_lock_is_stolen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(paths::LOCK);
+        fs::write(&path, DEAD.to_string()).unwrap();
+
+        let lock = BuildLock::acquire(dir.path(), false).unwrap();
+        assert_eq!(lock_owner(&path), Some(process::id()));
+        drop(lock);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn live_lock_is_not_stolen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(paths::LOCK);
+        fs::write(&path, process::id().to_string()).unwrap();
+
+        assert!(BuildLock::acquire(dir.path(), false).is_err());
+        assert_eq!(lock_owner(&path), Some(process::id()));
+    }
+
+    #[test]
+    fn lock_acquired_after_read_is_returned() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(paths::LOCK);
+        // other process stole the stale lock and acquired it before this
+        // process got to steal it
+        fs::write(&path, process::id().to_string()).unwrap();
+
+        steal_lock(&path, DEAD).unwrap();
+        assert_eq!(lock_owner(&path), Some(process::id()));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn stolen_lock_is_not_released() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(paths::LOCK);
+        let lock = BuildLock::acquire(dir.path(), false).unwrap();
+        fs::write(&path, DEAD.to_string()).unwrap();
+
+        drop(lock);
+        assert_eq!(lock_owner(&path), Some(DEAD));
+    }
+}
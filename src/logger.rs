@@ -0,0 +1,59 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use termal::formatc;
+
+/// Tiny logger that writes the log messages to stderr so that stdout stays
+/// clean.
+struct Logger;
+
+static LOGGER: Logger = Logger;
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Initializes the logger with the given verbosity:
+/// - `0`: no logs
+/// - `1` (`-v`): high level phases
+/// - `2` (`-vv`): per file decisions
+/// - `3` (`-vvv`): raw scanner events
+pub fn init(verbosity: u8) {
+    let level = match verbosity {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    // this fails only if the logger is already set
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        eprintln!(
+            "{}",
+            formatc!(
+                "{'gr}[{:<5} {}]{'_} {}",
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        );
+    }
+
+    fn flush(&self) {}
+}
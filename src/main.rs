@@ -1,98 +1,1060 @@
 use std::{
-    fs, io,
-    path::Path,
+    collections::HashSet,
+    env, fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
     process::{Command, ExitCode},
 };
 
-use arg_parser::{Action, Args};
-use builder::Builder;
+use arg_parser::{Action, Args, Generator};
+use builder::{load_failed, BuildEvent, Builder};
+use clean::{CleanMode, Removed};
+use compiler::{
+    config::{Asan, Std},
+    Compiler,
+};
 use config::Config;
+use diagnostics::DiagnosticsFormat;
 use dir_structure::DirStructure;
-use err::{Error, Result};
-use termal::{formatc, gradient, printcln};
+use err::{Error, Result, ResultExt};
+use file_type::Language;
+use lock::BuildLock;
+use log::info;
+use shell::split_shell;
+use size_report::SizeHistory;
+use stable_hash::StableHasher;
+use stamp::Stamp;
+use termal::{formatc, printcln};
 
-use crate::serde_config::{SerdeConfig, SerdeProject};
+use crate::serde_config::{
+    SerdeBuild, SerdeCompilerConfig, SerdeConfig, SerdeProject,
+    SerdeUserConfig, SerdeWorkspace,
+};
 
 mod arg_parser;
 mod builder;
+mod clean;
 mod compiler;
 mod config;
+mod definitions;
+mod dep_rules;
 mod dependency;
+mod depfile;
+mod diagnostics;
 mod dir_structure;
+mod duration;
+mod embed;
+mod env_expand;
 mod err;
 mod file_type;
+mod generated;
+mod glob;
+mod help;
+mod ignore_file;
 mod include_deps;
+mod iwyu;
+mod lock;
+mod logger;
+mod metadata;
+mod object_cache;
+mod paths;
+mod scaffold;
 mod serde_config;
+mod shell;
+mod size_report;
+mod stable_hash;
+mod stable_link;
+mod stamp;
+mod unit_test;
+mod unity;
+mod version;
+mod vscode;
 
 const CONF_FILE: &str = "ccpp.toml";
+/// Environment variables that change the build. The variables used in the
+/// configuration are also considered.
+const STAMP_VARS: [&str; 3] = ["CC", "CXX", "SOURCE_DATE_EPOCH"];
 
 fn main() -> ExitCode {
     match start() {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
             eprintln!("{}", formatc!("{'r}Failure:{'_} {}", e));
-            ExitCode::FAILURE
+            exit_code(&e)
+        }
+    }
+}
+
+/// Gets the exit code for the error. The exit code of the failed process is
+/// propagated.
+fn exit_code(e: &Error) -> ExitCode {
+    match e {
+        Error::ProcessFailed(Some(c))
+        | Error::JobFailed { code: Some(c), .. }
+            if (1..=255).contains(c) =>
+        {
+            ExitCode::from(*c as u8)
         }
+        Error::MemberFailed { err, .. } => exit_code(err),
+        _ => ExitCode::FAILURE,
     }
 }
 
 fn start() -> Result<()> {
     let args = Args::get()?;
+    logger::init(args.verbosity);
+
+    if matches!(
+        args.action,
+        Action::Clean | Action::Build | Action::Run | Action::Path
+    ) {
+        if let Some(ws) = SerdeWorkspace::from_toml_file(CONF_FILE)? {
+            return workspace(&args, ws);
+        }
+    }
+    if let Some(p) = &args.package {
+        return Err(Error::UnknownMember(p.clone()));
+    }
+
     match &args.action {
         Action::None => debug_code(&args),
         Action::Clean => clean(&args),
         Action::Build => build(&args),
         Action::Run => run(&args),
-        Action::Help => help(&args),
+        Action::Help(_) => help(&args),
         Action::New(dir) => new(&args, dir),
+        Action::Script(file) => script(&args, file),
+        Action::Generate(g) => generate(&args, *g),
+        Action::ConfigShow => config_show(&args),
+        Action::Path => path(&args),
+        Action::Rdeps(file) => rdeps(&args, file),
+        Action::Plan => plan(&args),
+        Action::SizeHistory => size_history(&args),
+        Action::CompilerVersion => compiler_version(&args),
+        Action::Version => version(&args),
+        Action::PrintFlags(file) => print_flags(&args, file),
+        Action::Test => test(&args),
+        Action::Preprocess(file) => preprocess(&args, file),
+    }
+}
+
+/// Runs the action for the selected members of the workspace. With
+/// `--keep-going`, the other members are still built when one fails.
+fn workspace(args: &Args, ws: SerdeWorkspace) -> Result<()> {
+    let members: Vec<_> = match &args.package {
+        Some(p) => {
+            let p = Path::new(p);
+            let member = ws
+                .members
+                .iter()
+                .find(|m| Path::new(m) == p)
+                .or_else(|| {
+                    ws.members.iter().find(|m| {
+                        Path::new(m).file_name() == Some(p.as_os_str())
+                    })
+                })
+                .ok_or_else(|| {
+                    Error::UnknownMember(p.to_string_lossy().into_owned())
+                })?;
+            vec![member]
+        }
+        None if matches!(args.action, Action::Run | Action::Path) => {
+            return Err(Error::MemberRequired)
+        }
+        None => ws.members.iter().collect(),
+    };
+
+    let root = env::current_dir()?;
+    // check all the members before building any of them
+    for m in &members {
+        if !root.join(m).join(CONF_FILE).exists() {
+            return Err(Error::MissingMember((*m).clone()));
+        }
+    }
+
+    let mut failed = vec![];
+    for m in members {
+        // the output of `path` is used by scripts
+        if args.action != Action::Path {
+            printcln!("{'g bold}  Workspace{'_} member {}", m);
+        }
+        // each member has its own directory in the bin directory
+        let mut margs = args.clone();
+        margs.bin_dir = bin_dir_override(args).map(|d| root.join(d).join(m));
+
+        env::set_current_dir(root.join(m))?;
+        let res = match args.action {
+            Action::Clean => clean(&margs),
+            Action::Run => run(&margs),
+            Action::Path => path(&margs),
+            _ => build(&margs),
+        };
+        env::set_current_dir(&root)?;
+
+        let Err(err) = res else {
+            continue;
+        };
+        if !args.keep_going {
+            return Err(Error::MemberFailed {
+                member: m.clone(),
+                err: Box::new(err),
+            });
+        }
+        eprintln!("{}", formatc!("{'r}Failure:{'_} {}: {}", m, err));
+        failed.push(m);
+    }
+
+    match failed.first() {
+        Some(first) => Err(Error::WorkspaceFailed {
+            count: failed.len(),
+            first: (*first).clone(),
+        }),
+        None => Ok(()),
     }
 }
 
-fn clean(_args: &Args) -> Result<()> {
-    let conf = Config::from_toml_file(CONF_FILE)?;
-    match fs::remove_dir_all(&conf.release_build.compiler_conf.bin_root) {
-        Ok(_) => {}
-        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
-        Err(e) => Err(e)?,
+fn clean(args: &Args) -> Result<()> {
+    let conf = load_config(args)?;
+    let _release_lock = BuildLock::acquire(
+        &conf.release_build.compiler_conf.bin_root,
+        !args.no_wait,
+    )?;
+    let _debug_lock = BuildLock::acquire(
+        &conf.debug_build.compiler_conf.bin_root,
+        !args.no_wait,
+    )?;
+
+    let mut removed = Removed::default();
+    for build in [&conf.release_build, &conf.debug_build] {
+        clean::clean(&build.compiler_conf.bin_root, args.clean, &mut removed)?;
     }
-    match fs::remove_dir_all(&conf.debug_build.compiler_conf.bin_root) {
-        Ok(_) => {}
-        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
-        Err(e) => Err(e)?,
+    // the object cache is shared by all projects, it is removed only by the
+    // projects that use it
+    let object_cache =
+        conf.debug_build.object_cache || conf.release_build.object_cache;
+    if object_cache && args.clean != CleanMode::Artifacts {
+        clean::clean_shared(&paths::object_cache_dir()?, &mut removed)?;
+    }
+    if let Some(link) = &conf.stable_target {
+        if let Some(size) = stable_link::remove(link)? {
+            removed.artifacts += 1;
+            removed.size += size;
+        }
+    }
+
+    if removed.caches == 0 {
+        printcln!(
+            "{'g bold}    Removed{'_} {} files, {}",
+            removed.artifacts,
+            removed.size_str()
+        );
+    } else {
+        printcln!(
+            "{'g bold}    Removed{'_} {} files and {} cache files, {}",
+            removed.artifacts,
+            removed.caches,
+            removed.size_str()
+        );
     }
     Ok(())
 }
 
 fn build(args: &Args) -> Result<()> {
+    if !args.std_matrix.is_empty() {
+        return std_matrix(args);
+    }
+
+    let (conf, dir) = prepare(args)?;
+    let args = &with_dir_sources(args, &dir)?;
+    build_loaded(args, &conf, &dir)?;
+
+    // only objects were built or the binary is already in the outputs
+    if !args.files.is_empty() || args.print_outputs {
+        return Ok(());
+    }
+    let binary = env::current_dir()?.join(&profile_build(args, &conf).target);
+    if args.events {
+        print_event(&BuildEvent::Binary { path: &binary });
+    } else if !args.quiet {
+        println!("Binary: {}", binary.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// Builds the project once with each of the standards from `--std-matrix`
+/// and reports which of them passed.
+fn std_matrix(args: &Args) -> Result<()> {
+    let (mut conf, dir) = prepare(args)?;
+    let args = &with_dir_sources(args, &dir)?;
+    // the builds of the standards must not replace the normal binary
+    conf.stable_target = None;
+
+    let build = profile_build(args, &conf);
+    let bin_root = build.compiler_conf.bin_root.clone();
+    let target_name = build.target.file_name().map(PathBuf::from);
+    let (c_std, cpp_std) = (
+        build.compiler_conf.c_std.clone(),
+        build.compiler_conf.cpp_std.clone(),
+    );
+
+    let mut results = vec![];
+    for std in &args.std_matrix {
+        if !args.quiet {
+            printcln!("{'g bold}   Standard{'_} {}", std);
+        }
+        let build = if args.release {
+            &mut conf.release_build
+        } else {
+            &mut conf.debug_build
+        };
+        let cconf = &mut build.compiler_conf;
+        cconf.bin_root = bin_root.join(format!("std-{std}"));
+        cconf.c_std = c_std.clone();
+        cconf.cpp_std = cpp_std.clone();
+        match std.parse::<i32>() {
+            Ok(n) => {
+                cconf.c_std = Std::Number(n);
+                cconf.cpp_std = Std::Number(n);
+            }
+            Err(_) if std.starts_with("c++") || std.starts_with("gnu++") => {
+                cconf.cpp_std = Std::Name(std.clone())
+            }
+            Err(_) => cconf.c_std = Std::Name(std.clone()),
+        }
+        if let Some(name) = &target_name {
+            build.target = cconf.bin_root.join(name);
+        }
+
+        let res = build_loaded(args, &conf, &dir);
+        if let Err(e) = &res {
+            eprintln!("{}", formatc!("{'r}Failure:{'_} {}: {}", std, e));
+        }
+        results.push((std, res.is_ok()));
+    }
+
+    if !args.quiet {
+        println!();
+    }
+    for (std, passed) in &results {
+        if *passed {
+            printcln!("{'g bold}     Passed{'_} {}", std);
+        } else {
+            printcln!("{'r bold}     Failed{'_} {}", std);
+        }
+    }
+
+    let failed: Vec<_> = results.iter().filter(|(_, p)| !p).collect();
+    match failed.first() {
+        Some((first, _)) => Err(Error::StdMatrixFailed {
+            count: failed.len(),
+            first: (*first).clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Builds each source from `tests/` with the objects of the project (except
+/// the ones that define `main`) into a program and runs it. With `--auto`,
+/// the `main` of the programs is generated and it runs the `test_*`
+/// functions of the source.
+fn test(args: &Args) -> Result<()> {
+    let (mut conf, dir) = prepare(args)?;
+    let tests_root = Path::new(unit_test::TESTS_DIR);
+    if !tests_root.is_dir() {
+        return Err(Error::NoTests);
+    }
+    let mut tests = DirStructure::new(tests_root.to_owned());
+    tests.analyze()?;
+    if tests.srcs().is_empty() {
+        return Err(Error::NoTests);
+    }
+    // the test programs must not replace the normal binary
+    conf.stable_target = None;
+
+    let build = if args.release {
+        &mut conf.release_build
+    } else {
+        &mut conf.debug_build
+    };
+    let bin_root = build.compiler_conf.bin_root.clone();
+    let out_dir = bin_root.join(paths::TEST_PROGRAMS_DIR);
+    if args.auto_tests {
+        unit_test::write_assert_header(&out_dir)?;
+        build.compiler_conf.include_dirs.push(out_dir.clone());
+    }
+
+    let _lock = BuildLock::acquire(&bin_root, !args.no_wait)?;
+
+    // the sources in the tests may be also in the source directory
+    let tests_abs = tests_root.canonicalize().with_path("find", tests_root)?;
+    let mut srcs = embed::generate(&conf.embed, &embed_dir(&conf, args))?;
+    for s in dir.srcs() {
+        if s.canonicalize()
+            .with_path("find", s)?
+            .starts_with(&tests_abs)
+        {
+            continue;
+        }
+        if definitions::scan(s)?.main {
+            info!("Skipping {s:?} in tests, it defines main");
+            continue;
+        }
+        srcs.push(s.clone());
+    }
+
+    let mut results = vec![];
+    for test in tests.srcs() {
+        if !args.quiet {
+            printcln!("{'g bold}    Testing{'_} {}", test.to_string_lossy());
+        }
+        let res = run_test(args, &conf, test, &srcs, &out_dir);
+        if let Err(e) = &res {
+            eprintln!(
+                "{}",
+                formatc!("{'r}Failure:{'_} {}: {}", test.to_string_lossy(), e)
+            );
+        }
+        results.push((test, res.is_ok()));
+    }
+
+    if !args.quiet {
+        println!();
+    }
+    for (test, passed) in &results {
+        if *passed {
+            printcln!("{'g bold}     Passed{'_} {}", test.to_string_lossy());
+        } else {
+            printcln!("{'r bold}     Failed{'_} {}", test.to_string_lossy());
+        }
+    }
+
+    let failed: Vec<_> = results.iter().filter(|(_, p)| !p).collect();
+    match failed.first() {
+        Some((first, _)) => Err(Error::TestsFailed {
+            count: failed.len(),
+            first: (*first).clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Builds the program of the test source and runs it. Test sources that
+/// define `main` are run by themselves even with `--auto`.
+fn run_test(
+    args: &Args,
+    conf: &Config,
+    test: &Path,
+    srcs: &[PathBuf],
+    out_dir: &Path,
+) -> Result<()> {
+    let rel = test.strip_prefix(unit_test::TESTS_DIR).unwrap_or(test);
+    let runner = out_dir.join(rel);
+    let defs = definitions::scan(test)?;
+    let tests = if args.auto_tests && !defs.main {
+        let tests = defs.tests;
+        if tests.is_empty() && !args.quiet {
+            printcln!(
+                "{'y bold}    Warning{'_} there are no test functions in {}",
+                test.to_string_lossy()
+            );
+        }
+        Some(tests)
+    } else {
+        None
+    };
+    let test_abs = test.canonicalize().with_path("find", test)?;
+    unit_test::write_runner(&runner, &test_abs, tests.as_deref())?;
+
+    #[allow(unused_mut)]
+    let mut target = runner.with_extension("");
+    #[cfg(target_os = "windows")]
+    target.set_extension("exe");
+
+    // failures of the other tests must not affect this one
+    let mut bld = create_builder(args, conf)?;
+    if let Some(jobs) = args.jobs.or_else(makeflags_jobs) {
+        bld.set_jobs(jobs);
+    }
+    if args.quiet {
+        bld.set_quiet();
+    }
+    bld.build_all(&target, srcs.iter().chain([&runner]))?;
+
+    info!("Running test {target:?}");
+    let status = Command::new(&target)
+        .spawn()
+        .with_path("run", &target)?
+        .wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::ProcessFailed(status.code()))
+    }
+}
+
+/// Prints the absolute path to the binary.
+fn path(args: &Args) -> Result<()> {
+    let conf = load_config(args)?;
+    let target = match (&conf.stable_target, args.stable) {
+        (Some(link), true) => {
+            // the profile may have been cleaned since the link was created
+            if !stable_link::is_valid(link) {
+                stable_link::remove(link)?;
+                return Err(Error::BrokenStableLink(link.clone()));
+            }
+            link
+        }
+        (None, true) => return Err(Error::NoStableLink),
+        _ => &profile_build(args, &conf).target,
+    };
+    let binary = env::current_dir()?.join(target);
+    println!("{}", binary.to_string_lossy());
+    Ok(())
+}
+
+fn rdeps(args: &Args, file: &Path) -> Result<()> {
+    let (conf, dir) = prepare(args)?;
+    let mut bld = Builder::from_config(&conf, args.release)?;
+    for (src, obj, dirty) in bld.reverse_deps(file, dir.srcs())? {
+        if dirty {
+            printcln!(
+                "{'y bold}    Rebuild{'_} {} ({})",
+                src.to_string_lossy(),
+                obj.to_string_lossy()
+            );
+        } else {
+            printcln!(
+                "{'g bold} Up to date{'_} {} ({})",
+                src.to_string_lossy(),
+                obj.to_string_lossy()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Prints the command that compiles the source.
+fn print_flags(args: &Args, file: &Path) -> Result<()> {
+    let (conf, dir) = prepare(args)?;
+    if let Some(batch) = unity_batch(args, &conf, dir.srcs(), file)? {
+        eprintln!(
+            "{}",
+            formatc!(
+                "{'y bold}       Note{'_} {} is compiled inside the unity \
+                batch {}",
+                file.to_string_lossy(),
+                batch.to_string_lossy()
+            )
+        );
+    }
+    let mut bld = create_builder(args, &conf)?;
+    let cmd = bld.compile_command(file)?;
+    println!("{}", shell::command_to_string(&cmd));
+    Ok(())
+}
+
+/// Runs the compiler only with the preprocessor on the source, with all the
+/// arguments that are used to compile it.
+fn preprocess(args: &Args, file: &Path) -> Result<()> {
+    let conf = load_config(args)?;
+    let mut bld = create_builder(args, &conf)?;
+    let mut cmd = bld.preprocess_command(file, args.output.as_deref())?;
+    if args.verbosity > 0 {
+        eprintln!("{}", shell::command_to_string(&cmd));
+    }
+    let status = cmd
+        .spawn()
+        .with_path("run", cmd.get_program().as_ref())?
+        .wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::ProcessFailed(status.code()))
+    }
+}
+
+/// Prints the commands that would run to build the project.
+fn plan(args: &Args) -> Result<()> {
     let (conf, dir) = prepare(args)?;
-    build_loaded(args, &conf, &dir)
+    let args = &with_dir_sources(args, &dir)?;
+    let build = profile_build(args, &conf);
+
+    // planning doesn't write anything, the generated sources that don't
+    // exist yet are planned without their dependencies
+    let mut bld = create_builder(args, &conf)?;
+    let plan = if args.files.is_empty() {
+        let embedded = embed::sources(&conf.embed, &embed_dir(&conf, args))?;
+        let srcs = match &conf.project.entry {
+            Some(entry) => {
+                entry_sources(&mut bld, entry, &conf, dir.srcs(), args.quiet)?
+            }
+            None => dir.srcs().to_vec(),
+        };
+        let srcs = unity_sources(args, &conf, srcs)?;
+        bld.plan(&build.target, srcs.iter().chain(&embedded))?
+    } else {
+        bld.plan_objects(&args.files)?
+    };
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&plan)
+            .map_err(anyhow::Error::from)?;
+        println!("{json}");
+        return Ok(());
+    }
+
+    if plan.commands.is_empty() {
+        println!("Nothing to do");
+    }
+    for cmd in &plan.commands {
+        let file = cmd.provides.first().map(|p| p.to_string_lossy());
+        let file = file.unwrap_or_default();
+        let is_obj = cmd
+            .provides
+            .first()
+            .and_then(|p| p.typ)
+            .is_some_and(|t| t.state == file_type::FileState::Object);
+        if is_obj {
+            printcln!("{'y bold}    Compile{'_} {} ({})", file, cmd.reason);
+        } else {
+            printcln!("{'y bold}       Link{'_} {} ({})", file, cmd.reason);
+        }
+        println!("{}", shell::command_to_string(&cmd.command()));
+    }
+    Ok(())
+}
+
+/// Gets the build configuration of the selected profile.
+fn profile_build<'a>(args: &Args, conf: &'a Config) -> &'a config::Build {
+    if args.release {
+        &conf.release_build
+    } else {
+        &conf.debug_build
+    }
 }
 
 fn run(args: &Args) -> Result<()> {
+    if args.no_build {
+        let conf = load_config(args)?;
+        let target = &profile_build(args, &conf).target;
+        if !target.exists() {
+            return Err(Error::MissingBinary(target.clone()));
+        }
+        printcln!("{'g bold}    Running{'_} {}", conf.project.name);
+        return run_loaded(args, &conf);
+    }
+
     let (conf, dir) = prepare(args)?;
     // printcln!("{'g bold}  Compiling{'_}");
     // printcln!("{'g bold}    Linking{'_}");
-    build_loaded(args, &conf, &dir)?;
+    if !build_loaded(args, &conf, &dir)? {
+        printcln!("{'g bold}      Fresh{'_} {}", conf.project.name);
+    }
     printcln!("{'g bold}    Running{'_} {}", conf.project.name);
     run_loaded(args, &conf)
 }
 
+/// Loads the configuration of the project merged with the user
+/// configuration.
+fn load_config(args: &Args) -> Result<Config> {
+    let user = load_user_config(args)?;
+    info!("Loading configuration from {CONF_FILE}");
+    let bin_dir = bin_dir_override(args);
+    let mut conf =
+        Config::from_toml_file(CONF_FILE, bin_dir.as_deref(), user.as_ref())?;
+    conf.user_file = user_config_file(args);
+    // the sources must be recognized the same way by all the actions
+    file_type::configure(conf.project.extensions.clone());
+    Ok(conf)
+}
+
+/// Gets the bin directory from `--bin-dir` or from the `CCPP_BIN_ROOT`
+/// environment variable.
+fn bin_dir_override(args: &Args) -> Option<PathBuf> {
+    args.bin_dir
+        .clone()
+        .or_else(|| env::var_os("CCPP_BIN_ROOT").map(Into::into))
+        .filter(|d| !d.as_os_str().is_empty())
+}
+
+/// Loads the user configuration if it exists and it is not disabled.
+fn load_user_config(args: &Args) -> Result<Option<SerdeUserConfig>> {
+    if args.no_user_config {
+        return Ok(None);
+    }
+    let user = SerdeUserConfig::load()?;
+    if let Some((path, _)) = &user {
+        info!("Loading user configuration from {path:?}");
+    }
+    Ok(user.map(|(_, u)| u))
+}
+
+/// Gets the path of the user configuration file unless it is disabled.
+fn user_config_file(args: &Args) -> Option<PathBuf> {
+    if args.no_user_config {
+        None
+    } else {
+        SerdeUserConfig::path()
+    }
+}
+
 fn prepare(args: &Args) -> Result<(Config, DirStructure)> {
-    let conf = Config::from_toml_file(CONF_FILE)?;
-    let mut dir = DirStructure::from_config(&conf, args.release);
-    dir.analyze()?;
+    let conf = load_config(args)?;
+    let mut dir = DirStructure::from_config(&conf, args.release)?;
+    // there is no need to search for sources when building only specific
+    // files
+    if args.files.is_empty() || args.dir.is_some() {
+        info!("Searching for source files");
+        dir.analyze()?;
+        info!("Found {} source files", dir.srcs().len());
+    }
     Ok((conf, dir))
 }
 
-fn build_loaded(args: &Args, conf: &Config, dir: &DirStructure) -> Result<()> {
-    let mut bld = Builder::from_config(conf, args.release)?;
-    let target = if args.release {
-        &conf.release_build.target
+/// With `--dir`, the sources in the directory are added to the files whose
+/// objects are built.
+fn with_dir_sources(args: &Args, dir: &DirStructure) -> Result<Args> {
+    let mut res = args.clone();
+    if let Some(d) = &args.dir {
+        let srcs = dir.srcs_in(d)?;
+        if srcs.is_empty() {
+            return Err(Error::NoSourcesInDir(d.clone()));
+        }
+        // the explicit files may also be in the directory
+        let explicit: HashSet<_> = args
+            .files
+            .iter()
+            .filter_map(|f| f.canonicalize().ok())
+            .collect();
+        res.files.extend(srcs.into_iter().filter(|s| {
+            !s.canonicalize().is_ok_and(|s| explicit.contains(&s))
+        }));
+    }
+    Ok(res)
+}
+
+/// Builds the loaded project. Returns false if nothing had to be built.
+fn build_loaded(
+    args: &Args,
+    conf: &Config,
+    dir: &DirStructure,
+) -> Result<bool> {
+    let build = if args.release {
+        &conf.release_build
     } else {
-        &conf.debug_build.target
+        &conf.debug_build
+    };
+    // the lock is released when this function returns
+    let _lock =
+        BuildLock::acquire(&build.compiler_conf.bin_root, !args.no_wait)?;
+
+    // skip the whole build if none of the files used by the last build
+    // changed, builds that don't produce the same outputs every time can't
+    // be skipped
+    let env_vars = STAMP_VARS.map(env::var_os);
+    let stamp = Stamp::new(
+        build.compiler_conf.bin_root.join(paths::STAMP),
+        (
+            env::args().skip(1).collect::<Vec<_>>(),
+            env::current_dir()?,
+            dir.srcs(),
+            &conf.embed,
+            env_vars,
+            env_expand::expanded_vars(),
+        ),
+    );
+    let use_stamp = args.files.is_empty()
+        && build.metadata.files.is_empty()
+        && args.diagnostics.is_none()
+        && !args.iwyu
+        && !args.print_outputs
+        && args.size_report.is_none();
+    if use_stamp && stamp.is_fresh() {
+        info!("Nothing changed since the last build");
+        // run reports it by itself
+        if !args.quiet && !args.events && args.action != Action::Run {
+            println!("Nothing to do");
+        }
+        update_stable_link(args, conf, &build.target)?;
+        return Ok(false);
+    }
+    stamp.invalidate()?;
+
+    let mut bld = create_builder(args, conf)?;
+    if let Some(jobs) = args.jobs.or_else(makeflags_jobs) {
+        bld.set_jobs(jobs);
+    }
+    bld.set_job_timeout(args.job_timeout.or(build.job_timeout));
+    bld.set_keep_going(args.keep_going);
+    bld.set_max_errors(args.max_errors);
+    bld.set_retries(args.retries);
+    if args.diagnostics.is_some() {
+        bld.capture_output();
+    }
+    if args.quiet {
+        bld.set_quiet();
+    }
+    if args.events {
+        bld.set_event_handler(|e| print_event(&e));
+    }
+    let target = &build.target;
+    let bin_root = &build.compiler_conf.bin_root;
+    let failed_path = bin_root.join(paths::FAILED);
+
+    let res = if args.files.is_empty() {
+        let embedded = embed::generate(&conf.embed, &embed_dir(conf, args))?;
+
+        // build the files that failed last time first so that their errors
+        // are shown as soon as possible
+        let failed = if args.retry_failed {
+            load_failed(&failed_path)?
+        } else {
+            vec![]
+        };
+
+        let srcs = match &conf.project.entry {
+            Some(entry) => {
+                entry_sources(&mut bld, entry, conf, dir.srcs(), args.quiet)?
+            }
+            None => dir.srcs().to_vec(),
+        };
+        let srcs = with_unity(args, conf, srcs)?;
+
+        if failed.is_empty() {
+            info!("Building target {target:?}");
+            bld.build_all(target, srcs.iter().chain(&embedded))
+        } else {
+            info!("Retrying the failed files {failed:?}");
+            bld.build_objects(&failed).and_then(|_| {
+                info!("Building target {target:?}");
+                bld.build_all(target, srcs.iter().chain(&embedded))
+            })
+        }
+    } else {
+        info!("Building objects of {:?}", args.files);
+        bld.build_objects(&args.files)
     };
 
-    bld.build_all(target, dir.srcs())
+    bld.save_failed(&failed_path)?;
+    if let Some(format) = args.diagnostics {
+        report_diagnostics(format, &bld, bin_root)?;
+    }
+    let report = res?;
+    info!("Built {} files", report.built.len());
+
+    if use_stamp {
+        let embedded = conf.embed.iter().map(|e| e.as_path());
+        stamp.save(
+            bld.used_files()
+                .into_iter()
+                .chain(embedded)
+                .chain(conf.user_file.as_deref()),
+        )?;
+    }
+    update_stable_link(args, conf, target)?;
+
+    if args.files.is_empty() {
+        let linked = report.built.iter().any(|b| b == target);
+        let sizes =
+            SizeHistory::record(&bin_root.join(paths::SIZES), target, linked)?;
+        if let Some(mode) = args.size_report {
+            sizes.print_report(mode, target, bld.outputs());
+        }
+    }
+
+    if args.print_outputs {
+        let cwd = env::current_dir()?;
+        let outputs: Vec<_> =
+            bld.outputs().iter().map(|o| cwd.join(o)).collect();
+        if args.events {
+            print_event(&BuildEvent::Outputs {
+                files: outputs.iter().map(|o| o.as_path()).collect(),
+            });
+        } else {
+            for o in outputs {
+                println!("{}", o.to_string_lossy());
+            }
+        }
+    }
+
+    if args.iwyu {
+        let srcs = if args.files.is_empty() {
+            dir.srcs()
+        } else {
+            &args.files
+        };
+        iwyu::check(bld.compiler(), srcs, args.iwyu_fail)?;
+    }
+
+    Ok(!report.built.is_empty())
+}
+
+/// Creates the builder with the arguments and link dependencies shared by
+/// `build` and `plan`.
+fn create_builder(args: &Args, conf: &Config) -> Result<Builder> {
+    let build = profile_build(args, conf);
+    let mut bld = Builder::from_config(conf, args.release)?;
+    bld.add_args(&args.cflags, &args.ldflags);
+    // changes in the configuration may change the link flags
+    if let Some(file) = &conf.file {
+        bld.add_link_dependency(file);
+    }
+    if let Some(file) = conf.user_file.as_ref().filter(|f| f.is_file()) {
+        bld.add_link_dependency(file);
+    }
+    if let Some(def) = &build.compiler_conf.module_def {
+        if cfg!(windows) {
+            bld.add_link_dependency(def);
+        }
+    }
+    Ok(bld)
+}
+
+/// Gets the directory with the sources generated from the embedded files.
+fn embed_dir(conf: &Config, args: &Args) -> PathBuf {
+    profile_build(args, conf)
+        .compiler_conf
+        .bin_root
+        .join(paths::EMBED_DIR)
+}
+
+/// With unity build, replaces the sources with the generated batches (see
+/// [`unity::generate`]).
+fn with_unity(
+    args: &Args,
+    conf: &Config,
+    srcs: Vec<PathBuf>,
+) -> Result<Vec<PathBuf>> {
+    let build = profile_build(args, conf);
+    match &build.unity {
+        Some(u) => {
+            let dir = build.compiler_conf.bin_root.join(paths::UNITY_DIR);
+            unity::generate(&srcs, u, &build.metadata.files, &dir)
+        }
+        None => Ok(srcs),
+    }
+}
+
+/// Gets the sources with the unity batches like [`with_unity`] without
+/// writing the batches.
+fn unity_sources(
+    args: &Args,
+    conf: &Config,
+    srcs: Vec<PathBuf>,
+) -> Result<Vec<PathBuf>> {
+    let build = profile_build(args, conf);
+    match &build.unity {
+        Some(u) => {
+            let dir = build.compiler_conf.bin_root.join(paths::UNITY_DIR);
+            unity::sources(&srcs, u, &build.metadata.files, &dir)
+        }
+        None => Ok(srcs),
+    }
+}
+
+/// Gets the unity batch that includes the source, if the unity build is
+/// enabled and the source is in a batch.
+fn unity_batch(
+    args: &Args,
+    conf: &Config,
+    srcs: &[PathBuf],
+    file: &Path,
+) -> Result<Option<PathBuf>> {
+    let build = profile_build(args, conf);
+    match &build.unity {
+        Some(u) => {
+            let dir = build.compiler_conf.bin_root.join(paths::UNITY_DIR);
+            unity::batch_of(file, srcs, u, &build.metadata.files, &dir)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Points the stable link to the binary after full build.
+fn update_stable_link(
+    args: &Args,
+    conf: &Config,
+    target: &Path,
+) -> Result<()> {
+    match &conf.stable_target {
+        Some(link) if args.files.is_empty() => {
+            stable_link::update(target, link)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Gets the sources reachable from the entry and warns about the skipped
+/// sources.
+fn entry_sources(
+    bld: &mut Builder,
+    entry: &Path,
+    conf: &Config,
+    srcs: &[PathBuf],
+    quiet: bool,
+) -> Result<Vec<PathBuf>> {
+    let res =
+        bld.reachable_sources(entry, srcs, conf.project.entry_pairing)?;
+    if !quiet {
+        for s in srcs.iter().filter(|s| !res.contains(s)) {
+            printcln!(
+                "{'y bold}   Skipping{'_} {}, it is not reachable from {}",
+                s.to_string_lossy(),
+                entry.to_string_lossy()
+            );
+        }
+    }
+    Ok(res)
+}
+
+/// Prints the build event as single line of JSON.
+fn print_event(e: &BuildEvent) {
+    // serializing the event cannot fail
+    if let Ok(e) = serde_json::to_string(e) {
+        println!("{e}");
+    }
+}
+
+/// Reports the diagnostics from the compiler output in the given format.
+fn report_diagnostics(
+    format: DiagnosticsFormat,
+    bld: &Builder,
+    bin_root: &Path,
+) -> Result<()> {
+    let diags = diagnostics::parse_all(bld.captured_output());
+    match format {
+        DiagnosticsFormat::Github => {
+            print!("{}", diagnostics::to_github(&diags))
+        }
+        DiagnosticsFormat::Sarif => {
+            let path = bin_root.join(paths::SARIF);
+            let sarif =
+                serde_json::to_string_pretty(&diagnostics::to_sarif(&diags))
+                    .map_err(anyhow::Error::from)?;
+            fs::create_dir_all(bin_root)
+                .with_path("create directory", bin_root)?;
+            fs::write(&path, sarif).with_path("write", &path)?;
+            info!("Diagnostics written to {path:?}");
+        }
+    }
+    Ok(())
+}
+
+/// Gets the job count from `-jN` in `MAKEFLAGS` when ccpp is invoked from
+/// make. Returns [`None`] if the job count is not limited.
+fn makeflags_jobs() -> Option<usize> {
+    let flags = env::var("MAKEFLAGS").ok()?;
+    // variable definitions follow after ` -- `
+    let jobs = flags
+        .split_whitespace()
+        .take_while(|f| *f != "--")
+        .filter_map(|f| {
+            f.strip_prefix("--jobs=").or_else(|| f.strip_prefix("-j"))
+        })
+        .last()?;
+    let jobs = jobs.parse().ok().filter(|j| *j != 0)?;
+    info!("Using {jobs} jobs from MAKEFLAGS");
+    Some(jobs)
 }
 
 fn run_loaded(args: &Args, conf: &Config) -> Result<()> {
@@ -102,14 +1064,201 @@ fn run_loaded(args: &Args, conf: &Config) -> Result<()> {
         &conf.debug_build.target
     };
 
-    Command::new(target)
+    let runner = args.runner.as_ref().or(conf.run.runner.as_ref());
+    let mut cmd = if let Some(runner) = runner {
+        let mut runner = split_shell(runner)?.into_iter();
+        let prog = runner.next().ok_or_else(|| {
+            Error::Generic("The runner command is empty".to_owned())
+        })?;
+        let runner: Vec<_> = runner.collect();
+
+        let mut cmd = Command::new(&prog);
+        cmd.args(&runner);
+        // gdb needs `--args` to pass the arguments to the target
+        let is_gdb = Path::new(&prog).file_name().is_some_and(|n| n == "gdb");
+        if is_gdb && !runner.iter().any(|a| a == "--args") {
+            cmd.arg("--args");
+        }
+        cmd.arg(target);
+        cmd
+    } else {
+        Command::new(target)
+    };
+
+    info!("Running target {target:?}");
+    let status = cmd
         .args(args.app_args.iter())
-        .spawn()?
+        .spawn()
+        .with_path("run", cmd.get_program().as_ref())?
         .wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::ProcessFailed(status.code()))
+    }
+}
+
+fn script(args: &Args, file: &Path) -> Result<()> {
+    let file = file.canonicalize().with_path("find", file)?;
+    let bin_dir = match &args.bin_dir {
+        Some(d) => d.clone(),
+        None => script_cache_dir(&file, &args.script_flags)?,
+    };
+    let user = load_user_config(args)?;
+    let mut conf = Config::for_script(
+        &file,
+        &bin_dir,
+        args.script_flags.clone(),
+        user.as_ref(),
+    )?;
+    conf.user_file = user_config_file(args);
+    let src_root = conf.debug_build.compiler_conf.src_root.clone();
+    let dir = DirStructure::with_files(src_root, vec![file]);
+    build_loaded(args, &conf, &dir)?;
+    run_loaded(args, &conf)
+}
+
+/// Gets the directory where the script is built. It is unique for each
+/// script and its flags.
+fn script_cache_dir(file: &Path, flags: &[String]) -> Result<PathBuf> {
+    let mut hasher = StableHasher::new();
+    file.hash(&mut hasher);
+    flags.hash(&mut hasher);
+    Ok(paths::user_cache_dir()?
+        .join("script")
+        .join(format!("{:016x}", hasher.finish())))
+}
+
+fn generate(args: &Args, generator: Generator) -> Result<()> {
+    let conf = load_config(args)?;
+    match generator {
+        Generator::Vscode => vscode::generate(&conf, Path::new("")),
+    }
+}
+
+/// Prints the version of ccpp and the information about its build. With
+/// `--verbose` also prints the compilers.
+fn version(args: &Args) -> Result<()> {
+    match option_env!("CCPP_GIT_HASH") {
+        Some(hash) => printcln!(
+            "{'g bold}ccpp{'_} {} ({})",
+            env!("CARGO_PKG_VERSION"),
+            hash
+        ),
+        None => printcln!("{'g bold}ccpp{'_} {}", env!("CARGO_PKG_VERSION")),
+    }
+    println!(
+        "target: {}",
+        option_env!("CCPP_TARGET").unwrap_or("unknown")
+    );
+
+    let conf = Config::without_project(None)?;
+    println!(
+        "debug: {}",
+        profile_summary(&conf.debug_build.compiler_conf)
+    );
+    println!(
+        "release: {}",
+        profile_summary(&conf.release_build.compiler_conf)
+    );
+
+    if args.verbosity > 0 {
+        println!();
+        compiler_version(args)?;
+    }
     Ok(())
 }
 
-fn new(_args: &Args, dir: &Path) -> Result<()> {
+/// Summarizes the settings of the compiler configuration of profile.
+fn profile_summary(conf: &compiler::config::Config) -> String {
+    let std = |s: &Std| match s {
+        Std::Number(n) => n.to_string(),
+        Std::Name(n) => n.clone(),
+    };
+    let asan = match conf.asan {
+        Asan::Auto => "auto",
+        Asan::Enabled => "true",
+        Asan::Disabled => "false",
+    };
+    let ndebug = conf.defines.iter().any(|(n, _)| n == "NDEBUG");
+    format!(
+        "optimization = {}, dbg_symbols = {}, asan = {}, ndebug = {}, \
+        c_std = {}, cpp_std = {}",
+        conf.optimization,
+        conf.dbg_symbols,
+        asan,
+        ndebug,
+        std(&conf.c_std),
+        std(&conf.cpp_std)
+    )
+}
+
+/// Prints the versions of the compilers that would build the project.
+fn compiler_version(args: &Args) -> Result<()> {
+    let conf = if Path::new(CONF_FILE).exists() {
+        load_config(args)?
+    } else {
+        Config::without_project(load_user_config(args)?.as_ref())?
+    };
+    let build = profile_build(args, &conf);
+    let compiler = Compiler::new(
+        build.cc.clone(),
+        build.cpp.clone(),
+        &build.compiler_conf,
+    );
+
+    for (i, (name, lang)) in [("C", Language::C), ("C++", Language::Cpp)]
+        .into_iter()
+        .enumerate()
+    {
+        if i != 0 {
+            println!();
+        }
+        let (bin, typ) = compiler.resolve_bin(lang);
+        let path = which::which(&bin).unwrap_or_else(|_| bin.clone());
+        printcln!(
+            "{'g bold}{} compiler:{'_} {} ({})",
+            name,
+            path.to_string_lossy(),
+            typ
+        );
+        match compiler::version(&bin) {
+            Some(v) => println!("{}", v.trim_end()),
+            None => println!("Failed to get the version"),
+        }
+    }
+    Ok(())
+}
+
+/// Prints the recorded sizes of the binary.
+fn size_history(args: &Args) -> Result<()> {
+    let conf = load_config(args)?;
+    let bin_root = &profile_build(args, &conf).compiler_conf.bin_root;
+    SizeHistory::load(&bin_root.join(paths::SIZES))?.print_table();
+    Ok(())
+}
+
+/// Prints the configuration merged with the user configuration.
+fn config_show(args: &Args) -> Result<()> {
+    let user = if args.no_user_config {
+        println!("# user configuration: disabled");
+        None
+    } else if let Some((path, user)) = SerdeUserConfig::load()? {
+        println!("# user configuration: {}", path.to_string_lossy());
+        Some(user)
+    } else {
+        println!("# user configuration: not found");
+        None
+    };
+    println!("# project configuration: {CONF_FILE}");
+    println!();
+
+    let conf = SerdeConfig::from_toml_file(CONF_FILE, user.as_ref())?;
+    print!("{}", conf.to_toml_string()?);
+    Ok(())
+}
+
+fn new(args: &Args, dir: &Path) -> Result<()> {
     let name = if let Some(name) = dir.file_name() {
         name.to_string_lossy()
     } else {
@@ -118,69 +1267,107 @@ fn new(_args: &Args, dir: &Path) -> Result<()> {
         )));
     };
 
+    let defaults = load_user_config(args)?
+        .and_then(|u| u.new)
+        .unwrap_or_default();
+    let cpp = match defaults.language.as_deref() {
+        None | Some("c") => false,
+        Some("cpp" | "c++") => true,
+        Some(l) => {
+            return Err(Error::Generic(format!(
+                "Invalid language `{l}` in the section `[new]` of the user \
+                configuration, expected `c` or `cpp`"
+            )))
+        }
+    };
+
+    let build = defaults.std.map(|std| {
+        let (c_std, cpp_std) = if cpp {
+            (None, Some(std))
+        } else {
+            (Some(std), None)
+        };
+        SerdeBuild {
+            compiler_configuration: Some(SerdeCompilerConfig {
+                c_std,
+                cpp_std,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    });
+
+    let authors = scaffold::git_authors();
     let conf = SerdeConfig {
         project: SerdeProject {
-            name: name.into_owned(),
-            src: None,
-            bin: None,
+            name: name.to_string(),
+            authors: authors.clone(),
+            license: args.license.map(|l| l.spdx().to_owned()),
+            ..SerdeProject::default()
         },
+        build,
         ..SerdeConfig::default()
     };
 
+    // the files from the template take precedence over the generated files
+    if let Some(template) = &args.template {
+        scaffold::apply_template(template, dir, &name)?;
+    }
+
     let conf_path = dir.join("ccpp.toml");
     let src_path = dir.join("src");
-    conf.to_toml_file(conf_path)?;
+    if args.template.is_none() || !conf_path.exists() {
+        conf.to_toml_file(conf_path)?;
+    } else {
+        scaffold::fill_project(&conf_path, &authors, args.license)?;
+    }
+    if let Some(license) = args.license {
+        license.write(dir, &name, &authors)?;
+    }
     if !src_path.exists() {
-        fs::create_dir_all(&src_path)?;
-        fs::write(
-            src_path.join("main.c"),
-            "#include <stdio.h>
+        fs::create_dir_all(&src_path)
+            .with_path("create directory", &src_path)?;
+        let main_path = src_path.join(if cpp { "main.cpp" } else { "main.c" });
+        if cpp {
+            fs::write(
+                &main_path,
+                "#include <iostream>
+
+int main() {
+    std::cout << \"Hello World!\" << std::endl;
+}
+",
+            )
+            .with_path("write", &main_path)?;
+        } else {
+            fs::write(
+                &main_path,
+                "#include <stdio.h>
 
 int main(void) {
     printf(\"Hello World!\\n\");
 }
 ",
-        )?;
-        fs::write(dir.join(".gitignore"), "bin\n")?;
+            )
+            .with_path("write", &main_path)?;
+        }
+        let gitignore_path = dir.join(".gitignore");
+        if !gitignore_path.exists() || args.template.is_none() {
+            let gitignore = defaults.gitignore.as_deref().unwrap_or("bin\n");
+            fs::write(&gitignore_path, gitignore)
+                .with_path("write", &gitignore_path)?;
+        }
     }
 
     Ok(())
 }
 
-fn help(_args: &Args) -> Result<()> {
-    let v: Option<&str> = option_env!("CARGO_PKG_VERSION");
-    printcln!(
-        "Welcome to {'g i}ccpp{'_} help by {}{'_}
-Version: {}
-
-{'g}Usage:
-  {'w}ccpp <action>{'_} {'gr}[flags] [-- [arg] [arg] ...]
-
-{'g}Actions:
-  {'y}help  h  -h  -?  --help{'_}
-    Shows this help.
-
-  {'y}clean{'_}
-    Delete all compiled files (binary and object files).
-
-  {'y}build{'_}
-    Build the source code.
-
-  {'y}run{'_}
-    Build the source and run the app with the arguments after `--`.
-
-  {'y}new {'w}<project folder>{'_}
-    Create a new project in the given folder. The project name will be the
-    folder name. If the folder doesn't exist, it is created.
-
-{'g}Flags:
-  {'y}-r  --release{'_}
-    Build/run in release mode.
-",
-        gradient("BonnyAD9", (250, 50, 170), (180, 50, 240)),
-        v.unwrap_or("unknown")
-    );
-    Ok(())
+fn help(args: &Args) -> Result<()> {
+    let topic = match &args.action {
+        Action::Help(t) => t.as_deref(),
+        _ => None,
+    };
+    help::print(topic)
 }
 
 fn debug_code(_args: &Args) -> Result<()> {
@@ -0,0 +1,120 @@
+use std::{
+    env,
+    process::{Command, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::debug;
+
+use crate::config::Metadata;
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Gets the compiler arguments with defines for the enabled metadata.
+/// `profile` is the name of the build profile (e.g. `debug`).
+///
+/// The defines are:
+/// - `BUILD_GIT_HASH`: string with the short hash of the git commit,
+///   `"unknown"` if the project is not in git repository
+/// - `BUILD_GIT_DIRTY`: `1` if there are uncommited changes, otherwise `0`
+/// - `BUILD_DATE`: string with the UTC time of the build in ISO 8601 format,
+///   `SOURCE_DATE_EPOCH` is used if it is set
+/// - `BUILD_PROFILE`: string with the name of the profile
+pub fn defines(meta: &Metadata, profile: &str) -> Vec<String> {
+    let mut res = vec![];
+
+    if meta.git_hash {
+        let hash = git(&["rev-parse", "--short", "HEAD"]);
+        let hash = hash.as_deref().map_or("unknown", |h| h.trim());
+        res.push(format!("-DBUILD_GIT_HASH=\"{hash}\""));
+    }
+
+    if meta.git_dirty {
+        let dirty = git(&["status", "--porcelain"])
+            .is_some_and(|s| !s.trim().is_empty());
+        res.push(format!("-DBUILD_GIT_DIRTY={}", dirty as u8));
+    }
+
+    if meta.timestamp {
+        res.push(format!("-DBUILD_DATE=\"{}\"", format_time(build_time())));
+    }
+
+    if meta.profile {
+        res.push(format!("-DBUILD_PROFILE=\"{profile}\""));
+    }
+
+    res
+}
+
+/// Gets the current year, `SOURCE_DATE_EPOCH` is used if it is set.
+pub fn year() -> i64 {
+    civil_date(build_time()).0
+}
+
+/// Runs git with the given arguments and returns its stdout. Returns
+/// [`None`] if git fails (e.g. it is not installed or this is not git
+/// repository).
+pub fn git(args: &[&str]) -> Option<String> {
+    let out = Command::new("git")
+        .args(args)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        debug!("git {args:?} failed with {}", out.status);
+        return None;
+    }
+    String::from_utf8(out.stdout).ok()
+}
+
+/// Formats the unix time as `YYYY-MM-DDThh:mm:ssZ`.
+pub fn format_time(time: u64) -> String {
+    let secs = time % 86400;
+    let (year, month, day) = civil_date(time);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Gets the time of the build as seconds since the unix epoch.
+fn build_time() -> u64 {
+    // allow reproducible builds
+    if let Some(t) = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|t| t.parse().ok())
+    {
+        return t;
+    }
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Gets the UTC date (year, month and day) of the unix time.
+fn civil_date(time: u64) -> (i64, i64, i64) {
+    let days = (time / 86400) as i64;
+
+    // convert days to civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
@@ -0,0 +1,374 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    time::SystemTime,
+};
+
+use log::debug;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    err::{Error, Result, ResultExt},
+    file_type::{FileState, FileType},
+};
+
+/// Maximum total size of the cached objects in bytes. When it is exceeded,
+/// the least recently used objects are removed.
+const MAX_SIZE: u64 = 1 << 30;
+
+/// Cache of compiled objects shared by all projects. The objects are stored
+/// by SHA-256 of the compile command, the version of the compiler, the
+/// contents of all the compiled files and the headers in the include
+/// directories, so the same inputs reuse the object even if the files were
+/// touched (e.g. by switching git branches).
+pub struct ObjectCache {
+    dir: PathBuf,
+    /// Include directories (`-I` and `-isystem`) whose headers are part of
+    /// the key
+    include_dirs: Vec<PathBuf>,
+    /// SHA-256 of the output of `--version` of the compilers, [`None`] if it
+    /// cannot be retrieved
+    versions: HashMap<OsString, Option<String>>,
+    /// SHA-256 of the headers in the include directories
+    dir_hashes: HashMap<PathBuf, String>,
+    /// True if any object was stored
+    stored: bool,
+}
+
+/// Key of an object in the cache. The object is stored under the hash and
+/// the full key is stored next to it, so that a restored object always has
+/// exactly the same inputs.
+pub struct CacheKey {
+    hash: String,
+    key: String,
+}
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+impl ObjectCache {
+    /// Creates cache that stores the objects in the given directory. The
+    /// headers in the include directories are part of the keys of all the
+    /// objects, because the includes are resolved in them only by the
+    /// compiler.
+    pub fn new(dir: PathBuf, include_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            dir,
+            include_dirs,
+            versions: HashMap::new(),
+            dir_hashes: HashMap::new(),
+            stored: false,
+        }
+    }
+
+    /// Gets the key of the object compiled by the command from the given
+    /// files. Returns [`None`] if the version of the compiler is unknown.
+    pub fn key<'a, I>(
+        &mut self,
+        cmd: &Command,
+        inputs: I,
+    ) -> Result<Option<CacheKey>>
+    where
+        I: IntoIterator<Item = &'a Path>,
+    {
+        let Some(version) = self.version(cmd.get_program()) else {
+            return Ok(None);
+        };
+
+        // debug formatting escapes the values, so the key is unambiguous
+        let mut key = format!("version {version}\n");
+        _ = writeln!(key, "program {:?}", cmd.get_program());
+        for a in cmd.get_args() {
+            _ = writeln!(key, "arg {a:?}");
+        }
+        // the order of the included files is not stable
+        let mut inputs: Vec<_> = inputs.into_iter().collect();
+        inputs.sort();
+        for i in inputs {
+            let content = fs::read(i).with_path("read", i)?;
+            _ = writeln!(key, "input {} {i:?}", sha256(&content));
+        }
+        for d in self.include_dirs.clone() {
+            let hash = self.dir_hash(&d)?;
+            _ = writeln!(key, "include {hash} {d:?}");
+        }
+
+        Ok(Some(CacheKey {
+            hash: sha256(key.as_bytes()),
+            key,
+        }))
+    }
+
+    /// Copies the cached object with the key to `out`. Returns false if the
+    /// object is not cached.
+    pub fn restore(&self, key: &CacheKey, out: &Path) -> Result<bool> {
+        let (cached, key_file) = self.paths(key);
+        if !cached.is_file() {
+            return Ok(false);
+        }
+        // protects against collisions and against objects stored by older
+        // versions
+        if fs::read_to_string(key_file).ok().as_ref() != Some(&key.key) {
+            debug!("Key of the cached object {cached:?} doesn't match");
+            return Ok(false);
+        }
+        if let Some(p) = out.parent() {
+            fs::create_dir_all(p).with_path("create directory", p)?;
+        }
+        fs::copy(&cached, out).with_path("write", out)?;
+        // the modification time is the time of the last use for eviction
+        fs::File::options()
+            .write(true)
+            .open(&cached)
+            .and_then(|f| f.set_modified(SystemTime::now()))
+            .with_path("write", &cached)?;
+        Ok(true)
+    }
+
+    /// Stores the object to the cache under the key.
+    pub fn store(&mut self, key: &CacheKey, obj: &Path) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_path("create directory", &self.dir)?;
+        let (cached, key_file) = self.paths(key);
+        // other builds may use the cache at the same time, so the files must
+        // appear in the cache only when they are complete
+        let tmp = self.dir.join(format!("{}.{}.tmp", key.hash, process::id()));
+        let res = fs::write(&tmp, &key.key)
+            .and_then(|_| fs::rename(&tmp, key_file))
+            .and_then(|_| fs::copy(obj, &tmp))
+            .and_then(|_| fs::rename(&tmp, &cached));
+        if let Err(e) = res {
+            _ = fs::remove_file(&tmp);
+            return Err(Error::io_at("write", &cached, e));
+        }
+        self.stored = true;
+        Ok(())
+    }
+
+    /// Removes the least recently used objects when the total size of the
+    /// cache exceeds the limit. It does nothing if no object was stored.
+    pub fn evict(&self) -> Result<()> {
+        if self.stored {
+            self.evict_to(MAX_SIZE)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+impl ObjectCache {
+    /// Removes the least recently used objects until their total size is at
+    /// most `max_size`.
+    fn evict_to(&self, max_size: u64) -> Result<()> {
+        let mut objects = vec![];
+        let mut total = 0;
+        for e in fs::read_dir(&self.dir).with_path("read", &self.dir)? {
+            let e = e.with_path("read", &self.dir)?;
+            let path = e.path();
+            if path.extension() != Some(OsStr::new("o")) {
+                continue;
+            }
+            let meta = e.metadata().with_path("read", &path)?;
+            let modified = meta.modified().with_path("read", &path)?;
+            total += meta.len();
+            objects.push((modified, meta.len(), path));
+        }
+        if total <= max_size {
+            return Ok(());
+        }
+
+        objects.sort();
+        for (_, size, path) in objects {
+            if total <= max_size {
+                break;
+            }
+            debug!("Evicting {path:?} from the object cache");
+            fs::remove_file(&path).with_path("remove", &path)?;
+            _ = fs::remove_file(path.with_extension("key"));
+            total -= size;
+        }
+        Ok(())
+    }
+
+    /// Gets the path to the cached object and to its key.
+    fn paths(&self, key: &CacheKey) -> (PathBuf, PathBuf) {
+        (
+            self.dir.join(format!("{}.o", key.hash)),
+            self.dir.join(format!("{}.key", key.hash)),
+        )
+    }
+
+    /// Gets the hash of all the headers in the directory.
+    fn dir_hash(&mut self, dir: &Path) -> Result<String> {
+        if let Some(h) = self.dir_hashes.get(dir) {
+            return Ok(h.clone());
+        }
+
+        let mut headers = vec![];
+        let mut dirs = vec![dir.to_owned()];
+        while let Some(d) = dirs.pop() {
+            let entries = match fs::read_dir(&d) {
+                Ok(e) => e,
+                // the directory may be created later (e.g. the embedded
+                // files)
+                Err(_) => continue,
+            };
+            for e in entries {
+                let path = e.with_path("read", &d)?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if is_header(&path) {
+                    headers.push(path);
+                }
+            }
+        }
+        headers.sort();
+
+        let mut hasher = Sha256::new();
+        for h in headers {
+            let rel = h.strip_prefix(dir).unwrap_or(&h);
+            let content = fs::read(&h).with_path("read", &h)?;
+            hasher.update(format!("{rel:?} {}\n", sha256(&content)));
+        }
+        let hash = hex(&hasher.finalize());
+        self.dir_hashes.insert(dir.to_owned(), hash.clone());
+        Ok(hash)
+    }
+
+    /// Gets hash of the output of `--version` of the compiler.
+    fn version(&mut self, bin: &OsStr) -> Option<String> {
+        self.versions
+            .entry(bin.to_owned())
+            .or_insert_with(|| {
+                let out = Command::new(bin)
+                    .arg("--version")
+                    .stdin(Stdio::null())
+                    .stderr(Stdio::null())
+                    .output();
+                match out {
+                    Ok(o) if o.status.success() => Some(sha256(&o.stdout)),
+                    _ => {
+                        debug!(
+                            "Cannot get version of {bin:?}, objects will not \
+                            be cached"
+                        );
+                        None
+                    }
+                }
+            })
+            .clone()
+    }
+}
+
+/// Checks whether the file may be included. Headers of some libraries
+/// (e.g. the C++ standard library) have no extension.
+fn is_header(path: &Path) -> bool {
+    match path.extension() {
+        Some(ext) => matches!(
+            FileType::from_ext(ext),
+            Some(FileType {
+                state: FileState::Header,
+                ..
+            })
+        ),
+        None => true,
+    }
+}
+
+fn sha256(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut s, b| {
+        _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    fn key(name: &str) -> CacheKey {
+        CacheKey {
+            hash: sha256(name.as_bytes()),
+            key: format!("{name}\n"),
+        }
+    }
+
+    #[test]
+    fn restores_only_matching_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let obj = dir.path().join("main.o");
+        fs::write(&obj, "object").unwrap();
+        let mut cache = ObjectCache::new(dir.path().join("cache"), vec![]);
+        cache.store(&key("a"), &obj).unwrap();
+
+        let out = dir.path().join("out/main.o");
+        assert!(cache.restore(&key("a"), &out).unwrap());
+        assert_eq!(fs::read_to_string(&out).unwrap(), "object");
+
+        let other = CacheKey {
+            key: "b\n".into(),
+            ..key("a")
+        };
+        assert!(!cache.restore(&other, &out).unwrap());
+        assert!(!cache.restore(&key("b"), &out).unwrap());
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let obj = dir.path().join("main.o");
+        fs::write(&obj, [0; 100]).unwrap();
+        let out = dir.path().join("out.o");
+        let mut cache = ObjectCache::new(dir.path().join("cache"), vec![]);
+        for k in ["a", "b", "c"] {
+            cache.store(&key(k), &obj).unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+        // using the object makes it the most recently used
+        assert!(cache.restore(&key("a"), &out).unwrap());
+
+        cache.evict_to(200).unwrap();
+        assert!(cache.restore(&key("a"), &out).unwrap());
+        assert!(!cache.restore(&key("b"), &out).unwrap());
+        assert!(cache.restore(&key("c"), &out).unwrap());
+        assert!(!cache.paths(&key("b")).1.exists());
+    }
+
+    #[test]
+    fn headers_without_extension_are_hashed() {
+        let dir = tempfile::tempdir().unwrap();
+        let inc = dir.path().join("include");
+        fs::create_dir_all(inc.join("lib")).unwrap();
+        fs::write(inc.join("lib/vector"), "1").unwrap();
+        fs::write(inc.join("lib.o"), "1").unwrap();
+
+        let hash = |cache: &mut ObjectCache| cache.dir_hash(&inc).unwrap();
+        let mut cache = ObjectCache::new(dir.path().join("cache"), vec![]);
+        let first = hash(&mut cache);
+        fs::write(inc.join("lib.o"), "2").unwrap();
+        assert_eq!(
+            hash(&mut ObjectCache::new(dir.path().into(), vec![])),
+            first
+        );
+        fs::write(inc.join("lib/vector"), "2").unwrap();
+        assert_ne!(
+            hash(&mut ObjectCache::new(dir.path().into(), vec![])),
+            first
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::err::{Error, Result};
+
+// Names of the files and directories that ccpp creates in the bin directory
+// of a profile. New files should be added here so that `clean` knows about
+// them.
+
+/// Lock of the bin directory, see [`crate::lock::BuildLock`]
+pub const LOCK: &str = ".ccpp-lock";
+/// List of sources that failed to compile in the last build
+pub const FAILED: &str = ".ccpp-failed";
+/// Hashes of the dependencies compared by content
+pub const HASHES: &str = ".ccpp-hashes";
+/// Included files that didn't exist when the objects were compiled
+pub const MISSING: &str = ".ccpp-missing";
+/// Hash of all the files used by the last successful build
+pub const STAMP: &str = ".ccpp-stamp";
+/// Cached results of probing the compilers (e.g. supported flags)
+pub const PROBES: &str = ".ccpp-probes";
+/// Arguments of all the objects whose changes rebuild the objects (the name
+/// and version of the project and `--cflag`), it is updated only when they
+/// change
+pub const OBJ_ARGS: &str = ".ccpp-obj-args";
+/// Arguments from `--ldflag`, it is updated only when they change so that
+/// the executable is relinked
+pub const LINK_ARGS: &str = ".ccpp-link-args";
+/// Sizes of the binary after the last builds as JSON
+pub const SIZES: &str = ".ccpp-sizes";
+/// Diagnostics in the SARIF format
+pub const SARIF: &str = "diagnostics.sarif";
+/// Generated sources with the embedded files
+pub const EMBED_DIR: &str = "embed";
+/// Generated programs of the tests from `ccpp test`
+pub const TEST_PROGRAMS_DIR: &str = "tests";
+/// Generated batches of unity build
+pub const UNITY_DIR: &str = "unity";
+/// Objects of the project sources (unless disabled with `obj_subdir`)
+pub const OBJ_DIR: &str = "project";
+
+/// Files that only speed up or affect planning of the next build. All other
+/// files except the lock are build artifacts.
+pub const CACHES: &[&str] = &[FAILED, HASHES, MISSING, PROBES, STAMP];
+
+/// Gets the directory of ccpp in the cache directory of the user
+/// (`$XDG_CACHE_HOME/ccpp`). It is shared by all the projects.
+pub fn user_cache_dir() -> Result<PathBuf> {
+    let cache = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            if cfg!(windows) {
+                env::var_os("LOCALAPPDATA").map(PathBuf::from)
+            } else {
+                env::var_os("HOME").map(|h| Path::new(&h).join(".cache"))
+            }
+        })
+        .ok_or_else(|| {
+            Error::Generic(
+                "Cannot find the cache directory, set `XDG_CACHE_HOME`"
+                    .to_owned(),
+            )
+        })?;
+    Ok(cache.join("ccpp"))
+}
+
+/// Gets the directory of the object cache shared by all the projects.
+pub fn object_cache_dir() -> Result<PathBuf> {
+    Ok(user_cache_dir()?.join("objects"))
+}
@@ -0,0 +1,211 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+use crate::{
+    err::{Error, Result},
+    metadata,
+    serde_config::SerdeUserConfig,
+};
+
+/// License whose text can be written to new project.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum License {
+    Mit,
+    Apache2,
+}
+
+const MIT: &str = include_str!("licenses/MIT");
+const APACHE_2: &str = include_str!("licenses/Apache-2.0");
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+impl License {
+    /// Parses the license name given on the command line, case
+    /// insensitive.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "mit" => Some(Self::Mit),
+            "apache-2.0" | "apache2" | "apache" => Some(Self::Apache2),
+            _ => None,
+        }
+    }
+
+    /// Gets the SPDX identifier of the license.
+    pub fn spdx(self) -> &'static str {
+        match self {
+            Self::Mit => "MIT",
+            Self::Apache2 => "Apache-2.0",
+        }
+    }
+
+    /// Writes the text of the license to `LICENSE` in the directory. Existing
+    /// file is not overwritten. If there are no authors, the copyright holder
+    /// is `The <name> Authors`.
+    pub fn write(
+        self,
+        dir: &Path,
+        name: &str,
+        authors: &[String],
+    ) -> Result<()> {
+        let path = dir.join("LICENSE");
+        if path.exists() {
+            debug!("Not writing {path:?}, it already exists");
+            return Ok(());
+        }
+
+        let holder = if authors.is_empty() {
+            format!("The {name} Authors")
+        } else {
+            authors.join(", ")
+        };
+        let text = match self {
+            Self::Mit => MIT
+                .replace("{{year}}", &metadata::year().to_string())
+                .replace("{{authors}}", &holder),
+            Self::Apache2 => APACHE_2.to_owned(),
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+/// Gets the author from the git configuration in the form `name <email>`.
+/// Returns empty list if the name is not configured.
+pub fn git_authors() -> Vec<String> {
+    let name = git_config("user.name");
+    let email = git_config("user.email");
+    match (name, email) {
+        (Some(n), Some(e)) => vec![format!("{n} <{e}>")],
+        (Some(n), None) => vec![n],
+        _ => vec![],
+    }
+}
+
+/// Copies the files of the user template with the given name to the
+/// directory. `{{name}}` in the file names and in the contents of text files
+/// is replaced with the name of the project. Existing files are
+/// overwritten.
+pub fn apply_template(template: &str, dir: &Path, name: &str) -> Result<()> {
+    let templates = SerdeUserConfig::templates_dir().ok_or_else(|| {
+        Error::Generic(
+            "Cannot find the user configuration directory with the templates"
+                .to_owned(),
+        )
+    })?;
+
+    let available = available_templates(&templates);
+    if !available.iter().any(|t| t == template) {
+        if available.is_empty() {
+            return Err(Error::NoTemplates(templates));
+        }
+        return Err(Error::UnknownTemplate {
+            name: template.to_owned(),
+            available,
+        });
+    }
+
+    copy_template(&templates.join(template), dir, name)
+}
+
+/// Adds the authors and the license to `[project]` in the configuration
+/// copied from a template, unless the template sets them. The rest of the
+/// file is kept as it is.
+pub fn fill_project(
+    path: &Path,
+    authors: &[String],
+    license: Option<License>,
+) -> Result<()> {
+    let text = fs::read_to_string(path)?;
+    let Some(project) = text
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut t| t.remove("project"))
+    else {
+        debug!("No `[project]` in {path:?}, it is not filled");
+        return Ok(());
+    };
+
+    let mut lines = String::new();
+    if !authors.is_empty() && project.get("authors").is_none() {
+        let authors = toml::Value::from(authors.to_vec());
+        lines.push_str(&format!("authors = {authors}\n"));
+    }
+    if let Some(l) = license.filter(|_| project.get("license").is_none()) {
+        let spdx = toml::Value::from(l.spdx());
+        lines.push_str(&format!("license = {spdx}\n"));
+    }
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    // the section may also be an inline table, that is left as it is
+    let Some(pos) = text
+        .lines()
+        .position(|l| l.trim_start().starts_with("[project]"))
+    else {
+        debug!("`[project]` in {path:?} is not a table header");
+        return Ok(());
+    };
+    let mut res: Vec<_> = text.lines().collect();
+    res.insert(pos + 1, lines.trim_end());
+    fs::write(path, res.join("\n") + "\n")?;
+    Ok(())
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Gets the value from git configuration. Returns [`None`] if git fails or
+/// the value is not set.
+fn git_config(key: &str) -> Option<String> {
+    let value = metadata::git(&["config", key])?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+/// Gets the sorted names of the templates in the directory.
+fn available_templates(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut res: Vec<_> = entries
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    res.sort();
+    res
+}
+
+/// Copies the template directory recursively.
+fn copy_template(from: &Path, to: &Path, name: &str) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let file_name = entry
+            .file_name()
+            .to_string_lossy()
+            .replace("{{name}}", name);
+        let target: PathBuf = to.join(file_name);
+        if entry.file_type()?.is_dir() {
+            copy_template(&entry.path(), &target, name)?;
+            continue;
+        }
+
+        let contents = fs::read(entry.path())?;
+        // binary files are copied as they are
+        match String::from_utf8(contents) {
+            Ok(text) => fs::write(&target, text.replace("{{name}}", name))?,
+            Err(e) => fs::write(&target, e.into_bytes())?,
+        }
+        debug!("Copied {:?} to {target:?}", entry.path());
+    }
+    Ok(())
+}
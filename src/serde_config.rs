@@ -1,16 +1,33 @@
 use std::{
+    env,
     fs::{self, read_to_string},
+    io,
     path::{Path, PathBuf},
 };
 
 use serde::{Deserialize, Serialize};
+use termal::formatc;
 
 use crate::{
-    compiler::config::{Optimization, Std},
-    config::{Build, CompilerConfig, Config, Project},
-    err::Result,
+    compiler::config::{
+        Asan, CustomCompiler, Optimization, Std, STRICT_C_ARGS,
+    },
+    config::{
+        Build, CompilerConfig, ConditionalSource, Config, Metadata, Project,
+        Run, Unity,
+    },
+    dep_rules::{Compare, DepRule},
+    dependency::Pairing,
+    duration::HumanDuration,
+    env_expand::expand_env,
+    err::{Error, Result, ResultExt},
+    file_type::ExtConfig,
+    version::Version,
 };
 
+/// Number of sources in single unity batch if it is not configured
+const DEFAULT_UNITY_BATCH: usize = 16;
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct SerdeConfig {
     pub project: SerdeProject,
@@ -20,13 +37,89 @@ pub struct SerdeConfig {
     pub debug_build: Option<SerdeBuild>,
     #[serde(default)]
     pub release_build: Option<SerdeBuild>,
+    #[serde(default)]
+    pub conditional_sources: Option<Vec<SerdeConditionalSource>>,
+    #[serde(default)]
+    pub run: Option<SerdeRun>,
+    #[serde(default)]
+    pub embed: Option<SerdeEmbed>,
+    #[serde(default)]
+    pub compiler: Option<SerdeCompiler>,
+}
+
+/// Workspace with multiple independent projects.
+#[derive(Deserialize)]
+pub struct SerdeWorkspace {
+    /// Directories of the projects, each with its own `ccpp.toml`
+    pub members: Vec<String>,
+}
+
+/// Only the workspace section of the configuration file.
+#[derive(Deserialize)]
+struct SerdeWorkspaceFile {
+    #[serde(default)]
+    workspace: Option<SerdeWorkspace>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SerdeProject {
     pub name: String,
+    #[serde(default)]
+    pub version: Option<Version>,
+    /// Description of the project, it doesn't affect the build
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Authors of the project, it doesn't affect the build
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+    /// SPDX identifier of the license, it doesn't affect the build
+    #[serde(default)]
+    pub license: Option<String>,
     pub src: Option<String>,
     pub bin: Option<String>,
+    #[serde(default)]
+    pub source_extensions: Option<SerdeExtensions>,
+    #[serde(default)]
+    pub case_sensitive_extensions: Option<bool>,
+    /// Dependencies whose changes don't cause rebuild
+    #[serde(default)]
+    pub dep_ignore: Option<Vec<String>>,
+    #[serde(default)]
+    pub dep_compare: Option<Vec<SerdeDepCompare>>,
+    /// How changes of the files that don't match `dep_compare` are detected
+    #[serde(default)]
+    pub fingerprint: Option<Compare>,
+    /// Put objects to `<bin>/<profile>/project` instead of `<bin>/<profile>`
+    #[serde(default)]
+    pub obj_subdir: Option<bool>,
+    /// Skip sources ignored by `.gitignore`
+    #[serde(default)]
+    pub respect_gitignore: Option<bool>,
+    /// Compile only the sources reachable from this file
+    #[serde(default)]
+    pub entry: Option<String>,
+    #[serde(default)]
+    pub entry_pairing: Option<Pairing>,
+    /// Link the last built binary to `<bin>/<name>`
+    #[serde(default)]
+    pub stable_link: Option<bool>,
+    /// Diagnostics from files that match these patterns are not shown
+    #[serde(default)]
+    pub suppress_diagnostics_from: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SerdeDepCompare {
+    pub files: Vec<String>,
+    pub compare: Compare,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct SerdeExtensions {
+    #[serde(default)]
+    pub c: Vec<String>,
+    #[serde(default)]
+    pub cpp: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -37,27 +130,307 @@ pub struct SerdeBuild {
     pub cpp: Option<String>,
     #[serde(default)]
     pub compiler_configuration: Option<SerdeCompilerConfig>,
+    #[serde(default)]
+    pub metadata: Option<SerdeMetadata>,
+    #[serde(default)]
+    pub metadata_files: Option<Vec<String>>,
+    #[serde(default)]
+    pub emit_depfiles: Option<bool>,
+    #[serde(default)]
+    pub object_cache: Option<bool>,
+    #[serde(default)]
+    pub job_timeout: Option<HumanDuration>,
+    #[serde(default)]
+    pub extra_objects: Option<Vec<String>>,
+    #[serde(default)]
+    pub unity: Option<SerdeUnity>,
+    #[serde(default)]
+    pub unity_exclude: Option<Vec<String>>,
+    #[serde(default)]
+    pub exclude_sources: Option<Vec<String>>,
+    #[serde(default)]
+    pub include_sources: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct SerdeUnity {
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    #[serde(default)]
+    pub batch_size: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct SerdeMetadata {
+    #[serde(default)]
+    pub git_hash: Option<bool>,
+    #[serde(default)]
+    pub git_dirty: Option<bool>,
+    #[serde(default)]
+    pub timestamp: Option<bool>,
+    #[serde(default)]
+    pub profile: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct SerdeEmbed {
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct SerdeRun {
+    #[serde(default)]
+    pub runner: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SerdeConditionalSource {
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub platform: Option<String>,
+    #[serde(default)]
+    pub define: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct SerdeCompiler {
+    /// Compiler that ccpp doesn't know
+    #[serde(default)]
+    pub custom: Option<SerdeCustomCompiler>,
+}
+
+/// Templates of the arguments of custom compiler, the templates that are not
+/// set use the syntax of gcc.
+#[derive(Serialize, Deserialize)]
+pub struct SerdeCustomCompiler {
+    pub bin: String,
+    #[serde(default)]
+    pub compile: Option<Vec<String>>,
+    #[serde(default)]
+    pub link: Option<Vec<String>>,
+    #[serde(default)]
+    pub preprocess: Option<Vec<String>>,
+    #[serde(default)]
+    pub optimization: Option<Vec<String>>,
+    #[serde(default)]
+    pub debug_optimization: Option<Vec<String>>,
+    #[serde(default)]
+    pub dbg_symbols: Option<Vec<String>>,
+    #[serde(default)]
+    pub asan: Option<Vec<String>>,
+    #[serde(default)]
+    pub std: Option<Vec<String>>,
+    #[serde(default)]
+    pub define: Option<Vec<String>>,
+    #[serde(default)]
+    pub define_value: Option<Vec<String>>,
+    #[serde(default)]
+    pub include_dir: Option<Vec<String>>,
+    #[serde(default)]
+    pub warn: Option<Vec<String>>,
+    #[serde(default)]
+    pub no_warn: Option<Vec<String>>,
+    #[serde(default)]
+    pub sysroot: Option<Vec<String>>,
+    #[serde(default)]
+    pub whole_archive: Option<Vec<String>>,
+    #[serde(default)]
+    pub map_file: Option<Vec<String>>,
+    #[serde(default)]
+    pub system_include_dir: Option<Vec<String>>,
+    #[serde(default)]
+    pub strict_c: Option<Vec<String>>,
+    #[serde(default)]
+    pub gc_sections: Option<Vec<String>>,
+    #[serde(default)]
+    pub gc_sections_link: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct SerdeCompilerConfig {
     pub optimization: Option<Optimization>,
-    pub asan: Option<bool>,
+    pub asan: Option<Asan>,
     pub dbg_symbols: Option<bool>,
+    pub gc_sections: Option<bool>,
+    /// Make implicit declarations errors in C
+    pub strict_c: Option<bool>,
+    /// Define `NDEBUG`, enabled by default in release builds
+    pub ndebug: Option<bool>,
     pub c_std: Option<Std>,
     pub cpp_std: Option<Std>,
     pub defines: Option<Vec<(String, Option<String>)>>,
     pub warn: Option<Vec<String>>,
     pub no_warn: Option<Vec<String>>,
     pub args: Option<Vec<String>>,
+    pub compile_args: Option<Vec<String>>,
+    pub link_args: Option<Vec<String>>,
+    pub c_args: Option<Vec<String>>,
+    pub cpp_args: Option<Vec<String>>,
+    pub sysroot: Option<String>,
+    pub module_def: Option<String>,
+    /// Static libraries linked with all their objects
+    pub whole_archive: Option<Vec<String>>,
+    /// Linker map file, relative to the bin directory of the profile
+    pub map_file: Option<String>,
+    /// Include directories whose headers don't produce warnings
+    pub system_include_dirs: Option<Vec<String>>,
+}
+
+/// User configuration that is merged beneath the configuration of each
+/// project.
+#[derive(Serialize, Deserialize, Default)]
+pub struct SerdeUserConfig {
+    #[serde(default)]
+    pub build: Option<SerdeBuild>,
+    #[serde(default)]
+    pub debug_build: Option<SerdeBuild>,
+    #[serde(default)]
+    pub release_build: Option<SerdeBuild>,
+    #[serde(default)]
+    pub new: Option<SerdeNew>,
+}
+
+/// Defaults for new projects.
+#[derive(Serialize, Deserialize, Default)]
+pub struct SerdeNew {
+    /// Language of the template, `c` or `cpp`
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Contents of the `.gitignore`
+    #[serde(default)]
+    pub gitignore: Option<String>,
+    /// Standard of the language
+    #[serde(default)]
+    pub std: Option<Std>,
 }
 
 impl Config {
-    pub fn from_toml_file<P>(path: P) -> Result<Self>
+    /// Loads the configuration from the file. If `bin_dir` is set, it
+    /// overrides the bin directory from the configuration. The user
+    /// configuration `user` is used for values that are not set in the file.
+    pub fn from_toml_file<P>(
+        path: P,
+        bin_dir: Option<&Path>,
+        user: Option<&SerdeUserConfig>,
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let conf = SerdeConfig::from_toml_file(path, user)?;
+        Ok(Config {
+            file: Some(path.to_owned()),
+            ..conf.resolve(bin_dir)
+        })
+    }
+
+    /// Creates default configuration merged with the user configuration for
+    /// the actions that may run outside of project.
+    pub fn without_project(user: Option<&SerdeUserConfig>) -> Result<Self> {
+        let mut conf = SerdeConfig::default();
+        if let Some(user) = user {
+            conf.merge_user(user);
+        }
+        conf.expand_env()?;
+        Ok(conf.resolve(None))
+    }
+
+    /// Creates default configuration for building single source file
+    /// `file` into `bin_dir`. `args` are passed to the compiler.
+    pub fn for_script(
+        file: &Path,
+        bin_dir: &Path,
+        args: Vec<String>,
+        user: Option<&SerdeUserConfig>,
+    ) -> Result<Self> {
+        let name = file
+            .file_stem()
+            .map_or("main".into(), |n| n.to_string_lossy().into_owned());
+        let src = file.parent().unwrap_or(Path::new("."));
+
+        let mut conf = SerdeConfig {
+            project: SerdeProject {
+                name,
+                src: Some(src.to_string_lossy().into_owned()),
+                stable_link: Some(false),
+                ..SerdeProject::default()
+            },
+            build: Some(SerdeBuild {
+                compiler_configuration: Some(SerdeCompilerConfig {
+                    args: Some(args),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..SerdeConfig::default()
+        };
+        if let Some(user) = user {
+            conf.merge_user(user);
+        }
+        conf.expand_env()?;
+        Ok(conf.resolve(Some(bin_dir)))
+    }
+}
+
+impl SerdeWorkspace {
+    /// Loads the workspace section from the configuration file. Returns
+    /// [`None`] if the file doesn't exist or it has no workspace section.
+    pub fn from_toml_file<P>(path: P) -> Result<Option<Self>>
     where
         P: AsRef<Path>,
     {
-        Ok(toml::from_str::<SerdeConfig>(&read_to_string(path)?)?.resolve())
+        let path = path.as_ref();
+        let src = match read_to_string(path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::io_at("read", path, e)),
+        };
+        let file: SerdeWorkspaceFile =
+            toml::from_str(&src).map_err(|e| toml_error(path, &src, e))?;
+        Ok(file.workspace)
+    }
+}
+
+impl SerdeUserConfig {
+    /// Gets the path to the user configuration file:
+    /// `$XDG_CONFIG_HOME/ccpp/config.toml` (`~/.config` by default) or
+    /// `%APPDATA%\ccpp\config.toml` on windows.
+    pub fn path() -> Option<PathBuf> {
+        Self::dir().map(|d| d.join("config.toml"))
+    }
+
+    /// Gets the directory with the user templates for `ccpp new --template`.
+    pub fn templates_dir() -> Option<PathBuf> {
+        Self::dir().map(|d| d.join("templates"))
+    }
+
+    /// Gets the directory of the user configuration.
+    fn dir() -> Option<PathBuf> {
+        let dir = if cfg!(windows) {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    env::var_os("HOME").map(|h| Path::new(&h).join(".config"))
+                })
+        };
+        dir.map(|d| d.join("ccpp"))
+    }
+
+    /// Loads the user configuration. Returns [`None`] if there is no user
+    /// configuration file.
+    pub fn load() -> Result<Option<(PathBuf, Self)>> {
+        let path = match Self::path() {
+            Some(p) if p.is_file() => p,
+            _ => return Ok(None),
+        };
+        let src = read_to_string(&path).with_path("read", &path)?;
+        match toml::from_str(&src) {
+            Ok(c) => Ok(Some((path, c))),
+            Err(e) => Err(toml_error(&path, &src, e)),
+        }
     }
 }
 
@@ -67,23 +440,75 @@ impl Default for SerdeProject {
             name: "main".into(),
             src: None,
             bin: None,
+            source_extensions: None,
+            case_sensitive_extensions: None,
+            dep_ignore: None,
+            dep_compare: None,
+            fingerprint: None,
+            obj_subdir: None,
+            respect_gitignore: None,
+            entry: None,
+            entry_pairing: None,
+            stable_link: None,
+            suppress_diagnostics_from: None,
+            version: None,
+            description: None,
+            authors: vec![],
+            license: None,
         }
     }
 }
 
 impl SerdeConfig {
+    /// Loads the configuration from the file and merges it with the user
+    /// configuration.
+    pub fn from_toml_file<P>(
+        path: P,
+        user: Option<&SerdeUserConfig>,
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let src = read_to_string(path).with_path("read", path)?;
+        let mut conf: SerdeConfig =
+            toml::from_str(&src).map_err(|e| toml_error(path, &src, e))?;
+        if let Some(user) = user {
+            conf.merge_user(user);
+        }
+        conf.expand_env()?;
+        Ok(conf)
+    }
+
+    /// Uses the values from the user configuration that are not set in
+    /// this configuration. Lists are appended to the lists from the user
+    /// configuration.
+    pub fn merge_user(&mut self, user: &SerdeUserConfig) {
+        self.build = merge_build(self.build.take(), user.build.clone());
+        self.debug_build =
+            merge_build(self.debug_build.take(), user.debug_build.clone());
+        self.release_build =
+            merge_build(self.release_build.take(), user.release_build.clone());
+    }
+
+    pub fn to_toml_string(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
     pub fn to_toml_file<P>(&self, path: P) -> Result<()>
     where
         P: AsRef<Path>,
     {
+        let path = path.as_ref();
         let value = toml::to_string_pretty(self)?;
-        fs::write(path, value)?;
+        fs::write(path, value).with_path("write", path)?;
         Ok(())
     }
 
-    fn resolve(self) -> Config {
-        let bin =
-            Path::new(self.project.bin.as_ref().map_or("bin", |s| s.as_str()));
+    fn resolve(self, bin_dir: Option<&Path>) -> Config {
+        let bin = bin_dir.unwrap_or_else(|| {
+            Path::new(self.project.bin.as_ref().map_or("bin", |s| s.as_str()))
+        });
         let src_root: PathBuf = self
             .project
             .src
@@ -97,38 +522,167 @@ impl SerdeConfig {
         let mut debug_target = bin_debug_root.join(&self.project.name);
         #[allow(unused_mut)]
         let mut release_target = bin_release_root.join(&self.project.name);
+        #[allow(unused_mut)]
+        let mut stable_target = bin.join(&self.project.name);
 
         #[cfg(target_os = "windows")]
         {
             debug_target.set_extension("exe");
             release_target.set_extension("exe");
+            stable_target.set_extension("exe");
         }
+        let stable_target = self
+            .project
+            .stable_link
+            .unwrap_or(true)
+            .then_some(stable_target);
 
         let common = self.build.unwrap_or_default();
         let debug_build = self.debug_build.unwrap_or_default();
         let release_build = self.release_build.unwrap_or_default();
+        let conditional_sources = self
+            .conditional_sources
+            .unwrap_or_default()
+            .into_iter()
+            .map(|c| c.resolve(&src_root))
+            .collect();
+
+        let embed: Vec<PathBuf> = self
+            .embed
+            .unwrap_or_default()
+            .files
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        let mut debug_build = debug_build.resolve_debug(
+            common.clone(),
+            debug_target,
+            src_root.clone(),
+            bin_debug_root,
+        );
+        let mut release_build = release_build.resolve_release(
+            common,
+            release_target,
+            src_root,
+            bin_release_root,
+        );
+
+        // the embedded headers are generated in `<bin_root>/embed` and are
+        // included as `<embed/header.h>`
+        if !embed.is_empty() {
+            for b in [&mut debug_build, &mut release_build] {
+                let root = b.compiler_conf.bin_root.clone();
+                b.compiler_conf.include_dirs.push(root);
+            }
+        }
+
+        let obj_subdir = self.project.obj_subdir.unwrap_or(true);
+        debug_build.compiler_conf.obj_subdir = obj_subdir;
+        release_build.compiler_conf.obj_subdir = obj_subdir;
+
+        // the name and version are available to all the sources
+        let project = self.project.resolve();
+        for b in [&mut debug_build, &mut release_build] {
+            b.compiler_conf.defines.extend(project.defines());
+        }
+
+        let custom = self
+            .compiler
+            .and_then(|c| c.custom)
+            .map(SerdeCustomCompiler::resolve);
+        debug_build.compiler_conf.custom = custom.clone();
+        release_build.compiler_conf.custom = custom;
 
         Config {
-            project: self.project.resolve(),
-            debug_build: debug_build.resolve_debug(
-                common.clone(),
-                debug_target,
-                src_root.clone(),
-                bin_debug_root,
-            ),
-            release_build: release_build.resolve_release(
-                common,
-                release_target,
-                src_root,
-                bin_release_root,
-            ),
+            file: None,
+            user_file: None,
+            project,
+            debug_build,
+            release_build,
+            conditional_sources,
+            run: self.run.unwrap_or_default().resolve(),
+            embed,
+            stable_target,
+        }
+    }
+}
+
+impl SerdeRun {
+    fn resolve(self) -> Run {
+        Run {
+            runner: self.runner,
         }
     }
 }
 
 impl SerdeProject {
     fn resolve(self) -> Project {
-        Project { name: self.name }
+        let default = ExtConfig::default();
+        let ext = self.source_extensions.unwrap_or_default();
+
+        // the ignored files take precedence over the other comparisons
+        let mut dep_rules: Vec<_> = self
+            .dep_ignore
+            .unwrap_or_default()
+            .into_iter()
+            .map(|pattern| DepRule {
+                pattern,
+                compare: Compare::Ignore,
+                code_only: false,
+            })
+            .collect();
+        dep_rules.extend(
+            self.dep_compare
+                .unwrap_or_default()
+                .into_iter()
+                .flat_map(|c| {
+                    c.files.into_iter().map(move |pattern| DepRule {
+                        pattern,
+                        compare: c.compare,
+                        code_only: false,
+                    })
+                }),
+        );
+        // the default for all the sources and headers in the project is the
+        // last rule, outputs (objects and binaries) are still compared by
+        // their modification time
+        if let Some(compare) = self.fingerprint {
+            dep_rules.push(DepRule {
+                pattern: "**".into(),
+                compare,
+                code_only: true,
+            });
+        }
+
+        Project {
+            name: self.name,
+            version: self.version,
+            extensions: ExtConfig {
+                case_sensitive: self
+                    .case_sensitive_extensions
+                    .unwrap_or(default.case_sensitive),
+                c_sources: ext.c,
+                cpp_sources: ext.cpp,
+            },
+            dep_rules,
+            respect_gitignore: self.respect_gitignore.unwrap_or(false),
+            entry: self.entry.map(Into::into),
+            entry_pairing: self.entry_pairing.unwrap_or_default(),
+            suppress_diagnostics_from: self
+                .suppress_diagnostics_from
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl SerdeConditionalSource {
+    fn resolve(self, src_root: &Path) -> ConditionalSource {
+        ConditionalSource {
+            files: self.files.into_iter().map(|f| src_root.join(f)).collect(),
+            platform: self.platform,
+            define: self.define,
+        }
     }
 }
 
@@ -140,6 +694,11 @@ impl SerdeBuild {
         src_root: PathBuf,
         bin_root: PathBuf,
     ) -> Build {
+        let metadata = resolve_metadata(
+            self.metadata,
+            common.metadata,
+            self.metadata_files.or(common.metadata_files),
+        );
         let compiler_configuration =
             match (self.compiler_configuration, common.compiler_configuration)
             {
@@ -159,6 +718,38 @@ impl SerdeBuild {
             cc: self.cc.or(common.cc).map(Into::into),
             cpp: self.cpp.or(common.cpp).map(Into::into),
             compiler_conf: compiler_configuration,
+            metadata,
+            emit_depfiles: self
+                .emit_depfiles
+                .or(common.emit_depfiles)
+                .unwrap_or_default(),
+            object_cache: self
+                .object_cache
+                .or(common.object_cache)
+                .unwrap_or_default(),
+            job_timeout: self.job_timeout.or(common.job_timeout).map(|t| t.0),
+            extra_objects: self
+                .extra_objects
+                .or(common.extra_objects)
+                .unwrap_or_default()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            unity: resolve_unity(
+                self.unity,
+                common.unity,
+                self.unity_exclude.or(common.unity_exclude),
+            ),
+            exclude_sources: merge_vec(
+                common.exclude_sources,
+                self.exclude_sources,
+            )
+            .unwrap_or_default(),
+            include_sources: merge_vec(
+                common.include_sources,
+                self.include_sources,
+            )
+            .unwrap_or_default(),
         }
     }
 
@@ -169,6 +760,11 @@ impl SerdeBuild {
         src_root: PathBuf,
         bin_root: PathBuf,
     ) -> Build {
+        let metadata = resolve_metadata(
+            self.metadata,
+            common.metadata,
+            self.metadata_files.or(common.metadata_files),
+        );
         let compiler_conf =
             match (self.compiler_configuration, common.compiler_configuration)
             {
@@ -185,13 +781,183 @@ impl SerdeBuild {
             cc: self.cc.or(common.cc).map(Into::into),
             cpp: self.cpp.or(common.cpp).map(Into::into),
             compiler_conf,
+            metadata,
+            emit_depfiles: self
+                .emit_depfiles
+                .or(common.emit_depfiles)
+                .unwrap_or_default(),
+            object_cache: self
+                .object_cache
+                .or(common.object_cache)
+                .unwrap_or_default(),
+            job_timeout: self.job_timeout.or(common.job_timeout).map(|t| t.0),
+            extra_objects: self
+                .extra_objects
+                .or(common.extra_objects)
+                .unwrap_or_default()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            unity: resolve_unity(
+                self.unity,
+                common.unity,
+                self.unity_exclude.or(common.unity_exclude),
+            ),
+            exclude_sources: merge_vec(
+                common.exclude_sources,
+                self.exclude_sources,
+            )
+            .unwrap_or_default(),
+            include_sources: merge_vec(
+                common.include_sources,
+                self.include_sources,
+            )
+            .unwrap_or_default(),
         }
     }
 }
 
+impl SerdeConfig {
+    /// Expands the environment variables in the paths and in the arguments
+    /// of the compiler.
+    fn expand_env(&mut self) -> Result<()> {
+        expand_opt(&mut self.project.src, "project.src")?;
+        expand_opt(&mut self.project.bin, "project.bin")?;
+        for (name, build) in [
+            ("build", &mut self.build),
+            ("debug_build", &mut self.debug_build),
+            ("release_build", &mut self.release_build),
+        ] {
+            if let Some(b) = build {
+                b.expand_env(name)?;
+            }
+        }
+        if let Some(run) = &mut self.run {
+            expand_opt(&mut run.runner, "run.runner")?;
+        }
+        Ok(())
+    }
+}
+
+impl SerdeBuild {
+    fn expand_env(&mut self, section: &str) -> Result<()> {
+        let key = |k| format!("{section}.{k}");
+        expand_opt(&mut self.cc, &key("cc"))?;
+        expand_opt(&mut self.cpp, &key("cpp"))?;
+        expand_list(&mut self.extra_objects, &key("extra_objects"))?;
+        expand_list(&mut self.unity_exclude, &key("unity_exclude"))?;
+        expand_list(&mut self.exclude_sources, &key("exclude_sources"))?;
+        expand_list(&mut self.include_sources, &key("include_sources"))?;
+        if let Some(c) = &mut self.compiler_configuration {
+            c.expand_env(&key("compiler_configuration"))?;
+        }
+        Ok(())
+    }
+}
+
+impl SerdeCompilerConfig {
+    fn expand_env(&mut self, section: &str) -> Result<()> {
+        let key = |k| format!("{section}.{k}");
+        expand_list(&mut self.args, &key("args"))?;
+        expand_list(&mut self.compile_args, &key("compile_args"))?;
+        expand_list(&mut self.link_args, &key("link_args"))?;
+        expand_list(&mut self.c_args, &key("c_args"))?;
+        expand_list(&mut self.cpp_args, &key("cpp_args"))?;
+        expand_opt(&mut self.sysroot, &key("sysroot"))?;
+        expand_opt(&mut self.module_def, &key("module_def"))?;
+        expand_list(&mut self.whole_archive, &key("whole_archive"))?;
+        expand_opt(&mut self.map_file, &key("map_file"))?;
+        expand_list(
+            &mut self.system_include_dirs,
+            &key("system_include_dirs"),
+        )?;
+        for (_, value) in self.defines.iter_mut().flatten() {
+            expand_opt(value, &key("defines"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Expands the environment variables in the value of the configuration key.
+fn expand_value(value: &str, key: &str) -> Result<String> {
+    expand_env(value).map_err(|err| Error::EnvExpansion {
+        key: key.to_owned(),
+        err,
+    })
+}
+
+fn expand_opt(value: &mut Option<String>, key: &str) -> Result<()> {
+    if let Some(v) = value {
+        *v = expand_value(v, key)?;
+    }
+    Ok(())
+}
+
+fn expand_list(values: &mut Option<Vec<String>>, key: &str) -> Result<()> {
+    for v in values.iter_mut().flatten() {
+        *v = expand_value(v, key)?;
+    }
+    Ok(())
+}
+
+/// Merges the metadata options from the profile with the options from the
+/// common build configuration.
+fn resolve_metadata(
+    profile: Option<SerdeMetadata>,
+    common: Option<SerdeMetadata>,
+    files: Option<Vec<String>>,
+) -> Metadata {
+    let p = profile.unwrap_or_default();
+    let c = common.unwrap_or_default();
+    Metadata {
+        git_hash: p.git_hash.or(c.git_hash).unwrap_or_default(),
+        git_dirty: p.git_dirty.or(c.git_dirty).unwrap_or_default(),
+        timestamp: p.timestamp.or(c.timestamp).unwrap_or_default(),
+        profile: p.profile.or(c.profile).unwrap_or_default(),
+        files: files
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    }
+}
+
+/// Merges the unity options from the profile with the options from the
+/// common build configuration. Returns [`None`] if unity build is not
+/// enabled.
+fn resolve_unity(
+    profile: Option<SerdeUnity>,
+    common: Option<SerdeUnity>,
+    exclude: Option<Vec<String>>,
+) -> Option<Unity> {
+    let p = profile.unwrap_or_default();
+    let c = common.unwrap_or_default();
+    if !p.enabled.or(c.enabled).unwrap_or_default() {
+        return None;
+    }
+    Some(Unity {
+        batch_size: p
+            .batch_size
+            .or(c.batch_size)
+            .unwrap_or(DEFAULT_UNITY_BATCH)
+            .max(1),
+        exclude: exclude
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    })
+}
+
+/// Merges the list from the common build configuration (`$a`) with the list
+/// from the profile (`$b`). Lists that are set in both are concatenated,
+/// except when the list in the profile is empty, in that case the result is
+/// empty (the profile can remove the values from the common configuration).
+/// If neither is set, `$default` is used.
 macro_rules! vec_join_or {
     ($default:expr, $a:expr, $b:expr) => {
         match ($a, $b) {
+            (Some(_), Some(b)) if b.is_empty() => b,
             (Some(mut a), Some(mut b)) => {
                 a.append(&mut b);
                 a
@@ -202,7 +968,108 @@ macro_rules! vec_join_or {
     };
 }
 
+/// Adds `NDEBUG` to the defines if it is enabled and the defines don't
+/// already contain it.
+fn with_ndebug(
+    mut defines: Vec<(String, Option<String>)>,
+    ndebug: bool,
+) -> Vec<(String, Option<String>)> {
+    if ndebug && !defines.iter().any(|(n, _)| n == "NDEBUG") {
+        defines.push(("NDEBUG".into(), None));
+    }
+    defines
+}
+
+/// Merges build configuration with configuration with lower priority.
+fn merge_build(
+    build: Option<SerdeBuild>,
+    lower: Option<SerdeBuild>,
+) -> Option<SerdeBuild> {
+    match (build, lower) {
+        (Some(b), Some(l)) => Some(b.merge(l)),
+        (b, l) => b.or(l),
+    }
+}
+
+/// Merges lists the same way as [`vec_join_or`], but keeps the result unset
+/// if neither list is set.
+fn merge_vec<T>(
+    lower: Option<Vec<T>>,
+    upper: Option<Vec<T>>,
+) -> Option<Vec<T>> {
+    match (lower, upper) {
+        (None, None) => None,
+        (l, u) => Some(vec_join_or!(vec![], l, u)),
+    }
+}
+
+impl SerdeBuild {
+    /// Merges with configuration with lower priority. Values from `self` are
+    /// preferred and lists are appended to the lists from `lower`.
+    fn merge(self, lower: SerdeBuild) -> SerdeBuild {
+        let compiler_configuration = match (
+            self.compiler_configuration,
+            lower.compiler_configuration,
+        ) {
+            (Some(c), Some(l)) => Some(c.merge(l)),
+            (c, l) => c.or(l),
+        };
+        SerdeBuild {
+            cc: self.cc.or(lower.cc),
+            cpp: self.cpp.or(lower.cpp),
+            compiler_configuration,
+            metadata: self.metadata.or(lower.metadata),
+            metadata_files: self.metadata_files.or(lower.metadata_files),
+            emit_depfiles: self.emit_depfiles.or(lower.emit_depfiles),
+            object_cache: self.object_cache.or(lower.object_cache),
+            job_timeout: self.job_timeout.or(lower.job_timeout),
+            extra_objects: self.extra_objects.or(lower.extra_objects),
+            unity: self.unity.or(lower.unity),
+            unity_exclude: self.unity_exclude.or(lower.unity_exclude),
+            exclude_sources: merge_vec(
+                lower.exclude_sources,
+                self.exclude_sources,
+            ),
+            include_sources: merge_vec(
+                lower.include_sources,
+                self.include_sources,
+            ),
+        }
+    }
+}
+
 impl SerdeCompilerConfig {
+    /// Merges with configuration with lower priority. Values from `self` are
+    /// preferred and lists are appended to the lists from `lower`.
+    fn merge(self, lower: SerdeCompilerConfig) -> SerdeCompilerConfig {
+        SerdeCompilerConfig {
+            optimization: self.optimization.or(lower.optimization),
+            asan: self.asan.or(lower.asan),
+            dbg_symbols: self.dbg_symbols.or(lower.dbg_symbols),
+            gc_sections: self.gc_sections.or(lower.gc_sections),
+            strict_c: self.strict_c.or(lower.strict_c),
+            ndebug: self.ndebug.or(lower.ndebug),
+            c_std: self.c_std.or(lower.c_std),
+            cpp_std: self.cpp_std.or(lower.cpp_std),
+            defines: merge_vec(lower.defines, self.defines),
+            warn: merge_vec(lower.warn, self.warn),
+            no_warn: merge_vec(lower.no_warn, self.no_warn),
+            args: merge_vec(lower.args, self.args),
+            compile_args: merge_vec(lower.compile_args, self.compile_args),
+            link_args: merge_vec(lower.link_args, self.link_args),
+            c_args: merge_vec(lower.c_args, self.c_args),
+            cpp_args: merge_vec(lower.cpp_args, self.cpp_args),
+            sysroot: self.sysroot.or(lower.sysroot),
+            module_def: self.module_def.or(lower.module_def),
+            whole_archive: merge_vec(lower.whole_archive, self.whole_archive),
+            map_file: self.map_file.or(lower.map_file),
+            system_include_dirs: merge_vec(
+                lower.system_include_dirs,
+                self.system_include_dirs,
+            ),
+        }
+    }
+
     fn resolve_debug(
         self,
         common: SerdeCompilerConfig,
@@ -210,23 +1077,66 @@ impl SerdeCompilerConfig {
         bin_root: PathBuf,
     ) -> CompilerConfig {
         CompilerConfig {
+            // before `bin_root` is moved
+            map_file: self
+                .map_file
+                .or(common.map_file)
+                .map(|m| bin_root.join(m)),
             bin_root,
             src_root,
+            include_dirs: vec![],
+            system_include_dirs: vec_join_or!(
+                vec![],
+                common.system_include_dirs,
+                self.system_include_dirs
+            )
+            .into_iter()
+            .map(Into::into)
+            .collect(),
             optimization: self
                 .optimization
                 .or(common.optimization)
-                .unwrap_or(Optimization::None),
-            asan: self.asan.or(common.asan).unwrap_or(true),
+                .unwrap_or(Optimization::Debug),
+            asan: self.asan.or(common.asan).unwrap_or(Asan::Auto),
             dbg_symbols: self
                 .dbg_symbols
                 .or(common.dbg_symbols)
                 .unwrap_or(true),
+            gc_sections: self
+                .gc_sections
+                .or(common.gc_sections)
+                .unwrap_or_default(),
+            strict_c: self.strict_c.or(common.strict_c).unwrap_or_default(),
             c_std: self.c_std.or(common.c_std).unwrap_or(17.into()),
             cpp_std: self.cpp_std.or(common.cpp_std).unwrap_or(20.into()),
-            defines: vec_join_or!(vec![], common.defines, self.defines),
+            defines: with_ndebug(
+                vec_join_or!(vec![], common.defines, self.defines),
+                self.ndebug.or(common.ndebug).unwrap_or_default(),
+            ),
             warn: vec_join_or!(vec!["all".into()], common.warn, self.warn),
             no_warn: vec_join_or!(vec![], common.no_warn, self.no_warn),
             args: vec_join_or!(vec![], common.args, self.args),
+            compile_args: vec_join_or!(
+                vec![],
+                common.compile_args,
+                self.compile_args
+            ),
+            link_args: vec_join_or!(vec![], common.link_args, self.link_args),
+            c_args: vec_join_or!(vec![], common.c_args, self.c_args),
+            cpp_args: vec_join_or!(vec![], common.cpp_args, self.cpp_args),
+            sysroot: self.sysroot.or(common.sysroot),
+            module_def: self.module_def.or(common.module_def).map(Into::into),
+            whole_archive: vec_join_or!(
+                vec![],
+                common.whole_archive,
+                self.whole_archive
+            )
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+            driver_args: vec![],
+            obj_subdir: true,
+            custom: None,
         }
     }
 
@@ -237,27 +1147,399 @@ impl SerdeCompilerConfig {
         bin_root: PathBuf,
     ) -> CompilerConfig {
         CompilerConfig {
+            // before `bin_root` is moved
+            map_file: self
+                .map_file
+                .or(common.map_file)
+                .map(|m| bin_root.join(m)),
             bin_root,
             src_root,
+            include_dirs: vec![],
+            system_include_dirs: vec_join_or!(
+                vec![],
+                common.system_include_dirs,
+                self.system_include_dirs
+            )
+            .into_iter()
+            .map(Into::into)
+            .collect(),
             optimization: self
                 .optimization
                 .or(common.optimization)
                 .unwrap_or(Optimization::All),
-            asan: self.asan.or(common.asan).unwrap_or_default(),
+            asan: self.asan.or(common.asan).unwrap_or(Asan::Disabled),
             dbg_symbols: self
                 .dbg_symbols
                 .or(common.dbg_symbols)
                 .unwrap_or_default(),
+            gc_sections: self
+                .gc_sections
+                .or(common.gc_sections)
+                .unwrap_or_default(),
+            strict_c: self.strict_c.or(common.strict_c).unwrap_or_default(),
             c_std: self.c_std.or(common.c_std).unwrap_or(17.into()),
             cpp_std: self.cpp_std.or(common.cpp_std).unwrap_or(20.into()),
-            defines: vec_join_or!(
-                vec![("NDEBUG".into(), None)],
-                common.defines,
-                self.defines
+            defines: with_ndebug(
+                vec_join_or!(vec![], common.defines, self.defines),
+                self.ndebug.or(common.ndebug).unwrap_or(true),
             ),
             warn: vec_join_or!(vec!["all".to_owned()], common.warn, self.warn),
             no_warn: vec_join_or!(vec![], common.no_warn, self.no_warn),
             args: vec_join_or!(vec![], common.args, self.args),
+            compile_args: vec_join_or!(
+                vec![],
+                common.compile_args,
+                self.compile_args
+            ),
+            link_args: vec_join_or!(vec![], common.link_args, self.link_args),
+            c_args: vec_join_or!(vec![], common.c_args, self.c_args),
+            cpp_args: vec_join_or!(vec![], common.cpp_args, self.cpp_args),
+            sysroot: self.sysroot.or(common.sysroot),
+            module_def: self.module_def.or(common.module_def).map(Into::into),
+            whole_archive: vec_join_or!(
+                vec![],
+                common.whole_archive,
+                self.whole_archive
+            )
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+            driver_args: vec![],
+            obj_subdir: true,
+            custom: None,
+        }
+    }
+}
+
+impl SerdeCustomCompiler {
+    fn resolve(self) -> CustomCompiler {
+        let args = |t: Option<Vec<String>>, def: &[&str]| {
+            t.unwrap_or_else(|| def.iter().map(|&a| a.into()).collect())
+        };
+        CustomCompiler {
+            bin: self.bin.into(),
+            compile: args(self.compile, &["-c", "-o", "{out}", "{src}"]),
+            link: args(self.link, &["-o", "{out}", "{objs}"]),
+            preprocess: args(self.preprocess, &["-E", "{src}"]),
+            optimization: args(self.optimization, &["-O{level}"]),
+            debug_optimization: args(self.debug_optimization, &["-Og"]),
+            dbg_symbols: args(self.dbg_symbols, &["-g"]),
+            asan: args(self.asan, &["-fsanitize=address"]),
+            std: args(self.std, &["-std={std}"]),
+            define: args(self.define, &["-D{name}"]),
+            define_value: args(self.define_value, &["-D{name}={value}"]),
+            include_dir: args(self.include_dir, &["-I", "{dir}"]),
+            warn: args(self.warn, &["-W{name}"]),
+            no_warn: args(self.no_warn, &["-Wno-{name}"]),
+            sysroot: args(self.sysroot, &["--sysroot={dir}"]),
+            whole_archive: args(
+                self.whole_archive,
+                &["-Wl,--whole-archive", "{lib}", "-Wl,--no-whole-archive"],
+            ),
+            map_file: args(self.map_file, &["-Wl,-Map={file}"]),
+            strict_c: args(self.strict_c, STRICT_C_ARGS),
+            gc_sections: args(
+                self.gc_sections,
+                &["-ffunction-sections", "-fdata-sections"],
+            ),
+            gc_sections_link: args(
+                self.gc_sections_link,
+                if cfg!(target_os = "macos") {
+                    &["-Wl,-dead_strip"]
+                } else {
+                    &["-Wl,--gc-sections"]
+                },
+            ),
+            system_include_dir: args(
+                self.system_include_dir,
+                &["-isystem", "{dir}"],
+            ),
+        }
+    }
+}
+
+/// Creates error with the location of the error in the file and the line
+/// with the error, e.g.:
+/// ```text
+/// ccpp.toml:2:8: invalid string
+///   |
+/// 2 | name = "app
+///   |        ^
+/// ```
+fn toml_error(path: &Path, src: &str, err: toml::de::Error) -> Error {
+    let msg = err.message().trim_end();
+    let start = if let Some(span) = err.span() {
+        span.start.min(src.len())
+    } else {
+        return Error::TomlParse {
+            file: path.to_owned(),
+            msg: msg.to_owned(),
+        };
+    };
+
+    let line_start = src[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = src[start..].find('\n').map_or(src.len(), |i| i + start);
+    let line = src[line_start..line_end].trim_end_matches('\r');
+    let line_num = src[..line_start].matches('\n').count() + 1;
+    let col = src[line_start..start].chars().count() + 1;
+
+    let num = line_num.to_string();
+    let pad = " ".repeat(num.len());
+    let caret_pad: String = line
+        .chars()
+        .take(col - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    Error::TomlParse {
+        file: path.to_owned(),
+        msg: formatc!(
+            "{}:{}: {}
+{'b}{} |
+{} |{'_} {}
+{'b}{} |{'_} {}{'r}^{'_}",
+            num,
+            col,
+            msg,
+            pad,
+            num,
+            line,
+            pad,
+            caret_pad
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// List fields of the compiler configuration with getter of their
+    /// resolved values.
+    type ListField = (&'static str, fn(&CompilerConfig) -> Vec<String>);
+
+    fn paths(p: &[PathBuf]) -> Vec<String> {
+        p.iter().map(|p| p.to_string_lossy().into_owned()).collect()
+    }
+
+    const LISTS: &[ListField] = &[
+        ("warn", |c| c.warn.clone()),
+        ("no_warn", |c| c.no_warn.clone()),
+        ("args", |c| c.args.clone()),
+        ("compile_args", |c| c.compile_args.clone()),
+        ("link_args", |c| c.link_args.clone()),
+        ("c_args", |c| c.c_args.clone()),
+        ("cpp_args", |c| c.cpp_args.clone()),
+        ("whole_archive", |c| paths(&c.whole_archive)),
+        ("system_include_dirs", |c| paths(&c.system_include_dirs)),
+    ];
+
+    fn resolve(src: &str) -> Config {
+        let conf: SerdeConfig = toml::from_str(src).unwrap();
+        conf.resolve(None)
+    }
+
+    /// Creates configuration with the list set in `[build]` and the profiles
+    /// (if the values are [`Some`]).
+    fn with_list(
+        name: &str,
+        common: Option<&str>,
+        profile: Option<&str>,
+    ) -> String {
+        let mut res = "[project]\nname = \"app\"\n".to_owned();
+        if let Some(c) = common {
+            res += &format!("[build.compiler_configuration]\n{name} = {c}\n");
         }
+        if let Some(p) = profile {
+            res += &format!(
+                "[debug_build.compiler_configuration]\n{name} = {p}\n\
+                [release_build.compiler_configuration]\n{name} = {p}\n"
+            );
+        }
+        res
+    }
+
+    #[test]
+    fn profile_list_is_appended() {
+        for (name, get) in LISTS {
+            let conf =
+                resolve(&with_list(name, Some("[\"a\"]"), Some("[\"b\"]")));
+            assert_eq!(
+                get(&conf.debug_build.compiler_conf),
+                ["a", "b"],
+                "{name}"
+            );
+            assert_eq!(
+                get(&conf.release_build.compiler_conf),
+                ["a", "b"],
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn empty_profile_list_replaces_common() {
+        for (name, get) in LISTS {
+            let conf = resolve(&with_list(name, Some("[\"a\"]"), Some("[]")));
+            assert!(get(&conf.debug_build.compiler_conf).is_empty(), "{name}");
+            assert!(
+                get(&conf.release_build.compiler_conf).is_empty(),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn common_list_is_used_without_profile() {
+        for (name, get) in LISTS {
+            let conf = resolve(&with_list(name, Some("[\"a\"]"), None));
+            assert_eq!(get(&conf.debug_build.compiler_conf), ["a"], "{name}");
+            assert_eq!(
+                get(&conf.release_build.compiler_conf),
+                ["a"],
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn empty_warn_removes_default() {
+        let conf = resolve(&with_list("warn", None, None));
+        assert_eq!(conf.debug_build.compiler_conf.warn, ["all"]);
+        let conf = resolve(&with_list("warn", None, Some("[]")));
+        assert!(conf.debug_build.compiler_conf.warn.is_empty());
+        assert!(conf.release_build.compiler_conf.warn.is_empty());
+    }
+
+    /// Names of the defines that are not added by ccpp.
+    fn define_names(conf: &CompilerConfig) -> Vec<&str> {
+        conf.defines
+            .iter()
+            .map(|(n, _)| n.as_str())
+            .filter(|n| !n.starts_with("CCPP_"))
+            .collect()
+    }
+
+    #[test]
+    fn defines_merge() {
+        let a = "[[\"A\", \"1\"]]";
+        let b = "[[\"B\", \"2\"]]";
+        let conf = resolve(&with_list("defines", Some(a), Some(b)));
+        assert_eq!(define_names(&conf.debug_build.compiler_conf), ["A", "B"]);
+
+        let conf = resolve(&with_list("defines", Some(a), Some("[]")));
+        assert!(define_names(&conf.debug_build.compiler_conf).is_empty());
+        // `NDEBUG` is controlled by `ndebug`, not by the list
+        assert_eq!(
+            define_names(&conf.release_build.compiler_conf),
+            ["NDEBUG"]
+        );
+    }
+
+    #[test]
+    fn exclude_sources_merge() {
+        let conf = resolve(
+            "[project]\nname = \"app\"\n\
+            [build]\nexclude_sources = [\"a.c\"]\n\
+            [debug_build]\nexclude_sources = [\"b.c\"]\n\
+            [release_build]\nexclude_sources = []\n",
+        );
+        assert_eq!(conf.debug_build.exclude_sources, ["a.c", "b.c"]);
+        assert!(conf.release_build.exclude_sources.is_empty());
+    }
+
+    #[test]
+    fn include_sources_merge() {
+        let conf = resolve(
+            "[project]\nname = \"app\"\n\
+            [build]\ninclude_sources = [\"a.c\"]\n\
+            [debug_build]\ninclude_sources = [\"b.c\"]\n",
+        );
+        assert_eq!(conf.debug_build.include_sources, ["a.c", "b.c"]);
+        assert_eq!(conf.release_build.include_sources, ["a.c"]);
+    }
+
+    #[test]
+    fn user_lists_are_merged_beneath() {
+        let user: SerdeUserConfig = toml::from_str(
+            "[build.compiler_configuration]\nwarn = [\"extra\"]\n\
+            args = [\"-m64\"]\n",
+        )
+        .unwrap();
+        let mut conf: SerdeConfig = toml::from_str(
+            "[project]\nname = \"app\"\n\
+            [build.compiler_configuration]\nwarn = [\"all\"]\nargs = []\n",
+        )
+        .unwrap();
+        conf.merge_user(&user);
+        let conf = conf.resolve(None).debug_build.compiler_conf;
+        assert_eq!(conf.warn, ["extra", "all"]);
+        assert!(conf.args.is_empty());
+    }
+
+    /// Parses the configuration and returns the first line of the error
+    /// and the code frame (the last three lines) without colors.
+    fn parse_error(src: &str) -> (String, [String; 3]) {
+        let err = toml::from_str::<SerdeConfig>(src).err().unwrap();
+        let Error::TomlParse { msg, .. } =
+            toml_error(Path::new("ccpp.toml"), src, err)
+        else {
+            panic!("expected parse error");
+        };
+        let msg = strip_colors(&msg);
+        let lines: Vec<_> = msg.lines().map(str::to_owned).collect();
+        let frame = lines[lines.len() - 3..].to_vec().try_into().unwrap();
+        (lines[0].clone(), frame)
+    }
+
+    fn strip_colors(s: &str) -> String {
+        let mut res = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.find(|c| c.is_ascii_alphabetic());
+            } else {
+                res.push(c);
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn toml_error_unterminated_string() {
+        let (msg, frame) = parse_error("[project]\nname = \"app\n");
+        // the string ends at the end of the line
+        assert!(msg.starts_with("2:12: "), "{msg}");
+        assert_eq!(frame[0], "  |");
+        assert_eq!(frame[1], "2 | name = \"app");
+        assert_eq!(frame[2], "  |            ^");
+    }
+
+    #[test]
+    fn toml_error_wrong_type() {
+        let (msg, frame) = parse_error(
+            "[project]\nname = \"app\"\n\n[build]\nemit_depfiles = 1\n",
+        );
+        assert!(msg.starts_with("5:17: "), "{msg}");
+        assert_eq!(frame[1], "5 | emit_depfiles = 1");
+        assert_eq!(frame[2], "  |                 ^");
+    }
+
+    #[test]
+    fn toml_error_keeps_tabs_and_crlf() {
+        let (msg, frame) = parse_error("[project]\r\n\tname = 5\r\n");
+        assert!(msg.starts_with("2:9: "), "{msg}");
+        assert_eq!(frame[1], "2 | \tname = 5");
+        assert_eq!(frame[2], "  | \t       ^");
+    }
+
+    #[test]
+    fn toml_error_multi_digit_line() {
+        let src = format!("{}[project\n", "\n".repeat(11));
+        let (msg, frame) = parse_error(&src);
+        assert!(msg.starts_with("12:9: "), "{msg}");
+        assert_eq!(frame[0], "   |");
+        assert_eq!(frame[1], "12 | [project");
+        assert_eq!(frame[2], "   |         ^");
     }
 }
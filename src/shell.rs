@@ -0,0 +1,163 @@
+use std::{borrow::Cow, ffi::OsStr, process::Command};
+
+use crate::err::{Error, Result};
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Quotes the argument so that it can be pasted into shell (POSIX shell or
+/// PowerShell on windows). Arguments that don't need quoting are returned
+/// unchanged.
+pub fn quote(arg: &str) -> Cow<'_, str> {
+    let safe = |c: char| {
+        c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | ',' | '+')
+    };
+    // `:`, `=`, `@` and `%` may have special meaning in PowerShell
+    let posix_safe = |c: char| {
+        safe(c) || (cfg!(unix) && matches!(c, ':' | '=' | '@' | '%'))
+    };
+
+    if !arg.is_empty() && arg.chars().all(posix_safe) {
+        return arg.into();
+    }
+
+    // in single quotes everything is literal except the quote itself
+    if cfg!(windows) {
+        format!("'{}'", arg.replace('\'', "''")).into()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''")).into()
+    }
+}
+
+/// Converts the command into string that can be pasted into shell.
+pub fn command_to_string(cmd: &Command) -> String {
+    let mut res = quote_os(cmd.get_program()).into_owned();
+    for a in cmd.get_args() {
+        res.push(' ');
+        res.push_str(&quote_os(a));
+    }
+    res
+}
+
+/// Splits the string into arguments similarly to how shell would. Arguments
+/// are separated by whitespace, whitespace can be preserved with quotes (`'`
+/// or `"`) or by escaping it with `\`.
+pub fn split_shell(s: &str) -> Result<Vec<String>> {
+    let mut res = vec![];
+    let mut cur = String::new();
+    // true if there is argument in `cur`, it may be empty string
+    let mut in_arg = false;
+    let mut quote = None;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('\'')) => cur.push(c),
+            ('\\', _) => {
+                in_arg = true;
+                if let Some(c) = chars.next() {
+                    cur.push(c);
+                }
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (c, Some(_)) => cur.push(c),
+            ('"' | '\'', None) => {
+                in_arg = true;
+                quote = Some(c);
+            }
+            (c, None) if c.is_whitespace() => {
+                if in_arg {
+                    res.push(std::mem::take(&mut cur));
+                    in_arg = false;
+                }
+            }
+            (c, None) => {
+                in_arg = true;
+                cur.push(c);
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err(Error::Generic(format!("Unclosed quote in `{s}`")));
+    }
+
+    if in_arg {
+        res.push(cur);
+    }
+
+    Ok(res)
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+fn quote_os(arg: &OsStr) -> Cow<'_, str> {
+    match arg.to_string_lossy() {
+        Cow::Borrowed(a) => quote(a),
+        Cow::Owned(a) => quote(&a).into_owned().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safe_args_are_not_quoted() {
+        assert_eq!(quote("-O2"), "-O2");
+        assert_eq!(quote("src/main.c"), "src/main.c");
+        assert_eq!(quote("žluťoučký.c"), "žluťoučký.c");
+        assert_eq!(quote("文件.c"), "文件.c");
+    }
+
+    #[test]
+    fn special_args_are_quoted() {
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("$HOME"), "'$HOME'");
+        assert_eq!(quote("a\nb"), "'a\nb'");
+        assert_eq!(quote("*.c"), "'*.c'");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn embedded_quotes_posix() {
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote("-DNAME=\"app\""), "'-DNAME=\"app\"'");
+        assert_eq!(quote("-DA=1"), "-DA=1");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn embedded_quotes_powershell() {
+        assert_eq!(quote("it's"), "'it''s'");
+        assert_eq!(quote("-DA=1"), "'-DA=1'");
+    }
+
+    #[test]
+    fn command_is_quoted() {
+        let mut cmd = Command::new("gcc");
+        cmd.args(["-c", "my file.c", "-DX=$Y"]);
+        assert_eq!(command_to_string(&cmd), "gcc -c 'my file.c' '-DX=$Y'");
+    }
+
+    #[test]
+    fn split() {
+        let args =
+            split_shell("gcc  -m32 'a b' \"c d\" e\\ f '' x\\'y").unwrap();
+        assert_eq!(args, ["gcc", "-m32", "a b", "c d", "e f", "", "x'y"]);
+        assert!(split_shell("").unwrap().is_empty());
+        assert!(split_shell("'unclosed").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quote_round_trip() {
+        let args = ["a b", "it's", "$x", "a\nb", "", "文件", "\\", "\"q\""];
+        let line: Vec<_> = args.iter().map(|a| quote(a)).collect();
+        assert_eq!(split_shell(&line.join(" ")).unwrap(), args);
+    }
+}
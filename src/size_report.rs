@@ -0,0 +1,201 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use termal::{formatc, printcln};
+
+use crate::{err::Result, metadata};
+
+/// What is printed by `--size-report`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SizeReport {
+    /// Only the size of the binary
+    Binary,
+    /// The size of the binary and the largest object files
+    Objects,
+}
+
+/// Sizes of the binary after the last builds that linked it, the oldest is
+/// first.
+#[derive(Serialize, Deserialize, Default)]
+pub struct SizeHistory {
+    entries: Vec<SizeEntry>,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone)]
+struct SizeEntry {
+    /// Time of the build as seconds since the unix epoch
+    time: u64,
+    /// Size of the binary in bytes
+    size: u64,
+}
+
+/// Max number of builds kept in the history.
+const MAX_ENTRIES: usize = 20;
+/// Number of the largest objects printed with [`SizeReport::Objects`].
+const TOP_OBJECTS: usize = 10;
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+impl SizeReport {
+    /// Parses the value of `--size-report=<value>`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "binary" => Some(Self::Binary),
+            "objects" => Some(Self::Objects),
+            _ => None,
+        }
+    }
+}
+
+impl SizeHistory {
+    /// Loads the history from the file. Missing or invalid file is empty
+    /// history.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = match fs::read_to_string(path) {
+            Ok(d) => d,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(serde_json::from_str(&data).unwrap_or_else(|e| {
+            debug!("Ignoring invalid size history {path:?}: {e}");
+            Self::default()
+        }))
+    }
+
+    /// Records the size of the binary to the history in the file. The size
+    /// is recorded only if the binary was linked or if the history is empty,
+    /// so that the builds that didn't change the binary don't fill the
+    /// history.
+    pub fn record(path: &Path, binary: &Path, linked: bool) -> Result<Self> {
+        let mut res = Self::load(path)?;
+        if !linked && !res.entries.is_empty() {
+            return Ok(res);
+        }
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        res.entries.push(SizeEntry {
+            time,
+            size: fs::metadata(binary)?.len(),
+        });
+        let extra = res.entries.len().saturating_sub(MAX_ENTRIES);
+        res.entries.drain(..extra);
+
+        let json = serde_json::to_string(&res).map_err(anyhow::Error::from)?;
+        fs::write(path, json)?;
+        Ok(res)
+    }
+
+    /// Prints the size of the binary with the change since the previous
+    /// build. With [`SizeReport::Objects`] also prints the largest of the
+    /// object files from `outputs`.
+    pub fn print_report(
+        &self,
+        mode: SizeReport,
+        binary: &Path,
+        outputs: &[PathBuf],
+    ) {
+        let Some(cur) = self.entries.last() else {
+            return;
+        };
+        let prev = self.entries.iter().rev().nth(1);
+        let change = match prev {
+            Some(p) if p.size != cur.size => {
+                format!(" ({})", format_delta(cur.size, p.size, 0))
+            }
+            Some(_) => " (no change)".to_owned(),
+            None => String::new(),
+        };
+        printcln!(
+            "{'g bold}       Size{'_} {} {}{}",
+            binary.to_string_lossy(),
+            format_size(cur.size),
+            change
+        );
+
+        if mode != SizeReport::Objects {
+            return;
+        }
+
+        let mut objs: Vec<_> = outputs
+            .iter()
+            .filter(|o| o.extension().is_some_and(|e| e == "o"))
+            .filter_map(|o| Some((fs::metadata(o).ok()?.len(), o)))
+            .collect();
+        objs.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        for (size, obj) in objs.into_iter().take(TOP_OBJECTS) {
+            println!("{:>11} {}", format_size(size), obj.to_string_lossy());
+        }
+    }
+
+    /// Prints the table with the recorded sizes and their changes.
+    pub fn print_table(&self) {
+        if self.entries.is_empty() {
+            println!("No sizes recorded, build the project first");
+            return;
+        }
+
+        printcln!(
+            "{'bold}{:<20}  {:>11}  {:>11}{'_}",
+            "Date",
+            "Size",
+            "Change"
+        );
+        let mut prev: Option<u64> = None;
+        for e in &self.entries {
+            let change = match prev {
+                Some(p) => format_delta(e.size, p, 11),
+                None => String::new(),
+            };
+            println!(
+                "{:<20}  {:>11}  {}",
+                metadata::format_time(e.time),
+                format_size(e.size),
+                change
+            );
+            prev = Some(e.size);
+        }
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Formats the size in bytes with binary units (e.g. `12.3 KiB`).
+fn format_size(size: u64) -> String {
+    const KIB: u64 = 1024;
+    const MIB: u64 = 1024 * KIB;
+    if size < KIB {
+        format!("{size} B")
+    } else if size < MIB {
+        format!("{:.1} KiB", size as f64 / KIB as f64)
+    } else {
+        format!("{:.1} MiB", size as f64 / MIB as f64)
+    }
+}
+
+/// Formats the change of the size aligned to the right to the given width,
+/// green if it got smaller and red if it got larger.
+fn format_delta(cur: u64, prev: u64, width: usize) -> String {
+    if cur < prev {
+        let delta = format!("-{}", format_size(prev - cur));
+        formatc!("{'g}{:>width$}{'_}", delta, width = width)
+    } else if cur > prev {
+        let delta = format!("+{}", format_size(cur - prev));
+        formatc!("{'r}{:>width$}{'_}", delta, width = width)
+    } else {
+        format!("{:>width$}", "0 B")
+    }
+}
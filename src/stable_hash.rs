@@ -0,0 +1,59 @@
+use std::hash::Hasher;
+
+use sha2::{Digest, Sha256};
+
+/// Hasher with output that doesn't change between runs, versions of rust or
+/// versions of ccpp so that it can be used for names and keys stored on the
+/// disk.
+#[derive(Default)]
+pub struct StableHasher(Sha256);
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+impl StableHasher {
+    /// Creates new hasher.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        let res = self.0.clone().finalize();
+        u64::from_le_bytes(res[..8].try_into().unwrap())
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    // Lengths are hashed as `usize`, make them the same on all platforms.
+    fn write_usize(&mut self, i: usize) {
+        self.0.update((i as u64).to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::Hash;
+
+    use super::*;
+
+    fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+        let mut hasher = StableHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(hash("main.c"), hash(&"main.c".to_string()));
+        assert_ne!(hash("main.c"), hash("main.cpp"));
+        // Known value so that any change of the algorithm is noticed.
+        let mut hasher = StableHasher::new();
+        hasher.write(b"abc");
+        assert_eq!(hasher.finish(), 0xeacf018fbf1678ba);
+    }
+}
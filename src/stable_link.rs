@@ -0,0 +1,70 @@
+use std::{fs, io, path::Path};
+
+use log::{debug, info};
+
+use crate::err::Result;
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Points the stable link to the target. It is relative symlink on unix and
+/// hard link (or copy if hard link is not possible) on windows. Existing
+/// link is replaced, even if it is broken.
+pub fn update(target: &Path, link: &Path) -> Result<()> {
+    // the stable path may collide with the profile directories (e.g. project
+    // named `debug`)
+    if fs::symlink_metadata(link).is_ok_and(|m| m.is_dir()) {
+        debug!("Not linking {link:?}, it is directory");
+        return Ok(());
+    }
+
+    remove(link)?;
+    info!("Linking {link:?} to {target:?}");
+    create(target, link)?;
+    Ok(())
+}
+
+/// Removes the stable link if it exists. Returns the size of the removed
+/// file.
+pub fn remove(link: &Path) -> Result<Option<u64>> {
+    let meta = match fs::symlink_metadata(link) {
+        Ok(m) if !m.is_dir() => m,
+        Ok(_) => return Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    fs::remove_file(link)?;
+    Ok(Some(meta.len()))
+}
+
+/// Checks whether the stable link points to existing binary.
+pub fn is_valid(link: &Path) -> bool {
+    // follows the symlink, so it fails for broken links
+    fs::metadata(link).is_ok_and(|m| m.is_file())
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+#[cfg(unix)]
+fn create(target: &Path, link: &Path) -> io::Result<()> {
+    // relative link still works when the project is moved
+    let dir = link.parent().unwrap_or(Path::new(""));
+    let target = match target.strip_prefix(dir) {
+        Ok(t) => t.to_owned(),
+        Err(_) => std::path::absolute(target)?,
+    };
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn create(target: &Path, link: &Path) -> io::Result<()> {
+    // symlinks need special privileges on windows
+    if let Err(e) = fs::hard_link(target, link) {
+        debug!("Failed to hard link {link:?}, copying instead: {e}");
+        fs::copy(target, link)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::{
+    fs,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use log::debug;
+
+use crate::{
+    err::{Error, Result, ResultExt},
+    stable_hash::StableHasher,
+};
+
+/// Stamp of a successful build. It contains hash of the modification times
+/// of all the files used by the build (sources, headers, configuration and
+/// outputs) so that a build without any changes can be skipped without
+/// scanning the dependencies.
+pub struct Stamp {
+    /// File where the stamp is stored
+    path: PathBuf,
+    /// Hash of everything that affects the build except the files
+    key: u64,
+}
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+impl Stamp {
+    /// Creates stamp stored in the given file. `key` must contain everything
+    /// other than the files that affects the build (e.g. arguments and the
+    /// list of sources).
+    pub fn new<K>(path: PathBuf, key: K) -> Self
+    where
+        K: Hash,
+    {
+        let mut hasher = StableHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        key.hash(&mut hasher);
+        Self {
+            path,
+            key: hasher.finish(),
+        }
+    }
+
+    /// Checks whether nothing changed since the stamp was saved.
+    pub fn is_fresh(&self) -> bool {
+        let stamp = match fs::read_to_string(&self.path) {
+            Ok(s) => s,
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    debug!("Failed to read the stamp: {e}");
+                }
+                return false;
+            }
+        };
+
+        let mut lines = stamp.lines();
+        let recorded =
+            lines.next().and_then(|h| u64::from_str_radix(h, 16).ok());
+        let files: Vec<_> = lines.map(Path::new).collect();
+        let fresh = recorded == Some(self.hash(&files));
+        debug!("The stamp is {}", if fresh { "fresh" } else { "stale" });
+        fresh
+    }
+
+    /// Removes the saved stamp so that it is not used if the build fails.
+    pub fn invalidate(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(Error::io_at("remove", &self.path, e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Saves the stamp with the files used by the build.
+    pub fn save<I, P>(&self, files: I) -> Result<()>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut files: Vec<_> =
+            files.into_iter().map(|f| f.as_ref().to_owned()).collect();
+        files.sort();
+        files.dedup();
+
+        let mut res = format!("{:016x}\n", self.hash(&files));
+        for f in &files {
+            res.push_str(&f.to_string_lossy());
+            res.push('\n');
+        }
+        debug!("Saving stamp with {} files to {:?}", files.len(), self.path);
+        fs::write(&self.path, res).with_path("write", &self.path)?;
+        Ok(())
+    }
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+impl Stamp {
+    /// Hashes the key with the modification times of the files. Files that
+    /// don't exist are also hashed so that their creation is detected.
+    fn hash<P>(&self, files: &[P]) -> u64
+    where
+        P: AsRef<Path>,
+    {
+        let mut hasher = StableHasher::new();
+        self.key.hash(&mut hasher);
+        for f in files {
+            let f = f.as_ref();
+            f.hash(&mut hasher);
+            // the representation of the time may differ between versions of
+            // rust, so it is hashed as the seconds and nanoseconds
+            fs::metadata(f)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| (d.as_secs(), d.subsec_nanos()))
+                .hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
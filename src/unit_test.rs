@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use crate::{err::Result, generated::write_if_changed};
+
+/// Directory with the test sources, relative to the project
+pub const TESTS_DIR: &str = "tests";
+/// Header with the assertions for the automatically discovered tests
+pub const ASSERT_HEADER: &str = "ccpp_assert.h";
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Writes the header with the assertions to the directory, unless it is
+/// already there.
+pub fn write_assert_header(dir: &Path) -> Result<()> {
+    write_if_changed(&dir.join(ASSERT_HEADER), ASSERT_HEADER_CONTENT)
+}
+
+/// Writes source of the test program that includes the test source. With
+/// `tests`, the source also has `main` that runs each of the test functions
+/// and fails if any of them failed. Without `tests`, the test source must
+/// define its own `main`. The source is written only if it changed so that
+/// the test is not rebuilt.
+pub fn write_runner(
+    path: &Path,
+    test: &Path,
+    tests: Option<&[String]>,
+) -> Result<()> {
+    // backslashes would be escapes in the include
+    let test = test.to_string_lossy().replace('\\', "/");
+    let mut res = "// Generated by ccpp, don't edit.\n".to_owned();
+
+    let Some(tests) = tests else {
+        res += &format!("#include \"{test}\"\n");
+        return write_if_changed(path, &res);
+    };
+
+    res += &format!(
+        "#include <setjmp.h>
+#include <stdio.h>
+
+jmp_buf ccpp_test_jmp;
+
+#include \"{test}\"
+
+static int ccpp_run_test(const char *name, void (*test)(void)) {{
+    if (setjmp(ccpp_test_jmp)) {{
+        printf(\"test %s ... FAILED\\n\", name);
+        fflush(stdout);
+        return 1;
+    }}
+    test();
+    printf(\"test %s ... ok\\n\", name);
+    fflush(stdout);
+    return 0;
+}}
+
+int main(void) {{
+    int failed = 0;
+"
+    );
+    for t in tests {
+        res += &format!("    failed += ccpp_run_test(\"{t}\", {t});\n");
+    }
+    res += "    return failed != 0;\n}\n";
+
+    write_if_changed(path, &res)
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+const ASSERT_HEADER_CONTENT: &str = "// Generated by ccpp, don't edit.
+#ifndef CCPP_ASSERT_H_INCLUDED
+#define CCPP_ASSERT_H_INCLUDED
+
+#include <setjmp.h>
+#include <stdio.h>
+
+extern jmp_buf ccpp_test_jmp;
+
+// Fails the current test if the condition is false.
+#define CCPP_ASSERT(cond) \\
+    do { \\
+        if (!(cond)) { \\
+            fflush(stdout); \\
+            fprintf( \\
+                stderr, \\
+                \"%s:%d: assertion failed: %s\\n\", \\
+                __FILE__, \\
+                __LINE__, \\
+                #cond \\
+            ); \\
+            longjmp(ccpp_test_jmp, 1); \\
+        } \\
+    } while (0)
+
+#endif // CCPP_ASSERT_H_INCLUDED
+";
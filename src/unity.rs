@@ -0,0 +1,191 @@
+use std::{
+    collections::BTreeMap,
+    hash::Hasher,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+use crate::{
+    config::Unity,
+    definitions,
+    err::{Result, ResultExt},
+    generated::write_if_changed,
+    stable_hash::StableHasher,
+};
+
+/// Path of unity batch and the sources in it
+type Batch = (PathBuf, Vec<PathBuf>);
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Generates the unity batches of the sources into `out_dir`. Each batch
+/// includes at most [`Unity::batch_size`] sources with the same extension
+/// from the same directory. The excluded sources, the `metadata` files (they
+/// have their own defines) and the sources that define `main` are not in
+/// any batch. Returns the sources that should be compiled instead of `srcs`:
+/// the batches and the sources that are not in any batch.
+///
+/// The batches are written only if they changed and the sources are sorted,
+/// so the same sources always produce the same batches and the objects of
+/// the batches are rebuilt only when some of their sources changed. The
+/// batches are named by the hash of their directory, so adding source
+/// changes only the batches of its directory.
+pub fn generate(
+    srcs: &[PathBuf],
+    unity: &Unity,
+    metadata: &[PathBuf],
+    out_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    let (mut res, batches) = split(srcs, unity, metadata, out_dir)?;
+    for (path, batch) in batches {
+        write_if_changed(&path, &batch_source(&batch))?;
+        res.push(path);
+    }
+    Ok(res)
+}
+
+/// Gets the sources that [`generate`] returns without writing the batches.
+pub fn sources(
+    srcs: &[PathBuf],
+    unity: &Unity,
+    metadata: &[PathBuf],
+    out_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    let (mut res, batches) = split(srcs, unity, metadata, out_dir)?;
+    res.extend(batches.into_iter().map(|(p, _)| p));
+    Ok(res)
+}
+
+/// Gets the batch that includes the source `src`, if it is in any batch.
+pub fn batch_of(
+    src: &Path,
+    srcs: &[PathBuf],
+    unity: &Unity,
+    metadata: &[PathBuf],
+    out_dir: &Path,
+) -> Result<Option<PathBuf>> {
+    // the source that doesn't exist is not in any batch, compiling it
+    // reports the error
+    let Ok(src) = src.canonicalize() else {
+        return Ok(None);
+    };
+    let (_, batches) = split(srcs, unity, metadata, out_dir)?;
+    Ok(batches
+        .into_iter()
+        .find(|(_, b)| b.contains(&src))
+        .map(|(p, _)| p))
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+/// Splits the sources to the sources that are not in any batch and the
+/// batches with their sources.
+fn split(
+    srcs: &[PathBuf],
+    unity: &Unity,
+    metadata: &[PathBuf],
+    out_dir: &Path,
+) -> Result<(Vec<PathBuf>, Vec<Batch>)> {
+    let mut res = vec![];
+    let mut batches = vec![];
+    // sources by their extension and directory
+    let mut groups: BTreeMap<_, Vec<PathBuf>> = BTreeMap::new();
+    let exclude: Vec<_> = unity
+        .exclude
+        .iter()
+        .chain(metadata)
+        .filter_map(|e| e.canonicalize().ok())
+        .collect();
+
+    for src in srcs {
+        let abs = src.canonicalize().with_path("find", src)?;
+        if exclude.contains(&abs) {
+            debug!("{src:?} is excluded from the unity build");
+            res.push(src.clone());
+        } else if definitions::scan(src)?.main {
+            debug!("{src:?} defines main, it is not in unity batch");
+            res.push(src.clone());
+        } else {
+            let ext = src.extension().unwrap_or_default();
+            // the directory as given, so that the names don't depend on
+            // the location of the project
+            let dir = src.parent().unwrap_or(Path::new("")).to_owned();
+            groups
+                .entry((ext.to_string_lossy().into_owned(), dir))
+                .or_default()
+                .push(abs);
+        }
+    }
+
+    for ((ext, dir), mut srcs) in groups {
+        srcs.sort();
+        let mut hasher = StableHasher::new();
+        hasher.write(dir.to_string_lossy().as_bytes());
+        let dir = hasher.finish();
+        for (i, batch) in srcs.chunks(unity.batch_size).enumerate() {
+            let name = format!("batch_{ext}_{dir:016x}_{i}.{ext}");
+            let path = out_dir.join(name);
+            batches.push((path, batch.to_vec()));
+        }
+    }
+
+    Ok((res, batches))
+}
+
+fn batch_source(srcs: &[PathBuf]) -> String {
+    let mut res = "// Generated by ccpp, don't edit.\n".to_owned();
+    for s in srcs {
+        // backslashes would be escapes in the include
+        let s = s.to_string_lossy().replace('\\', "/");
+        res += &format!("#include \"{s}\"\n");
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Creates the sources in the directory and splits them to batches.
+    fn batches(root: &Path, srcs: &[&str]) -> Vec<Batch> {
+        let srcs: Vec<_> = srcs.iter().map(|s| root.join(s)).collect();
+        for s in &srcs {
+            fs::create_dir_all(s.parent().unwrap()).unwrap();
+            fs::write(s, "int f(void);\n").unwrap();
+        }
+        let unity = Unity {
+            batch_size: 2,
+            exclude: vec![],
+        };
+        split(&srcs, &unity, &[], &root.join("unity")).unwrap().1
+    }
+
+    #[test]
+    fn new_source_changes_only_batches_of_its_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let srcs = ["a/x1.c", "a/x2.c", "a/x3.c", "b/y1.c", "b/y2.c"];
+        let old = batches(root, &srcs);
+        assert_eq!(old.len(), 3);
+        for (_, b) in &old {
+            assert!(b.iter().all(|s| s.parent() == b[0].parent()), "{b:?}");
+        }
+
+        let mut with_new = srcs.to_vec();
+        with_new.push("a/x0.c");
+        let new = batches(root, &with_new);
+        assert_eq!(new.len(), 3);
+        let unchanged: Vec<_> =
+            old.iter().filter(|b| new.contains(b)).collect();
+        assert_eq!(unchanged.len(), 1, "{new:?}");
+        let dir = unchanged[0].1[0].parent().unwrap();
+        assert!(dir.ends_with("b"), "{dir:?}");
+    }
+}
@@ -0,0 +1,80 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Version of the project in the form `major.minor.patch` with optional
+/// suffix (e.g. `1.2.0-beta`).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// Text after the patch number including the separator (e.g. `-beta`)
+    pub suffix: String,
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid version `{s}`, expected `major.minor.patch` with \
+                optional suffix (e.g. `1.2.0-beta`)"
+            )
+        };
+
+        let mut parts = s.splitn(3, '.');
+        let num = |s: Option<&str>| {
+            s.filter(|s| {
+                !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+            })
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(invalid)
+        };
+        let major = num(parts.next())?;
+        let minor = num(parts.next())?;
+
+        let rest = parts.next().ok_or_else(invalid)?;
+        let len = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let patch = num(Some(&rest[..len]))?;
+        let suffix = &rest[len..];
+        if !suffix.is_empty() && !suffix.starts_with(['-', '+']) {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            suffix: suffix.to_owned(),
+        })
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}{}",
+            self.major, self.minor, self.patch, self.suffix
+        )
+    }
+}
+
+impl TryFrom<String> for Version {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Version> for String {
+    fn from(value: Version) -> Self {
+        value.to_string()
+    }
+}
@@ -0,0 +1,95 @@
+use std::{fs, path::Path};
+
+use serde_json::{json, Value};
+use termal::printcln;
+
+use crate::{config::Config, err::Result};
+
+//===========================================================================//
+//                                   Public                                  //
+//===========================================================================//
+
+/// Generates `tasks.json` and `launch.json` for VS Code in the directory
+/// `.vscode` in `dir`. Existing files are not overwritten.
+pub fn generate(conf: &Config, dir: &Path) -> Result<()> {
+    let dir = dir.join(".vscode");
+    fs::create_dir_all(&dir)?;
+    write_json(&dir.join("tasks.json"), &tasks())?;
+    write_json(&dir.join("launch.json"), &launch(conf))?;
+    Ok(())
+}
+
+//===========================================================================//
+//                                  Private                                  //
+//===========================================================================//
+
+fn tasks() -> Value {
+    let task = |label: &str, args: &[&str]| {
+        json!({
+            "label": label,
+            "type": "shell",
+            "command": "ccpp",
+            "args": args,
+            "problemMatcher": ["$gcc"],
+        })
+    };
+
+    let mut build = task("ccpp: build", &["build"]);
+    build["group"] = json!({ "kind": "build", "isDefault": true });
+
+    json!({
+        "version": "2.0.0",
+        "tasks": [
+            build,
+            task("ccpp: build release", &["build", "-r"]),
+            task("ccpp: run", &["run"]),
+            task("ccpp: clean", &["clean"]),
+        ],
+    })
+}
+
+fn launch(conf: &Config) -> Value {
+    let target = conf.debug_build.target.to_string_lossy();
+    // relative paths are relative to the project
+    let program = if conf.debug_build.target.is_absolute() {
+        target.into_owned()
+    } else {
+        format!("${{workspaceFolder}}/{target}")
+    };
+    let debugger = if cfg!(target_os = "macos") {
+        "lldb"
+    } else {
+        "gdb"
+    };
+
+    json!({
+        "version": "0.2.0",
+        "configurations": [{
+            "name": format!("Debug {}", conf.project.name),
+            "type": "cppdbg",
+            "request": "launch",
+            "program": program,
+            "args": [],
+            "cwd": "${workspaceFolder}",
+            "MIMode": debugger,
+            "preLaunchTask": "ccpp: build",
+        }],
+    })
+}
+
+/// Writes the json to the file, if the file doesn't exist.
+fn write_json(path: &Path, value: &Value) -> Result<()> {
+    if path.exists() {
+        printcln!(
+            "{'y bold}   Skipping{'_} {}, it already exists",
+            path.to_string_lossy()
+        );
+        return Ok(());
+    }
+
+    // serializing json value cannot fail
+    let json = serde_json::to_string_pretty(value).unwrap_or_default();
+    fs::write(path, json + "\n")?;
+    printcln!("{'g bold}  Generated{'_} {}", path.to_string_lossy());
+    Ok(())
+}
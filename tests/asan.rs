@@ -0,0 +1,34 @@
+mod common;
+
+use common::Project;
+
+#[test]
+fn unavailable_asan_is_disabled_with_warning() {
+    let p = Project::new("[project]\nname = \"app\"\n");
+    p.file("src/main.c", "int main(void) { return 0; }\n");
+    // inject the result of the probe through its cache
+    p.file("bin/debug/.ccpp-probes", "gcc\tasan\t0\n");
+
+    let out = p.run(&["print-flags", "src/main.c"]);
+    assert!(out.status.success(), "{}", common::describe(&out));
+    assert!(!common::stdout(&out).contains("-fsanitize=address"));
+    assert!(
+        common::describe(&out).contains("AddressSanitizer runtime is not"),
+        "{}",
+        common::describe(&out)
+    );
+}
+
+#[test]
+fn unavailable_asan_is_error_when_required() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build.compiler_configuration]\nasan = true\n",
+    );
+    p.file("src/main.c", "int main(void) { return 0; }\n");
+    p.file("bin/debug/.ccpp-probes", "gcc\tasan\t0\n");
+
+    let out = p.run(&["build"]);
+    assert!(!out.status.success(), "{}", common::describe(&out));
+    assert!(common::stderr(&out).contains("asan = false"));
+}
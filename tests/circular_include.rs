@@ -0,0 +1,47 @@
+mod common;
+
+use common::{describe, stdout, Project};
+
+/// Plans the build of the project and gets the warnings about circular
+/// includes with paths relative to the project.
+fn cycle_warnings(p: &Project) -> Vec<String> {
+    let out = p.run(&["plan"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    let root = p.root().canonicalize().unwrap();
+    let root = format!("{}/", root.to_string_lossy());
+    stdout(&out)
+        .lines()
+        .filter_map(|l| l.split_once("circular include: "))
+        .map(|(_, c)| c.replace(&root, ""))
+        .collect()
+}
+
+#[test]
+fn mutually_including_headers_are_reported() {
+    let p = Project::c(&[
+        (
+            "src/main.c",
+            "#include \"a.h\"\nint main(void) { return 0; }\n",
+        ),
+        ("src/a.h", "#include \"b.h\"\n"),
+        ("src/b.h", "#include \"a.h\"\n"),
+    ]);
+    assert_eq!(cycle_warnings(&p), ["src/a.h -> src/b.h -> src/a.h"]);
+}
+
+#[test]
+fn whole_cycle_is_reported() {
+    let p = Project::c(&[
+        (
+            "src/main.c",
+            "#include \"a.h\"\nint main(void) { return 0; }\n",
+        ),
+        ("src/a.h", "#include \"b.h\"\n"),
+        ("src/b.h", "#include \"c.h\"\n"),
+        ("src/c.h", "#include \"a.h\"\n"),
+    ]);
+    assert_eq!(
+        cycle_warnings(&p),
+        ["src/a.h -> src/b.h -> src/c.h -> src/a.h"]
+    );
+}
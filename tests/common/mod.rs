@@ -0,0 +1,131 @@
+//! Helpers for the tests that run ccpp on temporary projects.
+
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use tempfile::TempDir;
+
+/// Temporary project. The user configuration and caches of ccpp are also
+/// temporary, so the tests don't depend on the environment.
+pub struct Project {
+    dir: TempDir,
+    /// Directory used as `HOME` and for the user caches
+    home: TempDir,
+}
+
+impl Project {
+    /// Creates project with the given `ccpp.toml`.
+    pub fn new(toml: &str) -> Self {
+        let res = Self {
+            dir: tempfile::tempdir().unwrap(),
+            home: tempfile::tempdir().unwrap(),
+        };
+        res.file("ccpp.toml", toml);
+        res
+    }
+
+    /// Creates C project with the given sources and without address
+    /// sanitizer (so that it doesn't have to be probed).
+    pub fn c(files: &[(&str, &str)]) -> Self {
+        let res = Self::new(
+            "[project]\nname = \"app\"\n\n\
+            [build.compiler_configuration]\nasan = false\n",
+        );
+        for (path, content) in files {
+            res.file(path, content);
+        }
+        res
+    }
+
+    /// Writes file relative to the project, the directories are created.
+    pub fn file(&self, path: &str, content: &str) -> &Self {
+        let path = self.path(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        self
+    }
+
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.dir.path().join(path)
+    }
+
+    pub fn root(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn home(&self) -> &Path {
+        self.home.path()
+    }
+
+    /// Gets command that runs ccpp in the project.
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_ccpp"));
+        cmd.args(args)
+            .current_dir(self.root())
+            .env("HOME", self.home())
+            .env("XDG_CONFIG_HOME", self.home().join(".config"))
+            .env("XDG_CACHE_HOME", self.home().join(".cache"))
+            .env_remove("CCPP_BIN_ROOT")
+            .env_remove("CC")
+            .env_remove("CXX")
+            .env_remove("MAKEFLAGS");
+        cmd
+    }
+
+    /// Runs ccpp in the project.
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// Runs ccpp with `--events` and returns the files whose commands
+    /// started (relative to the project). Panics if `success` doesn't match.
+    pub fn build(&self, args: &[&str], success: bool) -> Vec<String> {
+        let mut all = vec!["build", "--events"];
+        all.extend(args);
+        let out = self.run(&all);
+        assert_eq!(out.status.success(), success, "{}", describe(&out));
+        started(&out)
+    }
+}
+
+/// Gets the files from the `started` events in the output.
+pub fn started(out: &Output) -> Vec<String> {
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|l| serde_json::from_str::<serde_json::Value>(l).ok())
+        .filter(|e| e["event"] == "started")
+        .flat_map(|e| {
+            e["files"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|f| f.as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Gets stdout and stderr of the process for assertion messages.
+pub fn describe(out: &Output) -> String {
+    format!(
+        "status: {}\nstdout:\n{}\nstderr:\n{}",
+        out.status,
+        String::from_utf8_lossy(&out.stdout),
+        String::from_utf8_lossy(&out.stderr)
+    )
+}
+
+/// Gets stderr of the process.
+pub fn stderr(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stderr).into_owned()
+}
+
+/// Gets stdout of the process.
+pub fn stdout(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
@@ -0,0 +1,30 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{env, fs, os::unix, path::PathBuf};
+
+use common::{describe, Project};
+
+/// Finds the binary in `PATH`.
+fn which(bin: &str) -> PathBuf {
+    env::split_paths(&env::var_os("PATH").unwrap())
+        .map(|d| d.join(bin))
+        .find(|p| p.is_file())
+        .unwrap_or_else(|| panic!("{bin} is not in PATH"))
+}
+
+#[test]
+fn c_project_needs_only_c_compiler() {
+    let p = Project::c(&[("src/main.c", "int main(void) { return 0; }\n")]);
+    // gcc runs the assembler and the linker from `PATH`
+    let path = p.home().join("path");
+    fs::create_dir_all(&path).unwrap();
+    for bin in ["gcc", "as", "ld"] {
+        unix::fs::symlink(which(bin), path.join(bin)).unwrap();
+    }
+
+    let out = p.command(&["build"]).env("PATH", &path).output().unwrap();
+    assert!(out.status.success(), "{}", describe(&out));
+    assert!(p.path("bin/debug/app").is_file());
+}
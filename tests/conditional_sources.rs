@@ -0,0 +1,28 @@
+mod common;
+
+use common::Project;
+
+#[test]
+fn only_matching_conditional_sources_are_compiled() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build.compiler_configuration]\nasan = false\n\
+        compile_args = [\"-DTRACE\"]\n\n\
+        [[conditional_sources]]\nfiles = [\"plan9.c\", \"trace.c\"]\n\
+        platform = \"plan9\"\n\n\
+        [[conditional_sources]]\nfiles = [\"trace.c\"]\ndefine = \"TRACE\"\n",
+    );
+    p.file(
+        "src/main.c",
+        "int trace(void);\nint main(void) { return trace(); }\n",
+    )
+    .file("src/plan9.c", "this is not C\n")
+    .file("src/trace.c", "int trace(void) { return 0; }\n");
+
+    let started = p.build(&[], true);
+    assert!(
+        started.contains(&"bin/debug/project/trace.c.o".to_owned()),
+        "{started:?}"
+    );
+    assert!(started.iter().all(|f| !f.contains("plan9")), "{started:?}");
+}
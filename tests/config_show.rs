@@ -0,0 +1,22 @@
+mod common;
+
+use common::{describe, stdout, Project};
+
+#[test]
+fn shown_config_round_trips() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [debug_build.compiler_configuration]\noptimization = \"debug\"\n\n\
+        [release_build.compiler_configuration]\noptimization = \"z\"\n",
+    );
+    let out = p.run(&["config", "show", "--no-user-config"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    let shown = stdout(&out);
+    assert!(shown.contains("optimization = \"z\""), "{shown}");
+    assert!(shown.contains("optimization = \"debug\""), "{shown}");
+
+    p.file("ccpp.toml", &shown);
+    let out = p.run(&["config", "show", "--no-user-config"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    assert_eq!(stdout(&out), shown);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::Project;
+
+#[test]
+fn custom_compiler_removes_unused_sections() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build.compiler_configuration]\nasan = false\ngc_sections = true\n\n\
+        [compiler.custom]\nbin = \"gcc\"\n\
+        gc_sections = [\"-ffunction-sections\"]\n",
+    );
+    p.file("src/main.c", "int main(void) { return 0; }\n");
+
+    let out = p.run(&["print-flags", "src/main.c"]);
+    assert!(out.status.success(), "{}", common::describe(&out));
+    let flags = common::stdout(&out);
+    assert!(flags.contains(" -ffunction-sections"), "{flags}");
+    assert!(!flags.contains("-fdata-sections"), "{flags}");
+
+    let out = p.run(&["build"]);
+    assert!(out.status.success(), "{}", common::describe(&out));
+    let link = if cfg!(target_os = "macos") {
+        "-Wl,-dead_strip"
+    } else {
+        "-Wl,--gc-sections"
+    };
+    assert!(common::stdout(&out).contains(link));
+}
+
+#[test]
+fn custom_compiler_preprocesses_with_template() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build.compiler_configuration]\nasan = false\n\
+        compile_args = [\"-DVALUE=42\"]\n\n\
+        [compiler.custom]\nbin = \"gcc\"\n\
+        compile = [\"-c\", \"{src}\", \"-o{out}\"]\n\
+        preprocess = [\"-E\", \"-P\", \"{src}\"]\n",
+    );
+    p.file("src/main.c", "int main(void) { return VALUE; }\n");
+
+    let out = p.run(&["preprocess", "src/main.c", "-o", "main.i"]);
+    assert!(out.status.success(), "{}", common::describe(&out));
+    let main = std::fs::read_to_string(p.path("main.i")).unwrap();
+    assert!(main.contains("return 42;"), "{main}");
+    // -P removes the line markers
+    assert!(!main.contains("# 1"), "{main}");
+    assert!(!p.path("bin").join("debug/project/src/main.c.o").exists());
+}
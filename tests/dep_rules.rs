@@ -0,0 +1,83 @@
+mod common;
+
+use std::{
+    fs::{self, File},
+    time::{Duration, SystemTime},
+};
+
+use common::Project;
+
+const CONF: &str = "[project]\nname = \"app\"\nfingerprint = \"content\"\n\n\
+    [build.compiler_configuration]\nasan = false\n";
+
+/// Writes the file with modification time in the future so that it is
+/// newer than the objects.
+fn touch_write(p: &Project, path: &str, content: &str) {
+    p.file(path, content);
+    let time = SystemTime::now() + Duration::from_secs(10);
+    File::options()
+        .write(true)
+        .open(p.path(path))
+        .unwrap()
+        .set_modified(time)
+        .unwrap();
+}
+
+#[test]
+fn partial_build_doesnt_hide_changes() {
+    let p = Project::new(CONF);
+    p.file("src/h.h", "#define VAL 0\n");
+    p.file("src/a.c", "#include \"h.h\"\nint a(void) { return VAL; }\n");
+    p.file("src/b.c", "#include \"h.h\"\nint b(void) { return VAL; }\n");
+    p.file("src/main.c", "int main(void) { return 0; }\n");
+    p.build(&[], true);
+
+    // the hash of the header is recorded only for the rebuilt object
+    touch_write(&p, "src/h.h", "#define VAL 1\n");
+    assert_eq!(p.build(&["src/a.c"], true), ["bin/debug/project/a.c.o"]);
+    let started = p.build(&[], true);
+    assert!(
+        started.contains(&"bin/debug/project/b.c.o".to_owned()),
+        "{started:?}"
+    );
+    assert!(!started.contains(&"bin/debug/project/a.c.o".to_owned()));
+}
+
+#[test]
+fn same_content_doesnt_rebuild() {
+    let p = Project::new(CONF);
+    p.file("src/h.h", "#define VAL 0\n");
+    p.file(
+        "src/main.c",
+        "#include \"h.h\"\nint main(void) { return VAL; }\n",
+    );
+    p.build(&[], true);
+
+    touch_write(&p, "src/h.h", "#define VAL 0\n");
+    assert!(p.build(&[], true).is_empty());
+}
+
+#[test]
+fn editing_comment_doesnt_rebuild() {
+    let p = Project::new(&CONF.replace("\"content\"", "\"stripped\""));
+    p.file("src/h.h", "#define VAL 0\n");
+    p.file(
+        "src/main.c",
+        "#include \"h.h\"\nint main(void) { return VAL; }\n",
+    );
+    p.build(&[], true);
+
+    touch_write(&p, "src/h.h", "/* value */\n#define VAL  0 // zero\n");
+    touch_write(
+        &p,
+        "src/main.c",
+        "#include \"h.h\"\n// main\nint main(void) { return VAL; }\n",
+    );
+    assert!(p.build(&[], true).is_empty());
+
+    // only the sources and headers are compared by content
+    let hashes = fs::read_to_string(p.path("bin/debug/.ccpp-hashes")).unwrap();
+    assert!(hashes.contains("h.h"), "{hashes}");
+    assert!(!hashes.contains(".o\n"), "{hashes}");
+    assert!(!hashes.contains("ccpp.toml"), "{hashes}");
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use std::fs;
+
+use common::Project;
+
+#[test]
+fn depfiles_are_written_for_up_to_date_objects() {
+    let p = Project::c(&[
+        (
+            "src/main.c",
+            "#include \"a.h\"\nint main(void) { return A; }\n",
+        ),
+        ("src/a.h", "#define A 0\n"),
+    ]);
+    p.build(&[], true);
+
+    let conf = fs::read_to_string(p.path("ccpp.toml")).unwrap();
+    p.file(
+        "ccpp.toml",
+        &format!("{conf}\n[build]\nemit_depfiles = true\n"),
+    );
+    let started = p.build(&[], true);
+    assert!(!started.contains(&"bin/debug/project/main.c.o".to_owned()));
+
+    let depfile = p.path("bin/debug/project/main.c.o.d");
+    let content = fs::read_to_string(depfile).unwrap();
+    assert!(
+        content.starts_with("bin/debug/project/main.c.o: \\\n src/main.c"),
+        "{content}"
+    );
+    assert!(content.contains("src/a.h:\n"), "{content}");
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::Project;
+
+fn project() -> Project {
+    Project::c(&[
+        ("src/main.c", "int main(void) { return 0; }\n"),
+        ("src/other.c", "int other(void) { return 0; }\n"),
+        ("src/feature/a.c", "int a(void) { return 0; }\n"),
+        ("src/feature/b.c", "int b(void) { return 0; }\n"),
+    ])
+}
+
+#[test]
+fn dir_is_merged_with_files() {
+    let p = project();
+    let mut started = p.build(&["--dir", "src/feature", "src/other.c"], true);
+    started.sort();
+    assert_eq!(
+        started,
+        [
+            "bin/debug/project/feature/a.c.o",
+            "bin/debug/project/feature/b.c.o",
+            "bin/debug/project/other.c.o",
+        ]
+    );
+}
+
+#[test]
+fn file_in_dir_is_built_once() {
+    let p = project();
+    let mut started =
+        p.build(&["--dir", "src/feature", "src/feature/a.c"], true);
+    started.sort();
+    assert_eq!(
+        started,
+        [
+            "bin/debug/project/feature/a.c.o",
+            "bin/debug/project/feature/b.c.o",
+        ]
+    );
+}
+
+#[test]
+fn source_listed_twice_is_built_once() {
+    let p = Project::c(&[
+        (
+            "src/main.c",
+            "#include \"a.h\"\nint main(void) { return 0; }\n",
+        ),
+        ("src/a.h", "int a(void);\n"),
+    ]);
+    assert_eq!(
+        p.build(&["src/main.c", "src/main.c", "./src/main.c"], true),
+        ["bin/debug/project/main.c.o"]
+    );
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use std::fs;
+
+use common::{describe, stdout, Project};
+
+#[test]
+fn driver_args_come_first() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build.compiler_configuration]\nasan = false\n\
+        compile_args = [\"-DX\"]\nwarn = [\"all\"]\n",
+    );
+    p.file("src/main.c", "int main(void) { return 0; }\n");
+
+    let out = p.command(&["plan"]).env("CC", "gcc -m32 -pipe").output();
+    let out = out.unwrap();
+    assert!(out.status.success(), "{}", describe(&out));
+    let stdout = stdout(&out);
+    let cmd = stdout
+        .lines()
+        .find(|l| l.contains("src/main.c"))
+        .unwrap_or_else(|| panic!("{}", describe(&out)));
+    assert!(cmd.starts_with("gcc "), "{cmd}");
+    let driver = cmd.find(" -m32 -pipe ").expect(cmd);
+    assert!(cmd.find("-Og").is_some_and(|i| i > driver), "{cmd}");
+    assert!(cmd.find("-DX").is_some_and(|i| i > driver), "{cmd}");
+    assert!(cmd.find("-Wall").is_some_and(|i| i > driver), "{cmd}");
+
+    // the probes run with the driver arguments
+    let probes = fs::read_to_string(p.path("bin/debug/.ccpp-probes"));
+    assert!(probes.unwrap().contains("gcc -m32 -pipe\t"));
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use std::fs;
+
+use common::{describe, stderr, Project};
+
+#[test]
+fn missing_config_and_missing_header_are_distinguishable() {
+    let p = Project::new("");
+    fs::remove_file(p.path("ccpp.toml")).unwrap();
+    let out = p.run(&["build"]);
+    assert!(!out.status.success(), "{}", describe(&out));
+    let config = stderr(&out);
+    assert!(config.contains("Failed to read ccpp.toml"), "{config}");
+
+    let p = Project::c(&[(
+        "src/main.c",
+        "#include \"missing.h\"\nint main(void) { return 0; }\n",
+    )]);
+    let out = p.run(&["build"]);
+    assert!(!out.status.success(), "{}", describe(&out));
+    let header = describe(&out);
+    assert!(header.contains("missing.h was not found"), "{header}");
+    assert!(header.contains("Failed to compile src/main.c"), "{header}");
+    assert!(!header.contains("ccpp.toml"), "{header}");
+}
+
+#[test]
+fn unreadable_hashes_name_the_file() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\nfingerprint = \"content\"\n\n\
+        [build.compiler_configuration]\nasan = false\n",
+    );
+    p.file("src/main.c", "int main(void) { return 0; }\n");
+    fs::create_dir_all(p.path("bin/debug/.ccpp-hashes")).unwrap();
+    let out = p.run(&["build"]);
+    assert!(!out.status.success(), "{}", describe(&out));
+    assert!(stderr(&out).contains(".ccpp-hashes"), "{}", describe(&out));
+}
+
+#[test]
+fn missing_compiler_is_named() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build.compiler_configuration]\nasan = false\n\n\
+        [compiler.custom]\nbin = \"ccpp-missing-cc\"\n",
+    );
+    p.file("src/main.c", "int main(void) { return 0; }\n");
+    // stderr of the compiler is forwarded only when building in parallel
+    for args in [["build", "-j", "1"], ["build", "-j", "2"]] {
+        let out = p.run(&args);
+        assert!(!out.status.success(), "{}", describe(&out));
+        let err = stderr(&out);
+        assert!(err.contains("Failed to run ccpp-missing-cc"), "{err}");
+    }
+}
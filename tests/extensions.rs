@@ -0,0 +1,31 @@
+mod common;
+
+use common::{describe, stdout, Project};
+
+const CONF: &str = "[project]
+name = \"app\"
+case_sensitive_extensions = false
+
+[project.source_extensions]
+c = [\"xc\"]
+
+[build.compiler_configuration]
+asan = false
+";
+
+#[test]
+fn custom_extensions_in_all_actions() {
+    let p = Project::new(CONF);
+    p.file("src/main.XC", "int main(void) { return 0; }\n");
+
+    let out = p.run(&["print-flags", "src/main.XC"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    assert!(
+        stdout(&out).contains(" -x c src/main.XC "),
+        "{}",
+        stdout(&out)
+    );
+
+    let built = p.build(&[], true);
+    assert_eq!(built, ["bin/debug/project/main.XC.o", "bin/debug/app"]);
+}
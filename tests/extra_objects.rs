@@ -0,0 +1,62 @@
+mod common;
+
+use std::{fs, process::Command, time::Duration};
+
+use common::{describe, stderr, Project};
+
+/// Compiles tiny object that isn't built by ccpp.
+fn blob(p: &Project, value: i32) {
+    let src = p.path("blob.c");
+    fs::write(&src, format!("int blob(void) {{ return {value}; }}\n"))
+        .unwrap();
+    fs::create_dir_all(p.path("vendor")).unwrap();
+    let out = Command::new("gcc")
+        .arg("-c")
+        .arg(&src)
+        .arg("-o")
+        .arg(p.path("vendor/blob.o"))
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{}", describe(&out));
+}
+
+fn project() -> Project {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build]\nextra_objects = [\"vendor/blob.o\"]\n\n\
+        [build.compiler_configuration]\nasan = false\n",
+    );
+    p.file(
+        "src/main.c",
+        "int blob(void);\nint main(void) { return blob(); }\n",
+    );
+    p
+}
+
+#[test]
+fn tiny_object_is_linked() {
+    let p = project();
+    blob(&p, 3);
+    let out = p.run(&["run"]);
+    assert_eq!(out.status.code(), Some(3), "{}", describe(&out));
+
+    // newer object relinks the executable without compiling the sources
+    std::thread::sleep(Duration::from_millis(20));
+    blob(&p, 4);
+    assert_eq!(p.build(&[], true), ["bin/debug/app"]);
+    let out = p.run(&["run"]);
+    assert_eq!(out.status.code(), Some(4), "{}", describe(&out));
+}
+
+#[test]
+fn missing_object_names_config_key() {
+    let p = project();
+    let out = p.run(&["build"]);
+    assert!(!out.status.success(), "{}", describe(&out));
+    assert!(
+        stderr(&out).contains("`build.extra_objects`"),
+        "{}",
+        describe(&out)
+    );
+    assert!(!p.path("bin/debug/project/main.c.o").exists());
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use std::fs;
+
+use common::Project;
+
+const MAIN: &str = "int ok(void);\nint main(void) { return ok(); }\n";
+
+#[test]
+fn objects_of_failed_build_are_up_to_date() {
+    let p = Project::c(&[
+        ("src/main.c", MAIN),
+        ("src/ok.c", "int ok(void) { return 0; }\n"),
+        ("src/bad.c", "int bad = ;\n"),
+    ]);
+    let first = p.build(&["-k"], false);
+    assert!(first.contains(&"bin/debug/project/ok.c.o".to_owned()));
+
+    p.file("src/bad.c", "int bad = 1;\n");
+    let second = p.build(&[], true);
+    assert_eq!(second, ["bin/debug/project/bad.c.o", "bin/debug/app"]);
+}
+
+#[test]
+fn failed_sources_are_persisted() {
+    let p = Project::c(&[
+        ("src/main.c", MAIN),
+        ("src/ok.c", "int ok(void) { return 0; }\n"),
+        ("src/bad.c", "int bad = ;\n"),
+    ]);
+    let failed = p.path("bin/debug/.ccpp-failed");
+    p.build(&["-k"], false);
+    assert_eq!(fs::read_to_string(&failed).unwrap(), "src/bad.c\n");
+
+    // the failed source is compiled first, before the other changed sources
+    p.file("src/bad.c", "int bad = 1;\n");
+    p.file("src/ok.c", "int ok(void) { return 1 - 1; }\n");
+    let retried = p.build(&["--retry-failed", "-j", "1"], true);
+    assert_eq!(retried[0], "bin/debug/project/bad.c.o");
+    assert!(retried.contains(&"bin/debug/project/ok.c.o".to_owned()));
+    assert!(!failed.exists());
+}
+
+#[test]
+fn retried_outputs_are_printed_once() {
+    let p = Project::c(&[
+        ("src/main.c", MAIN),
+        ("src/ok.c", "int ok(void) { return 0; }\n"),
+        ("src/bad.c", "int bad = ;\n"),
+    ]);
+    p.build(&["-k"], false);
+    p.file("src/bad.c", "int bad = 1;\n");
+
+    let out = p.run(&["build", "--retry-failed", "--print-outputs"]);
+    assert!(out.status.success(), "{}", common::describe(&out));
+    let stdout = common::stdout(&out);
+    let root = p.root().to_string_lossy();
+    let outputs: Vec<_> =
+        stdout.lines().filter(|l| l.starts_with(&*root)).collect();
+    assert_eq!(outputs.len(), 4, "{stdout}");
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use common::Project;
+
+const FILES: &[(&str, &str)] = &[
+    ("src/main.c", "int main(void) { return 0; }\n"),
+    ("src/gen.c", "int gen(void) { return 0; }\n"),
+    (".gitignore", "bin/\nsrc/gen.c\n"),
+];
+
+#[test]
+fn gitignore_is_not_respected_by_default() {
+    let p = Project::c(FILES);
+    let started = p.build(&[], true);
+    assert!(started.contains(&"bin/debug/project/gen.c.o".to_owned()));
+}
+
+#[test]
+fn gitignored_sources_are_skipped() {
+    let p = Project::c(FILES);
+    p.file(
+        "ccpp.toml",
+        "[project]\nname = \"app\"\nrespect_gitignore = true\n\n\
+        [build.compiler_configuration]\nasan = false\n",
+    );
+    let started = p.build(&[], true);
+    assert_eq!(started, ["bin/debug/project/main.c.o", "bin/debug/app"]);
+}
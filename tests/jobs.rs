@@ -0,0 +1,75 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    time::{Duration, Instant},
+};
+
+use common::{describe, stderr, stdout, Project};
+
+/// Creates project compiled with a mock compiler that runs the script before
+/// running gcc.
+fn project(script: &str, build: &str) -> Project {
+    let p = Project::new("");
+    p.file("cc.sh", &format!("#!/bin/sh\n{script}\nexec gcc \"$@\"\n"));
+    let cc = p.path("cc.sh");
+    fs::set_permissions(&cc, fs::Permissions::from_mode(0o755)).unwrap();
+    p.file(
+        "ccpp.toml",
+        &format!(
+            "[project]\nname = \"app\"\n\n\
+            [build]\n{build}\n\n\
+            [build.compiler_configuration]\nasan = false\n\n\
+            [compiler.custom]\nbin = {cc:?}\n"
+        ),
+    );
+    p.file("src/main.c", "int main(void) { return 0; }\n");
+    p
+}
+
+#[test]
+fn job_exceeding_timeout_is_killed() {
+    // exec, so that the killed process is the sleep
+    let p = project(
+        "case \"$*\" in *main.c*) exec sleep 30;; esac",
+        "job_timeout = \"1s\"",
+    );
+
+    let start = Instant::now();
+    let out = p.run(&["build", "--events"]);
+    assert!(
+        start.elapsed() < Duration::from_secs(20),
+        "{}",
+        describe(&out)
+    );
+    assert!(!out.status.success(), "{}", describe(&out));
+    assert!(stderr(&out).contains("timed out"), "{}", describe(&out));
+    assert!(
+        stdout(&out).contains("\"timed_out\":true"),
+        "{}",
+        describe(&out)
+    );
+    assert!(!p.path("bin/debug/app").exists());
+}
+
+#[test]
+fn crashed_job_is_retried_after_backoff() {
+    let crash = "case \"$*\" in *main.c*)\n\
+        if [ ! -e crashed ]; then touch crashed; kill -SEGV $$; fi;;\n\
+        esac";
+    let p = project(crash, "");
+
+    let out = p.run(&["build"]);
+    assert!(!out.status.success(), "{}", describe(&out));
+
+    fs::remove_file(p.path("crashed")).unwrap();
+    let start = Instant::now();
+    let out = p.run(&["build", "--retry", "1"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    assert!(stdout(&out).contains("Retrying"), "{}", describe(&out));
+    assert!(start.elapsed() >= Duration::from_millis(500));
+    assert!(p.path("bin/debug/app").is_file());
+}
@@ -0,0 +1,39 @@
+#![cfg(not(target_os = "macos"))]
+
+mod common;
+
+use common::{describe, stdout, Project};
+
+/// Gets the link command from the plan of project with the source.
+fn link_command(src: &str) -> String {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build.compiler_configuration]\nasan = false\n\
+        whole_archive = [\"vendor/libplugins.a\"]\n\
+        map_file = \"app.map\"\n",
+    );
+    p.file(src, "int main() { return 0; }\n");
+    let out = p.run(&["plan"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    stdout(&out)
+        .lines()
+        .find(|l| l.contains(" -o bin/debug/app "))
+        .unwrap_or_else(|| panic!("{}", describe(&out)))
+        .to_owned()
+}
+
+#[test]
+fn link_args_of_c_and_cpp() {
+    for src in ["src/main.c", "src/main.cpp"] {
+        let cmd = link_command(src);
+        assert!(
+            cmd.contains(
+                " -Wl,--whole-archive vendor/libplugins.a \
+                -Wl,--no-whole-archive"
+            ),
+            "{cmd}"
+        );
+        assert!(cmd.contains(" -Wl,-Map="), "{cmd}");
+        assert!(cmd.contains("app.map"), "{cmd}");
+    }
+}
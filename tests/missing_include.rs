@@ -0,0 +1,54 @@
+mod common;
+
+use common::{describe, stdout, Project};
+
+const MAIN: &str = "#if __has_include(\"local.h\")\n#include \"local.h\"\n\
+    #endif\n#ifndef VAL\n#define VAL 0\n#endif\n\
+    int main(void) { return VAL; }\n";
+
+#[test]
+fn created_header_rebuilds_includer() {
+    let p = Project::c(&[("src/main.c", MAIN)]);
+    p.build(&[], true);
+    assert!(p.build(&[], true).is_empty());
+
+    p.file("src/local.h", "#define VAL 1\n");
+    assert_eq!(
+        p.build(&[], true),
+        ["bin/debug/project/main.c.o", "bin/debug/app"]
+    );
+    assert_eq!(p.run(&["run"]).status.code(), Some(1));
+}
+
+#[test]
+fn failed_compile_notes_missing_include() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build.compiler_configuration]\nasan = false\n\
+        system_include_dirs = [\"vendor\"]\n",
+    );
+    p.file(
+        "src/main.c",
+        "/* the configuration\n   is generated */\n\
+        \t#include \"gone.h\"\n#include \"vendor.h\"\n#include \"util.h\"\n\
+        int main(void) { return 0; }\n",
+    )
+    .file("src/util.h", "#include \\\n  \"config.h\"\n")
+    .file("vendor/vendor.h", "int vendor(void);\n");
+
+    let out = p.run(&["build"]);
+    assert!(!out.status.success(), "{}", describe(&out));
+    // the headers found in the include directories are not reported
+    let notes: Vec<_> = stdout(&out)
+        .lines()
+        .filter(|l| l.contains("Note"))
+        .map(|l| l[l.find("src/").unwrap()..].to_owned())
+        .collect();
+    assert_eq!(
+        notes,
+        [
+            "src/main.c:3:2: included file gone.h was not found",
+            "src/util.h:1:1: included file config.h was not found",
+        ]
+    );
+}
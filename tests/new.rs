@@ -0,0 +1,51 @@
+mod common;
+
+use std::fs;
+
+use common::{describe, Project};
+
+#[test]
+fn template_config_gets_authors_and_license() {
+    let p = Project::new("");
+    let templates = p.home().join(".config/ccpp/templates");
+    fs::create_dir_all(templates.join("lib/src")).unwrap();
+    fs::create_dir_all(templates.join("own")).unwrap();
+    fs::write(
+        templates.join("lib/ccpp.toml"),
+        "# library template\n[project]\nname = \"{{name}}\"\n\n\
+        [build]\ntarget = \"lib{{name}}\"\n",
+    )
+    .unwrap();
+    fs::write(
+        templates.join("own/ccpp.toml"),
+        "[project]\nname = \"{{name}}\"\nlicense = \"GPL-3.0\"\n",
+    )
+    .unwrap();
+    fs::write(
+        p.home().join(".gitconfig"),
+        "[user]\nname = Jan\nemail = jan@example.com\n",
+    )
+    .unwrap();
+
+    let out =
+        p.run(&["new", "mylib", "--template", "lib", "--license", "mit"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    let conf = fs::read_to_string(p.path("mylib/ccpp.toml")).unwrap();
+    assert_eq!(
+        conf,
+        "# library template\n[project]\n\
+        authors = [\"Jan <jan@example.com>\"]\nlicense = \"MIT\"\n\
+        name = \"mylib\"\n\n[build]\ntarget = \"libmylib\"\n"
+    );
+
+    // the values set by the template are kept
+    let out =
+        p.run(&["new", "other", "--template", "own", "--license", "mit"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    let conf = fs::read_to_string(p.path("other/ccpp.toml")).unwrap();
+    assert_eq!(
+        conf,
+        "[project]\nauthors = [\"Jan <jan@example.com>\"]\n\
+        name = \"other\"\nlicense = \"GPL-3.0\"\n"
+    );
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::fs;
+
+use common::{describe, stdout, Project};
+
+fn project() -> Project {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build]\nobject_cache = true\n\n\
+        [build.compiler_configuration]\nasan = false\n\
+        system_include_dirs = [\"vendor\"]\n",
+    );
+    p.file(
+        "src/main.c",
+        "#include <lib.h>\nint main(void) { return VALUE; }\n",
+    )
+    .file("vendor/lib.h", "#define VALUE 1\n");
+    p
+}
+
+#[test]
+fn changed_vendored_header_isnt_restored() {
+    let p = project();
+    let out = p.run(&["run"]);
+    assert_eq!(out.status.code(), Some(1), "{}", describe(&out));
+
+    let out = p.run(&["clean"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    p.file("vendor/lib.h", "#define VALUE 2\n");
+    let out = p.run(&["run"]);
+    assert_eq!(out.status.code(), Some(2), "{}", describe(&out));
+    assert!(!stdout(&out).contains("Cached"), "{}", describe(&out));
+
+    // the old object is still in the cache
+    p.run(&["clean"]);
+    p.file("vendor/lib.h", "#define VALUE 1\n");
+    let out = p.run(&["run"]);
+    assert_eq!(out.status.code(), Some(1), "{}", describe(&out));
+    assert!(stdout(&out).contains("Cached"), "{}", describe(&out));
+}
+
+#[test]
+fn object_with_different_key_isnt_restored() {
+    let p = project();
+    p.build(&[], true);
+    let cache = p.home().join(".cache/ccpp/objects");
+    let keys: Vec<_> = fs::read_dir(&cache)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "key"))
+        .collect();
+    assert!(!keys.is_empty());
+    for k in keys {
+        fs::write(k, "other key\n").unwrap();
+    }
+
+    p.run(&["clean"]);
+    let out = p.run(&["build"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    assert!(!stdout(&out).contains("Cached"), "{}", describe(&out));
+}
+
+#[test]
+fn clean_cache_removes_object_cache() {
+    let p = project();
+    p.build(&[], true);
+    let cache = p.home().join(".cache/ccpp/objects");
+    assert!(cache.is_dir());
+
+    let out = p.run(&["clean"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    assert!(cache.is_dir());
+    let out = p.run(&["clean", "--cache"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    assert!(!cache.exists());
+}
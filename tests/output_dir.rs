@@ -0,0 +1,26 @@
+mod common;
+
+use std::fs;
+
+use common::{describe, stderr, Project};
+
+#[test]
+fn directory_at_object_path_errors() {
+    let p = Project::c(&[("src/main.c", "int main(void) { return 0; }\n")]);
+    fs::create_dir_all(p.path("bin/debug/project/main.c.o")).unwrap();
+
+    let out = p.run(&["build"]);
+    assert!(!out.status.success(), "{}", describe(&out));
+    let err = stderr(&out);
+    assert!(
+        err.contains("main.c.o exists, but it is not a file"),
+        "{err}"
+    );
+    assert!(!err.contains("panicked"), "{err}");
+    assert!(!p.path("bin/debug/app").exists());
+
+    // planning reports the same error
+    let out = p.run(&["plan"]);
+    assert!(!out.status.success(), "{}", describe(&out));
+    assert!(stderr(&out).contains("not a file"), "{}", describe(&out));
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use std::fs;
+
+use common::{describe, stdout, Project};
+
+const MAIN: &str =
+    "#include <embed/data_txt.h>\nint main(void) { return 0; }\n";
+
+#[test]
+fn plan_doesnt_write_anything() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build]\nunity = { enabled = true, batch_size = 2 }\n\n\
+        [build.compiler_configuration]\nasan = false\n\n\
+        [embed]\nfiles = [\"data.txt\"]\n",
+    );
+    p.file("src/main.c", MAIN)
+        .file("src/a.c", "int a(void) { return 1; }\n")
+        .file("src/b.c", "int b(void) { return 2; }\n")
+        .file("data.txt", "hello\n");
+
+    let out = p.run(&["plan"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    // only the cached toolchain probes may be written
+    let written = fs::read_dir(p.path("bin/debug"))
+        .map(|d| d.map(|e| e.unwrap().file_name()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    assert!(written.iter().all(|f| f == ".ccpp-probes"), "{written:?}");
+    assert!(
+        stdout(&out).contains("embed/data_txt.c"),
+        "{}",
+        describe(&out)
+    );
+
+    p.build(&[], true);
+    let args = fs::read_dir(p.path("bin/debug"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.is_file())
+        .map(|p| (fs::read(&p).unwrap(), p))
+        .collect::<Vec<_>>();
+
+    p.file(
+        "ccpp.toml",
+        &fs::read_to_string(p.path("ccpp.toml"))
+            .unwrap()
+            .replace("name = \"app\"", "name = \"app\"\nversion = \"1.0.0\""),
+    );
+    let out = p.run(&["plan"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    assert!(
+        stdout(&out).contains("the arguments changed"),
+        "{}",
+        describe(&out)
+    );
+    for (content, path) in &args {
+        assert_eq!(&fs::read(path).unwrap(), content, "{path:?}");
+    }
+
+    // the plan didn't save the changed arguments
+    let out = p.run(&["plan"]);
+    assert!(
+        stdout(&out).contains("the arguments changed"),
+        "{}",
+        describe(&out)
+    );
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::{describe, stderr, stdout, Project};
+
+#[test]
+fn source_in_unity_batch_is_noted() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build]\nunity = { enabled = true, batch_size = 2 }\n\n\
+        [build.compiler_configuration]\nasan = false\n",
+    );
+    p.file("src/main.c", "int main(void) { return 0; }\n")
+        .file("src/a.c", "int a(void) { return 1; }\n")
+        .file("src/b.c", "int b(void) { return 2; }\n");
+
+    let out = p.run(&["print-flags", "src/a.c"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    assert!(
+        stderr(&out).contains("src/a.c is compiled inside the unity batch"),
+        "{}",
+        describe(&out)
+    );
+    assert!(stdout(&out).contains(" src/a.c"), "{}", describe(&out));
+
+    // main is not in any batch
+    let out = p.run(&["print-flags", "src/main.c"]);
+    assert!(out.status.success(), "{}", describe(&out));
+    assert!(!stderr(&out).contains("unity batch"), "{}", describe(&out));
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use common::{describe, started, Project};
+
+#[test]
+fn debug_only_source_is_compiled_only_in_debug() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build]\nexclude_sources = [\"src/${TRACE_DIR}/**\"]\n\n\
+        [build.compiler_configuration]\nasan = false\n\n\
+        [debug_build]\ninclude_sources = [\"src/trace/**\"]\n",
+    );
+    p.file("src/main.c", "int main(void) { return 0; }\n")
+        .file("src/trace/trace.c", "int trace(void) { return 1; }\n");
+
+    let build = |args: &[&str]| {
+        let mut all = vec!["build", "--events"];
+        all.extend(args);
+        let out = p.command(&all).env("TRACE_DIR", "trace").output();
+        let out = out.unwrap();
+        assert!(out.status.success(), "{}", describe(&out));
+        started(&out)
+    };
+
+    let debug = build(&[]);
+    assert!(
+        debug.contains(&"bin/debug/project/trace/trace.c.o".to_owned()),
+        "{debug:?}"
+    );
+    let release = build(&["-r"]);
+    assert!(release.iter().all(|f| !f.contains("trace")), "{release:?}");
+    assert!(!release.is_empty());
+}
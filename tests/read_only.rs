@@ -0,0 +1,82 @@
+mod common;
+
+use std::{collections::BTreeMap, fs, path::Path, time::SystemTime};
+
+use common::{describe, stderr, Project};
+
+/// Gets all the files and directories in the directory with their
+/// modification times.
+fn snapshot(dir: &Path) -> BTreeMap<String, SystemTime> {
+    let mut res = BTreeMap::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(d) = dirs.pop() {
+        for e in fs::read_dir(&d).unwrap() {
+            let e = e.unwrap();
+            let meta = e.metadata().unwrap();
+            let rel = e.path().strip_prefix(dir).unwrap().to_owned();
+            res.insert(
+                rel.to_string_lossy().into_owned(),
+                meta.modified().unwrap(),
+            );
+            if meta.is_dir() {
+                dirs.push(e.path());
+            }
+        }
+    }
+    res
+}
+
+#[cfg(unix)]
+fn set_read_only(dir: &Path, read_only: bool) {
+    use std::os::unix::fs::PermissionsExt;
+
+    for (path, _) in snapshot(dir) {
+        let path = dir.join(path);
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let mode = if read_only {
+            mode & !0o222
+        } else {
+            mode | 0o200
+        };
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+    let mode = if read_only { 0o555 } else { 0o755 };
+    fs::set_permissions(dir, fs::Permissions::from_mode(mode)).unwrap();
+}
+
+#[test]
+fn build_writes_only_to_bin_dir() {
+    let p = Project::c(&[
+        (
+            "src/main.c",
+            "#include \"util.h\"\nint main(void) { return f(); }\n",
+        ),
+        ("src/util.h", "int f(void);\n"),
+        (
+            "src/util.c",
+            "#include \"util.h\"\nint f(void) { return 0; }\n",
+        ),
+    ]);
+    let bin = tempfile::tempdir().unwrap();
+    let bin_dir = bin.path().to_str().unwrap();
+    let before = snapshot(p.root());
+    #[cfg(unix)]
+    set_read_only(p.root(), true);
+
+    for args in [
+        &["build", "--bin-dir", bin_dir][..],
+        &["build", "--bin-dir", bin_dir, "-r"],
+        &["run", "--bin-dir", bin_dir],
+        &["plan", "--bin-dir", bin_dir],
+    ] {
+        let out = p.run(args);
+        assert!(out.status.success(), "{args:?}: {}", describe(&out));
+        assert!(!stderr(&out).contains("arning"), "{}", describe(&out));
+    }
+
+    #[cfg(unix)]
+    set_read_only(p.root(), false);
+    assert_eq!(snapshot(p.root()), before);
+    assert!(bin.path().join("debug/app").is_file());
+    assert!(bin.path().join("release/app").is_file());
+}
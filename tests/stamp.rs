@@ -0,0 +1,47 @@
+mod common;
+
+use common::Project;
+
+const MAIN: &str = "int main(void) { return 0; }\n";
+const FRESH: &str = "Nothing changed since the last build";
+
+/// Builds the project with the environment variables and checks whether
+/// the build was skipped because of fresh stamp.
+fn skipped(p: &Project, env: &[(&str, &str)]) -> bool {
+    let out = p
+        .command(&["build", "-v"])
+        .envs(env.iter().copied())
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{}", common::describe(&out));
+    common::stderr(&out).contains(FRESH)
+}
+
+#[test]
+fn compiler_from_environment_invalidates_stamp() {
+    let p = Project::c(&[("src/main.c", MAIN)]);
+    assert!(!skipped(&p, &[]));
+    assert!(skipped(&p, &[]));
+
+    assert!(!skipped(&p, &[("CC", "gcc -DFROM_ENV")]));
+    assert!(skipped(&p, &[("CC", "gcc -DFROM_ENV")]));
+    assert!(!skipped(&p, &[("SOURCE_DATE_EPOCH", "0")]));
+}
+
+#[test]
+fn expanded_variables_invalidate_stamp() {
+    let p = Project::new(
+        "[project]\nname = \"app\"\n\n\
+        [build.compiler_configuration]\nasan = false\n\
+        args = [\"-DVAL=${CCPP_TEST_VAL:-0}\"]\n",
+    );
+    p.file("src/main.c", MAIN);
+    assert!(!skipped(&p, &[]));
+    assert!(skipped(&p, &[]));
+
+    assert!(!skipped(&p, &[("CCPP_TEST_VAL", "1")]));
+    assert!(skipped(&p, &[("CCPP_TEST_VAL", "1")]));
+    // variables that are not used don't matter
+    let env = [("CCPP_TEST_VAL", "1"), ("CCPP_OTHER_VAL", "1")];
+    assert!(skipped(&p, &env));
+}
@@ -0,0 +1,25 @@
+mod common;
+
+use std::fs;
+
+use common::Project;
+
+#[test]
+fn user_config_is_dependency_of_the_build() {
+    let p = Project::c(&[("src/main.c", "int main(void) { return 0; }\n")]);
+    assert_eq!(
+        p.build(&[], true),
+        ["bin/debug/project/main.c.o", "bin/debug/app"]
+    );
+    assert!(p.build(&[], true).is_empty());
+
+    // creating the user configuration may change the link flags
+    let dir = p.home().join(".config/ccpp");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("config.toml"), "# empty\n").unwrap();
+    assert_eq!(p.build(&[], true), ["bin/debug/app"]);
+    assert!(p.build(&[], true).is_empty());
+
+    // it is ignored with `--no-user-config`
+    assert!(p.build(&["--no-user-config"], true).is_empty());
+}